@@ -0,0 +1,179 @@
+//! `terris adopt`: take over worktrees made with plain `git worktree add`,
+//! so they count as managed and have a creation time like the ones terris
+//! created itself. `terris disown` does the opposite.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use serde::Serialize;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::lock;
+use crate::metadata::{Metadata, WorktreeMeta};
+use crate::{
+    Worktree, align_rows, gc, repo_root, resolve_worktree, unix_now, worktree_branch_short,
+    worktree_name,
+};
+
+#[derive(Args)]
+pub struct AdoptArgs {
+    /// Worktrees to adopt
+    #[arg(
+        value_name = "target",
+        required_unless_present = "all",
+        conflicts_with = "all"
+    )]
+    targets: Vec<String>,
+    /// Adopt every linked worktree terris does not manage yet, except
+    /// disowned ones
+    #[arg(long)]
+    all: bool,
+}
+
+#[derive(Args)]
+pub struct DisownArgs {
+    /// Worktrees to stop managing
+    #[arg(value_name = "target", required = true)]
+    targets: Vec<String>,
+}
+
+/// An adopted worktree, as reported.
+#[derive(Serialize)]
+struct Adopted<'a> {
+    index: Option<u32>,
+    name: String,
+    branch: Option<&'a str>,
+    path: &'a Path,
+}
+
+pub fn cmd_adopt(
+    backend: &dyn Backend,
+    args: &AdoptArgs,
+    config: &Config,
+    json: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let _lock = lock::registry()?;
+    let mut metadata = Metadata::load()?;
+    let selected: Vec<&Worktree> = if args.all {
+        worktrees
+            .iter()
+            .filter(|wt| !wt.main && wt.prunable.is_none() && wt.path.is_dir())
+            .filter(|wt| {
+                !metadata.managed(&wt.path)
+                    && !metadata.get(&wt.path).is_some_and(|meta| meta.disowned)
+            })
+            .collect()
+    } else {
+        let cwd = std::env::current_dir().context("read current directory")?;
+        let mut selected = Vec::new();
+        for target in &args.targets {
+            let wt = resolve_worktree(target, &worktrees, &cwd, config.fuzzy)?;
+            if wt.main {
+                bail!("'{}' is the primary worktree, not one to adopt", target);
+            }
+            if metadata.managed(&wt.path) {
+                eprintln!("{} is already managed by terris", worktree_name(wt));
+            } else if !selected.iter().any(|s: &&Worktree| s.path == wt.path) {
+                selected.push(wt);
+            }
+        }
+        selected
+    };
+    if selected.is_empty() {
+        if args.all {
+            eprintln!("Every worktree is already managed by terris");
+        }
+        return Ok(());
+    }
+
+    if dry_run {
+        for wt in &selected {
+            println!("Would adopt {} ({})", worktree_name(wt), wt.path.display());
+        }
+        return Ok(());
+    }
+    for wt in &selected {
+        // Its age counts from when git made it, as best the directory tells.
+        let created = gc::modified(&wt.path).unwrap_or_else(unix_now);
+        let meta = metadata.worktrees.entry(wt.path.clone()).or_default();
+        meta.created = Some(created);
+        meta.repo = Some(root.clone());
+        meta.disowned = false;
+    }
+    metadata.assign_indices(&worktrees);
+    metadata.save()?;
+
+    let adopted: Vec<Adopted> = selected
+        .iter()
+        .map(|wt| Adopted {
+            index: metadata.index(&wt.path),
+            name: worktree_name(wt),
+            branch: worktree_branch_short(wt),
+            path: &wt.path,
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&adopted)?);
+        return Ok(());
+    }
+    let rows: Vec<[String; 3]> = adopted
+        .iter()
+        .map(|a| {
+            [
+                a.index.map_or(String::new(), |index| index.to_string()),
+                a.name.clone(),
+                a.path.display().to_string(),
+            ]
+        })
+        .collect();
+    for line in align_rows(&rows) {
+        println!("{}", line);
+    }
+    eprintln!("Adopted {} worktree(s)", adopted.len());
+    Ok(())
+}
+
+pub fn cmd_disown(
+    backend: &dyn Backend,
+    args: &DisownArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let _lock = lock::registry()?;
+    let mut metadata = Metadata::load()?;
+    for target in &args.targets {
+        let wt = resolve_worktree(target, &worktrees, &cwd, config.fuzzy)?;
+        if wt.main {
+            bail!(
+                "'{}' is the primary worktree, which terris never removes",
+                target
+            );
+        }
+        let name = worktree_name(wt);
+        if dry_run {
+            println!("Would disown {} ({})", name, wt.path.display());
+            continue;
+        }
+        // Forget everything, keeping only the note that gc must leave it be.
+        metadata.worktrees.insert(
+            wt.path.clone(),
+            WorktreeMeta {
+                disowned: true,
+                ..WorktreeMeta::default()
+            },
+        );
+        eprintln!("Disowned {} ({})", name, wt.path.display());
+    }
+    if !dry_run {
+        metadata.save()?;
+    }
+    Ok(())
+}
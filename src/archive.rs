@@ -0,0 +1,91 @@
+//! Pack a worktree's files into a `.tar.zst` before it goes away.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::{
+    Worktree, format_timestamp, print_path, repo_root, resolve_worktree, unix_now, worktree_name,
+};
+
+#[derive(Args)]
+pub struct ArchiveArgs {
+    /// Worktree to archive: a branch name, `main`, or `.`
+    #[arg(value_name = "branch")]
+    target: String,
+    /// Directory to write the archive to (defaults to the current directory)
+    #[arg(short, long, value_name = "dir")]
+    output: Option<PathBuf>,
+}
+
+pub fn cmd_archive(
+    backend: &dyn Backend,
+    args: &ArchiveArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let wt = resolve_worktree(&args.target, &worktrees, &cwd, config.fuzzy)?;
+    let dir = args.output.as_deref().unwrap_or(Path::new("."));
+    let file = archive_worktree(backend, wt, dir, dry_run)?;
+    if !dry_run {
+        print_path(&file);
+    }
+    Ok(())
+}
+
+/// Write `<dir>/<name>-<timestamp>.tar.zst` holding the worktree's tracked and
+/// untracked files (ignored files are skipped) and return its path.
+pub fn archive_worktree(
+    backend: &dyn Backend,
+    wt: &Worktree,
+    dir: &Path,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let name = worktree_name(wt);
+    let file_name = format!(
+        "{}-{}.tar.zst",
+        name.replace('/', "-"),
+        format_timestamp(unix_now())
+    );
+    let archive_path = dir.join(file_name);
+    if dry_run {
+        println!(
+            "Would archive {} to {}",
+            wt.path.display(),
+            archive_path.display()
+        );
+        return Ok(archive_path);
+    }
+
+    let files = backend.list_files(&wt.path)?;
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("create archive directory '{}'", dir.display()))?;
+    let out = File::create(&archive_path)
+        .with_context(|| format!("create archive '{}'", archive_path.display()))?;
+    let encoder = zstd::Encoder::new(out, 0)?.auto_finish();
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+
+    let prefix = PathBuf::from(name.replace('/', "-"));
+    for rel in files {
+        let abs = wt.path.join(&rel);
+        // Tracked files deleted in the working tree have nothing to pack.
+        if abs.symlink_metadata().is_err() {
+            continue;
+        }
+        builder
+            .append_path_with_name(&abs, prefix.join(&rel))
+            .with_context(|| format!("add '{}' to archive", rel.display()))?;
+    }
+    builder
+        .into_inner()
+        .with_context(|| format!("write archive '{}'", archive_path.display()))?;
+    Ok(archive_path)
+}
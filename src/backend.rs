@@ -0,0 +1,316 @@
+//! Version-control backends.
+//!
+//! Every interaction with the underlying VCS goes through the [`Backend`]
+//! trait so commands can run against the git CLI, alternative
+//! implementations, or in-memory doubles in tests.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::Worktree;
+
+pub mod git;
+pub mod jj;
+
+pub use git::GitCli;
+pub use jj::Jj;
+
+pub trait Backend {
+    /// Resolve the top-level directory of the repository containing `cwd`.
+    fn root(&self, cwd: &Path) -> Result<PathBuf>;
+    /// The git directory all worktrees of the repository at `root` share.
+    fn common_dir(&self, root: &Path) -> Result<PathBuf>;
+    /// List every worktree registered for the repository at `root`.
+    fn list_worktrees(&self, root: &Path) -> Result<Vec<Worktree>>;
+    /// Whether a local branch named `branch` exists.
+    fn branch_exists(&self, root: &Path, branch: &str) -> Result<bool>;
+    /// Create a worktree at `path` with `branch` checked out.
+    fn add_worktree(&self, root: &Path, path: &Path, branch: &str) -> Result<()>;
+    /// Create a worktree at `path` with `rev` checked out and no branch.
+    fn add_detached(&self, root: &Path, path: &Path, rev: &str) -> Result<()>;
+    /// Remove the worktree at `path`; `force` discards uncommitted changes.
+    fn remove_worktree(&self, root: &Path, path: &Path, force: bool) -> Result<()>;
+    /// Whether the worktree at `path` has uncommitted or untracked changes.
+    fn is_dirty(&self, path: &Path) -> Result<bool>;
+    /// [`Backend::is_dirty`] for each of `paths`, checked in parallel.
+    fn dirty_all(&self, paths: &[&Path]) -> Vec<Result<bool>>;
+    /// Count the work in `path` that is not safely stored elsewhere.
+    fn change_summary(&self, path: &Path) -> Result<ChangeSummary>;
+    /// Tracked and untracked (non-ignored) files in `path`, relative to it.
+    fn list_files(&self, path: &Path) -> Result<Vec<PathBuf>>;
+    /// Snapshot uncommitted and untracked files in `path` to a backup ref
+    /// named after `name`, returning the ref when one was written.
+    fn backup_changes(&self, path: &Path, name: &str) -> Result<Option<String>>;
+    /// Commit time (unix seconds) of the worktree's HEAD, if it has one.
+    fn last_commit_time(&self, path: &Path) -> Result<Option<i64>>;
+    /// The commit checked out at `path`, if it has one.
+    fn head_commit(&self, path: &Path) -> Result<Option<Commit>>;
+    /// Commits on `branch` but not on `base` whose message matches the
+    /// extended regular expression `pattern`, newest first.
+    fn search_commits(
+        &self,
+        root: &Path,
+        branch: &str,
+        base: &str,
+        pattern: &str,
+        ignore_case: bool,
+    ) -> Result<Vec<Commit>>;
+    /// Whether every commit on `branch` is reachable from `base`.
+    fn is_merged(&self, root: &Path, branch: &str, base: &str) -> Result<bool>;
+    /// How many commits on `branch` no other branch, tag, or remote ref
+    /// holds: what deleting the branch would lose.
+    fn unique_commits(&self, root: &Path, branch: &str) -> Result<usize>;
+    /// Whether `branch` tracks an upstream that no longer exists.
+    fn upstream_gone(&self, root: &Path, branch: &str) -> Result<bool>;
+    /// How each local branch tracks its upstream, read in one go. Empty for
+    /// a repository without remotes, whose branches could not track any.
+    fn tracking(&self, root: &Path) -> Result<HashMap<String, Tracking>>;
+    /// Delete the local branch; `force` also deletes unmerged branches.
+    fn delete_branch(&self, root: &Path, branch: &str, force: bool) -> Result<()>;
+    /// Create the local branch `branch` pointing at `start`.
+    fn create_branch(&self, root: &Path, branch: &str, start: &str) -> Result<()>;
+    /// Ignore `pattern` in this clone only, without touching `.gitignore`.
+    fn exclude_locally(&self, root: &Path, pattern: &str) -> Result<()>;
+    /// Let each worktree of the repository at `root` have config of its own
+    /// (`extensions.worktreeConfig`). Returns whether it had to be turned on.
+    fn enable_worktree_config(&self, root: &Path) -> Result<bool>;
+    /// The value of `key` in the own config of the worktree at `path`.
+    fn worktree_config(&self, path: &Path, key: &str) -> Result<Option<String>>;
+    /// Set `key` in the own config of the worktree at `path`, or unset it.
+    fn set_worktree_config(&self, path: &Path, key: &str, value: Option<&str>) -> Result<()>;
+    /// Add a value for `key` to the own config of the worktree at `path`,
+    /// keeping any it has.
+    fn add_worktree_config(&self, path: &Path, key: &str, value: &str) -> Result<()>;
+    /// The entries of the repository's own config as the primary worktree at
+    /// `root` sees them: the shared ones, then its own. Keys come lowercased
+    /// except for subsections.
+    fn own_config(&self, root: &Path) -> Result<Vec<(String, String)>>;
+    /// Drop all local changes and untracked (non-ignored) files in `path`,
+    /// first moving it to `rev` when one is given.
+    fn reset_to(&self, path: &Path, rev: Option<&str>) -> Result<()>;
+    /// Check out the existing local branch `branch` in the worktree at `path`.
+    fn switch_branch(&self, path: &Path, branch: &str) -> Result<()>;
+    /// Stash the uncommitted changes in `path`, and untracked files when
+    /// `untracked` is set, leaving it clean. Returns the stash commit, or
+    /// `None` when there was nothing to stash.
+    fn stash_push(&self, path: &Path, untracked: bool) -> Result<Option<String>>;
+    /// Apply `stash` to the worktree at `path`, restoring what was staged.
+    fn stash_apply(&self, path: &Path, stash: &str) -> Result<()>;
+    /// Remove `stash` from the stash list.
+    fn stash_drop(&self, root: &Path, stash: &str) -> Result<()>;
+    /// Apply a unified diff to the files and index of the worktree at `path`.
+    fn apply_patch(&self, path: &Path, patch: &[u8]) -> Result<()>;
+    /// Show the difference between the working trees at `from` and `to`,
+    /// uncommitted and untracked files included, limited to `paths` when
+    /// any are given. Output goes straight to the terminal.
+    fn diff_worktrees(&self, from: &Path, to: &Path, paths: &[String]) -> Result<()>;
+    /// Land `branch`, checked out at `source`, on `base` the way `how` says.
+    /// `base_worktree` is where `base` is checked out, if anywhere; merging
+    /// and squashing happen there.
+    fn integrate(
+        &self,
+        source: &Path,
+        branch: &str,
+        base: &str,
+        base_worktree: Option<&Path>,
+        how: Integration,
+    ) -> Result<()>;
+    /// Rebase the branch checked out at `path` onto `onto`, moving only the
+    /// commits after `upstream` when given. Returns `false` when the rebase
+    /// stopped on a conflict, left for the user to resolve in that worktree.
+    fn rebase(&self, path: &Path, onto: &str, upstream: Option<&str>) -> Result<bool>;
+    /// Fetch every remote once; all worktrees see the updated refs.
+    fn fetch(&self, root: &Path) -> Result<()>;
+    /// The branch `branch` tracks, e.g. `origin/main`, if any.
+    fn upstream(&self, root: &Path, branch: &str) -> Result<Option<String>>;
+    /// The URL of the remote named `remote`, if it exists.
+    fn remote_url(&self, root: &Path, remote: &str) -> Result<Option<String>>;
+    /// How `branch` compares with its upstream, or `None` if it has none.
+    fn upstream_divergence(&self, root: &Path, branch: &str) -> Result<Option<Divergence>>;
+    /// Fast-forward the branch checked out at `path` to its upstream.
+    fn fast_forward(&self, path: &Path) -> Result<()>;
+    /// Push `branch` to its remote (`origin` unless configured otherwise).
+    fn push_branch(&self, root: &Path, branch: &str) -> Result<()>;
+    /// Resolve `rev` to a full commit id.
+    fn resolve_rev(&self, root: &Path, rev: &str) -> Result<String>;
+    /// Drop administrative entries for worktrees whose directory is gone.
+    fn prune(&self, root: &Path) -> Result<()>;
+    /// Point the repository at `root` and its worktrees at each other again
+    /// after a move; `moved` are the new paths of worktrees that moved too.
+    fn repair_worktrees(&self, root: &Path, moved: &[PathBuf]) -> Result<()>;
+}
+
+/// How `graduate` lands a branch on its base.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Integration {
+    /// Rebase the branch onto the base, then fast-forward the base.
+    Rebase,
+    /// Merge commit on the base.
+    #[default]
+    Merge,
+    /// One commit on the base with all of the branch's changes.
+    Squash,
+}
+
+/// Whether a local branch tracks a branch on a remote.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tracking {
+    Tracked,
+    /// It tracked one that has since been deleted, usually once merged.
+    Gone,
+    Untracked,
+}
+
+/// Commits on each side of a branch and its upstream.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Divergence {
+    /// Commits on the branch that the upstream lacks.
+    pub ahead: usize,
+    /// Commits on the upstream that the branch lacks.
+    pub behind: usize,
+}
+
+/// A commit's identity and headline.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Commit {
+    pub id: String,
+    /// First line of the message.
+    pub summary: String,
+    pub author: String,
+    /// Commit time, unix seconds.
+    pub time: i64,
+}
+
+impl Commit {
+    /// Parse `id`, `author`, `time`, and `summary` separated by NULs.
+    fn parse(output: &str) -> Option<Self> {
+        let mut fields = output.trim_end_matches('\n').splitn(4, '\0');
+        let id = fields.next()?.to_string();
+        let author = fields.next()?.to_string();
+        let time = fields.next()?.parse().ok()?;
+        let summary = fields.next()?.to_string();
+        Some(Self {
+            id,
+            summary,
+            author,
+            time,
+        })
+    }
+}
+
+/// Unsaved work in a worktree, shown before deleting it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChangeSummary {
+    /// Tracked files with staged or unstaged modifications.
+    pub modified: usize,
+    /// Untracked, non-ignored files.
+    pub untracked: usize,
+    /// Commits on HEAD that are not on any remote-tracking branch; without
+    /// remotes, not on the upstream or the primary worktree's branch.
+    pub unpushed: usize,
+}
+
+impl ChangeSummary {
+    /// Whether removing the worktree would discard files.
+    pub fn has_uncommitted(&self) -> bool {
+        self.modified > 0 || self.untracked > 0
+    }
+
+    /// One line per kind of unsaved work.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.modified > 0 {
+            lines.push(format!("{} modified file(s)", self.modified));
+        }
+        if self.untracked > 0 {
+            lines.push(format!("{} untracked file(s)", self.untracked));
+        }
+        if self.unpushed > 0 {
+            lines.push(format!("{} commit(s) not pushed or merged", self.unpushed));
+        }
+        lines
+    }
+}
+
+/// Pick the backend for the repository containing `cwd`.
+///
+/// The nearest of `cwd` and its ancestors holding a `.jj` directory or a
+/// `.git` entry decides: `.jj` selects the Jujutsu backend (including
+/// git-colocated jj repos, which have both), and `.git`, or neither, the
+/// git CLI. So a git worktree placed inside a jj repository stays git.
+///
+/// With `dry_run`, mutating operations print the commands they would run
+/// instead of running them.
+pub fn detect(cwd: &Path, dry_run: bool) -> Box<dyn Backend> {
+    if in_jj_repo(cwd) {
+        Box::new(Jj { dry_run })
+    } else {
+        Box::new(GitCli { dry_run })
+    }
+}
+
+fn in_jj_repo(cwd: &Path) -> bool {
+    cwd.ancestors()
+        .find_map(|dir| {
+            if dir.join(".jj").is_dir() {
+                Some(true)
+            } else {
+                dir.join(".git").exists().then_some(false)
+            }
+        })
+        .unwrap_or(false)
+}
+
+/// Print the command a dry run would have executed.
+pub(crate) fn print_would_run<S: AsRef<OsStr>>(program: &str, args: &[S], cwd: &Path) {
+    let mut line = String::from(program);
+    for arg in args {
+        let arg = arg.as_ref().to_string_lossy();
+        line.push(' ');
+        if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '\'' || c == '"') {
+            line.push_str(&format!("'{}'", arg.replace('\'', r"'\''")));
+        } else {
+            line.push_str(&arg);
+        }
+    }
+    println!("Would run: {} (in {})", line, cwd.display());
+}
+
+/// Log a finished external command at debug level (shown with `--verbose`).
+pub(crate) fn log_command(program: &str, args: &[String], cwd: &Path, started: Instant, ok: bool) {
+    tracing::debug!(
+        cwd = %cwd.display(),
+        elapsed = ?started.elapsed(),
+        ok,
+        "{} {}",
+        program,
+        args.join(" ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_nearest_repository_picks_the_backend() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let jj = temp.path().join("jj");
+        let git_inside = jj.join("wt");
+        std::fs::create_dir_all(jj.join(".jj")).unwrap();
+        std::fs::create_dir_all(jj.join(".git")).unwrap();
+        std::fs::create_dir_all(git_inside.join("src")).unwrap();
+        std::fs::write(git_inside.join(".git"), "gitdir: elsewhere\n").unwrap();
+
+        assert!(in_jj_repo(&jj));
+        assert!(!in_jj_repo(&git_inside.join("src")));
+        assert!(!in_jj_repo(temp.path()));
+    }
+}
@@ -0,0 +1,1298 @@
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use super::{
+    Backend, ChangeSummary, Commit, Divergence, Integration, Tracking, log_command, print_would_run,
+};
+use crate::Worktree;
+use crate::error::Error;
+
+/// Backend that shells out to the `git` binary found in `PATH`.
+#[derive(Default)]
+pub struct GitCli {
+    /// Print mutating commands instead of running them.
+    pub dry_run: bool,
+}
+
+impl Backend for GitCli {
+    fn root(&self, cwd: &Path) -> Result<PathBuf> {
+        let output = run_git(["rev-parse", "--show-toplevel"], cwd)
+            .context(Error::NotARepo { vcs: "git" })?;
+        // Checked here so an old git fails up front, not partway through.
+        require(GitVersion::WORKTREE_LIST, "terris")?;
+        Ok(PathBuf::from(output.trim()))
+    }
+
+    fn common_dir(&self, root: &Path) -> Result<PathBuf> {
+        // Relative to `root` when it is the primary worktree.
+        let output = run_git(["rev-parse", "--git-common-dir"], root)?;
+        Ok(root.join(output.trim()))
+    }
+
+    fn list_worktrees(&self, root: &Path) -> Result<Vec<Worktree>> {
+        // With -z, paths come through byte for byte, even if not UTF-8.
+        if version().is_none_or(|version| version >= GitVersion::WORKTREE_LIST_Z) {
+            let output = run_git_bytes(["worktree", "list", "--porcelain", "-z"], root)?;
+            return Ok(parse_worktrees_z(&output));
+        }
+        let output = run_git(["worktree", "list", "--porcelain"], root)?;
+        Ok(parse_worktrees(&output))
+    }
+
+    fn branch_exists(&self, root: &Path, branch: &str) -> Result<bool> {
+        let args: Vec<String> = vec![
+            "rev-parse".into(),
+            "--verify".into(),
+            "--quiet".into(),
+            format!("refs/heads/{}", branch),
+        ];
+        let started = Instant::now();
+        let mut command = git_command();
+        command.args(&args).current_dir(root);
+        let status = output_within_timeout(command, &args, false)
+            .context("check branch existence")?
+            .status;
+        log_command("git", &args, root, started, status.success());
+        Ok(status.success())
+    }
+
+    fn add_worktree(&self, root: &Path, path: &Path, branch: &str) -> Result<()> {
+        let args: Vec<OsString> = vec![
+            "worktree".into(),
+            "add".into(),
+            "--quiet".into(),
+            path.into(),
+            branch.into(),
+        ];
+        if self.dry_run {
+            print_would_run("git", &args, root);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, root)
+            .inspect_err(|err| undo_stalled_add(err, root, path))
+            .map_err(|err| checked_out_elsewhere(err, branch))
+    }
+
+    fn add_detached(&self, root: &Path, path: &Path, rev: &str) -> Result<()> {
+        let args: Vec<OsString> = vec![
+            "worktree".into(),
+            "add".into(),
+            "--quiet".into(),
+            "--detach".into(),
+            path.into(),
+            rev.into(),
+        ];
+        if self.dry_run {
+            print_would_run("git", &args, root);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, root).inspect_err(|err| undo_stalled_add(err, root, path))
+    }
+
+    fn remove_worktree(&self, root: &Path, path: &Path, force: bool) -> Result<()> {
+        let mut args: Vec<OsString> = vec!["worktree".into(), "remove".into()];
+        if force {
+            args.push("--force".into());
+        }
+        args.push(path.into());
+        if version().is_some_and(|version| version < GitVersion::WORKTREE_REMOVE) {
+            return remove_by_hand(root, path, force, self.dry_run);
+        }
+        if self.dry_run {
+            print_would_run("git", &args, root);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, root).map_err(|err| match err.downcast_ref::<Error>() {
+            Some(Error::Git { stderr, .. })
+                if stderr.contains("contains modified or untracked files") =>
+            {
+                Error::DirtyWorktree(path.to_path_buf()).into()
+            }
+            _ => err,
+        })
+    }
+
+    fn is_dirty(&self, path: &Path) -> Result<bool> {
+        is_dirty(path)
+    }
+
+    fn dirty_all(&self, paths: &[&Path]) -> Vec<Result<bool>> {
+        crate::jobs::map(paths, |path| is_dirty(path))
+    }
+
+    fn change_summary(&self, path: &Path) -> Result<ChangeSummary> {
+        let status = run_git(["status", "--porcelain"], path)?;
+        let mut summary = parse_status_counts(&status);
+        summary.unpushed = self.unpushed(path)?;
+        Ok(summary)
+    }
+
+    fn list_files(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        // Bytes, not text: file names need not be UTF-8.
+        let output = run_git_bytes(
+            [
+                "ls-files",
+                "-z",
+                "--cached",
+                "--others",
+                "--exclude-standard",
+            ],
+            path,
+        )?;
+        let mut files: Vec<PathBuf> = output
+            .split(|&byte| byte == 0)
+            .filter(|f| !f.is_empty())
+            .map(|f| PathBuf::from(OsStr::from_bytes(f)))
+            .collect();
+        files.dedup();
+        Ok(files)
+    }
+
+    fn backup_changes(&self, path: &Path, name: &str) -> Result<Option<String>> {
+        let ref_name = free_backup_ref(path, name)?;
+        if self.dry_run {
+            println!("Would save uncommitted changes to {}", ref_name);
+            return Ok(Some(ref_name));
+        }
+
+        let head = run_git(["rev-parse", "--verify", "--quiet", "HEAD"], path).ok();
+        let result = (|| {
+            let tree = snapshot_tree(path, "terris-backup-index")?;
+            let message = format!("terris backup of {}", name);
+            let mut args = vec!["commit-tree", tree.as_str(), "-m", &message];
+            if let Some(head) = &head {
+                args.extend(["-p", head.trim()]);
+            }
+            // A backup must not fail just because no identity is configured.
+            let fallback_ident = [
+                ("GIT_AUTHOR_NAME", OsStr::new("terris")),
+                ("GIT_AUTHOR_EMAIL", OsStr::new("terris@localhost")),
+                ("GIT_COMMITTER_NAME", OsStr::new("terris")),
+                ("GIT_COMMITTER_EMAIL", OsStr::new("terris@localhost")),
+            ];
+            let ident_env: &[(&str, &OsStr)] = match run_git(["var", "GIT_COMMITTER_IDENT"], path) {
+                Ok(_) => &[],
+                Err(_) => &fallback_ident,
+            };
+            let commit = run_git_env(args, path, ident_env)?;
+            run_git(["update-ref", &ref_name, commit.trim()], path)
+        })();
+        result.context("save backup of uncommitted changes")?;
+        Ok(Some(ref_name))
+    }
+
+    fn last_commit_time(&self, path: &Path) -> Result<Option<i64>> {
+        let output = run_git(["log", "-1", "--format=%ct"], path)?;
+        Ok(output.trim().parse().ok())
+    }
+
+    fn head_commit(&self, path: &Path) -> Result<Option<Commit>> {
+        let output = run_git(["log", "-1", "--format=%H%x00%an%x00%ct%x00%s"], path)?;
+        Ok(Commit::parse(&output))
+    }
+
+    fn search_commits(
+        &self,
+        root: &Path,
+        branch: &str,
+        base: &str,
+        pattern: &str,
+        ignore_case: bool,
+    ) -> Result<Vec<Commit>> {
+        let mut args = vec![
+            "log".to_string(),
+            "--format=%H%x00%an%x00%ct%x00%s".to_string(),
+            "--extended-regexp".to_string(),
+            format!("--grep={}", pattern),
+        ];
+        if ignore_case {
+            args.push("--regexp-ignore-case".to_string());
+        }
+        args.extend([
+            format!("refs/heads/{}", branch),
+            "--not".to_string(),
+            base.to_string(),
+            "--".to_string(),
+        ]);
+        let output = run_git(&args, root)?;
+        Ok(output.lines().filter_map(Commit::parse).collect())
+    }
+
+    fn is_merged(&self, root: &Path, branch: &str, base: &str) -> Result<bool> {
+        let args: Vec<String> = vec![
+            "merge-base".into(),
+            "--is-ancestor".into(),
+            format!("refs/heads/{}", branch),
+            base.to_string(),
+        ];
+        let started = Instant::now();
+        let mut command = git_command();
+        command.args(&args).current_dir(root);
+        let status = output_within_timeout(command, &args, false)
+            .context("check whether branch is merged")?
+            .status;
+        log_command("git", &args, root, started, status.success());
+        Ok(status.success())
+    }
+
+    fn unique_commits(&self, root: &Path, branch: &str) -> Result<usize> {
+        let output = run_git(
+            [
+                "rev-list".to_string(),
+                "--count".to_string(),
+                format!("refs/heads/{}", branch),
+                "--not".to_string(),
+                format!("--exclude={}", branch),
+                "--branches".to_string(),
+                "--tags".to_string(),
+                "--remotes".to_string(),
+            ],
+            root,
+        )?;
+        Ok(output.trim().parse().unwrap_or(0))
+    }
+
+    fn upstream_gone(&self, root: &Path, branch: &str) -> Result<bool> {
+        let ref_name = format!("refs/heads/{}", branch);
+        let output = run_git(
+            ["for-each-ref", "--format=%(upstream:track)", &ref_name],
+            root,
+        )?;
+        Ok(output.trim() == "[gone]")
+    }
+
+    fn tracking(&self, root: &Path) -> Result<HashMap<String, Tracking>> {
+        if run_git(["remote"], root)?.trim().is_empty() {
+            return Ok(HashMap::new());
+        }
+        let output = run_git(
+            [
+                "for-each-ref",
+                "--format=%(refname:short)%00%(upstream)%00%(upstream:track)",
+                "refs/heads",
+            ],
+            root,
+        )?;
+        Ok(output
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\0');
+                let branch = fields.next()?;
+                let tracking = match (fields.next()?, fields.next()?) {
+                    ("", _) => Tracking::Untracked,
+                    (_, "[gone]") => Tracking::Gone,
+                    _ => Tracking::Tracked,
+                };
+                Some((branch.to_string(), tracking))
+            })
+            .collect())
+    }
+
+    fn delete_branch(&self, root: &Path, branch: &str, force: bool) -> Result<()> {
+        let flag = if force { "-D" } else { "-d" };
+        let args: Vec<String> = vec!["branch".into(), flag.into(), branch.to_string()];
+        if self.dry_run {
+            print_would_run("git", &args, root);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, root)
+    }
+
+    fn create_branch(&self, root: &Path, branch: &str, start: &str) -> Result<()> {
+        let args: Vec<String> = vec!["branch".into(), branch.to_string(), start.to_string()];
+        if self.dry_run {
+            print_would_run("git", &args, root);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, root)
+    }
+
+    fn reset_to(&self, path: &Path, rev: Option<&str>) -> Result<()> {
+        let mut reset: Vec<String> = vec!["reset".into(), "--hard".into(), "--quiet".into()];
+        reset.extend(rev.map(String::from));
+        let clean: Vec<String> = vec!["clean".into(), "-fd".into(), "--quiet".into()];
+        if self.dry_run {
+            print_would_run("git", &reset, path);
+            print_would_run("git", &clean, path);
+            return Ok(());
+        }
+        run_git_silence_stdout(&reset, path)?;
+        run_git_silence_stdout(&clean, path)
+    }
+
+    fn switch_branch(&self, path: &Path, branch: &str) -> Result<()> {
+        // Gits from before `switch` do the same with `checkout`.
+        let subcommand = if version().is_some_and(|version| version < GitVersion::SWITCH) {
+            "checkout"
+        } else {
+            "switch"
+        };
+        let args: Vec<String> = vec![subcommand.into(), "--quiet".into(), branch.to_string()];
+        if self.dry_run {
+            print_would_run("git", &args, path);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, path).map_err(|err| checked_out_elsewhere(err, branch))
+    }
+
+    fn stash_push(&self, path: &Path, untracked: bool) -> Result<Option<String>> {
+        require(
+            GitVersion::STASH_PUSH,
+            "moving changes with `git stash push`",
+        )?;
+        let mut args: Vec<String> = vec!["stash".into(), "push".into(), "--quiet".into()];
+        if untracked {
+            args.push("--include-untracked".into());
+        }
+        args.extend(["--message".into(), "terris: carry".into()]);
+        if self.dry_run {
+            print_would_run("git", &args, path);
+            return Ok(Some("stash@{0}".into()));
+        }
+        // `stash push` succeeds without stashing anything when the tree is
+        // clean, so compare the top of the stash before and after.
+        let before = stash_top(path);
+        run_git_silence_stdout(&args, path)?;
+        let after = stash_top(path);
+        Ok(after.filter(|after| before.as_ref() != Some(after)))
+    }
+
+    fn stash_apply(&self, path: &Path, stash: &str) -> Result<()> {
+        let args: Vec<String> = vec![
+            "stash".into(),
+            "apply".into(),
+            "--index".into(),
+            "--quiet".into(),
+            stash.to_string(),
+        ];
+        if self.dry_run {
+            print_would_run("git", &args, path);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, path)
+    }
+
+    fn stash_drop(&self, root: &Path, stash: &str) -> Result<()> {
+        // `stash drop` wants a `stash@{n}` entry, not a commit.
+        let entry = if self.dry_run {
+            stash.to_string()
+        } else {
+            let list = run_git(["stash", "list", "--format=%H"], root)?;
+            let position = list
+                .lines()
+                .position(|commit| commit == stash)
+                .with_context(|| format!("stash {} is no longer in the stash list", stash))?;
+            format!("stash@{{{}}}", position)
+        };
+        let args: Vec<String> = vec!["stash".into(), "drop".into(), "--quiet".into(), entry];
+        if self.dry_run {
+            print_would_run("git", &args, root);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, root)
+    }
+
+    fn apply_patch(&self, path: &Path, patch: &[u8]) -> Result<()> {
+        let args: Vec<String> = vec!["apply".into(), "--index".into()];
+        if self.dry_run {
+            print_would_run("git", &args, path);
+            return Ok(());
+        }
+        let started = Instant::now();
+        let mut child = git_command()
+            .args(&args)
+            .current_dir(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| run_context(&args))?;
+        let written = child.stdin.take().expect("stdin is piped").write_all(patch);
+        let output = child.wait_with_output().context("run git apply")?;
+        log_command("git", &args, path, started, output.status.success());
+        if !output.status.success() {
+            return Err(git_failure("git", &args, &output.stderr));
+        }
+        written.context("write patch to git apply")
+    }
+
+    fn diff_worktrees(&self, from: &Path, to: &Path, paths: &[String]) -> Result<()> {
+        let from_tree = snapshot_tree(from, "terris-diff-index")?;
+        let to_tree = snapshot_tree(to, "terris-diff-index")?;
+        let mut args: Vec<String> = vec!["diff".into(), from_tree, to_tree, "--".into()];
+        args.extend(paths.iter().cloned());
+        run_git_interactive(&args, from)
+    }
+
+    fn integrate(
+        &self,
+        source: &Path,
+        branch: &str,
+        base: &str,
+        base_worktree: Option<&Path>,
+        how: Integration,
+    ) -> Result<()> {
+        let target = base_worktree.unwrap_or(source);
+        match how {
+            Integration::Rebase => {
+                self.mutate(&["rebase", "--quiet", base], source)?;
+                match base_worktree {
+                    Some(target) => self.mutate(&["merge", "--ff-only", "--quiet", branch], target),
+                    // Fast-forwards a branch nobody has checked out.
+                    None => self.mutate(
+                        &["fetch", "--quiet", ".", &format!("{}:{}", branch, base)],
+                        source,
+                    ),
+                }
+            }
+            Integration::Merge => self.mutate(
+                &["merge", "--no-ff", "--no-edit", "--quiet", branch],
+                target,
+            ),
+            Integration::Squash => {
+                self.mutate(&["merge", "--squash", "--quiet", branch], target)?;
+                self.mutate(&["commit", "--no-edit", "--quiet"], target)
+            }
+        }
+    }
+
+    fn rebase(&self, path: &Path, onto: &str, upstream: Option<&str>) -> Result<bool> {
+        let args = match upstream {
+            Some(upstream) => vec!["rebase", "--quiet", "--onto", onto, upstream],
+            None => vec!["rebase", "--quiet", onto],
+        };
+        match self.mutate(&args, path) {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                let rebasing = ["rebase-merge", "rebase-apply"].iter().any(|dir| {
+                    run_git(["rev-parse", "--git-path", dir], path)
+                        .is_ok_and(|dir| path.join(dir.trim()).exists())
+                });
+                if rebasing { Ok(false) } else { Err(err) }
+            }
+        }
+    }
+
+    fn fetch(&self, root: &Path) -> Result<()> {
+        self.mutate(&["fetch", "--all", "--prune", "--quiet"], root)
+    }
+
+    fn upstream(&self, root: &Path, branch: &str) -> Result<Option<String>> {
+        let ref_name = format!("refs/heads/{}", branch);
+        let upstream = run_git(
+            ["for-each-ref", "--format=%(upstream:short)", &ref_name],
+            root,
+        )?;
+        let upstream = upstream.trim();
+        Ok((!upstream.is_empty()).then(|| upstream.to_string()))
+    }
+
+    fn remote_url(&self, root: &Path, remote: &str) -> Result<Option<String>> {
+        let key = format!("remote.{}.url", remote);
+        // `git config --get` exits with 1 when the key is unset.
+        Ok(run_git(["config", "--get", &key], root)
+            .ok()
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty()))
+    }
+
+    fn upstream_divergence(&self, root: &Path, branch: &str) -> Result<Option<Divergence>> {
+        let ref_name = format!("refs/heads/{}", branch);
+        let upstream = run_git(["for-each-ref", "--format=%(upstream)", &ref_name], root)?;
+        let upstream = upstream.trim();
+        if upstream.is_empty()
+            || run_git(["rev-parse", "--verify", "--quiet", upstream], root).is_err()
+        {
+            return Ok(None);
+        }
+        let range = format!("{}...{}", ref_name, upstream);
+        let counts = run_git(["rev-list", "--left-right", "--count", &range], root)?;
+        let mut counts = counts.split_whitespace().map(|n| n.parse().unwrap_or(0));
+        Ok(Some(Divergence {
+            ahead: counts.next().unwrap_or(0),
+            behind: counts.next().unwrap_or(0),
+        }))
+    }
+
+    fn prune(&self, root: &Path) -> Result<()> {
+        self.mutate(&["worktree", "prune"], root)
+    }
+
+    fn repair_worktrees(&self, root: &Path, moved: &[PathBuf]) -> Result<()> {
+        require(
+            GitVersion::WORKTREE_REPAIR,
+            "repairing worktree links with `git worktree repair`",
+        )?;
+        let mut args: Vec<OsString> = vec!["worktree".into(), "repair".into()];
+        args.extend(moved.iter().map(OsString::from));
+        if self.dry_run {
+            print_would_run("git", &args, root);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, root)
+    }
+
+    fn fast_forward(&self, path: &Path) -> Result<()> {
+        self.mutate(&["merge", "--ff-only", "--quiet", "@{upstream}"], path)
+    }
+
+    fn push_branch(&self, root: &Path, branch: &str) -> Result<()> {
+        let remote = run_git(
+            ["config", "--get", &format!("branch.{}.remote", branch)],
+            root,
+        )
+        .map(|remote| remote.trim().to_string())
+        .unwrap_or_else(|_| "origin".to_string());
+        self.mutate(&["push", "--quiet", &remote, branch], root)
+    }
+
+    fn resolve_rev(&self, root: &Path, rev: &str) -> Result<String> {
+        let output = run_git(
+            [
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{}^{{commit}}", rev),
+            ],
+            root,
+        )
+        .with_context(|| format!("'{}' is not a commit", rev))?;
+        Ok(output.trim().to_string())
+    }
+
+    fn exclude_locally(&self, root: &Path, pattern: &str) -> Result<()> {
+        // info/exclude lives in the common git dir, so it covers every worktree.
+        let file = run_git(["rev-parse", "--git-path", "info/exclude"], root)?;
+        let file = root.join(file.trim());
+        let existing = match std::fs::read_to_string(&file) {
+            Ok(existing) => existing,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).with_context(|| format!("read '{}'", file.display())),
+        };
+        if existing.lines().any(|line| line.trim() == pattern) {
+            return Ok(());
+        }
+        if self.dry_run {
+            println!("Would add {} to {}", pattern, file.display());
+            return Ok(());
+        }
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("create '{}'", parent.display()))?;
+        }
+        let separator = if existing.is_empty() || existing.ends_with('\n') {
+            ""
+        } else {
+            "\n"
+        };
+        std::fs::write(&file, format!("{}{}{}\n", existing, separator, pattern))
+            .with_context(|| format!("write '{}'", file.display()))
+    }
+
+    fn enable_worktree_config(&self, root: &Path) -> Result<bool> {
+        if config_value(&["--local", "--bool", "extensions.worktreeConfig"], root)?.as_deref()
+            == Some("true")
+        {
+            return Ok(false);
+        }
+        self.mutate(
+            &["config", "--local", "extensions.worktreeConfig", "true"],
+            root,
+        )?;
+        // Per git-worktree(1), once the extension is on these two only take
+        // effect from the primary worktree's own config.
+        for key in ["core.worktree", "core.bare"] {
+            if let Some(value) = config_value(&["--local", key], root)? {
+                self.mutate(&["config", "--worktree", key, &value], root)?;
+                self.mutate(&["config", "--local", "--unset", key], root)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn worktree_config(&self, path: &Path, key: &str) -> Result<Option<String>> {
+        config_value(&["--worktree", key], path)
+    }
+
+    fn set_worktree_config(&self, path: &Path, key: &str, value: Option<&str>) -> Result<()> {
+        match value {
+            Some(value) => self.mutate(&["config", "--worktree", key, value], path),
+            None => self.mutate(&["config", "--worktree", "--unset", key], path),
+        }
+    }
+
+    fn add_worktree_config(&self, path: &Path, key: &str, value: &str) -> Result<()> {
+        self.mutate(&["config", "--worktree", "--add", key, value], path)
+    }
+
+    fn own_config(&self, root: &Path) -> Result<Vec<(String, String)>> {
+        let mut entries = config_entries("--local", root)?;
+        // Without the extension `--worktree` reads the shared config again.
+        if config_value(&["--local", "--bool", "extensions.worktreeConfig"], root)?.as_deref()
+            == Some("true")
+        {
+            entries.extend(config_entries("--worktree", root)?);
+        }
+        Ok(entries)
+    }
+}
+
+pub(crate) fn parse_worktrees(output: &str) -> Vec<Worktree> {
+    parse_worktree_records(output.lines().map(|line| line.trim_end().as_bytes()))
+}
+
+/// Parse `git worktree list --porcelain -z`, whose fields end in NUL
+/// rather than a newline, so paths need not be valid UTF-8.
+pub(crate) fn parse_worktrees_z(output: &[u8]) -> Vec<Worktree> {
+    parse_worktree_records(output.split(|&byte| byte == 0))
+}
+
+fn parse_worktree_records<'a>(records: impl Iterator<Item = &'a [u8]>) -> Vec<Worktree> {
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).trim().to_string();
+    let mut worktrees = Vec::new();
+    let mut current: Option<Worktree> = None;
+    for record in records {
+        if let Some(path) = record.strip_prefix(b"worktree ") {
+            if let Some(wt) = current.take() {
+                worktrees.push(wt);
+            }
+            current = Some(Worktree {
+                path: PathBuf::from(OsStr::from_bytes(path)),
+                main: worktrees.is_empty(),
+                ..Worktree::default()
+            });
+            continue;
+        }
+        if let Some(wt) = current.as_mut() {
+            if let Some(head) = record.strip_prefix(b"HEAD ") {
+                wt.head = Some(text(head));
+            } else if let Some(branch) = record.strip_prefix(b"branch ") {
+                wt.branch = Some(text(branch));
+            } else if record == b"detached" {
+                wt.detached = true;
+            } else if record == b"locked" {
+                wt.locked = true;
+            } else if let Some(reason) = record.strip_prefix(b"locked ") {
+                wt.locked = true;
+                wt.lock_reason = Some(text(reason));
+            } else if let Some(prunable) = record.strip_prefix(b"prunable ") {
+                wt.prunable = Some(text(prunable));
+            }
+        }
+    }
+    if let Some(wt) = current.take() {
+        worktrees.push(wt);
+    }
+    worktrees
+}
+
+/// Count modified and untracked entries in `git status --porcelain` output.
+fn parse_status_counts(output: &str) -> ChangeSummary {
+    let mut summary = ChangeSummary::default();
+    for line in output.lines().filter(|l| !l.is_empty()) {
+        if line.starts_with("??") {
+            summary.untracked += 1;
+        } else {
+            summary.modified += 1;
+        }
+    }
+    summary
+}
+
+/// First `refs/terris/backup/<name>[-N]` that does not exist yet.
+fn free_backup_ref(cwd: &Path, name: &str) -> Result<String> {
+    for n in 1.. {
+        let candidate = if n == 1 {
+            format!("refs/terris/backup/{}", name)
+        } else {
+            format!("refs/terris/backup/{}-{}", name, n)
+        };
+        if run_git(["show-ref", "--verify", "--quiet", &candidate], cwd).is_err() {
+            return Ok(candidate);
+        }
+    }
+    unreachable!("exhausted backup ref names")
+}
+
+fn is_dirty(path: &Path) -> Result<bool> {
+    let output = run_git(["status", "--porcelain"], path)?;
+    Ok(!output.trim().is_empty())
+}
+
+pub(crate) fn run_git<I, S>(args: I, cwd: &Path) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    run_git_env(args, cwd, &[])
+}
+
+/// Like [`run_git`], with extra environment variables for the child.
+pub(crate) fn run_git_env<I, S>(args: I, cwd: &Path, env: &[(&str, &OsStr)]) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<OsString> = args.into_iter().map(|arg| arg.as_ref().into()).collect();
+    let output = run_retrying(&args, cwd, env, true)?;
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+/// Like [`run_git`], returning the output as it is rather than as text.
+pub(crate) fn run_git_bytes<I, S>(args: I, cwd: &Path) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<OsString> = args.into_iter().map(|arg| arg.as_ref().into()).collect();
+    run_retrying(&args, cwd, &[], true)
+}
+
+/// Run git with the terminal attached, so pagers and colors work.
+fn run_git_interactive(args: &[String], cwd: &Path) -> Result<()> {
+    let started = Instant::now();
+    let status = git_command()
+        .args(args)
+        .current_dir(cwd)
+        .status()
+        .with_context(|| run_context(args))?;
+    log_command("git", args, cwd, started, status.success());
+    if !status.success() {
+        return Err(Error::Git {
+            command: format!("git {}", args.join(" ")),
+            stderr: format!("exited with {}", status),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+pub(crate) fn run_git_silence_stdout<I, S>(args: I, cwd: &Path) -> Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<OsString> = args.into_iter().map(|arg| arg.as_ref().into()).collect();
+    run_retrying(&args, cwd, &[], false)?;
+    Ok(())
+}
+
+/// Run git, and while it fails only because another git process holds a
+/// lock, run it again after a growing pause, up to `lock-retries` times.
+/// Returns its stdout, if captured.
+fn run_retrying(
+    args: &[OsString],
+    cwd: &Path,
+    env: &[(&str, &OsStr)],
+    capture_stdout: bool,
+) -> Result<Vec<u8>> {
+    // For logs and messages only; git gets the arguments as they are.
+    let shown: Vec<String> = args
+        .iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let mut retries = LOCK_RETRIES.load(Ordering::Relaxed);
+    let mut pause = Duration::from_millis(100);
+    loop {
+        let started = Instant::now();
+        let mut command = git_command();
+        command
+            .args(args)
+            .envs(env.iter().copied())
+            .current_dir(cwd);
+        let output = output_within_timeout(command, &shown, capture_stdout)?;
+        log_command("git", &shown, cwd, started, output.status.success());
+        if output.status.success() {
+            return Ok(output.stdout);
+        }
+        if retries == 0 || !is_lock_contention(&output.stderr) {
+            return Err(git_failure("git", &shown, &output.stderr));
+        }
+        tracing::debug!(
+            "git {} hit a lock; retrying in {:?}",
+            shown.join(" "),
+            pause
+        );
+        std::thread::sleep(pause);
+        pause *= 2;
+        retries -= 1;
+    }
+}
+
+/// Whether git failed because a lock file another process holds exists,
+/// as opposed to anything retrying would not fix.
+fn is_lock_contention(stderr: &[u8]) -> bool {
+    let stderr = String::from_utf8_lossy(stderr);
+    stderr.contains(".lock': File exists")
+        || stderr.contains("Another git process seems to be running")
+}
+
+/// The `lock-retries` config key.
+static LOCK_RETRIES: AtomicU32 = AtomicU32::new(3);
+
+/// Retry git commands that fail on a held lock this many times.
+pub fn set_lock_retries(retries: u32) {
+    LOCK_RETRIES.store(retries, Ordering::Relaxed);
+}
+
+/// `git config [scope...] --get <key>`, or `None` when the key is not set,
+/// which git reports with exit code 1 and nothing on stderr.
+fn config_value(scope_and_key: &[&str], cwd: &Path) -> Result<Option<String>> {
+    let (key, scope) = scope_and_key.split_last().expect("a key is given");
+    let mut args = vec!["config"];
+    args.extend(scope);
+    args.extend(["--get", key]);
+    match run_git(&args, cwd) {
+        Ok(value) => Ok(Some(value.trim_end_matches('\n').to_string())),
+        Err(err) => match err.downcast_ref::<Error>() {
+            Some(Error::Git { stderr, .. }) if stderr.is_empty() => Ok(None),
+            _ => Err(err),
+        },
+    }
+}
+
+/// Every entry of one config scope, from `git config <scope> -z --list`:
+/// `key\nvalue` records ended by NUL, or a bare key for an implicit `true`.
+fn config_entries(scope: &str, cwd: &Path) -> Result<Vec<(String, String)>> {
+    let output = run_git(["config", scope, "-z", "--list"], cwd)?;
+    Ok(output
+        .split_terminator('\0')
+        .map(|record| match record.split_once('\n') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (record.to_string(), "true".to_string()),
+        })
+        .collect())
+}
+
+/// After `git worktree add` was killed for stalling, drop whatever it had
+/// checked out and registered, so no half-made worktree is left behind.
+fn undo_stalled_add(err: &anyhow::Error, root: &Path, path: &Path) {
+    if !matches!(err.downcast_ref::<Error>(), Some(Error::GitTimeout { .. })) {
+        return;
+    }
+    if path.exists()
+        && let Err(err) = std::fs::remove_dir_all(path)
+    {
+        tracing::warn!("could not remove '{}': {}", path.display(), err);
+    }
+    if let Err(err) = run_git(["worktree", "prune"], root) {
+        tracing::warn!("could not prune worktrees: {:#}", err);
+    }
+}
+
+/// Run a git command to completion with its stderr (and, if `capture_stdout`,
+/// its stdout) captured. Past the `git-timeout`, the command is killed and
+/// the error says which one stalled.
+fn output_within_timeout(
+    mut command: Command,
+    args: &[String],
+    capture_stdout: bool,
+) -> Result<Output> {
+    command
+        .stdin(Stdio::null())
+        .stdout(if capture_stdout {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stderr(Stdio::piped());
+    let describe = || format!("git {}", args.join(" "));
+    let Some(timeout) = timeout() else {
+        return command.output().with_context(|| run_context(args));
+    };
+    let mut child = command.spawn().with_context(|| run_context(args))?;
+    // Read both pipes as the command runs so it never blocks on a full one.
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            child.wait()?;
+            // Anything the command started, like a credential helper, may
+            // still hold the pipes open; their readers are left to finish.
+            return Err(Error::GitTimeout {
+                command: describe(),
+                seconds: timeout.as_secs(),
+            }
+            .into());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let collect = |reader: Option<JoinHandle<Vec<u8>>>| {
+        reader.map_or_else(Vec::new, |reader| reader.join().unwrap_or_default())
+    };
+    Ok(Output {
+        status,
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+    })
+}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        bytes
+    })
+}
+
+/// A git release, compared by its numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct GitVersion(u32, u32, u32);
+
+impl GitVersion {
+    /// `git worktree list --porcelain`, which everything in terris reads.
+    const WORKTREE_LIST: Self = Self(2, 7, 0);
+    const STASH_PUSH: Self = Self(2, 13, 0);
+    const WORKTREE_REMOVE: Self = Self(2, 17, 0);
+    const SWITCH: Self = Self(2, 23, 0);
+    const WORKTREE_REPAIR: Self = Self(2, 30, 0);
+    const WORKTREE_LIST_Z: Self = Self(2, 36, 0);
+
+    /// Read `git --version` output such as `git version 2.39.3 (Apple
+    /// Git-145)` or `git version 2.45.1.windows.1`.
+    fn parse(text: &str) -> Option<Self> {
+        let number = text.trim().strip_prefix("git version ")?;
+        let mut parts = number
+            .split(|c: char| !c.is_ascii_digit())
+            .map(|part| part.parse::<u32>().ok());
+        Some(Self(
+            parts.next()??,
+            parts.next().flatten().unwrap_or(0),
+            parts.next().flatten().unwrap_or(0),
+        ))
+    }
+}
+
+impl std::fmt::Display for GitVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// The version of the git executable, asked once per run. `None` when it
+/// cannot be told, in which case every feature is assumed to be there.
+fn version() -> Option<GitVersion> {
+    static VERSION: OnceLock<Option<GitVersion>> = OnceLock::new();
+    *VERSION.get_or_init(|| {
+        let output = git_command().arg("--version").output().ok()?;
+        let version = GitVersion::parse(&String::from_utf8_lossy(&output.stdout));
+        if version.is_none() {
+            tracing::warn!("could not read the git version; assuming a recent one");
+        }
+        version
+    })
+}
+
+/// Fail with a clear message when git is older than `required` for `feature`.
+fn require(required: GitVersion, feature: &str) -> Result<()> {
+    match version() {
+        Some(found) if found < required => Err(Error::GitTooOld {
+            feature: feature.to_string(),
+            required: required.to_string(),
+            found: found.to_string(),
+        }
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Remove a worktree the way `git worktree remove` would, for gits that
+/// lack it: refuse changes unless forced, delete the directory, and prune.
+fn remove_by_hand(root: &Path, path: &Path, force: bool, dry_run: bool) -> Result<()> {
+    if !force && path.is_dir() && is_dirty(path)? {
+        return Err(Error::DirtyWorktree(path.to_path_buf()).into());
+    }
+    if dry_run {
+        println!("Would remove directory {}", path.display());
+        print_would_run("git", &["worktree", "prune"], root);
+        return Ok(());
+    }
+    if path.exists() {
+        std::fs::remove_dir_all(path)
+            .with_context(|| format!("remove worktree directory '{}'", path.display()))?;
+    }
+    run_git_silence_stdout(["worktree", "prune"], root)
+}
+
+/// The git executable, fixed by [`set_program`] before the first command.
+static PROGRAM: OnceLock<OsString> = OnceLock::new();
+
+/// Run git as `TERRIS_GIT` if set, else as `configured` (the `git` config
+/// key), else as `git` from PATH. Either may be a path or a name to look up.
+pub fn set_program(configured: Option<&Path>) {
+    let program = program_from_env()
+        .or_else(|| configured.map(|path| path.as_os_str().to_owned()))
+        .unwrap_or_else(|| OsString::from("git"));
+    if PROGRAM.set(program).is_err() {
+        tracing::warn!("git executable chosen after the first git command");
+    }
+}
+
+/// A command for the chosen git executable.
+pub(crate) fn git_command() -> Command {
+    Command::new(
+        PROGRAM.get_or_init(|| program_from_env().unwrap_or_else(|| OsString::from("git"))),
+    )
+}
+
+/// What failed when git could not be started, naming the executable when
+/// it is not the usual one.
+fn run_context(args: &[String]) -> String {
+    let program = git_command().get_program().to_string_lossy().into_owned();
+    if program == "git" {
+        format!("run git {}", args.join(" "))
+    } else {
+        format!("run git {} (as {})", args.join(" "), program)
+    }
+}
+
+fn program_from_env() -> Option<OsString> {
+    std::env::var_os("TERRIS_GIT").filter(|program| !program.is_empty())
+}
+
+/// The `git-timeout` config key in seconds, or 0 for none.
+static TIMEOUT: AtomicU64 = AtomicU64::new(0);
+
+/// Kill git commands that run longer than `timeout`.
+pub fn set_timeout(timeout: Option<Duration>) {
+    TIMEOUT.store(
+        timeout.map_or(0, |timeout| timeout.as_secs()),
+        Ordering::Relaxed,
+    );
+}
+
+fn timeout() -> Option<Duration> {
+    match TIMEOUT.load(Ordering::Relaxed) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Build the typed error for a failed command from its captured stderr.
+pub(crate) fn git_failure(program: &str, args: &[String], stderr: &[u8]) -> anyhow::Error {
+    Error::Git {
+        command: format!("{} {}", program, args.join(" ")),
+        stderr: String::from_utf8_lossy(stderr).trim().to_string(),
+    }
+    .into()
+}
+
+impl GitCli {
+    /// Run a git command that changes something, or print it on a dry run.
+    fn mutate(&self, args: &[&str], cwd: &Path) -> Result<()> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        if self.dry_run {
+            print_would_run("git", &args, cwd);
+            return Ok(());
+        }
+        run_git_silence_stdout(&args, cwd)
+    }
+
+    /// Commits on HEAD that exist nowhere else: on no remote-tracking
+    /// branch, or, in a repository without any, not on the branch's
+    /// upstream or else the primary worktree's branch. Zero when there is
+    /// no commit yet or nothing to compare with.
+    fn unpushed(&self, path: &Path) -> Result<usize> {
+        if run_git(["rev-parse", "--verify", "--quiet", "HEAD"], path).is_err() {
+            return Ok(0);
+        }
+        let remotes = run_git(["for-each-ref", "--count=1", "refs/remotes"], path)?;
+        let against = if !remotes.trim().is_empty() {
+            "--remotes".to_string()
+        } else if let Ok(upstream) =
+            run_git(["rev-parse", "--verify", "--quiet", "@{upstream}"], path)
+        {
+            upstream.trim().to_string()
+        } else {
+            let primary = self
+                .list_worktrees(path)?
+                .into_iter()
+                .find(|wt| wt.main)
+                .and_then(|wt| wt.branch);
+            match primary.and_then(|branch| {
+                run_git(["rev-parse", "--verify", "--quiet", &branch], path).ok()
+            }) {
+                Some(commit) => commit.trim().to_string(),
+                None => return Ok(0),
+            }
+        };
+        let count = run_git(["rev-list", "--count", "HEAD", "--not", &against], path)?;
+        Ok(count.trim().parse().unwrap_or(0))
+    }
+}
+
+/// Write everything in the worktree at `path`, including uncommitted and
+/// untracked (non-ignored) files, as a tree object and return its id. A
+/// scratch index named `index_name` keeps the worktree's own index and files
+/// untouched.
+fn snapshot_tree(path: &Path, index_name: &str) -> Result<String> {
+    let index = run_git(["rev-parse", "--git-path", index_name], path)?;
+    let index = path.join(index.trim());
+    let env = [("GIT_INDEX_FILE", index.as_os_str())];
+    let result = (|| {
+        if run_git(["rev-parse", "--verify", "--quiet", "HEAD"], path).is_ok() {
+            run_git_env(["read-tree", "HEAD"], path, &env)?;
+        }
+        run_git_env(["add", "-A"], path, &env)?;
+        run_git_env(["write-tree"], path, &env)
+    })();
+    let _ = std::fs::remove_file(&index);
+    Ok(result?.trim().to_string())
+}
+
+/// The commit at the top of the stash, if there is one.
+fn stash_top(path: &Path) -> Option<String> {
+    run_git(["rev-parse", "--quiet", "--verify", "refs/stash"], path)
+        .ok()
+        .map(|commit| commit.trim().to_string())
+}
+
+/// Turn git's refusal to check out `branch` a second time into
+/// [`Error::WorktreeExists`] naming the worktree that holds it. This also
+/// covers worktrees that list as detached because they are mid-rebase or
+/// mid-bisect on the branch.
+fn checked_out_elsewhere(err: anyhow::Error, branch: &str) -> anyhow::Error {
+    let Some(Error::Git { stderr, .. }) = err.downcast_ref::<Error>() else {
+        return err;
+    };
+    match holder_path(stderr) {
+        Some(path) => Error::WorktreeExists {
+            branch: branch.to_string(),
+            path,
+        }
+        .into(),
+        None => err,
+    }
+}
+
+/// The path in `'x' is already checked out at '<path>'` (git < 2.42) or
+/// `'x' is already used by worktree at '<path>'`.
+fn holder_path(stderr: &str) -> Option<PathBuf> {
+    let (_, rest) = stderr
+        .split_once("is already checked out at '")
+        .or_else(|| stderr.split_once("is already used by worktree at '"))?;
+    let (path, _) = rest.split_once('\'')?;
+    Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nul_separated_worktrees_keep_paths_byte_for_byte() {
+        let input = b"worktree /repo\0HEAD 111111\0branch refs/heads/main\0\0worktree /wt/caf\xe9 \0HEAD 222222\0detached\0locked\0\0";
+        let worktrees = parse_worktrees_z(input);
+        assert_eq!(worktrees.len(), 2);
+        assert!(worktrees[0].main);
+        assert_eq!(worktrees[0].branch.as_deref(), Some("refs/heads/main"));
+        assert_eq!(worktrees[1].path.as_os_str().as_bytes(), b"/wt/caf\xe9 ");
+        assert!(worktrees[1].detached && worktrees[1].locked);
+    }
+
+    #[test]
+    fn only_held_locks_count_as_contention() {
+        assert!(is_lock_contention(
+            b"fatal: Unable to create '/r/.git/index.lock': File exists.\n\nAnother git process seems to be running in this repository"
+        ));
+        assert!(is_lock_contention(
+            b"error: cannot lock ref 'refs/heads/x': Unable to create '/r/.git/refs/heads/x.lock': File exists."
+        ));
+        assert!(!is_lock_contention(
+            b"fatal: a branch named 'x' already exists"
+        ));
+    }
+
+    #[test]
+    fn git_versions_are_read_from_every_vendor_format() {
+        assert_eq!(
+            GitVersion::parse("git version 2.39.3 (Apple Git-145)\n"),
+            Some(GitVersion(2, 39, 3))
+        );
+        assert_eq!(
+            GitVersion::parse("git version 2.45.1.windows.1"),
+            Some(GitVersion(2, 45, 1))
+        );
+        assert_eq!(
+            GitVersion::parse("git version 2.7"),
+            Some(GitVersion(2, 7, 0))
+        );
+        assert_eq!(GitVersion::parse("hub version 2.14.2"), None);
+        assert!(GitVersion(2, 9, 5) < GitVersion::WORKTREE_REMOVE);
+    }
+
+    #[test]
+    fn holder_path_reads_both_git_wordings() {
+        assert_eq!(
+            holder_path("fatal: 'topic' is already checked out at '/src/api'"),
+            Some(PathBuf::from("/src/api"))
+        );
+        assert_eq!(
+            holder_path("fatal: 'topic' is already used by worktree at '/wt/topic'"),
+            Some(PathBuf::from("/wt/topic"))
+        );
+        assert_eq!(holder_path("fatal: invalid reference: topic"), None);
+    }
+
+    #[test]
+    fn parse_worktrees_parses_porcelain() {
+        let input = "\
+worktree /repo
+HEAD 111111
+branch refs/heads/main
+
+worktree /repo/feature
+HEAD 222222
+detached
+locked
+prunable stale
+
+worktree /repo/held
+HEAD 333333
+branch refs/heads/held
+locked on a USB drive
+";
+        let worktrees = parse_worktrees(input);
+        assert_eq!(worktrees.len(), 3);
+        assert_eq!(worktrees[0].path, PathBuf::from("/repo"));
+        assert!(worktrees[0].main);
+        assert!(!worktrees[1].main);
+        assert_eq!(worktrees[0].head.as_deref(), Some("111111"));
+        assert_eq!(worktrees[0].branch.as_deref(), Some("refs/heads/main"));
+        assert!(!worktrees[0].detached);
+        assert!(!worktrees[0].locked);
+        assert!(worktrees[0].prunable.is_none());
+
+        assert_eq!(worktrees[1].path, PathBuf::from("/repo/feature"));
+        assert_eq!(worktrees[1].head.as_deref(), Some("222222"));
+        assert!(worktrees[1].branch.is_none());
+        assert!(worktrees[1].detached);
+        assert!(worktrees[1].locked);
+        assert_eq!(worktrees[1].prunable.as_deref(), Some("stale"));
+        assert!(worktrees[1].lock_reason.is_none());
+        assert!(worktrees[2].locked);
+        assert_eq!(worktrees[2].lock_reason.as_deref(), Some("on a USB drive"));
+    }
+
+    #[test]
+    fn parse_status_counts_splits_untracked() {
+        let summary = parse_status_counts(" M src/main.rs\nA  new.rs\n?? scratch.txt\n");
+        assert_eq!(summary.modified, 2);
+        assert_eq!(summary.untracked, 1);
+    }
+}
@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use anyhow::{Context, Result, bail};
+
+use super::git::git_failure;
+use super::{
+    Backend, ChangeSummary, Commit, Divergence, Integration, Tracking, log_command, print_would_run,
+};
+use crate::Worktree;
+use crate::error::Error;
+
+/// Backend for Jujutsu repositories, where workspaces play the role of
+/// worktrees. Workspaces created by terris are named after the bookmark they
+/// start from, so branch matching works the same as with git.
+#[derive(Default)]
+pub struct Jj {
+    /// Print mutating commands instead of running them.
+    pub dry_run: bool,
+}
+
+impl Backend for Jj {
+    fn root(&self, cwd: &Path) -> Result<PathBuf> {
+        let output = run_jj(["root"], cwd).context(Error::NotARepo { vcs: "jj" })?;
+        Ok(PathBuf::from(output.trim()))
+    }
+
+    fn common_dir(&self, root: &Path) -> Result<PathBuf> {
+        // The git repository backing the store: `.git` when colocated.
+        let output = run_jj(["git", "root"], root)?;
+        Ok(PathBuf::from(output.trim()))
+    }
+
+    fn list_worktrees(&self, root: &Path) -> Result<Vec<Worktree>> {
+        let output = run_jj(["workspace", "list"], root)?;
+        let mut worktrees = Vec::new();
+        for (name, head) in parse_workspaces(&output) {
+            let path = run_jj(["workspace", "root", "--name", &name], root)
+                .with_context(|| format!("resolve root of workspace '{}'", name))?;
+            worktrees.push(Worktree {
+                path: PathBuf::from(path.trim()),
+                head: Some(head),
+                main: name == "default",
+                branch: Some(name),
+                ..Worktree::default()
+            });
+        }
+        Ok(worktrees)
+    }
+
+    fn branch_exists(&self, root: &Path, branch: &str) -> Result<bool> {
+        let output = run_jj(
+            ["bookmark", "list", "-T", r#"if(!remote, name ++ "\n")"#],
+            root,
+        )
+        .context("check bookmark existence")?;
+        Ok(output.lines().any(|line| line.trim() == branch))
+    }
+
+    fn add_worktree(&self, root: &Path, path: &Path, branch: &str) -> Result<()> {
+        let path = path.to_string_lossy();
+        let args = ["workspace", "add", "--name", branch, "-r", branch, &path];
+        if self.dry_run {
+            print_would_run("jj", &args.map(String::from), root);
+            return Ok(());
+        }
+        run_jj_quiet(args, root)
+    }
+
+    fn add_detached(&self, root: &Path, path: &Path, rev: &str) -> Result<()> {
+        // Workspaces never check out a bookmark, so this is `add_worktree`
+        // with the workspace named after its directory.
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .with_context(|| format!("no directory name in '{}'", path.display()))?;
+        let path = path.to_string_lossy();
+        let args = ["workspace", "add", "--name", &name, "-r", rev, &path];
+        if self.dry_run {
+            print_would_run("jj", &args.map(String::from), root);
+            return Ok(());
+        }
+        run_jj_quiet(args, root)
+    }
+
+    fn remove_worktree(&self, root: &Path, path: &Path, _force: bool) -> Result<()> {
+        // jj snapshots the working copy into a commit, so forgetting a
+        // workspace never loses changes and needs no force flag.
+        let worktrees = self.list_worktrees(root)?;
+        let name = worktrees
+            .iter()
+            .find(|wt| wt.path == path)
+            .and_then(|wt| wt.branch.clone())
+            .with_context(|| format!("no jj workspace at '{}'", path.display()))?;
+        let args = ["workspace", "forget", &name];
+        if self.dry_run {
+            print_would_run("jj", &args.map(String::from), root);
+            println!("Would remove directory {}", path.display());
+            return Ok(());
+        }
+        run_jj_quiet(args, root)?;
+        std::fs::remove_dir_all(path)
+            .with_context(|| format!("remove workspace directory '{}'", path.display()))
+    }
+
+    fn is_dirty(&self, path: &Path) -> Result<bool> {
+        is_dirty(path)
+    }
+
+    fn dirty_all(&self, paths: &[&Path]) -> Vec<Result<bool>> {
+        crate::jobs::map(paths, |path| is_dirty(path))
+    }
+
+    fn change_summary(&self, path: &Path) -> Result<ChangeSummary> {
+        let diff = run_jj(["diff", "--summary"], path)?;
+        Ok(ChangeSummary {
+            modified: diff.lines().filter(|l| !l.is_empty()).count(),
+            ..ChangeSummary::default()
+        })
+    }
+
+    fn list_files(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        // jj tracks every non-ignored file automatically.
+        let output = run_jj(["file", "list"], path)?;
+        Ok(output
+            .lines()
+            .filter(|f| !f.is_empty())
+            .map(PathBuf::from)
+            .collect())
+    }
+
+    fn backup_changes(&self, _path: &Path, _name: &str) -> Result<Option<String>> {
+        // The working-copy commit already holds every change.
+        Ok(None)
+    }
+
+    fn last_commit_time(&self, path: &Path) -> Result<Option<i64>> {
+        let output = run_jj(
+            [
+                "log",
+                "-r",
+                "@",
+                "--no-graph",
+                "-T",
+                r#"committer.timestamp().format("%s")"#,
+            ],
+            path,
+        )?;
+        Ok(output.trim().parse().ok())
+    }
+
+    fn is_merged(&self, root: &Path, branch: &str, base: &str) -> Result<bool> {
+        let revset = format!(r#"bookmarks(exact:"{}") ~ ::{}"#, branch, base);
+        let output = run_jj(
+            ["log", "--no-graph", "-r", &revset, "-T", "commit_id"],
+            root,
+        )?;
+        Ok(output.trim().is_empty())
+    }
+
+    fn unique_commits(&self, _root: &Path, _branch: &str) -> Result<usize> {
+        // Deleting a bookmark leaves its commits visible; nothing is lost.
+        Ok(0)
+    }
+
+    fn search_commits(
+        &self,
+        root: &Path,
+        branch: &str,
+        base: &str,
+        pattern: &str,
+        ignore_case: bool,
+    ) -> Result<Vec<Commit>> {
+        let kind = if ignore_case { "regex-i" } else { "regex" };
+        let pattern = pattern.replace('\\', "\\\\").replace('"', "\\\"");
+        let revset = format!(
+            r#"(::bookmarks(exact:"{}") ~ ::{}) & description({}:"{}")"#,
+            branch, base, kind, pattern
+        );
+        let template = r#"commit_id ++ "\0" ++ author.name() ++ "\0" ++ committer.timestamp().format("%s") ++ "\0" ++ description.first_line() ++ "\n""#;
+        let output = run_jj(["log", "--no-graph", "-r", &revset, "-T", template], root)?;
+        Ok(output.lines().filter_map(Commit::parse).collect())
+    }
+
+    fn head_commit(&self, path: &Path) -> Result<Option<Commit>> {
+        let template = r#"commit_id ++ "\0" ++ author.name() ++ "\0" ++ committer.timestamp().format("%s") ++ "\0" ++ description.first_line()"#;
+        let output = run_jj(["log", "-r", "@", "--no-graph", "-T", template], path)?;
+        Ok(Commit::parse(&output))
+    }
+
+    fn upstream_gone(&self, _root: &Path, _branch: &str) -> Result<bool> {
+        // jj drops local bookmarks whose remote was deleted on fetch, so there
+        // is no "gone" state to report.
+        Ok(false)
+    }
+
+    fn tracking(&self, _root: &Path) -> Result<HashMap<String, Tracking>> {
+        // Bookmarks have no upstream setting, and those whose remote was
+        // deleted are dropped on fetch.
+        Ok(HashMap::new())
+    }
+
+    fn delete_branch(&self, root: &Path, branch: &str, _force: bool) -> Result<()> {
+        let args = ["bookmark", "delete", branch];
+        if self.dry_run {
+            print_would_run("jj", &args.map(String::from), root);
+            return Ok(());
+        }
+        run_jj_quiet(args, root)
+    }
+
+    fn create_branch(&self, root: &Path, branch: &str, start: &str) -> Result<()> {
+        let args = ["bookmark", "create", branch, "-r", start];
+        if self.dry_run {
+            print_would_run("jj", &args.map(String::from), root);
+            return Ok(());
+        }
+        run_jj_quiet(args, root)
+    }
+
+    fn exclude_locally(&self, _root: &Path, _pattern: &str) -> Result<()> {
+        // jj has no clone-local ignore file; only .gitignore applies.
+        Ok(())
+    }
+
+    fn enable_worktree_config(&self, _root: &Path) -> Result<bool> {
+        bail!("jj workspaces have no config of their own")
+    }
+
+    fn worktree_config(&self, _path: &Path, _key: &str) -> Result<Option<String>> {
+        bail!("jj workspaces have no config of their own")
+    }
+
+    fn set_worktree_config(&self, _path: &Path, _key: &str, _value: Option<&str>) -> Result<()> {
+        bail!("jj workspaces have no config of their own")
+    }
+
+    fn add_worktree_config(&self, _path: &Path, _key: &str, _value: &str) -> Result<()> {
+        bail!("jj workspaces have no config of their own")
+    }
+
+    fn own_config(&self, _root: &Path) -> Result<Vec<(String, String)>> {
+        bail!("jj keeps no git config to propagate")
+    }
+
+    fn reset_to(&self, path: &Path, rev: Option<&str>) -> Result<()> {
+        // Either way the discarded changes stay reachable in the op log.
+        let args: Vec<&str> = match rev {
+            Some(rev) => vec!["new", rev],
+            None => vec!["restore"],
+        };
+        if self.dry_run {
+            print_would_run(
+                "jj",
+                &args.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+                path,
+            );
+            return Ok(());
+        }
+        run_jj_quiet(args, path)
+    }
+
+    fn stash_push(&self, _path: &Path, _untracked: bool) -> Result<Option<String>> {
+        bail!("jj has no stash; move the working-copy change with `jj rebase -r @ -d <bookmark>`")
+    }
+
+    fn stash_apply(&self, _path: &Path, _stash: &str) -> Result<()> {
+        bail!("jj has no stash")
+    }
+
+    fn stash_drop(&self, _root: &Path, _stash: &str) -> Result<()> {
+        bail!("jj has no stash")
+    }
+
+    fn apply_patch(&self, _path: &Path, _patch: &[u8]) -> Result<()> {
+        bail!("jj cannot apply patches; use `git apply` in a colocated repository")
+    }
+
+    fn diff_worktrees(&self, from: &Path, to: &Path, paths: &[String]) -> Result<()> {
+        // Running jj inside each workspace snapshots its working copy first.
+        let working_copy = |path: &Path| {
+            run_jj(["log", "--no-graph", "-r", "@", "-T", "commit_id"], path)
+                .map(|id| id.trim().to_string())
+        };
+        let (from_id, to_id) = (working_copy(from)?, working_copy(to)?);
+        let mut args: Vec<String> = vec![
+            "diff".into(),
+            "--from".into(),
+            from_id,
+            "--to".into(),
+            to_id,
+        ];
+        args.extend(paths.iter().cloned());
+        let status = Command::new("jj")
+            .args(&args)
+            .current_dir(from)
+            .status()
+            .context("run jj diff")?;
+        if !status.success() {
+            bail!("jj diff exited with {}", status);
+        }
+        Ok(())
+    }
+
+    fn integrate(
+        &self,
+        _source: &Path,
+        _branch: &str,
+        _base: &str,
+        _base_worktree: Option<&Path>,
+        _how: Integration,
+    ) -> Result<()> {
+        bail!("graduating is not supported for jj; use `jj rebase` and `jj bookmark set`")
+    }
+
+    fn rebase(&self, path: &Path, onto: &str, _upstream: Option<&str>) -> Result<bool> {
+        // jj records conflicts in the rebased commits instead of stopping, and
+        // `-b` already leaves out commits that `onto` has.
+        let args = ["rebase", "-b", "@", "-d", onto];
+        if self.dry_run {
+            print_would_run("jj", &args.map(String::from), path);
+            return Ok(true);
+        }
+        run_jj_quiet(args, path)?;
+        Ok(true)
+    }
+
+    fn fetch(&self, root: &Path) -> Result<()> {
+        let args = ["git", "fetch", "--all-remotes"];
+        if self.dry_run {
+            print_would_run("jj", &args.map(String::from), root);
+            return Ok(());
+        }
+        run_jj_quiet(args, root)
+    }
+
+    fn upstream(&self, _root: &Path, _branch: &str) -> Result<Option<String>> {
+        // Bookmarks track remotes by name rather than through an upstream
+        // setting.
+        Ok(None)
+    }
+
+    fn remote_url(&self, root: &Path, remote: &str) -> Result<Option<String>> {
+        // One `<name> <url>` line per remote.
+        let output = run_jj(["git", "remote", "list"], root)?;
+        Ok(output.lines().find_map(|line| {
+            let (name, url) = line.split_once(' ')?;
+            (name == remote).then(|| url.trim().to_string())
+        }))
+    }
+
+    fn upstream_divergence(&self, _root: &Path, _branch: &str) -> Result<Option<Divergence>> {
+        // `jj git fetch` already moves tracked bookmarks, so nothing is left
+        // to fast-forward.
+        Ok(None)
+    }
+
+    fn prune(&self, _root: &Path) -> Result<()> {
+        // A workspace whose directory is gone only needs `jj workspace
+        // forget`, which would also drop its working-copy commit.
+        Ok(())
+    }
+
+    fn repair_worktrees(&self, _root: &Path, _moved: &[PathBuf]) -> Result<()> {
+        bail!("jj workspaces cannot be repaired by terris; run `jj workspace update-stale` in each")
+    }
+
+    fn fast_forward(&self, _path: &Path) -> Result<()> {
+        bail!("fast-forwarding is not supported with jj; `jj git fetch` moves tracked bookmarks")
+    }
+
+    fn push_branch(&self, root: &Path, branch: &str) -> Result<()> {
+        let args = ["git", "push", "--bookmark", branch];
+        if self.dry_run {
+            print_would_run("jj", &args.map(String::from), root);
+            return Ok(());
+        }
+        run_jj_quiet(args, root)
+    }
+
+    fn resolve_rev(&self, root: &Path, rev: &str) -> Result<String> {
+        let output = run_jj(["log", "--no-graph", "-r", rev, "-T", "commit_id"], root)
+            .with_context(|| format!("'{}' is not a single revision", rev))?;
+        Ok(output.trim().to_string())
+    }
+
+    fn switch_branch(&self, path: &Path, branch: &str) -> Result<()> {
+        // A workspace has no checked-out bookmark; start new work on top of it.
+        let args = ["new", branch];
+        if self.dry_run {
+            print_would_run("jj", &args.map(String::from), path);
+            return Ok(());
+        }
+        run_jj_quiet(args, path)
+    }
+}
+
+/// Parse `jj workspace list` lines of the form `<name>: <change> <commit> <description>`.
+fn parse_workspaces(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, rest) = line.split_once(": ")?;
+            let mut fields = rest.split_whitespace();
+            let _change = fields.next()?;
+            let commit = fields.next()?;
+            Some((name.trim().to_string(), commit.to_string()))
+        })
+        .collect()
+}
+
+fn is_dirty(path: &Path) -> Result<bool> {
+    let output = run_jj(
+        [
+            "log",
+            "-r",
+            "@",
+            "--no-graph",
+            "-T",
+            r#"if(empty, "", "dirty")"#,
+        ],
+        path,
+    )?;
+    Ok(!output.trim().is_empty())
+}
+
+fn run_jj<I, S>(args: I, cwd: &Path) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args_vec: Vec<String> = args
+        .into_iter()
+        .map(|arg| arg.as_ref().to_string_lossy().to_string())
+        .collect();
+    let started = Instant::now();
+    let output = Command::new("jj")
+        .args(&args_vec)
+        .arg("--color=never")
+        .current_dir(cwd)
+        .output()
+        .with_context(|| format!("run jj {}", args_vec.join(" ")))?;
+    log_command("jj", &args_vec, cwd, started, output.status.success());
+    if !output.status.success() {
+        return Err(git_failure("jj", &args_vec, &output.stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn run_jj_quiet<I, S>(args: I, cwd: &Path) -> Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args_vec: Vec<String> = args
+        .into_iter()
+        .map(|arg| arg.as_ref().to_string_lossy().to_string())
+        .collect();
+    let started = Instant::now();
+    let output = Command::new("jj")
+        .args(&args_vec)
+        .arg("--quiet")
+        .current_dir(cwd)
+        .stdout(Stdio::null())
+        .output()
+        .with_context(|| format!("run jj {}", args_vec.join(" ")))?;
+    log_command("jj", &args_vec, cwd, started, output.status.success());
+    if !output.status.success() {
+        return Err(git_failure("jj", &args_vec, &output.stderr));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_workspaces_reads_name_and_commit() {
+        let input = "\
+default: qpvuntsm 230dd059 (empty) (no description set)
+feature: rlvkpnrz 9a45c67d add feature
+";
+        let workspaces = parse_workspaces(input);
+        assert_eq!(
+            workspaces,
+            vec![
+                ("default".to_string(), "230dd059".to_string()),
+                ("feature".to_string(), "9a45c67d".to_string()),
+            ]
+        );
+    }
+}
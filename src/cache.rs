@@ -0,0 +1,103 @@
+//! Worktree status computed ahead of time by `terris daemon` (and briefly
+//! remembered by `terris prompt`), so `terris` and `terris du` can answer
+//! without walking every worktree. Stored as JSON next to the metadata
+//! store.
+//!
+//! Entries expire, and one whose worktree has moved to another commit is
+//! ignored, so a stopped daemon leaves nothing misleading behind for long.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::ChangeSummary;
+use crate::du::Usage;
+use crate::forge::CiEntry;
+use crate::{Worktree, registry_base_dir, unix_now};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cache {
+    /// Keyed by worktree path.
+    #[serde(default)]
+    pub worktrees: BTreeMap<PathBuf, Status>,
+    /// Body of the last desktop notification `terris daemon` sent, keyed by
+    /// repository root, so an unchanged situation is announced only once.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub notified: BTreeMap<PathBuf, String>,
+    /// CI status of branches, keyed by repository root and then branch.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ci: BTreeMap<PathBuf, BTreeMap<String, CiEntry>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Status {
+    /// Unix time after which the entry is no longer used.
+    pub expires: i64,
+    /// The worktree's commit when the entry was written.
+    pub head: Option<String>,
+    pub summary: ChangeSummary,
+    /// Missing from entries written by `terris prompt`, which does not
+    /// measure disk usage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+impl Cache {
+    fn file() -> Result<PathBuf> {
+        Ok(registry_base_dir()?.join("cache.json"))
+    }
+
+    /// Read the cache, treating a missing file as empty.
+    pub fn load() -> Result<Self> {
+        let file = Self::file()?;
+        let data = match std::fs::read_to_string(&file) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("read '{}'", file.display()));
+            }
+        };
+        serde_json::from_str(&data).with_context(|| format!("parse '{}'", file.display()))
+    }
+
+    /// Write the cache atomically so concurrent readers never see a partial
+    /// file. It is saved without the registry lock, so each save goes
+    /// through a temporary file of its own.
+    pub fn save(&self) -> Result<()> {
+        let file = Self::file()?;
+        let dir = file.parent().expect("the cache is in the registry");
+        std::fs::create_dir_all(dir).with_context(|| format!("create '{}'", dir.display()))?;
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)
+            .with_context(|| format!("create a temporary file in '{}'", dir.display()))?;
+        tmp.write_all(serde_json::to_string_pretty(self)?.as_bytes())
+            .with_context(|| format!("write '{}'", tmp.path().display()))?;
+        tmp.persist(&file)
+            .with_context(|| format!("write '{}'", file.display()))?;
+        Ok(())
+    }
+
+    /// The entry for `wt`, if it is still current.
+    pub fn get(&self, wt: &Worktree) -> Option<&Status> {
+        self.worktrees
+            .get(&wt.path)
+            .filter(|status| status.expires > unix_now() && status.head == wt.head)
+    }
+
+    /// Drop expired entries and those for directories that no longer exist.
+    pub fn retain_live(&mut self) {
+        let now = unix_now();
+        self.worktrees
+            .retain(|path, status| status.expires > now && path.is_dir());
+    }
+}
+
+/// The cache, or an empty one if it cannot be read; it only ever saves time.
+pub fn load() -> Cache {
+    Cache::load().unwrap_or_else(|err| {
+        tracing::warn!("could not read status cache: {:#}", err);
+        Cache::default()
+    })
+}
@@ -0,0 +1,49 @@
+//! Sharing one cargo target directory between a repository's worktrees, so
+//! each new worktree does not start a full build from scratch.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::backend::Backend;
+
+const CONFIG: &str = ".cargo/config.toml";
+
+/// Point cargo in the new worktree at `path` to the primary worktree's
+/// `target/` by writing `.cargo/config.toml`. Does nothing outside Rust
+/// projects, and leaves an existing (usually tracked) config alone.
+pub fn share_target(backend: &dyn Backend, root: &Path, path: &Path, dry_run: bool) -> Result<()> {
+    if !path.join("Cargo.toml").exists() {
+        return Ok(());
+    }
+    let Some(primary) = backend.list_worktrees(root)?.into_iter().find(|wt| wt.main) else {
+        return Ok(());
+    };
+    let target_dir = primary.path.join("target");
+    let file = path.join(CONFIG);
+    if file.exists() {
+        eprintln!(
+            "Note: {} already exists; set CARGO_TARGET_DIR={} to share the target directory",
+            file.display(),
+            target_dir.display()
+        );
+        return Ok(());
+    }
+    if dry_run {
+        println!(
+            "Would write {} with target-dir = {}",
+            file.display(),
+            target_dir.display()
+        );
+        return Ok(());
+    }
+    let contents = format!(
+        "# Written by terris: share the primary worktree's build directory.\n[build]\ntarget-dir = {}\n",
+        toml::Value::String(target_dir.to_string_lossy().into_owned())
+    );
+    let dir = path.join(".cargo");
+    std::fs::create_dir_all(&dir).with_context(|| format!("create '{}'", dir.display()))?;
+    std::fs::write(&file, contents).with_context(|| format!("write '{}'", file.display()))?;
+    // Keep the generated file out of `git status` in every worktree.
+    backend.exclude_locally(root, &format!("/{}", CONFIG))
+}
@@ -0,0 +1,121 @@
+//! Moving uncommitted work into a new worktree: `terris create <branch>
+//! --carry` takes it from the current worktree, for work started on the
+//! wrong branch, and `--from-stash` revives a stash.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::error::Error;
+use crate::{
+    add_new_worktree, current_worktree, find_worktree_by_branch, metadata, print_path, repo_root,
+    worktree_name,
+};
+
+pub fn cmd_create_carrying(
+    backend: &dyn Backend,
+    branch: &str,
+    untracked: bool,
+    config: &Config,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let source = current_worktree(&worktrees, &cwd)
+        .context("run `--carry` from inside the worktree that has the changes")?;
+    if let Some(wt) = find_worktree_by_branch(branch, &worktrees)? {
+        return Err(Error::WorktreeExists {
+            branch: branch.to_string(),
+            path: wt.path.clone(),
+        }
+        .into());
+    }
+    // A new branch starts where the changes were made, so they apply cleanly.
+    let start = if backend.branch_exists(&root, branch)? {
+        None
+    } else {
+        Some(
+            source
+                .head
+                .clone()
+                .context("the current worktree has no commit to start the branch from")?,
+        )
+    };
+
+    let stash = backend.stash_push(&source.path, untracked)?;
+    if stash.is_none() {
+        eprintln!("No changes to carry in '{}'", worktree_name(source));
+    }
+    let path = match add_new_worktree(backend, &root, branch, start.as_deref(), config, dry_run) {
+        Ok(path) => path,
+        Err(err) => {
+            if let Some(stash) = &stash {
+                restore(backend, &root, &source.path, stash);
+            }
+            return Err(err);
+        }
+    };
+    if let Some(stash) = &stash {
+        unpack(backend, &root, &path, stash)?;
+    }
+    if !dry_run {
+        metadata::record_use(&path);
+        print_path(&path);
+    }
+    Ok(path)
+}
+
+pub fn cmd_create_from_stash(
+    backend: &dyn Backend,
+    branch: &str,
+    stash: &str,
+    config: &Config,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let root = repo_root(backend)?;
+    if backend.branch_exists(&root, branch)? {
+        bail!(
+            "branch '{}' already exists; `--from-stash` starts a new branch where the stash was made",
+            branch
+        );
+    }
+    let commit = backend.resolve_rev(&root, stash)?;
+    let base = backend.resolve_rev(&root, &format!("{}^1", commit))?;
+    let path = add_new_worktree(backend, &root, branch, Some(&base), config, dry_run)?;
+    unpack(backend, &root, &path, &commit)?;
+    if !dry_run {
+        metadata::record_use(&path);
+        print_path(&path);
+    }
+    Ok(path)
+}
+
+/// Apply `stash` in the new worktree at `path`, then drop it.
+fn unpack(backend: &dyn Backend, root: &Path, path: &Path, stash: &str) -> Result<()> {
+    backend.stash_apply(path, stash).with_context(|| {
+        format!(
+            "apply the changes in '{}'; they are kept in stash {}",
+            path.display(),
+            stash
+        )
+    })?;
+    backend.stash_drop(root, stash)
+}
+
+/// Put stashed changes back where they came from after a failed create.
+fn restore(backend: &dyn Backend, root: &Path, source: &Path, stash: &str) {
+    let restored = backend
+        .stash_apply(source, stash)
+        .and_then(|()| backend.stash_drop(root, stash));
+    if let Err(err) = restored {
+        eprintln!(
+            "Warning: could not restore the changes to '{}' ({:#}); they are kept in stash {}",
+            source.display(),
+            err,
+            stash
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! `terris checkout`: point an existing worktree at another branch instead
+//! of creating a new worktree for it.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::error::Error;
+use crate::{
+    Worktree, find_worktree_by_branch, offer_existing_worktree, print_path, repo_root,
+    resolve_worktree_to_change, worktree_name,
+};
+
+#[derive(Args)]
+pub struct CheckoutArgs {
+    /// Worktree to switch
+    #[arg(value_name = "target")]
+    target: String,
+    /// Branch to check out; created from the worktree's current commit if it
+    /// does not exist
+    #[arg(value_name = "branch")]
+    branch: String,
+}
+
+pub fn cmd_checkout(
+    backend: &dyn Backend,
+    args: &CheckoutArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let action = format!("check out {} in", args.branch);
+    let Some(wt) =
+        resolve_worktree_to_change(&args.target, &worktrees, &cwd, config.fuzzy, &action)?
+    else {
+        return Ok(());
+    };
+    let path = match switch(backend, &root, &worktrees, wt, &args.branch, dry_run) {
+        Ok(()) => wt.path.clone(),
+        Err(err) => offer_existing_worktree(err)?,
+    };
+    print_path(&path);
+    Ok(())
+}
+
+fn switch(
+    backend: &dyn Backend,
+    root: &Path,
+    worktrees: &[Worktree],
+    wt: &Worktree,
+    branch: &str,
+    dry_run: bool,
+) -> Result<()> {
+    if let Some(holder) = find_worktree_by_branch(branch, worktrees)? {
+        if holder.path != wt.path {
+            // git refuses to check a branch out twice; say where it is.
+            return Err(Error::WorktreeExists {
+                branch: branch.to_string(),
+                path: holder.path.clone(),
+            }
+            .into());
+        }
+    } else {
+        if !backend.branch_exists(root, branch)? {
+            let head = wt
+                .head
+                .as_deref()
+                .context("the worktree has no commit to start the branch from")?;
+            backend
+                .create_branch(root, branch, head)
+                .with_context(|| format!("create branch '{}'", branch))?;
+            if !dry_run {
+                eprintln!(
+                    "Created branch {} from {}",
+                    branch,
+                    head.get(..8).unwrap_or(head)
+                );
+            }
+        }
+        backend
+            .switch_branch(&wt.path, branch)
+            .with_context(|| format!("check out '{}' in '{}'", branch, worktree_name(wt)))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! `terris clean`: retire worktrees whose work has landed.
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::{Worktree, forget_worktree, repo_root, worktree_branch_short, worktree_name};
+
+#[derive(Args)]
+pub struct CleanArgs {
+    /// Base branch to check merges against (defaults to the main worktree's branch)
+    #[arg(long, value_name = "branch")]
+    into: Option<String>,
+    /// Also delete the branch of each removed worktree
+    #[arg(long)]
+    delete_branch: bool,
+}
+
+/// Why a worktree qualifies for cleanup.
+#[derive(Debug, PartialEq)]
+enum Reason {
+    Merged,
+    UpstreamGone,
+}
+
+pub fn cmd_clean(
+    backend: &dyn Backend,
+    args: &CleanArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let base = match &args.into {
+        Some(base) => base.clone(),
+        None => worktrees
+            .iter()
+            .find(|wt| wt.main)
+            .and_then(worktree_branch_short)
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("main worktree has no branch; pass --into"))?,
+    };
+
+    let mut cleaned = 0;
+    let mut failed = 0;
+    for wt in worktrees.iter().filter(|wt| !wt.main) {
+        let Some(branch) = worktree_branch_short(wt) else {
+            continue;
+        };
+        if branch == base {
+            continue;
+        }
+        let Some(reason) = cleanup_reason(backend, &root, branch, &base)? else {
+            continue;
+        };
+        if let Some(skip) = skip_reason(backend, wt) {
+            println!("Skipped {}: {}", worktree_name(wt), skip);
+            continue;
+        }
+        match retire(backend, &root, wt, branch, &reason, args.delete_branch) {
+            Ok(()) => {
+                cleaned += 1;
+                if !dry_run {
+                    forget_worktree(&wt.path, config.hook);
+                    let why = match reason {
+                        Reason::Merged => format!("merged into {}", base),
+                        Reason::UpstreamGone => "upstream gone".to_string(),
+                    };
+                    println!("Deleted {} ({})", worktree_name(wt), why);
+                }
+            }
+            Err(err) => {
+                eprintln!("Error: clean '{}': {:#}", worktree_name(wt), err);
+                failed += 1;
+            }
+        }
+    }
+    if cleaned == 0 && failed == 0 {
+        println!("Nothing to clean.");
+    }
+    if failed > 0 {
+        bail!("failed to clean {} worktree(s)", failed);
+    }
+    Ok(())
+}
+
+fn cleanup_reason(
+    backend: &dyn Backend,
+    root: &Path,
+    branch: &str,
+    base: &str,
+) -> Result<Option<Reason>> {
+    if backend.is_merged(root, branch, base)? {
+        return Ok(Some(Reason::Merged));
+    }
+    if backend.upstream_gone(root, branch)? {
+        return Ok(Some(Reason::UpstreamGone));
+    }
+    Ok(None)
+}
+
+fn skip_reason(backend: &dyn Backend, wt: &Worktree) -> Option<&'static str> {
+    if wt.locked {
+        return Some("locked");
+    }
+    match backend.is_dirty(&wt.path) {
+        Ok(false) => None,
+        Ok(true) => Some("uncommitted changes"),
+        Err(_) => Some("could not read status"),
+    }
+}
+
+fn retire(
+    backend: &dyn Backend,
+    root: &Path,
+    wt: &Worktree,
+    branch: &str,
+    reason: &Reason,
+    delete_branch: bool,
+) -> Result<()> {
+    backend.remove_worktree(root, &wt.path, false)?;
+    if delete_branch {
+        // Squash-merged branches whose upstream is gone are not ancestors of
+        // the base, so they need a forced delete.
+        backend.delete_branch(root, branch, *reason == Reason::UpstreamGone)?;
+    }
+    Ok(())
+}
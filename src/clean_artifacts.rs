@@ -0,0 +1,142 @@
+//! `terris clean-artifacts`: delete build output across worktrees to get
+//! disk space back, leaving anything git tracks or would add alone.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::du::{dir_size, format_size};
+use crate::{repo_root, worktree_branch_short, worktree_name};
+
+/// Directories removed in every repository; `artifacts` in the config adds more.
+const KNOWN_ARTIFACTS: [&str; 3] = ["target", "node_modules", "dist"];
+
+#[derive(Args)]
+pub struct CleanArtifactsArgs {
+    /// Only clean worktrees whose branch matches a glob, e.g. 'feature/*'
+    #[arg(long, value_name = "glob")]
+    filter: Option<glob::Pattern>,
+}
+
+pub fn cmd_clean_artifacts(
+    backend: &dyn Backend,
+    args: &CleanArtifactsArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let patterns = KNOWN_ARTIFACTS
+        .iter()
+        .map(|p| p.to_string())
+        .chain(config.artifacts.iter().cloned())
+        .map(|p| {
+            let p = p.trim_end_matches('/').to_string();
+            glob::Pattern::new(&p).with_context(|| format!("invalid artifact pattern '{}'", p))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let nested: HashSet<&Path> = worktrees.iter().map(|wt| wt.path.as_path()).collect();
+
+    let (mut reclaimed, mut removed, mut failed) = (0, 0, 0);
+    for wt in &worktrees {
+        if let Some(filter) = &args.filter
+            && !worktree_branch_short(wt).is_some_and(|branch| filter.matches(branch))
+        {
+            continue;
+        }
+        let name = worktree_name(wt);
+        let files = match backend.list_files(&wt.path) {
+            Ok(files) => files,
+            Err(err) => {
+                println!("Skipped {}: could not list files ({:#})", name, err);
+                continue;
+            }
+        };
+        let mut found = Vec::new();
+        find_artifacts(&wt.path, Path::new(""), &patterns, &nested, &mut found);
+        for rel in found {
+            // Only directories git ignores entirely; a tracked or new file
+            // inside means it is not just build output.
+            if files.iter().any(|file| file.starts_with(&rel)) {
+                println!(
+                    "Kept {} in {}: it contains files git does not ignore",
+                    rel.display(),
+                    name
+                );
+                continue;
+            }
+            let dir = wt.path.join(&rel);
+            let size = dir_size(&dir, &nested);
+            if dry_run {
+                println!("Would remove {} ({})", dir.display(), format_size(size));
+                reclaimed += size;
+                removed += 1;
+                continue;
+            }
+            match std::fs::remove_dir_all(&dir) {
+                Ok(()) => {
+                    println!(
+                        "Removed {} in {} ({})",
+                        rel.display(),
+                        name,
+                        format_size(size)
+                    );
+                    reclaimed += size;
+                    removed += 1;
+                }
+                Err(err) => {
+                    eprintln!("Error: remove '{}': {}", dir.display(), err);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    if removed == 0 && failed == 0 {
+        println!("No build artifacts found.");
+    } else if dry_run {
+        println!("Would reclaim {}", format_size(reclaimed));
+    } else {
+        println!("Reclaimed {}", format_size(reclaimed));
+    }
+    if failed > 0 {
+        bail!("failed to remove {} artifact directory(ies)", failed);
+    }
+    Ok(())
+}
+
+/// Collect directories under `root.join(rel)` whose name, or path relative
+/// to `root`, matches one of `patterns`, without descending into matches,
+/// `.git`, or other worktrees.
+fn find_artifacts(
+    root: &Path,
+    rel: &Path,
+    patterns: &[glob::Pattern],
+    worktrees: &HashSet<&Path>,
+    found: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = std::fs::read_dir(root.join(rel)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if !entry.file_type().is_ok_and(|t| t.is_dir()) || entry.file_name() == ".git" {
+            continue;
+        }
+        let path = rel.join(entry.file_name());
+        if worktrees.contains(root.join(&path).as_path()) {
+            continue;
+        }
+        let name = entry.file_name();
+        let matched = patterns
+            .iter()
+            .any(|pattern| pattern.matches(&name.to_string_lossy()) || pattern.matches_path(&path));
+        if matched {
+            found.push(path);
+        } else {
+            find_artifacts(root, &path, patterns, worktrees, found);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! `--copy`: put a worktree's path on the system clipboard, to paste into an
+//! editor's "open folder" dialog or another terminal.
+//!
+//! On Linux the program that set the clipboard has to keep serving it, so
+//! terris starts a copy of itself in the background that holds the path
+//! until something else is copied.
+
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use arboard::Clipboard;
+
+/// Set in the background terris to the text it should hold.
+#[cfg(target_os = "linux")]
+const HOLD_VAR: &str = "TERRIS_CLIPBOARD_HOLD";
+
+/// Copy `path`, warning instead of failing: it is printed either way.
+pub fn copy(path: &Path, dry_run: bool) {
+    if dry_run {
+        println!("Would copy {} to the clipboard", path.display());
+        return;
+    }
+    if let Err(err) = set(&path.to_string_lossy()) {
+        eprintln!("Warning: could not copy the path: {:#}", err);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set(text: &str) -> Result<()> {
+    // Fail here, not unseen in the background, when there is no clipboard.
+    Clipboard::new().context("open the clipboard")?;
+    let exe = std::env::current_exe().context("find the terris executable")?;
+    Command::new(exe)
+        .env(HOLD_VAR, text)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .current_dir("/")
+        .spawn()
+        .context("start the process holding the clipboard")?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set(text: &str) -> Result<()> {
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .context("set the clipboard")
+}
+
+/// In the background terris started by [`copy`], serve the clipboard until
+/// another program takes it over, and return `true` so nothing else runs.
+#[cfg(target_os = "linux")]
+pub fn hold() -> bool {
+    use arboard::SetExtLinux;
+
+    let Some(text) = std::env::var_os(HOLD_VAR) else {
+        return false;
+    };
+    if let Ok(mut clipboard) = Clipboard::new() {
+        let _ = clipboard.set().wait().text(text.to_string_lossy());
+    }
+    true
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn hold() -> bool {
+    false
+}
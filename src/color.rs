@@ -0,0 +1,94 @@
+//! Colors for the worktree list. `--color auto`, the default, colors only
+//! when stdout is a terminal and `NO_COLOR` is unset or empty; `always` and
+//! `never` decide regardless.
+
+use std::io::IsTerminal;
+
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Wraps text in ANSI colors, or leaves it alone when colors are off.
+#[derive(Clone, Copy, Debug)]
+pub struct Paint {
+    enabled: bool,
+}
+
+impl Paint {
+    pub fn new(choice: ColorChoice) -> Self {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        };
+        Self { enabled }
+    }
+
+    pub fn green(self, text: &str) -> String {
+        self.wrap("32", text)
+    }
+
+    pub fn yellow(self, text: &str) -> String {
+        self.wrap("33", text)
+    }
+
+    pub fn red(self, text: &str) -> String {
+        self.wrap("31", text)
+    }
+
+    /// Color the `i`th of several sources of output, cycling through six
+    /// colors so neighbours differ.
+    pub fn nth(self, i: usize, text: &str) -> String {
+        const CYCLE: [&str; 6] = ["36", "33", "32", "35", "34", "31"];
+        self.wrap(CYCLE[i % CYCLE.len()], text)
+    }
+
+    /// Color a comma-separated list of worktree flags: red for what needs
+    /// fixing or saving, yellow for what is merely unusual or ready to clean
+    /// up.
+    pub fn flags(self, flags: &str) -> String {
+        let painted: Vec<String> = flags
+            .split(',')
+            .map(|flag| match flag {
+                "prunable" | "dirty" => self.red(flag),
+                "detached" | "locked" | "gone" => self.yellow(flag),
+                _ => flag.to_string(),
+            })
+            .collect();
+        painted.join(",")
+    }
+
+    fn wrap(self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_are_colored_by_severity_only_when_enabled() {
+        let on = Paint { enabled: true };
+        assert_eq!(
+            on.flags("locked,dirty"),
+            "\x1b[33mlocked\x1b[0m,\x1b[31mdirty\x1b[0m"
+        );
+        assert_eq!(on.flags("-"), "-");
+        let off = Paint { enabled: false };
+        assert_eq!(off.flags("locked,dirty"), "locked,dirty");
+    }
+}
@@ -0,0 +1,297 @@
+//! `terris completions install`: put the completion script where the shell
+//! finds it, or have the shell's rc file generate it on startup.
+//!
+//! An installed script is recorded next to the metadata, with a hash of
+//! what was written, and each later run checks it against the script this
+//! terris generates: one left as it was written is rewritten after an
+//! upgrade, while one that was edited or removed is left alone with a note.
+
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::{CompletionShell, completion_script, fnv1a, registry_base_dir};
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    #[command(subcommand)]
+    command: CompletionsCommand,
+}
+
+#[derive(Subcommand)]
+enum CompletionsCommand {
+    /// Install completions for a shell [default: the one $SHELL names]
+    Install {
+        #[arg(value_enum, value_name = "shell")]
+        shell: Option<CompletionShell>,
+        /// Add a line generating them to the shell's rc file instead (for
+        /// zsh, one adding their directory to fpath)
+        #[arg(long)]
+        rc: bool,
+        /// Change the rc file without asking
+        #[arg(short, long, requires = "rc")]
+        yes: bool,
+    },
+}
+
+/// A completion script terris wrote, to check on later runs.
+#[derive(Serialize, Deserialize)]
+struct Installed {
+    shell: CompletionShell,
+    path: PathBuf,
+    /// [`hash`] of the script as written, to tell edits from upgrades.
+    #[serde(default)]
+    hash: Option<String>,
+}
+
+pub fn cmd_completions(args: &CompletionsArgs, dry_run: bool) -> Result<()> {
+    let CompletionsCommand::Install { shell, rc, yes } = &args.command;
+    let shell = match shell {
+        Some(shell) => *shell,
+        None => detect()?,
+    };
+    let home = home()?;
+    let script = script_path(shell, &home);
+    match (shell, *rc) {
+        (CompletionShell::Bash, true) => {
+            let line = "eval \"$(terris --completions bash)\"";
+            return add_to_rc(&home.join(".bashrc"), line, *yes, dry_run);
+        }
+        (CompletionShell::Fish, true) => {
+            let line = "terris --completions fish | source";
+            let rc = config_home(&home).join("fish/config.fish");
+            return add_to_rc(&rc, line, *yes, dry_run);
+        }
+        _ => {}
+    }
+
+    if dry_run {
+        println!(
+            "Would write the {} completions to {}",
+            name(shell),
+            script.display()
+        );
+    } else {
+        write_script(shell, &script)?;
+        record(&Installed {
+            shell,
+            path: script.clone(),
+            hash: Some(hash(completion_script(shell))),
+        })?;
+        eprintln!(
+            "Wrote the {} completions to {}",
+            name(shell),
+            script.display()
+        );
+    }
+    match shell {
+        CompletionShell::Zsh if *rc => {
+            let dir = script.parent().expect("the script is in a directory");
+            let line = format!(
+                "fpath=({} $fpath)\nautoload -U compinit && compinit",
+                dir.display()
+            );
+            add_to_rc(&home.join(".zshrc"), &line, *yes, dry_run)?;
+        }
+        CompletionShell::Zsh => eprintln!(
+            "Make sure {} is in fpath before compinit runs, or run again with --rc",
+            script
+                .parent()
+                .expect("the script is in a directory")
+                .display()
+        ),
+        CompletionShell::Bash => {
+            eprintln!("bash-completion loads it in new shells; without it, run again with --rc")
+        }
+        CompletionShell::Fish => eprintln!("fish loads it in new shells"),
+    }
+    Ok(())
+}
+
+/// Bring the script `terris completions install` wrote up to date with this
+/// terris, or say that it was edited or is gone, and stop checking it.
+/// Problems are only logged, so they never get in the way of the command
+/// being run.
+pub fn verify() {
+    let Ok(file) = record_file() else {
+        return;
+    };
+    let Ok(data) = std::fs::read_to_string(&file) else {
+        return;
+    };
+    let Ok(mut installed) = serde_json::from_str::<Installed>(&data) else {
+        tracing::warn!("could not parse '{}'", file.display());
+        return;
+    };
+    let script = completion_script(installed.shell);
+    match std::fs::read_to_string(&installed.path) {
+        Ok(current) if current == script => {}
+        Ok(current) if installed.hash.as_deref() == Some(hash(&current).as_str()) => {
+            let updated = write_script(installed.shell, &installed.path).and_then(|()| {
+                installed.hash = Some(hash(script));
+                record(&installed)
+            });
+            match updated {
+                Ok(()) => eprintln!(
+                    "Updated the {} completions in {}",
+                    name(installed.shell),
+                    installed.path.display()
+                ),
+                Err(err) => tracing::warn!("could not update completions: {:#}", err),
+            }
+        }
+        Ok(_) => {
+            eprintln!(
+                "The {} completions at {} were changed since terris wrote them, so they are left as they are; run `terris completions install` to replace them",
+                name(installed.shell),
+                installed.path.display()
+            );
+            let _ = std::fs::remove_file(&file);
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!(
+                "The {} completions at {} are gone; run `terris completions install` to restore them",
+                name(installed.shell),
+                installed.path.display()
+            );
+            let _ = std::fs::remove_file(&file);
+        }
+        Err(err) => tracing::warn!("could not read '{}': {}", installed.path.display(), err),
+    }
+}
+
+/// [`fnv1a`] of `script`, in hex.
+fn hash(script: &str) -> String {
+    format!("{:016x}", fnv1a(script.as_bytes()))
+}
+
+/// The shell `$SHELL` names.
+fn detect() -> Result<CompletionShell> {
+    let shell = std::env::var_os("SHELL").context("$SHELL is not set; name the shell")?;
+    let name = Path::new(&shell)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match name.as_str() {
+        "bash" => Ok(CompletionShell::Bash),
+        "zsh" => Ok(CompletionShell::Zsh),
+        "fish" => Ok(CompletionShell::Fish),
+        _ => bail!("no completions for '{}'; name bash, zsh, or fish", name),
+    }
+}
+
+fn name(shell: CompletionShell) -> &'static str {
+    match shell {
+        CompletionShell::Bash => "bash",
+        CompletionShell::Zsh => "zsh",
+        CompletionShell::Fish => "fish",
+    }
+}
+
+/// Where each shell looks for a user's completions.
+fn script_path(shell: CompletionShell, home: &Path) -> PathBuf {
+    match shell {
+        CompletionShell::Bash => {
+            let data = std::env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .filter(|dir| dir.is_absolute())
+                .unwrap_or_else(|| home.join(".local/share"));
+            data.join("bash-completion/completions/terris")
+        }
+        CompletionShell::Zsh => home.join(".zsh/completions/_terris"),
+        CompletionShell::Fish => config_home(home).join("fish/completions/terris.fish"),
+    }
+}
+
+fn config_home(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home.join(".config"))
+}
+
+fn home() -> Result<PathBuf> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .context("HOME is not set")
+}
+
+fn write_script(shell: CompletionShell, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create '{}'", dir.display()))?;
+    }
+    std::fs::write(path, completion_script(shell))
+        .with_context(|| format!("write '{}'", path.display()))
+}
+
+fn record_file() -> Result<PathBuf> {
+    Ok(registry_base_dir()?.join("completions.json"))
+}
+
+fn record(installed: &Installed) -> Result<()> {
+    let file = record_file()?;
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create '{}'", dir.display()))?;
+    }
+    std::fs::write(&file, serde_json::to_string_pretty(installed)?)
+        .with_context(|| format!("write '{}'", file.display()))
+}
+
+/// Append `line` to `rc` after asking, unless it is there already.
+fn add_to_rc(rc: &Path, line: &str, yes: bool, dry_run: bool) -> Result<()> {
+    let current = match std::fs::read_to_string(rc) {
+        Ok(current) => current,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("read '{}'", rc.display())),
+    };
+    if current.contains(line) {
+        eprintln!("{} already loads the completions", rc.display());
+        return Ok(());
+    }
+    if dry_run {
+        println!("Would add to {}:\n{}", rc.display(), line);
+        return Ok(());
+    }
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            bail!(
+                "not asking without a terminal; pass --yes to change {}",
+                rc.display()
+            );
+        }
+        let accepted = dialoguer::Confirm::new()
+            .with_prompt(format!("Add to {}?\n{}\n", rc.display(), line))
+            .default(true)
+            .interact()
+            .context("confirm changing the rc file")?;
+        if !accepted {
+            eprintln!("Left {} alone", rc.display());
+            return Ok(());
+        }
+    }
+    if let Some(dir) = rc.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create '{}'", dir.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(rc)
+        .with_context(|| format!("open '{}'", rc.display()))?;
+    let separator = if current.is_empty() {
+        ""
+    } else if current.ends_with('\n') {
+        "\n"
+    } else {
+        "\n\n"
+    };
+    write!(file, "{}# terris completions\n{}\n", separator, line)
+        .with_context(|| format!("write '{}'", rc.display()))?;
+    eprintln!(
+        "Added the completions to {}; open a new shell to use them",
+        rc.display()
+    );
+    Ok(())
+}
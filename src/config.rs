@@ -0,0 +1,298 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/terris/config.toml`
+//! (falling back to `~/.config/terris/config.toml`). `TERRIS_CONFIG` points
+//! at a different file. A missing file means all defaults.
+
+use std::collections::BTreeMap;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::backend::Integration;
+use crate::devcontainer::Devcontainer;
+use crate::foreach::ForeachConfig;
+use crate::forge::ForgeConfig;
+use crate::hooks::Hooks;
+use crate::jumper::Jumper;
+use crate::list::ListConfig;
+use crate::naming;
+use crate::notify::NotifyConfig;
+use crate::open::Open;
+use crate::registry_base_dir;
+use crate::retention::Retention;
+use crate::script;
+use crate::workspace::WorkspaceConfig;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// Directory jumper to keep in sync with created and removed worktrees.
+    pub hook: Option<Jumper>,
+    /// When no branch matches a target exactly or by prefix, accept branches
+    /// containing the target's characters in order (`lgnpg` for `login-page`).
+    pub fuzzy: bool,
+    /// What `terris <branch>` does when the branch already has a worktree.
+    pub on_collision: Collision,
+    /// Create worktrees at `<registry>/<repo>/<branch>` without a random
+    /// suffix, ignoring any path template.
+    pub stable: bool,
+    /// Where new worktrees go by default.
+    pub location: Location,
+    /// Worktree path for the `registry` location; see [`naming`] for the
+    /// placeholders.
+    pub path_template: Option<String>,
+    /// In Rust projects, make new worktrees build into the primary
+    /// worktree's `target/` instead of their own.
+    pub shared_cargo_target: bool,
+    /// Write an `.envrc` with `TERRIS_*` variables into new worktrees and
+    /// `direnv allow` it.
+    pub direnv: bool,
+    /// Link `.devcontainer` into new worktrees, and possibly start them.
+    pub devcontainer: Devcontainer,
+    /// What `terris create` opens each new worktree in, unless given
+    /// `--no-open`; only with a terminal.
+    pub open: Option<Open>,
+    /// Rhai script returning the path template for each new worktree.
+    pub naming_script: Option<PathBuf>,
+    /// Commands run at points in a worktree's life.
+    pub hooks: Hooks,
+    /// What `terris gc` removes besides abandoned ephemeral worktrees.
+    pub gc: GcConfig,
+    /// Age limits `terris gc` enforces.
+    pub retention: Retention,
+    /// Desktop notifications from `terris gc` and `terris daemon`.
+    pub notify: NotifyConfig,
+    /// How the worktree list looks.
+    pub list: ListConfig,
+    /// Which forge hosts repositories whose remote URL does not say.
+    pub forge: ForgeConfig,
+    /// Defaults for `terris graduate`.
+    pub graduate: GraduateConfig,
+    /// How `terris foreach` shows output.
+    pub foreach: ForeachConfig,
+    /// How many subprocesses to run at once across worktrees; `--jobs`
+    /// overrides it.
+    pub jobs: Option<NonZeroUsize>,
+    /// The git executable, a path or a name looked up in PATH;
+    /// `TERRIS_GIT` overrides it.
+    pub git: Option<PathBuf>,
+    /// Seconds a git command may run before it is killed; unset waits
+    /// forever.
+    pub git_timeout: Option<NonZeroU64>,
+    /// How many times to retry a git command that failed on a lock another
+    /// process held; 3 when unset.
+    pub lock_retries: Option<u32>,
+    /// Seconds to wait for another terris to release the registry lock; 30
+    /// when unset.
+    pub registry_wait: Option<u64>,
+    /// Directory names or globs that `terris clean-artifacts` removes, on top
+    /// of `target`, `node_modules`, and `dist`.
+    pub artifacts: Vec<String>,
+    /// Globs of keys in the repository's own git config, e.g.
+    /// `core.hooksPath` or `merge.*.driver`, copied into each new worktree's
+    /// own config.
+    pub propagate_config: Vec<String>,
+    /// Git config set in each new worktree alone, keyed by a glob its branch
+    /// must match, e.g. `[worktree-config."oss/*"]`.
+    pub worktree_config: BTreeMap<String, BTreeMap<String, String>>,
+    /// Per-repository overrides, keyed by the repository's directory name.
+    pub repos: BTreeMap<String, RepoConfig>,
+    /// Sets of repositories `terris ws` works on together, keyed by name.
+    pub workspaces: BTreeMap<String, WorkspaceConfig>,
+    /// Set by `--description` for the worktree being created; not read from
+    /// the file.
+    #[serde(skip)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RepoConfig {
+    /// Replaces the registry directory as `{base}`, e.g. to keep this
+    /// repository's worktrees on another disk.
+    pub base_dir: Option<PathBuf>,
+    pub location: Option<Location>,
+    pub path_template: Option<String>,
+    /// Replaces the global `[retention]` for this repository.
+    pub retention: Option<Retention>,
+    /// Applied after the global `[worktree-config]`, so its values win.
+    pub worktree_config: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GcConfig {
+    /// Rhai script deciding, per worktree, whether `terris gc` removes it.
+    pub policy: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GraduateConfig {
+    /// How to land the branch when no `--rebase`/`--merge`/`--squash` is given.
+    pub strategy: Integration,
+    /// Push the base branch after landing.
+    pub push: bool,
+}
+
+/// Where a repository's worktrees are created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Location {
+    /// Under `~/.terris-worktrees`, laid out by `path-template`.
+    #[default]
+    Registry,
+    /// Under `<repo>/.worktrees/`, excluded from `git status`.
+    InRepo,
+    /// Next to the repository, as `../<repo>-<branch>`.
+    Sibling,
+}
+
+/// Strategy for creating a worktree whose branch already has one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Collision {
+    /// Fail with exit code 9
+    Error,
+    /// Print the existing worktree's path
+    #[default]
+    Reuse,
+    /// Branch off as `<branch>-2` (or the next free number) in a new worktree;
+    /// with stable paths, also number an occupied directory
+    Suffix,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let Some(file) = config_file() else {
+            return Ok(Self::default());
+        };
+        let data = match std::fs::read_to_string(&file) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("read config '{}'", file.display()));
+            }
+        };
+        let mut config =
+            Self::parse(&data).with_context(|| format!("parse config '{}'", file.display()))?;
+        // Scripts are found next to the config file.
+        let dir = file.parent().unwrap_or(Path::new(""));
+        for path in [&mut config.naming_script, &mut config.gc.policy]
+            .into_iter()
+            .flatten()
+        {
+            *path = script::resolve(path, dir);
+        }
+        for base in config
+            .repos
+            .values_mut()
+            .filter_map(|repo| repo.base_dir.as_mut())
+        {
+            *base = script::resolve(base, dir);
+        }
+        for repo in config
+            .workspaces
+            .values_mut()
+            .flat_map(|workspace| &mut workspace.repos)
+        {
+            *repo = script::resolve(repo, dir);
+        }
+        Ok(config)
+    }
+
+    /// The path template for the repository named `repo`.
+    pub fn path_template(&self, repo: &str) -> &str {
+        if self.stable {
+            return naming::STABLE_TEMPLATE;
+        }
+        let repo = self.repos.get(repo);
+        match repo.and_then(|r| r.location).unwrap_or(self.location) {
+            Location::InRepo => naming::IN_REPO_TEMPLATE,
+            Location::Sibling => naming::SIBLING_TEMPLATE,
+            Location::Registry => repo
+                .and_then(|r| r.path_template.as_deref())
+                .or(self.path_template.as_deref())
+                .unwrap_or(naming::DEFAULT_TEMPLATE),
+        }
+    }
+
+    /// The directory `{base}` stands for in the repository named `repo`'s
+    /// worktree paths: its `base-dir`, or else the registry.
+    pub fn base_dir(&self, repo: &str) -> Result<PathBuf> {
+        match self.repos.get(repo).and_then(|r| r.base_dir.clone()) {
+            Some(dir) => Ok(dir),
+            None => registry_base_dir(),
+        }
+    }
+
+    /// The age limits for the repository named `repo`.
+    pub fn retention(&self, repo: &str) -> &Retention {
+        self.repos
+            .get(repo)
+            .and_then(|r| r.retention.as_ref())
+            .unwrap_or(&self.retention)
+    }
+
+    pub fn parse(data: &str) -> Result<Self> {
+        Ok(toml::from_str(data)?)
+    }
+}
+
+/// The config file terris reads, whether or not it exists; `None` when
+/// there is no home directory to find it in.
+pub fn config_file() -> Option<PathBuf> {
+    if let Some(file) = std::env::var_os("TERRIS_CONFIG") {
+        return Some(PathBuf::from(file));
+    }
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("terris").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_hook_and_defaults_missing_keys() {
+        let config = Config::parse("hook = \"zoxide\"\n").unwrap();
+        assert_eq!(config.hook, Some(Jumper::Zoxide));
+        assert_eq!(Config::parse("").unwrap().hook, None);
+        assert!(Config::parse("hook = \"fasd\"\n").is_err());
+        let config = Config::parse("on-collision = \"suffix\"\n").unwrap();
+        assert_eq!(config.on_collision, Collision::Suffix);
+    }
+
+    #[test]
+    fn path_template_prefers_repo_then_global() {
+        let mut config = Config::parse(
+            r#"
+path-template = "{base}/{repo}/{branch}"
+
+[repos.api]
+path-template = "{repo_parent}/{repo}-{branch_slug}"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.path_template("api"),
+            "{repo_parent}/{repo}-{branch_slug}"
+        );
+        assert_eq!(config.path_template("web"), "{base}/{repo}/{branch}");
+        config.location = Location::InRepo;
+        assert_eq!(config.path_template("web"), naming::IN_REPO_TEMPLATE);
+        config.repos.entry("web".into()).or_default().location = Some(Location::Sibling);
+        assert_eq!(config.path_template("web"), naming::SIBLING_TEMPLATE);
+        config.stable = true;
+        assert_eq!(config.path_template("api"), naming::STABLE_TEMPLATE);
+        assert_eq!(
+            Config::default().path_template("api"),
+            naming::DEFAULT_TEMPLATE
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//! `terris config`: read and change the config file from the command line.
+//! Keys are dotted paths into it, such as `path-template` or
+//! `retention.default`, and `--repo <name>` puts them under
+//! `[repos.<name>]`. Edits keep the file's comments and layout, and a
+//! change that would leave it unreadable is refused.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Subcommand};
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+use crate::config::{self, Config};
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print a key's value as the config file sets it
+    Get {
+        #[arg(value_name = "key")]
+        key: String,
+        #[command(flatten)]
+        scope: Scope,
+    },
+    /// Set a key; the value is read as TOML, or else taken as a string
+    Set {
+        #[arg(value_name = "key")]
+        key: String,
+        #[arg(value_name = "value")]
+        value: String,
+        #[command(flatten)]
+        scope: Scope,
+    },
+    /// Remove a key from the config file
+    Unset {
+        #[arg(value_name = "key")]
+        key: String,
+        #[command(flatten)]
+        scope: Scope,
+    },
+    /// Open the config file in $VISUAL or $EDITOR
+    Edit,
+    /// Print where the config file is
+    Path,
+}
+
+#[derive(Args)]
+struct Scope {
+    /// Read or write the key under [repos.<name>] instead of globally
+    #[arg(long, value_name = "name")]
+    repo: Option<String>,
+}
+
+impl Scope {
+    /// The path to `key` from the top of the file.
+    fn path(&self, key: &str) -> Result<Vec<String>> {
+        let mut path = Vec::new();
+        if let Some(repo) = &self.repo {
+            path.extend(["repos".to_string(), repo.clone()]);
+        }
+        path.extend(split_key(key)?);
+        Ok(path)
+    }
+}
+
+pub fn cmd_config(args: &ConfigArgs, dry_run: bool) -> Result<()> {
+    let file = config::config_file().context("no home directory to keep the config file in")?;
+    match &args.command {
+        ConfigCommand::Get { key, scope } => {
+            let doc = read(&file)?;
+            let path = scope.path(key)?;
+            match lookup(doc.as_item(), &path) {
+                Some(item) => println!("{}", show(item)),
+                None => bail!("'{}' is not set in '{}'", path.join("."), file.display()),
+            }
+            Ok(())
+        }
+        ConfigCommand::Set { key, value, scope } => {
+            let mut doc = read(&file)?;
+            let path = scope.path(key)?;
+            set(&mut doc, &path, parse_value(value))?;
+            write(&file, &doc, &format!("set {}", path.join(".")), dry_run)
+        }
+        ConfigCommand::Unset { key, scope } => {
+            let mut doc = read(&file)?;
+            let path = scope.path(key)?;
+            if !unset(&mut doc, &path) {
+                bail!("'{}' is not set in '{}'", path.join("."), file.display());
+            }
+            write(&file, &doc, &format!("unset {}", path.join(".")), dry_run)
+        }
+        ConfigCommand::Edit => edit(&file, dry_run),
+        ConfigCommand::Path => {
+            crate::print_path(&file);
+            Ok(())
+        }
+    }
+}
+
+/// The config file as an editable document, empty when there is none.
+fn read(file: &Path) -> Result<DocumentMut> {
+    let data = match std::fs::read_to_string(file) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("read config '{}'", file.display())),
+    };
+    data.parse()
+        .with_context(|| format!("parse config '{}'", file.display()))
+}
+
+/// Save `doc` to `file`, unless terris could not read it back.
+fn write(file: &Path, doc: &DocumentMut, change: &str, dry_run: bool) -> Result<()> {
+    let data = doc.to_string();
+    Config::parse(&data).with_context(|| format!("refusing to {}", change))?;
+    if dry_run {
+        println!("Would {} in {}", change, file.display());
+        return Ok(());
+    }
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create '{}'", dir.display()))?;
+    }
+    std::fs::write(file, data).with_context(|| format!("write config '{}'", file.display()))
+}
+
+/// The user's editor: `$VISUAL`, else `$EDITOR`, else `vi`. It may be a
+/// command with arguments, such as `code --wait`, so run it through `sh`.
+pub fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(std::env::var_os)
+        .find(|editor| !editor.is_empty())
+        .map_or_else(
+            || "vi".into(),
+            |editor| editor.to_string_lossy().into_owned(),
+        )
+}
+
+fn edit(file: &Path, dry_run: bool) -> Result<()> {
+    let editor = editor();
+    if dry_run {
+        println!("Would run `{} {}`", editor, file.display());
+        return Ok(());
+    }
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create '{}'", dir.display()))?;
+    }
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(file)
+        .status()
+        .with_context(|| format!("run `{}`", editor))?;
+    if !status.success() {
+        bail!("`{}` exited with {}", editor, status);
+    }
+    if let Err(err) = read(file).and_then(|doc| Config::parse(&doc.to_string())) {
+        bail!(
+            "{:#}; run `terris config edit` again to fix it",
+            err.context(format!("'{}' is not valid", file.display()))
+        );
+    }
+    Ok(())
+}
+
+/// `key` split at its dots, except those inside a double-quoted part, as in
+/// `worktree-config."oss/*".user.email`.
+fn split_key(key: &str) -> Result<Vec<String>> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quoted = false;
+    let mut was_quoted = false;
+    for c in key.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                was_quoted = true;
+            }
+            '.' if !quoted => {
+                if part.is_empty() && !was_quoted {
+                    bail!("'{}' has an empty part", key);
+                }
+                parts.push(std::mem::take(&mut part));
+                was_quoted = false;
+            }
+            c => part.push(c),
+        }
+    }
+    if quoted {
+        bail!("'{}' has an unclosed '\"'", key);
+    }
+    if part.is_empty() && !was_quoted {
+        bail!("'{}' has an empty part", key);
+    }
+    parts.push(part);
+    Ok(parts)
+}
+
+/// `value` as TOML, such as `true`, `3`, or `["a", "b"]`, or else as a
+/// string, so `path-template` needs no quotes.
+fn parse_value(value: &str) -> Value {
+    value.parse().unwrap_or_else(|_| value.into())
+}
+
+fn lookup<'a>(item: &'a Item, path: &[String]) -> Option<&'a Item> {
+    path.iter().try_fold(item, |item, part| item.get(part))
+}
+
+/// A value as `terris config get` prints it: strings bare, anything else
+/// as TOML.
+fn show(item: &Item) -> String {
+    match item {
+        Item::Value(Value::String(s)) => s.value().clone(),
+        Item::Value(value) => value.to_string().trim().to_string(),
+        other => other.to_string().trim().to_string(),
+    }
+}
+
+fn set(doc: &mut DocumentMut, path: &[String], value: Value) -> Result<()> {
+    let (last, parents) = path.split_last().expect("keys have a part");
+    let mut table = doc.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for (i, part) in parents.iter().enumerate() {
+        let item = table.entry(part).or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        });
+        table = match item.as_table_like_mut() {
+            Some(table) => table,
+            None => bail!("'{}' is not a table", parents[..=i].join(".")),
+        };
+    }
+    table.insert(last, Item::Value(value));
+    Ok(())
+}
+
+/// Remove the key at `path`, returning whether it was there.
+fn unset(doc: &mut DocumentMut, path: &[String]) -> bool {
+    let (last, parents) = path.split_last().expect("keys have a part");
+    let mut item = doc.as_item_mut();
+    for part in parents {
+        match item.get_mut(part) {
+            Some(next) => item = next,
+            None => return false,
+        }
+    }
+    item.as_table_like_mut()
+        .and_then(|table| table.remove(last))
+        .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_split_at_dots_outside_quotes() {
+        assert_eq!(split_key("path-template").unwrap(), ["path-template"]);
+        assert_eq!(
+            split_key("worktree-config.\"oss/*\".user.email").unwrap(),
+            ["worktree-config", "oss/*", "user", "email"]
+        );
+        assert!(split_key("retention..default").is_err());
+        assert!(split_key("\"open").is_err());
+    }
+
+    #[test]
+    fn set_and_unset_keep_the_rest_of_the_file() {
+        let mut doc: DocumentMut = "# mine\nfuzzy = true\n".parse().unwrap();
+        let path = |key| {
+            Scope {
+                repo: Some("api".into()),
+            }
+            .path(key)
+            .unwrap()
+        };
+        set(&mut doc, &path("location"), parse_value("sibling")).unwrap();
+        set(&mut doc, &["jobs".into()], parse_value("4")).unwrap();
+        assert_eq!(
+            doc.to_string(),
+            "# mine\nfuzzy = true\njobs = 4\n\n[repos.api]\nlocation = \"sibling\"\n"
+        );
+        assert_eq!(
+            show(lookup(doc.as_item(), &path("location")).unwrap()),
+            "sibling"
+        );
+        assert!(set(&mut doc, &["jobs".into(), "x".into()], parse_value("1")).is_err());
+
+        assert!(unset(&mut doc, &path("location")));
+        assert!(!unset(&mut doc, &path("location")));
+        assert!(lookup(doc.as_item(), &path("location")).is_none());
+    }
+}
@@ -0,0 +1,82 @@
+//! `terris current`: which worktree the current directory belongs to, for
+//! shell prompts and scripts that behave differently per worktree. Outside
+//! every worktree, repository or not, it fails with its own exit code.
+
+use std::borrow::Cow;
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::error::Error;
+use crate::{
+    ListEntry, align_rows, current_worktree, metadata, print_path, repo_root,
+    worktree_branch_short, worktree_name,
+};
+
+#[derive(Args)]
+#[group(multiple = false)]
+pub struct CurrentArgs {
+    /// Print only the worktree's name
+    #[arg(long)]
+    name: bool,
+    /// Print only the branch; nothing for a detached worktree
+    #[arg(long)]
+    branch: bool,
+    /// Print only the worktree's path
+    #[arg(long)]
+    path: bool,
+}
+
+pub fn cmd_current(backend: &dyn Backend, args: &CurrentArgs, json: bool) -> Result<()> {
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let root = match repo_root(backend) {
+        Err(err) if matches!(err.downcast_ref(), Some(Error::NotARepo { .. })) => {
+            return Err(Error::NotInWorktree(cwd).into());
+        }
+        root => root?,
+    };
+    let worktrees = backend.list_worktrees(&root)?;
+    let Some(wt) = current_worktree(&worktrees, &cwd) else {
+        return Err(Error::NotInWorktree(cwd).into());
+    };
+
+    if json {
+        let metadata = metadata::indexed(&worktrees);
+        let entry = ListEntry {
+            index: metadata.index(&wt.path),
+            worktree: Cow::Borrowed(wt),
+            managed: metadata.managed(&wt.path),
+            usage: None,
+            dirty: None,
+            commit: None,
+            ci: None,
+            tracking: None,
+            pull_request: None,
+        };
+        println!("{}", serde_json::to_string_pretty(&entry)?);
+    } else if args.name {
+        println!("{}", worktree_name(wt));
+    } else if args.branch {
+        if let Some(branch) = worktree_branch_short(wt) {
+            println!("{}", branch);
+        }
+    } else if args.path {
+        print_path(&wt.path);
+    } else {
+        let rows = [
+            ["name".to_string(), worktree_name(wt)],
+            [
+                "branch".to_string(),
+                worktree_branch_short(wt)
+                    .unwrap_or("(detached)")
+                    .to_string(),
+            ],
+            ["path".to_string(), wt.path.display().to_string()],
+        ];
+        for line in align_rows(&rows) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+//! `terris daemon`: keep a repository's worktrees tidy in the background and
+//! their status cached, so `terris` and `terris du` answer without running
+//! git or walking directories.
+//!
+//! Each round prunes administrative entries for deleted worktree
+//! directories, collects what `terris gc` would, and refreshes the
+//! [`cache`](crate::cache). With notifications enabled, a change in what
+//! needs attention is announced once.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::cache::{self, Cache, Status};
+use crate::config::Config;
+use crate::{du, gc, notify, repo_root, unix_now};
+
+#[derive(Args)]
+pub struct DaemonArgs {
+    /// Seconds between rounds
+    #[arg(long, value_name = "secs", default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+    /// Run one round and exit, e.g. from cron
+    #[arg(long)]
+    once: bool,
+}
+
+pub fn cmd_daemon(
+    backend: &dyn Backend,
+    args: &DaemonArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    loop {
+        let round = run_round(backend, &root, config, args.interval, dry_run);
+        if args.once {
+            return round;
+        }
+        if let Err(err) = round {
+            eprintln!("Error: {:#}", err);
+        }
+        std::thread::sleep(Duration::from_secs(args.interval));
+    }
+}
+
+fn run_round(
+    backend: &dyn Backend,
+    root: &Path,
+    config: &Config,
+    interval: u64,
+    dry_run: bool,
+) -> Result<()> {
+    backend.prune(root).context("prune worktrees")?;
+    // A worktree that could not be collected should not keep the cache cold.
+    let collected = gc::collect(backend, root, config, dry_run);
+    refresh(backend, root, interval, dry_run)?;
+    if config.notify.enabled {
+        announce(backend, root, config, dry_run)?;
+    }
+    collected.map(drop)
+}
+
+/// Notify about what needs attention, unless the last round already did.
+fn announce(backend: &dyn Backend, root: &Path, config: &Config, dry_run: bool) -> Result<()> {
+    let worktrees = backend.list_worktrees(root)?;
+    let attention = notify::survey(backend, root, &worktrees, config)?;
+    let mut cache = cache::load();
+    let body = attention.body();
+    if cache.notified.get(root) == Some(&body) {
+        return Ok(());
+    }
+    if !attention.is_empty() {
+        notify::send(root, &attention, dry_run);
+    }
+    if !dry_run {
+        cache.notified.insert(root.to_path_buf(), body);
+        cache.save()?;
+    }
+    Ok(())
+}
+
+/// Recompute every worktree's status and usage. Entries outlive one missed
+/// round, then expire.
+fn refresh(backend: &dyn Backend, root: &Path, interval: u64, dry_run: bool) -> Result<()> {
+    let worktrees = backend.list_worktrees(root)?;
+    let usages = du::measure(backend, &worktrees, &Cache::default());
+    let expires = unix_now().saturating_add_unsigned(interval.saturating_mul(2));
+    let mut cache = cache::load();
+    cache.retain_live();
+    for (wt, usage) in worktrees.iter().zip(usages) {
+        match backend.change_summary(&wt.path) {
+            Ok(summary) => {
+                let status = Status {
+                    expires,
+                    head: wt.head.clone(),
+                    summary,
+                    usage: Some(usage),
+                };
+                cache.worktrees.insert(wt.path.clone(), status);
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "could not read status of '{}': {:#}",
+                    wt.path.display(),
+                    err
+                );
+                cache.worktrees.remove(&wt.path);
+            }
+        }
+    }
+    if !dry_run {
+        cache.save()?;
+    }
+    Ok(())
+}
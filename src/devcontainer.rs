@@ -0,0 +1,97 @@
+//! Dev containers per worktree: `terris create --devcontainer` makes the
+//! repository's `.devcontainer` available in the new worktree and, with
+//! `--devcontainer=up`, starts a container for it.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::backend::Backend;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Devcontainer {
+    /// Leave dev containers alone
+    #[default]
+    Off,
+    /// Link the primary worktree's .devcontainer into the new worktree
+    Link,
+    /// Link it, then run `devcontainer up`
+    Up,
+}
+
+/// Make `.devcontainer` available in the new worktree at `path`, then
+/// start the container if `mode` is [`Devcontainer::Up`]. A tracked
+/// `.devcontainer` is already there; an untracked one in the primary
+/// worktree is symlinked so edits apply everywhere.
+pub fn setup(
+    backend: &dyn Backend,
+    root: &Path,
+    path: &Path,
+    mode: Devcontainer,
+    dry_run: bool,
+) -> Result<()> {
+    if mode == Devcontainer::Off {
+        return Ok(());
+    }
+    let link = path.join(".devcontainer");
+    if !link.exists() {
+        let Some(primary) = backend.list_worktrees(root)?.into_iter().find(|wt| wt.main) else {
+            bail!("no primary worktree to take .devcontainer from");
+        };
+        let source = primary.path.join(".devcontainer");
+        if !source.is_dir() {
+            bail!("'{}' does not exist", source.display());
+        }
+        if dry_run {
+            println!("Would link {} to {}", link.display(), source.display());
+        } else {
+            std::os::unix::fs::symlink(&source, &link)
+                .with_context(|| format!("link '{}'", link.display()))?;
+            backend.exclude_locally(root, "/.devcontainer")?;
+        }
+    }
+    if mode == Devcontainer::Up {
+        up(path, &container_id(root, path), dry_run);
+    }
+    Ok(())
+}
+
+/// Label that tells this worktree's container apart from the others of
+/// the same repository: `<repo>-<worktree directory>`.
+fn container_id(root: &Path, path: &Path) -> String {
+    let name = |p: &Path| {
+        p.file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().into_owned())
+    };
+    format!("{}-{}", name(root), name(path))
+}
+
+/// Run `devcontainer up`, warning instead of failing: the worktree itself
+/// is ready either way.
+fn up(path: &Path, id: &str, dry_run: bool) {
+    let label = format!("terris.worktree={}", id);
+    if dry_run {
+        println!(
+            "Would run devcontainer up --workspace-folder {} --id-label {}",
+            path.display(),
+            label
+        );
+        return;
+    }
+    let status = Command::new("devcontainer")
+        .arg("up")
+        .arg("--workspace-folder")
+        .arg(path)
+        .args(["--id-label", &label])
+        .stdout(Stdio::from(std::io::stderr()))
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("Warning: `devcontainer up` exited with {}", status),
+        Err(err) => eprintln!("Warning: could not run devcontainer: {}", err),
+    }
+}
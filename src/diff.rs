@@ -0,0 +1,31 @@
+//! `terris diff <a> <b>`: compare two worktrees' files as they are on disk,
+//! uncommitted work included.
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::{repo_root, resolve_worktree};
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// Worktree to diff from
+    #[arg(value_name = "a")]
+    from: String,
+    /// Worktree to diff to
+    #[arg(value_name = "b")]
+    to: String,
+    /// Only compare these paths
+    #[arg(last = true, value_name = "paths")]
+    paths: Vec<String>,
+}
+
+pub fn cmd_diff(backend: &dyn Backend, args: &DiffArgs, config: &Config) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let from = resolve_worktree(&args.from, &worktrees, &cwd, config.fuzzy)?;
+    let to = resolve_worktree(&args.to, &worktrees, &cwd, config.fuzzy)?;
+    backend.diff_worktrees(&from.path, &to.path, &args.paths)
+}
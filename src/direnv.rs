@@ -0,0 +1,64 @@
+//! direnv integration: new worktrees get an `.envrc` describing where they
+//! are, and it is allowed right away so it loads on the first `cd`.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::backend::Backend;
+use crate::env::WorktreeEnv;
+
+/// Write `.envrc` in the new worktree described by `env` and run
+/// `direnv allow` on it. The file exports `TERRIS_*` variables, then
+/// includes an untracked `.envrc` from the primary worktree, if any. A
+/// tracked `.envrc` is left as it is and only allowed.
+pub fn setup(backend: &dyn Backend, root: &Path, env: &WorktreeEnv, dry_run: bool) -> Result<()> {
+    let file = env.path.join(".envrc");
+    if dry_run {
+        if !file.exists() {
+            println!("Would write {}", file.display());
+        }
+        println!("Would run direnv allow in {}", env.path.display());
+        return Ok(());
+    }
+    if !file.exists() {
+        let mut contents = String::from("# Written by terris.\n");
+        for (key, value) in env.vars() {
+            contents.push_str(&format!("export {}={}\n", key, shell_quote(&value)));
+        }
+        // Share the primary worktree's own (untracked) environment setup.
+        let shared = env.repo.join(".envrc");
+        if env.repo != env.path && shared.exists() {
+            contents.push_str(&format!(
+                "source_env {}\n",
+                shell_quote(&shared.to_string_lossy())
+            ));
+        }
+        std::fs::write(&file, contents).with_context(|| format!("write '{}'", file.display()))?;
+        backend.exclude_locally(root, "/.envrc")?;
+    }
+    allow(&env.path);
+    Ok(())
+}
+
+/// Run `direnv allow`, warning instead of failing: the worktree is usable
+/// without it.
+fn allow(path: &Path) {
+    let status = Command::new("direnv")
+        .arg("allow")
+        .arg(path)
+        .current_dir(path)
+        .stdout(Stdio::from(std::io::stderr()))
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("Warning: `direnv allow` exited with {}", status),
+        Err(err) => eprintln!("Warning: could not run direnv: {}", err),
+    }
+}
+
+/// Quote `value` for a POSIX shell.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -0,0 +1,189 @@
+//! `terris du`: how much disk each worktree uses, split into the files git
+//! sees and everything it ignores (build output, dependencies, caches).
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::cache::{self, Cache};
+use crate::jobs;
+use crate::{Worktree, align_rows, repo_root, worktree_name};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Usage {
+    /// Bytes in tracked and untracked, non-ignored files.
+    pub files: u64,
+    /// Bytes in everything else: ignored build artifacts and caches.
+    pub ignored: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.files + self.ignored
+    }
+}
+
+#[derive(Serialize)]
+struct DuEntry<'a> {
+    #[serde(flatten)]
+    worktree: &'a Worktree,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+pub fn cmd_du(backend: &dyn Backend, json: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let usages = measure(backend, &worktrees, &cache::load());
+    let mut entries: Vec<DuEntry> = worktrees
+        .iter()
+        .zip(usages)
+        .map(|(worktree, usage)| DuEntry { worktree, usage })
+        .collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.usage.total()));
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    let mut rows = vec![[
+        "NAME".to_string(),
+        "SIZE".to_string(),
+        "FILES".to_string(),
+        "IGNORED".to_string(),
+        "PATH".to_string(),
+    ]];
+    let mut sum = Usage::default();
+    for entry in &entries {
+        sum.files += entry.usage.files;
+        sum.ignored += entry.usage.ignored;
+        rows.push([
+            worktree_name(entry.worktree),
+            format_size(entry.usage.total()),
+            format_size(entry.usage.files),
+            format_size(entry.usage.ignored),
+            entry.worktree.path.display().to_string(),
+        ]);
+    }
+    rows.push([
+        "total".to_string(),
+        format_size(sum.total()),
+        format_size(sum.files),
+        format_size(sum.ignored),
+        String::new(),
+    ]);
+    for line in align_rows(&rows) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Disk usage of each of `worktrees`, in the same order, taken from `cache`
+/// where it has a current answer. The directory walks run in parallel; a
+/// worktree that cannot be read counts as empty.
+pub fn measure(backend: &dyn Backend, worktrees: &[Worktree], cache: &Cache) -> Vec<Usage> {
+    // Worktrees may live inside one another (e.g. under the primary
+    // checkout); each is only counted once, for itself.
+    let all: HashSet<&Path> = worktrees.iter().map(|wt| wt.path.as_path()).collect();
+    // Asking git for its file list is quick; walking the tree is not.
+    let pending: Vec<(&Worktree, Option<Usage>, Vec<PathBuf>)> = worktrees
+        .iter()
+        .map(|wt| match cache.get(wt).and_then(|status| status.usage) {
+            Some(usage) => (wt, Some(usage), Vec::new()),
+            None => (wt, None, backend.list_files(&wt.path).unwrap_or_default()),
+        })
+        .collect();
+    jobs::map(&pending, |(wt, cached, files)| {
+        cached.unwrap_or_else(|| usage(&wt.path, files, &all))
+    })
+}
+
+/// Usage of `wt` alone, not counting any of `worktrees` nested inside it.
+pub fn measure_one(
+    backend: &dyn Backend,
+    wt: &Worktree,
+    worktrees: &[Worktree],
+    cache: &Cache,
+) -> Usage {
+    if let Some(usage) = cache.get(wt).and_then(|status| status.usage) {
+        return usage;
+    }
+    let all: HashSet<&Path> = worktrees.iter().map(|wt| wt.path.as_path()).collect();
+    let files = backend.list_files(&wt.path).unwrap_or_default();
+    usage(&wt.path, &files, &all)
+}
+
+fn usage(path: &Path, files: &[PathBuf], worktrees: &HashSet<&Path>) -> Usage {
+    let total = dir_size(path, worktrees);
+    let files: u64 = files
+        .iter()
+        .filter_map(|file| std::fs::symlink_metadata(path.join(file)).ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+        .sum();
+    Usage {
+        files,
+        ignored: total.saturating_sub(files),
+    }
+}
+
+/// Bytes in regular files under `dir`, not following symlinks and skipping
+/// `.git` and other worktrees.
+pub fn dir_size(dir: &Path, worktrees: &HashSet<&Path>) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut size = 0;
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        if file_type.is_dir() {
+            if !worktrees.contains(path.as_path()) {
+                size += dir_size(&path, worktrees);
+            }
+        } else if file_type.is_file() {
+            size += entry.metadata().map_or(0, |meta| meta.len());
+        }
+    }
+    size
+}
+
+/// Human-readable size with binary units, e.g. `1.5G`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_size;
+
+    #[test]
+    fn format_size_uses_binary_units() {
+        assert_eq!(format_size(0), "0B");
+        assert_eq!(format_size(1023), "1023B");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(20 * 1024 * 1024), "20M");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0G");
+    }
+}
@@ -0,0 +1,123 @@
+//! The `TERRIS_*` variables that tell a command which worktree it runs in.
+//! Set for everything terris starts inside a worktree (hooks, `terris with`,
+//! ephemeral shells) and written to `.envrc` by the direnv integration.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::Result;
+
+use crate::backend::Backend;
+use crate::metadata::{Metadata, WorktreeMeta};
+use crate::port;
+
+/// Variables that are only set for some worktrees.
+const OPTIONAL: [&str; 4] = [
+    "TERRIS_BRANCH",
+    "TERRIS_BASE",
+    "TERRIS_DESCRIPTION",
+    "TERRIS_EPHEMERAL",
+];
+
+pub struct WorktreeEnv {
+    /// The worktree's directory name, unique within the repository.
+    pub name: String,
+    pub branch: Option<String>,
+    /// The primary worktree.
+    pub repo: PathBuf,
+    pub path: PathBuf,
+    /// What the branch was started from, when terris created it.
+    pub base: Option<String>,
+    pub ephemeral: bool,
+    /// Set with `--description` when the worktree was created.
+    pub description: Option<String>,
+    /// `TERRIS_PORT*` variables for ports reserved with `terris port`.
+    pub ports: Vec<(String, String)>,
+}
+
+impl WorktreeEnv {
+    /// Describe the worktree at `path`; `root` is any worktree of the same
+    /// repository.
+    pub fn new(
+        backend: &dyn Backend,
+        root: &Path,
+        path: &Path,
+        branch: Option<&str>,
+    ) -> Result<Self> {
+        let repo = backend
+            .list_worktrees(root)?
+            .into_iter()
+            .find(|wt| wt.main)
+            .map_or_else(|| root.to_path_buf(), |wt| wt.path);
+        let name = path.file_name().map_or_else(
+            || branch.unwrap_or_default().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let meta = match Metadata::load() {
+            Ok(metadata) => metadata.get(path).cloned().unwrap_or_default(),
+            Err(err) => {
+                tracing::warn!("could not read worktree metadata: {:#}", err);
+                WorktreeMeta::default()
+            }
+        };
+        Ok(Self {
+            name,
+            branch: branch.map(str::to_string),
+            repo,
+            path: path.to_path_buf(),
+            ports: port::env_vars(&meta),
+            base: meta.base,
+            ephemeral: meta.ephemeral,
+            description: meta.description,
+        })
+    }
+
+    /// Name and value of each variable; the optional ones only when set.
+    pub fn vars(&self) -> Vec<(String, String)> {
+        let mut vars = vec![("TERRIS_WORKTREE".to_string(), self.name.clone())];
+        let optional = [
+            ("TERRIS_BRANCH", &self.branch),
+            ("TERRIS_BASE", &self.base),
+            ("TERRIS_DESCRIPTION", &self.description),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                vars.push((key.to_string(), value.clone()));
+            }
+        }
+        let paths = [("TERRIS_REPO", &self.repo), ("TERRIS_PATH", &self.path)];
+        for (key, path) in paths {
+            vars.push((key.to_string(), path.to_string_lossy().into_owned()));
+        }
+        if self.ephemeral {
+            vars.push(("TERRIS_EPHEMERAL".to_string(), "1".to_string()));
+        }
+        vars.extend(self.ports.iter().cloned());
+        vars
+    }
+
+    /// Set the variables on `command`, clearing optional ones inherited from
+    /// a shell that is itself inside a worktree.
+    pub fn apply(&self, command: &mut Command) {
+        for key in OPTIONAL {
+            command.env_remove(key);
+        }
+        command.envs(self.vars());
+    }
+
+    /// The same context as one JSON object, for hooks to read from stdin.
+    pub fn to_json(&self, hook: &str) -> serde_json::Value {
+        serde_json::json!({
+            "hook": hook,
+            "worktree": self.name,
+            "branch": self.branch,
+            "base": self.base,
+            "repo": self.repo,
+            "path": self.path,
+            "ephemeral": self.ephemeral,
+            "description": self.description,
+            "ports": self.ports.iter().cloned().collect::<BTreeMap<_, _>>(),
+        })
+    }
+}
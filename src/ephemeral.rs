@@ -0,0 +1,153 @@
+//! Throwaway worktrees: `terris create --ephemeral <name>` opens a shell in a
+//! new worktree and removes it, with its branch, when the shell exits.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::env::WorktreeEnv;
+use crate::error::Error;
+use crate::metadata::WorktreeMeta;
+use crate::{
+    RemoveOptions, Worktree, add_new_worktree_with, current_worktree, find_worktree_by_branch,
+    remove_checked, repo_root, worktree_branch_short, worktree_name,
+};
+
+pub fn cmd_create_ephemeral(
+    backend: &dyn Backend,
+    name: &str,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    if let Some(wt) = find_worktree_by_branch(name, &worktrees)? {
+        // Never adopt an existing worktree: it would be deleted on exit.
+        return Err(Error::WorktreeExists {
+            branch: name.to_string(),
+            path: wt.path.clone(),
+        }
+        .into());
+    }
+
+    let owns_branch = !backend.branch_exists(&root, name)?;
+    let start = if owns_branch {
+        let cwd = std::env::current_dir().context("read current directory")?;
+        let head = current_worktree(&worktrees, &cwd)
+            .and_then(|wt| wt.head.clone())
+            .context("cannot tell which commit to start the branch from")?;
+        Some(head)
+    } else {
+        None
+    };
+    let pid = std::process::id();
+    let seed = WorktreeMeta {
+        ephemeral: true,
+        owns_branch,
+        pid: Some(pid),
+        ..WorktreeMeta::default()
+    };
+    let path = add_new_worktree_with(
+        backend,
+        &root,
+        name,
+        start.as_deref(),
+        seed,
+        config,
+        dry_run,
+    )?;
+    if dry_run {
+        println!(
+            "Would start a shell in {} and remove it on exit",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let shell = std::env::var_os("SHELL").unwrap_or_else(|| "/bin/sh".into());
+    eprintln!(
+        "Entering ephemeral worktree {}; it is removed when this shell exits.",
+        path.display()
+    );
+    let mut child = Command::new(&shell);
+    child.current_dir(&path).env("TERRIS_EPHEMERAL", "1");
+    WorktreeEnv::new(backend, &root, &path, Some(name))?.apply(&mut child);
+    let status = child
+        .status()
+        .with_context(|| format!("start shell '{}'", shell.to_string_lossy()));
+    if let Err(err) = &status {
+        eprintln!("Error: {:#}", err);
+    }
+
+    let worktrees = backend.list_worktrees(&root)?;
+    let Some(wt) = worktrees.iter().find(|wt| wt.path == path) else {
+        return Ok(());
+    };
+    let meta = WorktreeMeta {
+        ephemeral: true,
+        owns_branch,
+        ..WorktreeMeta::default()
+    };
+    let opts = RemoveOptions {
+        hook: config.hook,
+        pre_delete: &config.hooks.pre_delete,
+        ..RemoveOptions::default()
+    };
+    retire(backend, &root, wt, &meta, &opts)?;
+    status.map(|_| ())
+}
+
+/// Remove an ephemeral worktree, and its branch when terris created it and
+/// it holds no commits of its own; one that does is kept, so committed work
+/// is never lost. Uncommitted changes still go through the usual
+/// confirmation.
+pub fn retire(
+    backend: &dyn Backend,
+    root: &Path,
+    wt: &Worktree,
+    meta: &WorktreeMeta,
+    opts: &RemoveOptions,
+) -> Result<()> {
+    let name = worktree_name(wt);
+    if !remove_checked(backend, root, wt, opts)? {
+        return Ok(());
+    }
+    if meta.owns_branch
+        && let Some(branch) = worktree_branch_short(wt)
+    {
+        match backend.unique_commits(root, branch)? {
+            0 => {
+                let tip = backend.resolve_rev(root, branch)?;
+                backend.delete_branch(root, branch, true)?;
+                if !opts.dry_run {
+                    eprintln!("Deleted branch {} (was {})", branch, short(&tip));
+                }
+            }
+            commits => eprintln!(
+                "Kept branch {}: it has {} commit(s) no other branch has",
+                branch, commits
+            ),
+        }
+    }
+    if !opts.dry_run {
+        eprintln!("Removed ephemeral worktree {}", name);
+    }
+    Ok(())
+}
+
+/// The abbreviated form of a commit id, as git prints it.
+fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
+
+/// Whether the process that opened an ephemeral worktree is still running.
+pub fn is_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
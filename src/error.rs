@@ -0,0 +1,178 @@
+//! Typed failures and their process exit codes.
+//!
+//! Commands still return `anyhow::Result`; these variants are attached as the
+//! error or as context so `main` can recover the failure kind with
+//! `downcast_ref` and exit with a code scripts can branch on.
+
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("not a {vcs} repository (or any parent)")]
+    NotARepo { vcs: &'static str },
+    #[error("no worktree matches '{0}'")]
+    WorktreeNotFound(String),
+    #[error("'{target}' is ambiguous: {}", candidates.join(", "))]
+    AmbiguousTarget {
+        target: String,
+        candidates: Vec<String>,
+    },
+    #[error("branch '{0}' does not exist")]
+    BranchNotFound(String),
+    /// A git (or jj) command exited unsuccessfully; displays its stderr.
+    #[error("{stderr}")]
+    Git { command: String, stderr: String },
+    #[error("worktree '{}' has uncommitted changes", .0.display())]
+    DirtyWorktree(PathBuf),
+    #[error("branch '{branch}' already has a worktree at '{}'", path.display())]
+    WorktreeExists { branch: String, path: PathBuf },
+    #[error("'{}' is not inside a worktree", .0.display())]
+    NotInWorktree(PathBuf),
+    /// A command terris ran for the user failed; terris exits with its code.
+    #[error("`{command}` exited with code {code}")]
+    CommandFailed { command: String, code: u8 },
+    /// A git command ran past `git-timeout` and was killed.
+    #[error("`{command}` did not finish within {seconds}s and was killed")]
+    GitTimeout { command: String, seconds: u64 },
+    #[error("{feature} requires git >= {required}, but this git is {found}")]
+    GitTooOld {
+        feature: String,
+        required: String,
+        found: String,
+    },
+    /// Another terris held the registry lock for longer than terris waits.
+    #[error("the worktree registry is busy: another terris has held it for {seconds}s")]
+    RegistryBusy { seconds: u64 },
+    /// A command that discards work was given a target that only matched
+    /// by prefix or fuzzily, with no terminal to confirm it on.
+    #[error("'{target}' only partly matches {matched}")]
+    InexactTarget { target: String, matched: String },
+}
+
+impl Error {
+    /// Exit code reported for this failure. `1` is kept for untyped errors
+    /// and `2` is used by clap for usage errors.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::NotARepo { .. } => 3,
+            Error::WorktreeNotFound(_) => 4,
+            Error::AmbiguousTarget { .. } => 5,
+            Error::BranchNotFound(_) => 6,
+            Error::Git { .. } => 7,
+            Error::DirtyWorktree(_) => 8,
+            Error::WorktreeExists { .. } => 9,
+            Error::NotInWorktree(_) => 10,
+            Error::CommandFailed { code, .. } => *code,
+            Error::GitTimeout { .. } => 11,
+            Error::GitTooOld { .. } => 12,
+            Error::RegistryBusy { .. } => 13,
+            Error::InexactTarget { .. } => 14,
+        }
+    }
+
+    /// Stable identifier used in JSON error output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::NotARepo { .. } => "not_a_repo",
+            Error::WorktreeNotFound(_) => "worktree_not_found",
+            Error::AmbiguousTarget { .. } => "ambiguous_target",
+            Error::BranchNotFound(_) => "branch_not_found",
+            Error::Git { .. } => "git_failure",
+            Error::DirtyWorktree(_) => "dirty_worktree",
+            Error::WorktreeExists { .. } => "worktree_exists",
+            Error::NotInWorktree(_) => "not_in_worktree",
+            Error::CommandFailed { .. } => "command_failed",
+            Error::GitTimeout { .. } => "git_timeout",
+            Error::GitTooOld { .. } => "git_too_old",
+            Error::RegistryBusy { .. } => "registry_busy",
+            Error::InexactTarget { .. } => "inexact_target",
+        }
+    }
+
+    /// A suggested next step, when there is an obvious one.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Error::NotARepo { .. } => Some("run terris from inside a repository".into()),
+            Error::WorktreeNotFound(_) => Some("run `terris` to list worktrees".into()),
+            Error::AmbiguousTarget { .. } => {
+                Some("use more of the name, or the worktree's ID from the `terris` list".into())
+            }
+            Error::BranchNotFound(name) => Some(format!("create it with `git branch {}`", name)),
+            Error::Git { .. } => None,
+            Error::DirtyWorktree(_) => Some(
+                "commit or stash the changes, or remove with `git worktree remove --force`".into(),
+            ),
+            Error::WorktreeExists { branch, .. } => Some(format!(
+                "`terris path {}` prints its path; `--on-collision suffix` creates a new branch",
+                branch
+            )),
+            Error::NotInWorktree(_) => Some("run `terris` to list worktrees".into()),
+            Error::CommandFailed { .. } => None,
+            Error::GitTimeout { .. } => Some(
+                "look for a credential prompt or an unresponsive network mount, or raise `git-timeout` in the config".into(),
+            ),
+            Error::GitTooOld { .. } => {
+                Some("upgrade git, or point TERRIS_GIT at a newer one".into())
+            }
+            Error::RegistryBusy { .. } => Some(
+                "try again once the other terris (creating or removing worktrees) is done".into(),
+            ),
+            Error::InexactTarget { matched, .. } => Some(format!(
+                "name it in full, as `{}`, or by its ID from the `terris` list",
+                matched
+            )),
+        }
+    }
+}
+
+/// Exit code for an arbitrary error, looking through attached context.
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    err.downcast_ref::<Error>().map_or(1, Error::exit_code)
+}
+
+/// Whether errors should be reported as JSON: `--json` or `TERRIS_OUTPUT=json`.
+pub fn json_requested(flag: bool) -> bool {
+    flag || std::env::var("TERRIS_OUTPUT").is_ok_and(|v| v.eq_ignore_ascii_case("json"))
+}
+
+/// Render `err` as a single-line JSON object for tooling that wraps terris.
+pub fn to_json(err: &anyhow::Error) -> String {
+    let typed = err.downcast_ref::<Error>();
+    let context: Vec<String> = err.chain().skip(1).map(|e| e.to_string()).collect();
+    serde_json::json!({
+        "code": typed.map_or("error", Error::kind),
+        "exit_code": exit_code(err),
+        "message": err.to_string(),
+        "context": context,
+        "hint": typed.and_then(Error::hint),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn exit_code_sees_through_context() {
+        let err = Err::<(), _>(Error::BranchNotFound("x".into()))
+            .context("create worktree 'x'")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), 6);
+        assert_eq!(exit_code(&anyhow::anyhow!("untyped")), 1);
+    }
+
+    #[test]
+    fn to_json_includes_code_context_and_hint() {
+        let err = Err::<(), _>(Error::BranchNotFound("x".into()))
+            .context("create worktree 'x'")
+            .unwrap_err();
+        let value: serde_json::Value = serde_json::from_str(&to_json(&err)).unwrap();
+        assert_eq!(value["code"], "branch_not_found");
+        assert_eq!(value["exit_code"], 6);
+        assert_eq!(value["message"], "create worktree 'x'");
+        assert_eq!(value["context"][0], "branch 'x' does not exist");
+        assert_eq!(value["hint"], "create it with `git branch x`");
+    }
+}
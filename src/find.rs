@@ -0,0 +1,92 @@
+//! `terris find`: which worktree holds the commit that did something, by
+//! searching the messages of every branch's unmerged commits.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use serde::Serialize;
+
+use crate::backend::{Backend, Commit};
+use crate::{align_rows, repo_root, worktree_branch_short, worktree_name};
+
+#[derive(Args)]
+pub struct FindArgs {
+    /// Extended regular expression to match commit messages against
+    #[arg(long, value_name = "regex")]
+    message: String,
+    /// Match regardless of case
+    #[arg(short = 'i', long)]
+    ignore_case: bool,
+    /// Leave out commits already on this branch [default: the primary worktree's branch]
+    #[arg(long, value_name = "branch")]
+    base: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Match<'a> {
+    worktree: String,
+    path: &'a Path,
+    branch: &'a str,
+    commit: Commit,
+}
+
+pub fn cmd_find(backend: &dyn Backend, args: &FindArgs, json: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let base = match &args.base {
+        Some(base) => base.clone(),
+        None => worktrees
+            .iter()
+            .find(|wt| wt.main)
+            .and_then(worktree_branch_short)
+            .context("the primary worktree has no branch; pass `--base <branch>`")?
+            .to_string(),
+    };
+
+    let mut matches = Vec::new();
+    for wt in &worktrees {
+        let Some(branch) = worktree_branch_short(wt) else {
+            continue;
+        };
+        if branch == base {
+            continue;
+        }
+        let commits = backend
+            .search_commits(&root, branch, &base, &args.message, args.ignore_case)
+            .with_context(|| format!("search the commits of '{}'", branch))?;
+        matches.extend(commits.into_iter().map(|commit| Match {
+            worktree: worktree_name(wt),
+            path: &wt.path,
+            branch,
+            commit,
+        }));
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&matches)?);
+        return Ok(());
+    }
+    if matches.is_empty() {
+        bail!(
+            "no commit outside '{}' has a message matching '{}'",
+            base,
+            args.message
+        );
+    }
+    let rows: Vec<[String; 4]> = matches
+        .iter()
+        .map(|m| {
+            [
+                m.worktree.clone(),
+                m.branch.to_string(),
+                m.commit.id.chars().take(10).collect(),
+                m.commit.summary.clone(),
+            ]
+        })
+        .collect();
+    for line in align_rows(&rows) {
+        println!("{}", line);
+    }
+    Ok(())
+}
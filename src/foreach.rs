@@ -0,0 +1,273 @@
+//! `terris foreach -- <cmd...>`: run one command in every worktree in parallel,
+//! each line of output prefixed with the worktree it came from, the way
+//! docker-compose labels its services.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+
+use crate::backend::Backend;
+use crate::color::Paint;
+use crate::config::Config;
+use crate::env::WorktreeEnv;
+use crate::jobs;
+use crate::table::{self, Align};
+use crate::{Worktree, repo_root, worktree_branch_short, worktree_name};
+
+/// How often a running command is checked for exit or cancellation.
+const POLL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ForeachConfig {
+    /// How output is shown when `--output` is not given.
+    pub output: ForeachOutput,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ForeachOutput {
+    /// Print each line as soon as it is written
+    #[default]
+    Stream,
+    /// Print a worktree's output together once its command exits
+    Buffer,
+}
+
+#[derive(Args)]
+pub struct ForeachArgs {
+    /// Only run in worktrees whose branch matches a glob, e.g. 'feature/*'
+    #[arg(long, value_name = "glob")]
+    filter: Option<glob::Pattern>,
+    /// Interleave lines as they come, or keep each worktree's together
+    #[arg(long, value_enum, value_name = "mode")]
+    output: Option<ForeachOutput>,
+    /// Stop the other commands as soon as one fails
+    #[arg(long)]
+    fail_fast: bool,
+    /// Command to run in each worktree
+    #[arg(last = true, required = true, value_name = "cmd")]
+    command: Vec<String>,
+}
+
+/// How a worktree's command ended.
+enum Outcome {
+    Succeeded,
+    Failed(Option<i32>),
+    Canceled,
+}
+
+pub fn cmd_foreach(
+    backend: &dyn Backend,
+    args: &ForeachArgs,
+    config: &Config,
+    paint: Paint,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let selected: Vec<&Worktree> = worktrees
+        .iter()
+        .filter(|wt| wt.prunable.is_none() && wt.path.is_dir())
+        .filter(|wt| {
+            args.filter.as_ref().is_none_or(|filter| {
+                worktree_branch_short(wt).is_some_and(|branch| filter.matches(branch))
+            })
+        })
+        .collect();
+    let command = args.command.join(" ");
+    if dry_run {
+        for wt in &selected {
+            println!("Would run `{}` in {}", command, wt.path.display());
+        }
+        return Ok(());
+    }
+
+    let mut commands = Vec::new();
+    for wt in &selected {
+        let mut child = Command::new(&args.command[0]);
+        child.args(&args.command[1..]).current_dir(&wt.path);
+        match WorktreeEnv::new(backend, &root, &wt.path, worktree_branch_short(wt)) {
+            Ok(env) => env.apply(&mut child),
+            Err(err) => tracing::warn!("could not describe worktree: {:#}", err),
+        }
+        commands.push((worktree_name(wt), child));
+    }
+    let mode = args.output.unwrap_or(config.foreach.output);
+    run_all(commands, &command, mode, args.fail_fast, paint)
+}
+
+/// Run each named command at once, prefixing its output with its name, and
+/// fail naming those that did. With `fail_fast`, the first failure stops
+/// the rest.
+pub fn run_all(
+    commands: Vec<(String, Command)>,
+    command: &str,
+    mode: ForeachOutput,
+    fail_fast: bool,
+    paint: Paint,
+) -> Result<()> {
+    let width = commands
+        .iter()
+        .map(|(name, _)| table::width(name))
+        .max()
+        .unwrap_or(0);
+    let mut names = Vec::new();
+    let mut pending = Vec::new();
+    for (i, (name, mut child)) in commands.into_iter().enumerate() {
+        child
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // Its own process group, so canceling it also stops whatever it
+            // started, which would otherwise keep its output open.
+            .process_group(0);
+        let prefix = format!("{} |", table::pad(&name, width, Align::Left));
+        // Taken by whichever thread gets to run it.
+        pending.push((Mutex::new(Some(child)), paint.nth(i, &prefix)));
+        names.push(name);
+    }
+
+    let cancel = AtomicBool::new(false);
+    let outcomes = jobs::map(&pending, |(child, prefix)| {
+        let child = child.lock().expect("command lock poisoned").take();
+        let Some(child) = child.filter(|_| !cancel.load(Ordering::Relaxed)) else {
+            return Ok(Outcome::Canceled);
+        };
+        let outcome = run(child, prefix, mode, &cancel);
+        if fail_fast && !matches!(outcome, Ok(Outcome::Succeeded)) {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        outcome
+    });
+
+    let mut failed = Vec::new();
+    let mut canceled = 0;
+    for (name, outcome) in names.iter().zip(outcomes) {
+        match outcome {
+            Ok(Outcome::Succeeded) => {}
+            Ok(Outcome::Failed(code)) => {
+                let code = code.map_or("a signal".to_string(), |code| format!("code {}", code));
+                eprintln!("{}: `{}` exited with {}", name, command, code);
+                failed.push(name.as_str());
+            }
+            Ok(Outcome::Canceled) => canceled += 1,
+            Err(err) => {
+                eprintln!("{}: {:#}", name, err);
+                failed.push(name.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        let mut message = format!(
+            "`{}` failed in {} of {} worktree(s): {}",
+            command,
+            failed.len(),
+            names.len(),
+            failed.join(", ")
+        );
+        if canceled > 0 {
+            message.push_str(&format!("; {} canceled", canceled));
+        }
+        bail!(message);
+    }
+    Ok(())
+}
+
+/// Run one worktree's command, copying its output with `prefix` until it
+/// exits or `cancel` is set, in which case it is killed.
+fn run(
+    mut command: Command,
+    prefix: &str,
+    mode: ForeachOutput,
+    cancel: &AtomicBool,
+) -> Result<Outcome> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("run `{}`", program))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    // Buffered lines in the order they arrived, `true` for stderr.
+    let buffer = Arc::new(Mutex::new(Vec::<(bool, String)>::new()));
+    let outcome = std::thread::scope(|scope| {
+        scope.spawn(|| copy_lines(stdout, false, prefix, mode, &buffer));
+        scope.spawn(|| copy_lines(stderr, true, prefix, mode, &buffer));
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(if status.success() {
+                    Outcome::Succeeded
+                } else {
+                    Outcome::Failed(status.code())
+                });
+            }
+            if cancel.load(Ordering::Relaxed) {
+                // It may have exited meanwhile; either way it is done.
+                let _ = Command::new("kill")
+                    .args(["-KILL", "--", &format!("-{}", child.id())])
+                    .stderr(Stdio::null())
+                    .status();
+                let _ = child.kill();
+                child.wait()?;
+                return Ok(Outcome::Canceled);
+            }
+            std::thread::sleep(POLL);
+        }
+    });
+
+    let lines = buffer.lock().expect("buffer lock poisoned");
+    if !lines.is_empty() {
+        let mut out = std::io::stdout().lock();
+        let mut err = std::io::stderr().lock();
+        for (is_err, line) in lines.iter() {
+            if *is_err {
+                let _ = writeln!(err, "{} {}", prefix, line);
+            } else {
+                let _ = writeln!(out, "{} {}", prefix, line);
+            }
+        }
+    }
+    outcome
+}
+
+/// Copy each line of `pipe` to stdout or stderr after `prefix`, or into
+/// `buffer` to print once the command is done.
+fn copy_lines(
+    pipe: impl Read,
+    is_err: bool,
+    prefix: &str,
+    mode: ForeachOutput,
+    buffer: &Mutex<Vec<(bool, String)>>,
+) {
+    let mut reader = BufReader::new(pipe);
+    let mut bytes = Vec::new();
+    loop {
+        bytes.clear();
+        match reader.read_until(b'\n', &mut bytes) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let line = String::from_utf8_lossy(&bytes);
+        let line = line.trim_end_matches(['\n', '\r']);
+        match mode {
+            ForeachOutput::Buffer => buffer
+                .lock()
+                .expect("buffer lock poisoned")
+                .push((is_err, line.to_string())),
+            // One write per line, so lines from different worktrees never mix.
+            ForeachOutput::Stream if is_err => {
+                let _ = writeln!(std::io::stderr().lock(), "{} {}", prefix, line);
+            }
+            ForeachOutput::Stream => {
+                let _ = writeln!(std::io::stdout().lock(), "{} {}", prefix, line);
+            }
+        }
+    }
+}
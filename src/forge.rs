@@ -0,0 +1,473 @@
+//! What the forge hosting a repository knows about its branches, asked of
+//! `gh` for GitHub and `glab` for GitLab, so both handle authentication.
+//! Answers are cached, CI statuses for a couple of minutes and pull
+//! requests for ten in the metadata store, since each one costs a network
+//! round trip.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::cache::Cache;
+use crate::jobs;
+use crate::lock;
+use crate::metadata::Metadata;
+use crate::{Worktree, unix_now, worktree_branch_short};
+
+/// How long a CI status is reused before the forge is asked again.
+const CI_TTL: i64 = 120;
+
+/// How long a pull request lookup is reused before the forge is asked again.
+const PR_TTL: i64 = 600;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ForgeConfig {
+    /// The forge, for hosts whose name mentions neither GitHub nor GitLab.
+    pub kind: Option<Forge>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Forge {
+    GitHub,
+    GitLab,
+}
+
+impl Forge {
+    /// The forge hosting the repository at `root`: the configured one, or
+    /// the one named in the host of `origin`'s URL.
+    pub fn detect(backend: &dyn Backend, root: &Path, config: &ForgeConfig) -> Option<Self> {
+        if let Some(kind) = config.kind {
+            return Some(kind);
+        }
+        let url = backend.remote_url(root, "origin").ok()??.to_lowercase();
+        if url.contains("github") {
+            Some(Forge::GitHub)
+        } else if url.contains("gitlab") {
+            Some(Forge::GitLab)
+        } else {
+            None
+        }
+    }
+}
+
+/// The outcome of the latest CI run on a branch.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CiStatus {
+    Pending,
+    Passed,
+    Failed,
+    Canceled,
+}
+
+impl fmt::Display for CiStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            CiStatus::Pending => "pending",
+            CiStatus::Passed => "passed",
+            CiStatus::Failed => "failed",
+            CiStatus::Canceled => "canceled",
+        };
+        f.write_str(text)
+    }
+}
+
+/// A remembered answer; `None` when the branch has no runs or the forge
+/// could not be asked.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CiEntry {
+    pub expires: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<CiStatus>,
+}
+
+/// The latest CI status of each of `branches` in the repository at `root`,
+/// from the cache where it is current and otherwise asked of the forge,
+/// all branches at once.
+pub fn ci_statuses(forge: Forge, root: &Path, branches: &[&str]) -> HashMap<String, CiStatus> {
+    let mut cache = crate::cache::load();
+    let now = unix_now();
+    let known = cache.ci.entry(root.to_path_buf()).or_default();
+    let missing: Vec<&str> = branches
+        .iter()
+        .copied()
+        .filter(|branch| known.get(*branch).is_none_or(|entry| entry.expires <= now))
+        .collect();
+    let fetched = jobs::map(&missing, |&branch| {
+        let status = ci_status(forge, root, branch).unwrap_or_else(|err| {
+            tracing::warn!("could not read CI status of '{}': {:#}", branch, err);
+            None
+        });
+        (branch, status)
+    });
+    for (branch, status) in fetched {
+        known.insert(
+            branch.to_string(),
+            CiEntry {
+                expires: now + CI_TTL,
+                status,
+            },
+        );
+    }
+    known.retain(|_, entry| entry.expires > now);
+    let statuses = branches
+        .iter()
+        .filter_map(|&branch| Some((branch.to_string(), known.get(branch)?.status?)))
+        .collect();
+    save(&cache);
+    statuses
+}
+
+fn save(cache: &Cache) {
+    if let Err(err) = cache.save() {
+        tracing::warn!("could not save status cache: {:#}", err);
+    }
+}
+
+/// Where a pull or merge request stands with its reviewers.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Review {
+    Approved,
+    ChangesRequested,
+    Required,
+}
+
+/// An open pull request on GitHub, or merge request on GitLab.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PullRequest {
+    pub number: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub draft: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<Review>,
+    pub url: String,
+}
+
+impl fmt::Display for PullRequest {
+    /// `#12 open (approved)`, or `#12 draft` without a review decision.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.draft { "draft" } else { "open" };
+        write!(f, "#{} {}", self.number, state)?;
+        match self.review {
+            Some(Review::Approved) => f.write_str(" (approved)"),
+            Some(Review::ChangesRequested) => f.write_str(" (changes requested)"),
+            Some(Review::Required) => f.write_str(" (review required)"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A branch's open pull request as last looked up, kept in the metadata
+/// store; `found` is `None` when the branch had none.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PullRequestLookup {
+    pub branch: String,
+    pub checked: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub found: Option<PullRequest>,
+}
+
+/// The open pull request of each of `worktrees`' branches in the repository
+/// at `root`, keyed by worktree path: from the metadata store where the
+/// lookup is recent and for the same branch, otherwise asked of the forge,
+/// all branches at once.
+pub fn pull_requests(
+    forge: Forge,
+    root: &Path,
+    worktrees: &[Worktree],
+) -> HashMap<PathBuf, PullRequest> {
+    let mut metadata = Metadata::load().unwrap_or_else(|err| {
+        tracing::warn!("could not read worktree metadata: {:#}", err);
+        Metadata::default()
+    });
+    let now = unix_now();
+    let missing: Vec<(&Path, &str)> = worktrees
+        .iter()
+        .filter_map(|wt| Some((wt.path.as_path(), worktree_branch_short(wt)?)))
+        .filter(|(path, branch)| {
+            let lookup = metadata
+                .get(path)
+                .and_then(|meta| meta.pull_request.as_ref());
+            lookup.is_none_or(|lookup| lookup.branch != *branch || lookup.checked + PR_TTL <= now)
+        })
+        .collect();
+    let fetched = jobs::map(&missing, |&(path, branch)| {
+        let found = pull_request(forge, root, branch).unwrap_or_else(|err| {
+            tracing::warn!("could not look up pull request of '{}': {:#}", branch, err);
+            None
+        });
+        (path, branch, found)
+    });
+    if !fetched.is_empty() {
+        let record = |metadata: &mut Metadata| {
+            for (path, branch, found) in &fetched {
+                metadata
+                    .worktrees
+                    .entry(path.to_path_buf())
+                    .or_default()
+                    .pull_request = Some(PullRequestLookup {
+                    branch: branch.to_string(),
+                    checked: now,
+                    found: found.clone(),
+                });
+            }
+        };
+        record(&mut metadata);
+        // Reread under the lock, so lookups made meanwhile are not lost, and
+        // leave a store that cannot be read as it is.
+        let saved = lock::registry().and_then(|_lock| {
+            let mut current = Metadata::load()?;
+            record(&mut current);
+            current.save()
+        });
+        if let Err(err) = saved {
+            tracing::warn!("could not save pull requests: {:#}", err);
+        }
+    }
+    worktrees
+        .iter()
+        .filter_map(|wt| {
+            let branch = worktree_branch_short(wt)?;
+            let lookup = metadata.get(&wt.path)?.pull_request.as_ref()?;
+            let found = lookup.found.clone().filter(|_| lookup.branch == branch)?;
+            Some((wt.path.clone(), found))
+        })
+        .collect()
+}
+
+/// Ask the forge for the open pull request whose source is `branch`.
+fn pull_request(forge: Forge, root: &Path, branch: &str) -> Result<Option<PullRequest>> {
+    match forge {
+        Forge::GitHub => {
+            let output = run(
+                "gh",
+                &[
+                    "pr",
+                    "list",
+                    "--head",
+                    branch,
+                    "--limit",
+                    "1",
+                    "--json",
+                    "number,isDraft,reviewDecision,url",
+                ],
+                root,
+            )?;
+            parse_github_prs(&output)
+        }
+        Forge::GitLab => {
+            let endpoint = format!(
+                "projects/:id/merge_requests?source_branch={}&state=opened&per_page=1",
+                percent_encode(branch)
+            );
+            let output = run("glab", &["api", &endpoint], root)?;
+            parse_gitlab_mrs(&output)
+        }
+    }
+}
+
+/// Ask the forge for the latest CI run on `branch`.
+fn ci_status(forge: Forge, root: &Path, branch: &str) -> Result<Option<CiStatus>> {
+    match forge {
+        Forge::GitHub => {
+            let output = run(
+                "gh",
+                &[
+                    "run",
+                    "list",
+                    "--branch",
+                    branch,
+                    "--limit",
+                    "1",
+                    "--json",
+                    "status,conclusion",
+                ],
+                root,
+            )?;
+            parse_github_runs(&output)
+        }
+        Forge::GitLab => {
+            let endpoint = format!(
+                "projects/:id/pipelines?ref={}&per_page=1",
+                percent_encode(branch)
+            );
+            let output = run("glab", &["api", &endpoint], root)?;
+            parse_gitlab_pipelines(&output)
+        }
+    }
+}
+
+fn run(program: &str, args: &[&str], root: &Path) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(root)
+        .output()
+        .with_context(|| format!("run `{}`", program))?;
+    if !output.status.success() {
+        bail!(
+            "`{} {}` failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[derive(Deserialize)]
+struct GitHubRun {
+    status: String,
+    #[serde(default)]
+    conclusion: Option<String>,
+}
+
+/// `gh run list --json status,conclusion` output, newest run first.
+fn parse_github_runs(output: &str) -> Result<Option<CiStatus>> {
+    let runs: Vec<GitHubRun> = serde_json::from_str(output).context("parse `gh` output")?;
+    Ok(runs.first().map(|run| {
+        if run.status != "completed" {
+            return CiStatus::Pending;
+        }
+        match run.conclusion.as_deref() {
+            Some("success" | "neutral" | "skipped") => CiStatus::Passed,
+            Some("cancelled") => CiStatus::Canceled,
+            _ => CiStatus::Failed,
+        }
+    }))
+}
+
+#[derive(Deserialize)]
+struct GitLabPipeline {
+    status: String,
+}
+
+/// GitLab's pipelines API output, newest pipeline first.
+fn parse_gitlab_pipelines(output: &str) -> Result<Option<CiStatus>> {
+    let pipelines: Vec<GitLabPipeline> =
+        serde_json::from_str(output).context("parse `glab` output")?;
+    Ok(pipelines
+        .first()
+        .map(|pipeline| match pipeline.status.as_str() {
+            "success" => CiStatus::Passed,
+            "failed" => CiStatus::Failed,
+            "canceled" | "skipped" => CiStatus::Canceled,
+            _ => CiStatus::Pending,
+        }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GitHubPr {
+    number: u64,
+    #[serde(default)]
+    is_draft: bool,
+    #[serde(default)]
+    review_decision: Option<String>,
+    url: String,
+}
+
+/// `gh pr list --json number,isDraft,reviewDecision,url` output.
+fn parse_github_prs(output: &str) -> Result<Option<PullRequest>> {
+    let prs: Vec<GitHubPr> = serde_json::from_str(output).context("parse `gh` output")?;
+    Ok(prs.into_iter().next().map(|pr| PullRequest {
+        number: pr.number,
+        draft: pr.is_draft,
+        review: match pr.review_decision.as_deref() {
+            Some("APPROVED") => Some(Review::Approved),
+            Some("CHANGES_REQUESTED") => Some(Review::ChangesRequested),
+            Some("REVIEW_REQUIRED") => Some(Review::Required),
+            _ => None,
+        },
+        url: pr.url,
+    }))
+}
+
+#[derive(Deserialize)]
+struct GitLabMr {
+    iid: u64,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    detailed_merge_status: Option<String>,
+    web_url: String,
+}
+
+/// GitLab's merge requests API output. Its merge status tells when
+/// approvals or changes are still wanted, but not whether reviewers
+/// approved, so approval is never reported.
+fn parse_gitlab_mrs(output: &str) -> Result<Option<PullRequest>> {
+    let mrs: Vec<GitLabMr> = serde_json::from_str(output).context("parse `glab` output")?;
+    Ok(mrs.into_iter().next().map(|mr| PullRequest {
+        number: mr.iid,
+        draft: mr.draft,
+        review: match mr.detailed_merge_status.as_deref() {
+            Some("requested_changes") => Some(Review::ChangesRequested),
+            Some("not_approved") => Some(Review::Required),
+            _ => None,
+        },
+        url: mr.web_url,
+    }))
+}
+
+/// Escape `text` for a URL query value.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ci_status_is_read_from_either_forge() {
+        let github = |json| parse_github_runs(json).unwrap();
+        assert_eq!(github("[]"), None);
+        assert_eq!(
+            github(r#"[{"status":"in_progress","conclusion":""}]"#),
+            Some(CiStatus::Pending)
+        );
+        assert_eq!(
+            github(r#"[{"status":"completed","conclusion":"failure"}]"#),
+            Some(CiStatus::Failed)
+        );
+        let gitlab = |json| parse_gitlab_pipelines(json).unwrap();
+        assert_eq!(
+            gitlab(r#"[{"id":1,"status":"success"}]"#),
+            Some(CiStatus::Passed)
+        );
+        assert_eq!(gitlab(r#"[{"status":"running"}]"#), Some(CiStatus::Pending));
+        assert_eq!(percent_encode("fix/a b"), "fix%2Fa%20b");
+    }
+
+    #[test]
+    fn pull_requests_are_read_from_either_forge() {
+        assert_eq!(parse_github_prs("[]").unwrap(), None);
+        let pr = parse_github_prs(
+            r#"[{"number":12,"isDraft":false,"reviewDecision":"APPROVED","url":"https://github.com/o/r/pull/12"}]"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(pr.to_string(), "#12 open (approved)");
+        let mr = parse_gitlab_mrs(
+            r#"[{"iid":3,"draft":true,"detailed_merge_status":"not_approved","web_url":"https://gitlab.com/o/r/-/merge_requests/3"}]"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(mr.to_string(), "#3 draft (review required)");
+    }
+}
@@ -0,0 +1,378 @@
+//! `terris gc`: remove worktrees terris created for short-lived use and
+//! that were left behind, those older than their [`retention`] limit, and
+//! any others the `[gc] policy` script picks. It also offers to delete
+//! directories in the registry that are no longer any repository's worktree.
+//!
+//! [`retention`]: crate::retention
+
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::ephemeral;
+use crate::lock;
+use crate::metadata::{Metadata, WorktreeMeta};
+use crate::notify;
+use crate::retention::{Age, Retention, Rule};
+use crate::script::Script;
+use crate::{
+    RemoveOptions, Worktree, registry_base_dir, remove_checked, repo_name, repo_root, unix_now,
+    worktree_branch_short, worktree_name,
+};
+
+#[derive(Args)]
+pub struct GcArgs {
+    /// Delete orphaned directories in the registry without asking
+    #[arg(long)]
+    remove_orphans: bool,
+}
+
+pub fn cmd_gc(backend: &dyn Backend, args: &GcArgs, config: &Config, dry_run: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let collected = collect(backend, &root, config, dry_run)?;
+    if collected + sweep_orphans(config, args.remove_orphans, dry_run)? == 0 {
+        println!("Nothing to collect.");
+    }
+    if config.notify.enabled {
+        let worktrees = backend.list_worktrees(&root)?;
+        let attention = notify::survey(backend, &root, &worktrees, config)?;
+        if !attention.is_empty() {
+            notify::send(&root, &attention, dry_run);
+        }
+    }
+    Ok(())
+}
+
+/// Remove every worktree gc is responsible for, reporting failures as they
+/// happen. Returns how many were collected.
+pub fn collect(
+    backend: &dyn Backend,
+    root: &Path,
+    config: &Config,
+    dry_run: bool,
+) -> Result<usize> {
+    let worktrees = backend.list_worktrees(root)?;
+    let metadata = Metadata::load()?;
+    let policy = config.gc.policy.as_deref().map(Script::load).transpose()?;
+    let retention = config.retention(&repo_name(root));
+    let base = worktrees
+        .iter()
+        .find(|wt| wt.main)
+        .and_then(worktree_branch_short);
+    let opts = RemoveOptions {
+        dry_run,
+        hook: config.hook,
+        pre_delete: &config.hooks.pre_delete,
+        ..RemoveOptions::default()
+    };
+
+    let mut collected = 0;
+    let mut failed = 0;
+    for wt in worktrees.iter().filter(|wt| !wt.main) {
+        let meta = metadata.get(&wt.path);
+        let ephemeral = meta.filter(|meta| meta.ephemeral);
+        let removed = if let Some(meta) = ephemeral
+            && !meta.pid.is_some_and(ephemeral::is_alive)
+        {
+            ephemeral::retire(backend, root, wt, meta, &opts).map(|()| true)
+        } else if let Some((rule, age)) = over_limit(retention, wt, meta)? {
+            expire(backend, root, wt, meta, &rule, age, &opts)
+        } else if let Some(policy) = &policy
+            && ephemeral.is_none()
+        {
+            apply_policy(backend, root, wt, meta, base, policy, &opts)
+        } else {
+            continue;
+        };
+        match removed {
+            Ok(true) => collected += 1,
+            Ok(false) => {}
+            Err(err) => {
+                eprintln!("Error: gc '{}': {:#}", worktree_name(wt), err);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("failed to collect {} worktree(s)", failed);
+    }
+    Ok(collected)
+}
+
+/// List the orphaned directories in the registry, and in the directory of
+/// each repository with a `base-dir`, and remove them if `remove` is set or
+/// the user agrees. Returns how many were removed.
+fn sweep_orphans(config: &Config, remove: bool, dry_run: bool) -> Result<usize> {
+    // Worktrees being created hold the lock until git knows about them.
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(lock::registry()?)
+    };
+    let mut orphans = orphans(&registry_base_dir()?);
+    for (name, repo) in &config.repos {
+        if let Some(base) = &repo.base_dir {
+            orphans.extend(self::orphans(&base.join(name)));
+        }
+    }
+    if orphans.is_empty() {
+        return Ok(0);
+    }
+    if dry_run {
+        for path in &orphans {
+            println!("Would remove {} (orphaned)", path.display());
+        }
+        return Ok(orphans.len());
+    }
+    eprintln!("These directories in the registry are no longer a worktree of any repository:");
+    for path in &orphans {
+        eprintln!("  {}", path.display());
+    }
+    let remove = remove
+        || (std::io::stdin().is_terminal()
+            && dialoguer::Confirm::new()
+                .with_prompt("Remove them?")
+                .default(false)
+                .interact()
+                .context("confirm removing orphaned directories")?);
+    if !remove {
+        eprintln!("Kept them; pass `--remove-orphans` to remove them without asking.");
+        return Ok(0);
+    }
+    let mut removed = 0;
+    let mut failed = 0;
+    for path in &orphans {
+        match std::fs::remove_dir_all(path) {
+            Ok(()) => {
+                eprintln!("Removed {} (orphaned)", path.display());
+                removed += 1;
+            }
+            Err(err) => {
+                eprintln!("Error: remove '{}': {}", path.display(), err);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("failed to remove {} orphaned directory(ies)", failed);
+    }
+    Ok(removed)
+}
+
+/// The directories under the registry `base` that hold files but no
+/// worktree git still knows about, sorted.
+fn orphans(base: &Path) -> Vec<PathBuf> {
+    let mut orphans = Vec::new();
+    // The registry's own files, like the metadata, are not leftovers.
+    let _ = scan_entries(base, &mut orphans);
+    orphans.sort();
+    orphans
+}
+
+/// What a directory in the registry holds.
+#[derive(Debug, PartialEq)]
+enum Contents {
+    /// A worktree, or something terris did not make; it stays.
+    Live,
+    /// Nothing but empty directories.
+    Empty,
+    /// Files, none of them in a registered worktree.
+    Orphaned,
+}
+
+/// What `dir` holds, adding the orphaned directories inside it to
+/// `orphans` when it stays.
+fn scan(dir: &Path, orphans: &mut Vec<PathBuf>) -> Contents {
+    let dot_git = dir.join(".git");
+    if dot_git.is_file() {
+        return if registered(dir) {
+            Contents::Live
+        } else {
+            Contents::Orphaned
+        };
+    }
+    // A clone or a jj workspace.
+    if dot_git.exists() || dir.join(".jj").exists() {
+        return Contents::Live;
+    }
+    let mut children = Vec::new();
+    let Some((live, files)) = scan_entries(dir, &mut children) else {
+        return Contents::Live;
+    };
+    if live {
+        orphans.extend(children);
+        Contents::Live
+    } else if files || !children.is_empty() {
+        Contents::Orphaned
+    } else {
+        Contents::Empty
+    }
+}
+
+/// Scan each subdirectory of `dir`, collecting the orphaned ones into
+/// `children`. Returns whether any is live and whether `dir` holds anything
+/// but directories, or `None` if it cannot be read.
+fn scan_entries(dir: &Path, children: &mut Vec<PathBuf>) -> Option<(bool, bool)> {
+    let entries = std::fs::read_dir(dir).ok()?;
+    let mut live = false;
+    let mut files = false;
+    for entry in entries.flatten() {
+        if !entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            files = true;
+            continue;
+        }
+        let path = entry.path();
+        match scan(&path, children) {
+            Contents::Live => live = true,
+            Contents::Empty => {}
+            Contents::Orphaned => children.push(path),
+        }
+    }
+    Some((live, files))
+}
+
+/// Whether the checkout at `dir` is still a worktree of its repository: its
+/// `.git` file names an entry in the repository that points back at it. A
+/// `.git` file terris cannot make sense of counts as registered.
+pub(crate) fn registered(dir: &Path) -> bool {
+    let dot_git = dir.join(".git");
+    let Ok(contents) = std::fs::read_to_string(&dot_git) else {
+        return true;
+    };
+    let Some(gitdir) = contents.trim_end().strip_prefix("gitdir: ") else {
+        return true;
+    };
+    let gitdir = dir.join(gitdir);
+    let Ok(back) = std::fs::read_to_string(gitdir.join("gitdir")) else {
+        return false;
+    };
+    let back = gitdir.join(back.trim_end());
+    back.canonicalize().ok() == dot_git.canonicalize().ok()
+}
+
+/// Remove `wt` if the gc policy asks for it; worktrees terris does not
+/// manage, and locked, pooled, and disowned ones, are never offered.
+/// Returns whether it was removed.
+fn apply_policy(
+    backend: &dyn Backend,
+    root: &Path,
+    wt: &Worktree,
+    meta: Option<&WorktreeMeta>,
+    base: Option<&str>,
+    policy: &Script,
+    opts: &RemoveOptions,
+) -> Result<bool> {
+    if wt.locked
+        || !meta.is_some_and(WorktreeMeta::managed)
+        || meta.is_some_and(|meta| meta.pool.is_some() || meta.disowned)
+        || meta.is_some_and(|meta| meta.ephemeral && meta.pid.is_some_and(ephemeral::is_alive))
+    {
+        return Ok(false);
+    }
+    let meta = meta.cloned().unwrap_or_default();
+    let name = worktree_name(wt);
+    let branch = worktree_branch_short(wt);
+    let merged = match (branch, base) {
+        (Some(branch), Some(base)) if branch != base => backend.is_merged(root, branch, base)?,
+        _ => false,
+    };
+    let idle_since = idle_since(wt, Some(&meta));
+    let wt_context = serde_json::json!({
+        "name": name,
+        "branch": branch,
+        "path": wt.path,
+        "detached": wt.detached,
+        "merged": merged,
+        "dirty": backend.is_dirty(&wt.path)?,
+        "idle_days": idle_since.map_or(0, |since| (unix_now() - since) / 86_400),
+        "uses": meta.uses,
+        "base": meta.base,
+        "parent": meta.parent,
+        "description": meta.description,
+    });
+    if !policy.collects(wt_context)? || !remove_checked(backend, root, wt, opts)? {
+        return Ok(false);
+    }
+    if !opts.dry_run {
+        eprintln!("Removed {} (gc policy)", name);
+    }
+    Ok(true)
+}
+
+/// The retention rule `wt` has outgrown, with its age; worktrees terris
+/// does not manage, locked, pooled, disowned, and prunable ones, and
+/// ephemeral ones whose process is still running, have none.
+pub fn over_limit<'r>(
+    retention: &'r Retention,
+    wt: &Worktree,
+    meta: Option<&WorktreeMeta>,
+) -> Result<Option<(Rule<'r>, Age)>> {
+    if retention.is_empty()
+        || !meta.is_some_and(WorktreeMeta::managed)
+        || wt.locked
+        || wt.prunable.is_some()
+        || meta.is_some_and(|meta| meta.pool.is_some() || meta.disowned)
+        || meta.is_some_and(|meta| meta.ephemeral && meta.pid.is_some_and(ephemeral::is_alive))
+    {
+        return Ok(None);
+    }
+    let ephemeral = meta.is_some_and(|meta| meta.ephemeral);
+    let Some(rule) = retention.rule(worktree_branch_short(wt), ephemeral)? else {
+        return Ok(None);
+    };
+    let Some(since) = idle_since(wt, meta) else {
+        return Ok(None);
+    };
+    let age = Age(unix_now() - since);
+    Ok((age > rule.limit).then_some((rule, age)))
+}
+
+/// Remove `wt` for being older than `rule` allows, saying which rule did it.
+/// Returns whether it was removed.
+fn expire(
+    backend: &dyn Backend,
+    root: &Path,
+    wt: &Worktree,
+    meta: Option<&WorktreeMeta>,
+    rule: &Rule,
+    age: Age,
+    opts: &RemoveOptions,
+) -> Result<bool> {
+    let name = worktree_name(wt);
+    let reason = format!(
+        "unused for {}, over the {} limit for {}",
+        age, rule.limit, rule.name
+    );
+    if opts.dry_run {
+        println!("Would remove {} ({})", name, reason);
+    }
+    let removed = match meta {
+        Some(meta) if meta.ephemeral => {
+            ephemeral::retire(backend, root, wt, meta, opts).map(|()| true)?
+        }
+        _ => remove_checked(backend, root, wt, opts)?,
+    };
+    if removed && !opts.dry_run {
+        eprintln!("Removed {} ({})", name, reason);
+    }
+    Ok(removed)
+}
+
+/// Unix time `wt` was last opened with terris; worktrees never opened
+/// count from their creation, or else their last change.
+pub fn idle_since(wt: &Worktree, meta: Option<&WorktreeMeta>) -> Option<i64> {
+    meta.and_then(|meta| meta.last_used.or(meta.created))
+        .or_else(|| modified(&wt.path))
+}
+
+/// Unix time `path` was last modified.
+pub fn modified(path: &Path) -> Option<i64> {
+    let modified = path.metadata().ok()?.modified().ok()?;
+    let secs = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    i64::try_from(secs).ok()
+}
@@ -0,0 +1,123 @@
+//! `terris graduate`: land a worktree's branch on its base, then remove the
+//! worktree and the branch.
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+
+use crate::backend::{Backend, Integration};
+use crate::config::Config;
+use crate::error::Error;
+use crate::{
+    RemoveOptions, find_worktree_by_branch, remove_checked, repo_root, resolve_worktree,
+    worktree_branch_short,
+};
+
+#[derive(Args)]
+pub struct GraduateArgs {
+    /// Worktree whose branch to land
+    #[arg(value_name = "target")]
+    target: String,
+    /// Branch to land on [default: the primary worktree's branch]
+    #[arg(long, value_name = "branch")]
+    into: Option<String>,
+    /// Rebase onto the base, then fast-forward the base
+    #[arg(long, group = "how")]
+    rebase: bool,
+    /// Create a merge commit on the base
+    #[arg(long, group = "how")]
+    merge: bool,
+    /// Add all of the branch's changes to the base as one commit
+    #[arg(long, group = "how")]
+    squash: bool,
+    /// Push the base branch afterwards
+    #[arg(long)]
+    push: bool,
+}
+
+impl GraduateArgs {
+    fn integration(&self, config: &Config) -> Integration {
+        if self.rebase {
+            Integration::Rebase
+        } else if self.merge {
+            Integration::Merge
+        } else if self.squash {
+            Integration::Squash
+        } else {
+            config.graduate.strategy
+        }
+    }
+}
+
+pub fn cmd_graduate(
+    backend: &dyn Backend,
+    args: &GraduateArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let wt = resolve_worktree(&args.target, &worktrees, &cwd, config.fuzzy)?;
+    if wt.main {
+        bail!("cannot graduate the primary worktree");
+    }
+    let branch = worktree_branch_short(wt).context("the worktree has no branch to land")?;
+    let base = match &args.into {
+        Some(base) => base.clone(),
+        None => worktrees
+            .iter()
+            .find(|wt| wt.main)
+            .and_then(worktree_branch_short)
+            .context("the primary worktree has no branch; pass `--into <branch>`")?
+            .to_string(),
+    };
+    if base == branch {
+        bail!("'{}' cannot be landed on itself", branch);
+    }
+    let base_wt = find_worktree_by_branch(&base, &worktrees)?;
+    let how = args.integration(config);
+    if base_wt.is_none() && how != Integration::Rebase {
+        bail!(
+            "'{}' is not checked out in any worktree; merging needs one, or use `--rebase`",
+            base
+        );
+    }
+
+    // Nothing may be left behind in either worktree.
+    for path in std::iter::once(&wt.path).chain(base_wt.map(|b| &b.path)) {
+        if backend.is_dirty(path)? {
+            return Err(Error::DirtyWorktree(path.clone()).into());
+        }
+    }
+
+    let base_path = base_wt.map(|b| b.path.as_path());
+    backend
+        .integrate(&wt.path, branch, &base, base_path, how)
+        .with_context(|| {
+            format!(
+                "land '{}' on '{}'; resolve any conflict and run graduate again",
+                branch, base
+            )
+        })?;
+    if args.push || config.graduate.push {
+        backend
+            .push_branch(base_path.unwrap_or(&root), &base)
+            .with_context(|| format!("push '{}'", base))?;
+    }
+
+    let opts = RemoveOptions {
+        dry_run,
+        hook: config.hook,
+        pre_delete: &config.hooks.pre_delete,
+        ..RemoveOptions::default()
+    };
+    if !remove_checked(backend, &root, wt, &opts)? {
+        return Ok(());
+    }
+    // A squashed or rebased branch may not look merged to git; it has landed.
+    backend.delete_branch(&root, branch, true)?;
+    if !dry_run {
+        eprintln!("Graduated {} into {}", branch, base);
+    }
+    Ok(())
+}
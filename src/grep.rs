@@ -0,0 +1,160 @@
+//! `terris grep`: search the files of every worktree at once, with ripgrep
+//! when it is installed and `git grep` otherwise, prefixing each match with
+//! the worktree it was found in.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use serde::Serialize;
+
+use crate::backend::Backend;
+use crate::backend::git::git_command;
+use crate::jobs;
+use crate::{Worktree, repo_root, worktree_branch_short, worktree_name};
+
+#[derive(Args)]
+pub struct GrepArgs {
+    /// Regular expression to search for
+    #[arg(value_name = "pattern")]
+    pattern: String,
+    /// Match regardless of case
+    #[arg(short = 'i', long)]
+    ignore_case: bool,
+    /// Take the pattern as a literal string
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+    /// Only search worktrees whose branch matches a glob, e.g. 'feature/*'
+    #[arg(long, value_name = "glob")]
+    filter: Option<glob::Pattern>,
+}
+
+/// A matching line.
+#[derive(Serialize)]
+struct Hit<'a> {
+    worktree: String,
+    path: &'a Path,
+    file: String,
+    line: u64,
+    text: String,
+}
+
+pub fn cmd_grep(backend: &dyn Backend, args: &GrepArgs, json: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let selected: Vec<&Worktree> = worktrees
+        .iter()
+        .filter(|wt| wt.prunable.is_none() && wt.path.is_dir())
+        .filter(|wt| {
+            args.filter.as_ref().is_none_or(|filter| {
+                worktree_branch_short(wt).is_some_and(|branch| filter.matches(branch))
+            })
+        })
+        .collect();
+    let ripgrep = has_ripgrep();
+
+    let outputs = jobs::map(&selected, |wt| search(&wt.path, args, ripgrep));
+
+    let mut hits = Vec::new();
+    for (wt, output) in selected.iter().zip(outputs) {
+        let output = match output {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("Skipped {}: {:#}", worktree_name(wt), err);
+                continue;
+            }
+        };
+        hits.extend(output.lines().filter_map(|line| {
+            let (file, line, text) = parse_line(line)?;
+            Some(Hit {
+                worktree: worktree_name(wt),
+                path: &wt.path,
+                file: file.to_string(),
+                line,
+                text: text.to_string(),
+            })
+        }));
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&hits)?);
+        return Ok(());
+    }
+    if hits.is_empty() {
+        bail!("no worktree has a match for '{}'", args.pattern);
+    }
+    for hit in &hits {
+        println!("{}:{}:{}:{}", hit.worktree, hit.file, hit.line, hit.text);
+    }
+    Ok(())
+}
+
+fn has_ripgrep() -> bool {
+    Command::new("rg")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Search the worktree at `path`, returning the tool's output: one match
+/// per line, the file name followed by a NUL. No match is not an error.
+fn search(path: &Path, args: &GrepArgs, ripgrep: bool) -> Result<String> {
+    let program = if ripgrep { "rg" } else { "git" };
+    let mut command = if ripgrep {
+        let mut command = Command::new(program);
+        command.args(["--line-number", "--no-heading", "--null", "--color=never"]);
+        command
+    } else {
+        let mut command = git_command();
+        command.args(["grep", "--untracked", "-I", "-n", "-z", "-E"]);
+        command
+    };
+    if args.ignore_case {
+        command.arg("-i");
+    }
+    if args.fixed_strings {
+        command.arg("-F");
+    }
+    let output = command
+        .args(["-e", &args.pattern])
+        .current_dir(path)
+        .output()
+        .with_context(|| format!("run `{}`", program))?;
+    match output.status.code() {
+        Some(0 | 1) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        _ => bail!(
+            "`{}` failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    }
+}
+
+/// Split `file\0line\0text` (git grep) or `file\0line:text` (ripgrep).
+fn parse_line(line: &str) -> Option<(&str, u64, &str)> {
+    let (file, rest) = line.split_once('\0')?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+    let number = rest[..digits].parse().ok()?;
+    Some((file, number, &rest[digits + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_are_parsed_from_either_tool() {
+        assert_eq!(
+            parse_line("src/a:b.rs\u{0}12\u{0}let x = 1;"),
+            Some(("src/a:b.rs", 12, "let x = 1;"))
+        );
+        assert_eq!(
+            parse_line("README.md\u{0}3:see: docs"),
+            Some(("README.md", 3, "see: docs"))
+        );
+        assert_eq!(parse_line("no separator"), None);
+    }
+}
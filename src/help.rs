@@ -0,0 +1,280 @@
+//! `terris help`: a command's long help, or a page on a concept no single
+//! command covers, such as the registry or path templates. `terris man`
+//! renders the same as man pages, for packagers and `man terris`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, CommandFactory};
+use clap_mangen::roff::{Roff, roman};
+
+use crate::{Cli, pager};
+
+#[derive(Args)]
+pub struct HelpArgs {
+    /// A topic, or a command (and subcommand) to show the help of
+    #[arg(value_name = "topic")]
+    topic: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ManArgs {
+    /// Write terris.1, a page per command, and a page per help topic into
+    /// this directory instead of printing terris.1
+    #[arg(long, value_name = "dir")]
+    out_dir: Option<PathBuf>,
+}
+
+/// A page of `terris help <topic>`. Paragraphs are separated by blank
+/// lines; those indented by two spaces are shown as they are.
+struct Topic {
+    name: &'static str,
+    summary: &'static str,
+    body: &'static str,
+}
+
+const TOPICS: &[Topic] = &[
+    Topic {
+        name: "registry",
+        summary: "where terris keeps worktrees and what it knows about them",
+        body: r#"terris keeps new worktrees, and what it records about every worktree it
+manages, in the registry directory, ~/.terris-worktrees:
+
+  ~/.terris-worktrees/
+    <repo>/<branch>-<suffix>   a worktree, placed by the path template
+    <repo>/pool-<N>            a worktree of `terris pool`
+    metadata.json              last use, creation, base, and description
+    cache.json                 CI answers and the daemon's status and sizes
+    completions.json           the script `terris completions install` wrote
+    .lock                      held while a terris picks a path or records
+
+<repo> is the repository's directory name. A repository with `base-dir` set
+under [repos.<name>] keeps its worktrees and pool in that directory
+instead; the files above stay in the registry.
+
+Worktrees created elsewhere, with the in-repo or sibling locations or with
+plain git and `terris adopt`, are recorded in metadata.json all the same.
+terris processes take turns through .lock, waiting up to `registry-wait`
+seconds for each other.
+
+`terris repos` lists the repositories with recorded worktrees and forgets
+those that are gone. `terris export` prints every repository and worktree
+as JSON, and `terris import` recreates them on another machine. `terris gc`
+offers to delete directories in the registry, and in base-dirs, that are no
+longer a worktree of any repository.
+"#,
+    },
+    Topic {
+        name: "naming",
+        summary: "how new worktrees are named and placed",
+        body: r#"The path of a new worktree comes from the first of these that applies:
+
+  --stable, or stable = true     {base}/{repo}/{branch}
+  naming-script                  the template a Rhai script returns
+  location = "in-repo"           .worktrees/{branch}
+  location = "sibling"           {repo_parent}/{repo}-{branch_slug}
+  path-template                  a template of your own
+  otherwise                      {base}/{repo}/{branch}-{suffix}
+
+`location` and `path-template` can also be set per repository under
+[repos.<name>], which wins over the global keys. A template is a path with
+placeholders:
+
+  {base}          ~/.terris-worktrees, or the repository's base-dir
+  {repo}          the repository's directory name
+  {repo_parent}   the directory containing the repository
+  {branch}        the branch name; `/` in it makes nested directories
+  {branch_slug}   the branch name with `/` replaced by `-`
+  {date}          today's date (UTC) as YYYY-MM-DD
+  {suffix}        eight random lowercase letters
+
+A leading ~/ is the home directory, and a relative path is taken relative
+to the repository root; the top directory of a worktree inside the
+repository is added to .git/info/exclude.
+
+Without {suffix}, a branch always gets the same path. When a directory is
+already there, `on-collision` decides: error fails, and suffix uses
+<path>-2, -3, and so on.
+"#,
+    },
+    Topic {
+        name: "hooks",
+        summary: "commands run when worktrees are created and removed",
+        body: r#"Hooks are configured under [hooks]:
+
+  [hooks]
+  post-create = ["npm ci", ".terris/setup.rhai"]
+  pre-delete = ["docker compose down"]
+  install-frameworks = true
+
+post-create entries run in order inside each new worktree, after it is
+created and the registry lock is released. A failing one prints a warning
+and the worktree is kept. `terris reset --hooks` runs them again.
+
+pre-delete entries run inside a worktree before terris removes it. A
+failing one keeps the worktree, unless the removal is forced.
+
+Each entry runs with `sh -c` in the worktree, its output going to stderr.
+It gets these variables, and the same context as one JSON object on stdin:
+
+  TERRIS_HOOK          post-create or pre-delete
+  TERRIS_WORKTREE      the worktree's directory name
+  TERRIS_BRANCH        its branch, unset when detached
+  TERRIS_REPO          the primary worktree
+  TERRIS_PATH          the worktree
+  TERRIS_BASE          what terris started the branch from
+  TERRIS_DESCRIPTION   set with --description
+  TERRIS_EPHEMERAL     1 for ephemeral worktrees
+  TERRIS_PORT*         ports reserved with `terris port`
+
+An entry naming a .rhai file, relative to the worktree, is evaluated by
+terris instead, with the context as `ctx` and `sh(command)` returning a
+command's exit code.
+
+With install-frameworks, post-create also runs `pre-commit install`,
+`npx --no husky`, or `lefthook install` in worktrees using those tools.
+"#,
+    },
+    Topic {
+        name: "targets",
+        summary: "how commands find the worktree they are given",
+        body: r#"Commands taking a worktree, such as path, delete, reset, and diff,
+understand these besides branch names:
+
+  main   the primary worktree
+  .      the worktree containing the current directory
+  -      the worktree visited before the current one
+
+Otherwise the first of these that matches wins:
+
+  1. the branch name
+  2. the worktree's directory name
+  3. a number from the ID column of the worktree list
+  4. a unique prefix of the branch, or of one of its `/`-separated parts
+  5. with fuzzy = true, the target's characters in order in the branch
+
+Several matches at one step are an error listing them. delete, --rm,
+reset, and checkout ask before acting on a match from steps 4 or 5, and
+refuse one without a terminal to ask on.
+"#,
+    },
+];
+
+pub fn cmd_help(args: &HelpArgs, no_pager: bool) -> Result<()> {
+    let mut cmd = Cli::command();
+    cmd.build();
+    let text = match args.topic.as_slice() {
+        [] => {
+            let mut topics = String::from("Topics:\n");
+            for topic in TOPICS {
+                topics.push_str(&format!("  {:<10}  {}\n", topic.name, topic.summary));
+            }
+            topics.push_str("\nRun `terris help <topic>` for one, or `terris help <command>`.");
+            let mut cmd = cmd.after_long_help(topics);
+            cmd.render_long_help().to_string()
+        }
+        [name] if let Some(topic) = find(name) => topic.body.to_string(),
+        path => {
+            let mut sub = &mut cmd;
+            for name in path {
+                sub = match sub.find_subcommand_mut(name) {
+                    Some(sub) => sub,
+                    None => bail!(
+                        "no help topic or command named '{}'; topics: {}",
+                        path.join(" "),
+                        topic_names()
+                    ),
+                };
+            }
+            sub.render_long_help().to_string()
+        }
+    };
+    pager::show(&text, no_pager)
+}
+
+pub fn cmd_man(args: &ManArgs, dry_run: bool) -> Result<()> {
+    let cmd = Cli::command();
+    let Some(dir) = &args.out_dir else {
+        let mut out = std::io::stdout().lock();
+        return clap_mangen::Man::new(cmd)
+            .render(&mut out)
+            .context("write the man page");
+    };
+    if dry_run {
+        println!("Would write the man pages to {}", dir.display());
+        return Ok(());
+    }
+    std::fs::create_dir_all(dir).with_context(|| format!("create '{}'", dir.display()))?;
+    clap_mangen::generate_to(cmd, dir)
+        .with_context(|| format!("write the man pages to '{}'", dir.display()))?;
+    for topic in TOPICS {
+        write_topic(topic, dir)?;
+    }
+    eprintln!("Wrote the man pages to {}", dir.display());
+    Ok(())
+}
+
+fn find(name: &str) -> Option<&'static Topic> {
+    TOPICS.iter().find(|topic| topic.name == name)
+}
+
+fn topic_names() -> String {
+    TOPICS
+        .iter()
+        .map(|topic| topic.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Write `topic` as the section 7 page `terris-<topic>.7`.
+fn write_topic(topic: &Topic, dir: &Path) -> Result<()> {
+    let file = dir.join(format!("terris-{}.7", topic.name));
+    std::fs::write(&file, topic_page(topic)).with_context(|| format!("write '{}'", file.display()))
+}
+
+fn topic_page(topic: &Topic) -> String {
+    let title = format!("terris-{}", topic.name);
+    let mut roff = Roff::new();
+    roff.control("TH", [title.to_uppercase().as_str(), "7"])
+        .control("SH", ["NAME"])
+        .text([roman(format!("{} - {}", title, topic.summary))])
+        .control("SH", ["DESCRIPTION"]);
+    for paragraph in topic.body.split("\n\n") {
+        let paragraph = paragraph.trim_end();
+        if paragraph.starts_with("  ") {
+            roff.control("PP", [])
+                .control("nf", [])
+                .text([roman(paragraph)])
+                .control("fi", []);
+        } else {
+            let words: Vec<&str> = paragraph.split_whitespace().collect();
+            roff.control("PP", []).text([roman(words.join(" "))]);
+        }
+    }
+    roff.control("SH", ["SEE ALSO"]).text([roman("terris(1)")]);
+    roff.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topics_do_not_shadow_commands() {
+        let cmd = Cli::command();
+        for topic in TOPICS {
+            assert!(
+                cmd.find_subcommand(topic.name).is_none(),
+                "topic '{}' is also a command",
+                topic.name
+            );
+        }
+    }
+
+    #[test]
+    fn topic_pages_keep_indented_blocks() {
+        let page = topic_page(find("targets").unwrap());
+        assert!(page.contains("\n.TH TERRIS-TARGETS 7\n"));
+        assert!(page.contains(".nf\n  main   the primary worktree\n"));
+    }
+}
@@ -0,0 +1,143 @@
+//! User commands run at fixed points in a worktree's life, configured under
+//! `[hooks]` in the config file.
+//!
+//! Each hook runs with `sh -c` inside the worktree, with the `TERRIS_*`
+//! variables from [`WorktreeEnv`] plus `TERRIS_HOOK`, and gets the same
+//! context as a JSON object on stdin. An entry naming a `.rhai` file is
+//! evaluated by terris instead; see [`script`].
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use crate::env::WorktreeEnv;
+use crate::script;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Hooks {
+    /// Shell commands run, in order, inside each new worktree.
+    pub post_create: Vec<String>,
+    /// Shell commands run, in order, inside a worktree before terris removes
+    /// it. A failing one keeps the worktree, unless forced.
+    pub pre_delete: Vec<String>,
+    /// After the post-create hooks, install the git hooks of any hook
+    /// framework the worktree uses (pre-commit, husky, lefthook).
+    pub install_frameworks: bool,
+}
+
+/// Hook frameworks, by a file or directory that shows a worktree uses one,
+/// and the command that installs its git hooks.
+const FRAMEWORKS: &[(&[&str], &str)] = &[
+    (&[".pre-commit-config.yaml"], "pre-commit install"),
+    (&[".husky"], "npx --no husky"),
+    (
+        &[
+            "lefthook.yml",
+            ".lefthook.yml",
+            "lefthook.yaml",
+            ".lefthook.yaml",
+        ],
+        "lefthook install",
+    ),
+];
+
+/// Run the post-create hooks in the worktree described by `env`, then the
+/// install commands of its hook frameworks when asked to, warning instead of
+/// failing: the worktree already exists and is usable even if, say,
+/// dependency installation broke.
+pub fn post_create(hooks: &Hooks, env: &WorktreeEnv, dry_run: bool) {
+    let frameworks = if hooks.install_frameworks {
+        frameworks(&env.path)
+    } else {
+        Vec::new()
+    };
+    for command in hooks
+        .post_create
+        .iter()
+        .map(String::as_str)
+        .chain(frameworks)
+    {
+        if dry_run {
+            println!(
+                "Would run post-create hook `{}` in {}",
+                command,
+                env.path.display()
+            );
+            continue;
+        }
+        if let Err(err) = run("post-create", command, env) {
+            eprintln!("Warning: {:#}", err);
+        }
+    }
+}
+
+/// The install commands of the hook frameworks the worktree at `path` uses.
+fn frameworks(path: &Path) -> Vec<&'static str> {
+    FRAMEWORKS
+        .iter()
+        .filter(|(markers, _)| markers.iter().any(|marker| path.join(marker).exists()))
+        .map(|(_, command)| *command)
+        .collect()
+}
+
+/// Run the pre-delete hooks in the worktree described by `env`. The first
+/// failure stops the removal; with `force` it is only reported.
+pub fn pre_delete(
+    commands: &[String],
+    env: &WorktreeEnv,
+    force: bool,
+    dry_run: bool,
+) -> Result<()> {
+    for command in commands {
+        if dry_run {
+            println!(
+                "Would run pre-delete hook `{}` in {}",
+                command,
+                env.path.display()
+            );
+            continue;
+        }
+        match run("pre-delete", command, env) {
+            Ok(()) => {}
+            Err(err) if force => eprintln!("Warning: {:#}", err),
+            Err(err) => {
+                return Err(err).context("kept the worktree; pass --force to remove it anyway");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run `command` as the `hook` hook. Its stdout goes to stderr so
+/// `terris <branch>` still prints only the path.
+fn run(hook: &str, command: &str, env: &WorktreeEnv) -> Result<()> {
+    if script::is_script(command) {
+        return script::run_hook(command, hook, env)
+            .with_context(|| format!("hook `{}` failed", command));
+    }
+    let mut sh = Command::new("sh");
+    sh.args(["-c", command])
+        .current_dir(&env.path)
+        .env("TERRIS_HOOK", hook)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::from(std::io::stderr()));
+    env.apply(&mut sh);
+    let mut child = sh
+        .spawn()
+        .with_context(|| format!("could not run hook `{}`", command))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores its stdin may exit before reading it.
+        let _ = writeln!(stdin, "{}", env.to_json(hook));
+    }
+    let status = child
+        .wait()
+        .with_context(|| format!("could not run hook `{}`", command))?;
+    if !status.success() {
+        bail!("hook `{}` exited with {}", command, status);
+    }
+    Ok(())
+}
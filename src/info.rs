@@ -0,0 +1,204 @@
+//! `terris info`: everything terris knows about one worktree, from git's
+//! view of it to the bookkeeping in the metadata store.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Serialize;
+
+use crate::backend::{Backend, ChangeSummary, Commit, Divergence};
+use crate::cache;
+use crate::config::Config;
+use crate::du::{self, Usage, format_size};
+use crate::forge::{self, Forge, PullRequest};
+use crate::metadata::{Metadata, WorktreeMeta};
+use crate::{
+    Worktree, align_rows, format_age, repo_root, resolve_worktree, unix_now, worktree_branch_short,
+    worktree_name,
+};
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Branch, directory name, or list ID; `main`, `.`, and `-` also work
+    #[arg(value_name = "target")]
+    target: String,
+}
+
+#[derive(Serialize)]
+struct Info<'a> {
+    name: String,
+    #[serde(flatten)]
+    worktree: &'a Worktree,
+    upstream: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ahead: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    behind: Option<usize>,
+    commit: Option<Commit>,
+    /// Missing for worktrees whose directory is gone.
+    changes: Option<ChangeSummary>,
+    usage: Option<Usage>,
+    /// The branch's open pull or merge request, when the forge is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pull_request: Option<PullRequest>,
+    metadata: WorktreeMeta,
+}
+
+pub fn cmd_info(backend: &dyn Backend, args: &InfoArgs, config: &Config, json: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let wt = resolve_worktree(&args.target, &worktrees, &cwd, config.fuzzy)?;
+
+    let branch = worktree_branch_short(wt);
+    let upstream = match branch {
+        Some(branch) => backend.upstream(&root, branch)?,
+        None => None,
+    };
+    let divergence = match (branch, &upstream) {
+        (Some(branch), Some(_)) => backend.upstream_divergence(&root, branch)?,
+        _ => None,
+    };
+    let present = wt.prunable.is_none() && wt.path.is_dir();
+    let (commit, changes, usage) = if present {
+        let commit = match wt.head {
+            Some(_) => backend.head_commit(&wt.path)?,
+            None => None,
+        };
+        (
+            commit,
+            Some(backend.change_summary(&wt.path)?),
+            Some(du::measure_one(backend, wt, &worktrees, &cache::load())),
+        )
+    } else {
+        (None, None, None)
+    };
+    // Looked up before the store is read, which it updates.
+    let pull_request = match Forge::detect(backend, &root, &config.forge) {
+        Some(forge) if branch.is_some() => {
+            forge::pull_requests(forge, &root, std::slice::from_ref(wt)).remove(&wt.path)
+        }
+        _ => None,
+    };
+    let info = Info {
+        name: worktree_name(wt),
+        worktree: wt,
+        upstream,
+        ahead: divergence.map(|d| d.ahead),
+        behind: divergence.map(|d| d.behind),
+        commit,
+        changes,
+        usage,
+        pull_request,
+        metadata: Metadata::load()?.get(&wt.path).cloned().unwrap_or_default(),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        for line in align_rows(&rows(&info, divergence)) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// One `[label, value]` row per thing known, skipping what is not.
+fn rows(info: &Info, divergence: Option<Divergence>) -> Vec<[String; 2]> {
+    let now = unix_now();
+    let ago = |time: i64| format!("{} ago", format_age(now.saturating_sub(time)));
+    let wt = info.worktree;
+    let meta = &info.metadata;
+    let mut rows = Vec::new();
+    let mut row = |label: &str, value: String| rows.push([label.to_string(), value]);
+
+    row("name", info.name.clone());
+    if let Some(index) = meta.index {
+        row("id", index.to_string());
+    }
+    row("path", wt.path.display().to_string());
+    let kind = if wt.main { "primary" } else { "linked" };
+    row("kind", kind.to_string());
+    match worktree_branch_short(wt) {
+        Some(branch) => row("branch", branch.to_string()),
+        None => row("branch", "(detached)".to_string()),
+    }
+    if let Some(upstream) = &info.upstream {
+        let state = match divergence {
+            Some(Divergence {
+                ahead: 0,
+                behind: 0,
+            }) => " (up to date)".to_string(),
+            Some(d) => format!(" ({} ahead, {} behind)", d.ahead, d.behind),
+            None => " (gone)".to_string(),
+        };
+        row("upstream", format!("{}{}", upstream, state));
+    }
+    if let Some(pr) = &info.pull_request {
+        row("pr", format!("{} {}", pr, pr.url));
+    }
+    if let Some(commit) = &info.commit {
+        let short = commit.id.get(..10).unwrap_or(&commit.id);
+        row("head", format!("{} {}", short, commit.summary));
+        row("author", format!("{}, {}", commit.author, ago(commit.time)));
+    } else if let Some(head) = &wt.head {
+        row("head", head.clone());
+    }
+    if wt.locked {
+        let reason = wt.lock_reason.as_deref().unwrap_or("yes");
+        row("locked", reason.to_string());
+    }
+    if let Some(reason) = &wt.prunable {
+        row("prunable", reason.clone());
+    }
+    if let Some(changes) = &info.changes {
+        let lines = changes.lines();
+        let value = if lines.is_empty() {
+            "none".to_string()
+        } else {
+            lines.join(", ")
+        };
+        row("unsaved", value);
+    }
+    if let Some(usage) = &info.usage {
+        row(
+            "size",
+            format!(
+                "{} ({} files, {} ignored)",
+                format_size(usage.total()),
+                format_size(usage.files),
+                format_size(usage.ignored)
+            ),
+        );
+    }
+    if let Some(description) = &meta.description {
+        row("description", description.clone());
+    }
+    if let Some(created) = meta.created {
+        row("created", ago(created));
+    }
+    if let Some(base) = &meta.base {
+        row("base", base.clone());
+    }
+    if let Some(parent) = &meta.parent {
+        row("parent", parent.clone());
+    }
+    if let Some(last_used) = meta.last_used {
+        row("used", format!("{} ({} times)", ago(last_used), meta.uses));
+    }
+    if meta.ephemeral {
+        let owner = meta
+            .pid
+            .map_or(String::new(), |pid| format!(" (pid {})", pid));
+        row("ephemeral", format!("yes{}", owner));
+    }
+    if let Some(pool) = meta.pool {
+        row("pool", format!("{:?}", pool).to_lowercase());
+    }
+    if let Some(port) = meta.port {
+        let mut ports = vec![port.to_string()];
+        for (offset, service) in (1..).zip(&meta.services) {
+            ports.push(format!("{} {}", service, port + offset));
+        }
+        row("ports", ports.join(", "));
+    }
+    rows
+}
@@ -0,0 +1,80 @@
+//! How many subprocesses terris runs at once when it works on every
+//! worktree: status checks, `foreach`, `grep`, forge lookups, and disk
+//! usage. Set by `--jobs` or the `jobs` config key, and otherwise the number
+//! of CPUs.
+
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The configured limit, or 0 for the default.
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_limit(limit: Option<NonZeroUsize>) {
+    LIMIT.store(limit.map_or(0, NonZeroUsize::get), Ordering::Relaxed);
+}
+
+/// The most jobs to run at once.
+pub fn limit() -> usize {
+    match LIMIT.load(Ordering::Relaxed) {
+        0 => std::thread::available_parallelism().map_or(4, NonZeroUsize::get),
+        limit => limit,
+    }
+}
+
+/// `f` applied to each of `items`, at most [`limit`] at a time, with the
+/// results in the order of `items`.
+pub fn map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let workers = limit().min(items.len());
+    if workers <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<R>>> = items.iter().map(|_| Mutex::new(None)).collect();
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    let result = f(item);
+                    *results[i].lock().expect("result lock poisoned") = Some(result);
+                }
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| {
+            result
+                .into_inner()
+                .expect("result lock poisoned")
+                .expect("every item was run")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_keep_the_order_of_the_items_and_respect_the_limit() {
+        set_limit(NonZeroUsize::new(2));
+        let running = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        let items: Vec<u64> = (0..8).collect();
+        let doubled = map(&items, |&n| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(5 * (8 - n)));
+            running.fetch_sub(1, Ordering::SeqCst);
+            n * 2
+        });
+        set_limit(None);
+        assert_eq!(doubled, [0, 2, 4, 6, 8, 10, 12, 14]);
+        assert!(most.load(Ordering::SeqCst) <= 2);
+    }
+}
@@ -0,0 +1,113 @@
+//! `terris jump`: go to a worktree from anywhere by a fragment of its path.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::Args;
+
+use crate::error::Error;
+use crate::metadata::{self, Metadata};
+use crate::{print_path, unix_now};
+
+#[derive(Args)]
+pub struct JumpArgs {
+    /// Path fragments to match, in order; the last one must match the
+    /// worktree's directory name
+    #[arg(value_name = "query", required = true)]
+    query: Vec<String>,
+}
+
+/// Print the best-ranked worktree, across every repository terris has
+/// opened worktrees for, whose path matches the query.
+pub fn cmd_jump(args: &JumpArgs) -> Result<()> {
+    let metadata = Metadata::load()?;
+    let path = best_match(&metadata, &args.query, unix_now())
+        .ok_or_else(|| Error::WorktreeNotFound(args.query.join(" ")))?;
+    metadata::record_use(&path);
+    print_path(&path);
+    Ok(())
+}
+
+fn best_match(metadata: &Metadata, query: &[String], now: i64) -> Option<PathBuf> {
+    metadata
+        .worktrees
+        .iter()
+        .filter(|(path, _)| matches(path, query) && path.is_dir())
+        .max_by(|(_, a), (_, b)| a.frecency(now).total_cmp(&b.frecency(now)))
+        .map(|(path, _)| path.clone())
+}
+
+/// Case-insensitive match of each term, in order, against `path`, with the
+/// last term required to fall in the final component (like zoxide).
+fn matches(path: &Path, query: &[String]) -> bool {
+    let haystack = path.to_string_lossy().to_lowercase();
+    let last_component_start = haystack.rfind('/').map_or(0, |i| i + 1);
+    let mut pos = 0;
+    for (i, term) in query.iter().enumerate() {
+        let term = term.to_lowercase();
+        let Some(found) = haystack[pos..].find(&term) else {
+            return false;
+        };
+        pos += found + term.len();
+        if i + 1 == query.len() && pos <= last_component_start {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::WorktreeMeta;
+
+    #[test]
+    fn matches_terms_in_order_with_last_in_final_component() {
+        let path = Path::new("/home/me/.terris-worktrees/api/auth-fix-abcdefgh");
+        let query = |terms: &[&str]| terms.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert!(matches(path, &query(&["auth"])));
+        assert!(matches(path, &query(&["API", "auth"])));
+        assert!(!matches(path, &query(&["auth", "api"])));
+        assert!(!matches(path, &query(&["api"])));
+    }
+
+    #[test]
+    fn best_match_prefers_frecent_worktrees() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let old = temp.path().join("auth-old");
+        let hot = temp.path().join("auth-hot");
+        std::fs::create_dir_all(&old).unwrap();
+        std::fs::create_dir_all(&hot).unwrap();
+
+        let now = 1_000_000;
+        let mut metadata = Metadata::default();
+        metadata.worktrees.insert(
+            old,
+            WorktreeMeta {
+                last_used: Some(now - 30 * 86400),
+                uses: 10,
+                ..WorktreeMeta::default()
+            },
+        );
+        metadata.worktrees.insert(
+            hot.clone(),
+            WorktreeMeta {
+                last_used: Some(now - 60),
+                uses: 2,
+                ..WorktreeMeta::default()
+            },
+        );
+        metadata.worktrees.insert(
+            temp.path().join("auth-deleted"),
+            WorktreeMeta {
+                last_used: Some(now),
+                uses: 50,
+                ..WorktreeMeta::default()
+            },
+        );
+
+        let query = vec!["auth".to_string()];
+        assert_eq!(best_match(&metadata, &query, now), Some(hot));
+        assert_eq!(best_match(&metadata, &["nope".to_string()], now), None);
+    }
+}
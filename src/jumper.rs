@@ -0,0 +1,57 @@
+//! Keep directory jumpers such as zoxide in sync with terris worktrees, so
+//! `z <name>` reaches a worktree as soon as it exists.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Jumper {
+    Zoxide,
+    Autojump,
+}
+
+impl Jumper {
+    fn program(self) -> &'static str {
+        match self {
+            Jumper::Zoxide => "zoxide",
+            Jumper::Autojump => "autojump",
+        }
+    }
+}
+
+/// Add a newly created worktree to the jumper's database.
+pub fn register(jumper: Jumper, path: &Path) {
+    let path = path.as_os_str();
+    match jumper {
+        Jumper::Zoxide => run(jumper, &["add".as_ref(), path]),
+        Jumper::Autojump => run(jumper, &["--add".as_ref(), path]),
+    }
+}
+
+/// Drop a removed worktree from the jumper's database.
+pub fn unregister(jumper: Jumper, path: &Path) {
+    match jumper {
+        Jumper::Zoxide => run(jumper, &["remove".as_ref(), path.as_os_str()]),
+        // autojump cannot forget a single entry; purge every missing directory.
+        Jumper::Autojump => run(jumper, &["--purge".as_ref()]),
+    }
+}
+
+/// Run the jumper, warning instead of failing: a missing or broken jumper
+/// must not undo a worktree operation that already succeeded.
+fn run(jumper: Jumper, args: &[&std::ffi::OsStr]) {
+    let status = Command::new(jumper.program())
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("Warning: {} exited with {}", jumper.program(), status),
+        Err(err) => eprintln!("Warning: could not run {}: {}", jumper.program(), err),
+    }
+}
@@ -0,0 +1,882 @@
+//! The worktree list printed by a bare `terris`: which columns it shows, in
+//! what order (`--columns` or `[list] columns`), and how each is filled in.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{Backend, Commit, Tracking};
+use crate::cache::{self, Cache};
+use crate::color::Paint;
+use crate::config::Config;
+use crate::du;
+use crate::forge::{self, CiStatus, Forge, PullRequest};
+use crate::gc;
+use crate::metadata::{self, Metadata};
+use crate::pager;
+use crate::table::{self, Align, Overflow};
+use crate::{
+    ListEntry, ListSort, Worktree, format_age, primary_root, relative_path, repo_name, repo_root,
+    unix_now, worktree_branch_short, worktree_flags, worktree_name,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ListConfig {
+    /// Columns shown when `--columns` is not given.
+    pub columns: Option<Vec<ListColumn>>,
+    /// Named combinations of columns, order, and filters, for `--view`.
+    pub views: BTreeMap<String, ListView>,
+}
+
+impl ListConfig {
+    pub fn view(&self, name: &str) -> Result<&ListView> {
+        if let Some(view) = self.views.get(name) {
+            return Ok(view);
+        }
+        let known: Vec<&str> = self.views.keys().map(String::as_str).collect();
+        if known.is_empty() {
+            bail!(
+                "no list view named '{}'; define one under [list.views]",
+                name
+            );
+        }
+        bail!(
+            "no list view named '{}'; known views: {}",
+            name,
+            known.join(", ")
+        );
+    }
+}
+
+/// A saved way of looking at the list, shown with `--view <name>`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListView {
+    pub columns: Option<Vec<ListColumn>>,
+    pub sort: Option<ListSort>,
+    /// States a worktree must all be in to be listed, as the filter flags.
+    pub filter: Vec<ListFilter>,
+    /// Include worktrees without a branch, as `--all`.
+    pub all: bool,
+}
+
+/// A filter flag's name, for views.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListFilter {
+    Gone,
+    Locked,
+    Prunable,
+    Detached,
+    Managed,
+    Unmanaged,
+    Dirty,
+    Clean,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ListColumn {
+    /// Number to refer to the worktree by
+    Id,
+    /// Branch, or directory name without one
+    Name,
+    Branch,
+    /// Disk usage, as added by --du
+    Size,
+    Path,
+    /// detached, locked, prunable, dirty
+    Flags,
+    /// Whether terris created or adopted it
+    Managed,
+    /// Whether it has uncommitted changes
+    Dirty,
+    /// Time since terris created it
+    Age,
+    /// Time since it was last opened with terris
+    Used,
+    /// Short id of the checked-out commit
+    Head,
+    /// Time since the checked-out commit was made
+    Committed,
+    /// Summary line of the checked-out commit
+    Subject,
+    /// Author of the checked-out commit
+    Author,
+    /// Latest CI status of the branch, from GitHub or GitLab
+    Ci,
+    /// Open pull or merge request of the branch and its review status
+    Pr,
+}
+
+/// The columns shown without `--columns` or a configured default.
+pub const DEFAULT_COLUMNS: [ListColumn; 5] = [
+    ListColumn::Id,
+    ListColumn::Name,
+    ListColumn::Branch,
+    ListColumn::Path,
+    ListColumn::Flags,
+];
+
+impl ListColumn {
+    /// Whether the column shows the checked-out commit, read once per
+    /// worktree for all such columns.
+    fn needs_commit(self) -> bool {
+        matches!(
+            self,
+            ListColumn::Committed | ListColumn::Subject | ListColumn::Author
+        )
+    }
+
+    fn column(self) -> table::Column {
+        let (header, align, overflow) = match self {
+            ListColumn::Id => ("ID", Align::Right, Overflow::Keep),
+            ListColumn::Name => ("NAME", Align::Left, Overflow::End),
+            ListColumn::Branch => ("BRANCH", Align::Left, Overflow::End),
+            ListColumn::Size => ("SIZE", Align::Right, Overflow::Keep),
+            ListColumn::Path => ("PATH", Align::Left, Overflow::Start),
+            ListColumn::Flags => ("FLAGS", Align::Left, Overflow::Keep),
+            ListColumn::Managed => ("MANAGED", Align::Left, Overflow::Keep),
+            ListColumn::Dirty => ("DIRTY", Align::Left, Overflow::Keep),
+            ListColumn::Age => ("AGE", Align::Right, Overflow::Keep),
+            ListColumn::Used => ("USED", Align::Right, Overflow::Keep),
+            ListColumn::Head => ("HEAD", Align::Left, Overflow::Keep),
+            ListColumn::Committed => ("COMMITTED", Align::Right, Overflow::Keep),
+            ListColumn::Subject => ("SUBJECT", Align::Left, Overflow::End),
+            ListColumn::Author => ("AUTHOR", Align::Left, Overflow::End),
+            ListColumn::Ci => ("CI", Align::Left, Overflow::Keep),
+            ListColumn::Pr => ("PR", Align::Left, Overflow::Keep),
+        };
+        table::Column {
+            header,
+            align,
+            overflow,
+        }
+    }
+}
+
+/// How the list is printed, besides `--json`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ListFormat {
+    /// Aligned columns for people
+    #[default]
+    Table,
+    /// Comma-separated values, quoted where needed
+    Csv,
+    /// Tab-separated values, with tabs and newlines escaped
+    Tsv,
+}
+
+/// Narrow the list to worktrees in a given state; given together, a
+/// worktree must be in all of them.
+#[derive(Args, Clone, Copy, Debug, Default)]
+pub struct ListFilters {
+    /// List only worktrees whose branch's upstream was deleted
+    #[arg(long, conflicts_with_all = ["rm", "branch"])]
+    pub gone: bool,
+    /// List only locked worktrees
+    #[arg(long, conflicts_with_all = ["rm", "branch"])]
+    pub locked: bool,
+    /// List only worktrees whose directory is missing
+    #[arg(long, conflicts_with_all = ["rm", "branch"])]
+    pub prunable: bool,
+    /// List only worktrees without a branch checked out
+    #[arg(long, conflicts_with_all = ["rm", "branch"])]
+    pub detached: bool,
+    /// List only linked worktrees terris created or adopted
+    #[arg(long, conflicts_with_all = ["rm", "branch"])]
+    pub managed: bool,
+    /// List only linked worktrees terris did not create or adopt
+    #[arg(long, conflicts_with_all = ["rm", "branch", "managed"])]
+    pub unmanaged: bool,
+    /// List only worktrees with uncommitted or untracked changes
+    #[arg(long, conflicts_with_all = ["rm", "branch"])]
+    pub dirty: bool,
+    /// List only worktrees without uncommitted or untracked changes
+    #[arg(long, conflicts_with_all = ["rm", "branch", "dirty"])]
+    pub clean: bool,
+}
+
+impl ListFilters {
+    fn any(self) -> bool {
+        self.gone
+            || self.locked
+            || self.prunable
+            || self.detached
+            || self.managed
+            || self.unmanaged
+            || self.dirty
+            || self.clean
+    }
+
+    /// Also require the states a view filters on.
+    pub fn extend(&mut self, filters: &[ListFilter]) {
+        for filter in filters {
+            let flag = match filter {
+                ListFilter::Gone => &mut self.gone,
+                ListFilter::Locked => &mut self.locked,
+                ListFilter::Prunable => &mut self.prunable,
+                ListFilter::Detached => &mut self.detached,
+                ListFilter::Managed => &mut self.managed,
+                ListFilter::Unmanaged => &mut self.unmanaged,
+                ListFilter::Dirty => &mut self.dirty,
+                ListFilter::Clean => &mut self.clean,
+            };
+            *flag = true;
+        }
+    }
+}
+
+pub struct ListOptions {
+    /// Include worktrees without a branch.
+    pub all: bool,
+    pub sort: Option<ListSort>,
+    pub filters: ListFilters,
+    pub columns: Vec<ListColumn>,
+    pub format: ListFormat,
+    /// List every repository terris knows, grouped.
+    pub global: bool,
+    /// List only the repository with this name, grouped like `global`.
+    pub repo: Option<String>,
+    /// Show paths relative to this directory, in `--json` too.
+    pub relative: Option<PathBuf>,
+    pub json: bool,
+    pub paint: Paint,
+    pub no_pager: bool,
+}
+
+/// The columns to show: `--columns`, else the configured ones, else the
+/// defaults, with SIZE added before PATH for `--du`, COMMITTED, SUBJECT,
+/// and AUTHOR for `--last-commit`, and PR for `--prs`.
+pub fn columns(
+    requested: Option<Vec<ListColumn>>,
+    config: &ListConfig,
+    du: bool,
+    last_commit: bool,
+    prs: bool,
+) -> Vec<ListColumn> {
+    let mut columns = requested
+        .or_else(|| config.columns.clone())
+        .unwrap_or_else(|| DEFAULT_COLUMNS.to_vec());
+    let mut added = Vec::new();
+    if du {
+        added.push(ListColumn::Size);
+    }
+    if last_commit {
+        added.extend([
+            ListColumn::Committed,
+            ListColumn::Subject,
+            ListColumn::Author,
+        ]);
+    }
+    if prs {
+        added.push(ListColumn::Pr);
+    }
+    for column in added {
+        if !columns.contains(&column) {
+            let at = columns
+                .iter()
+                .position(|&col| col == ListColumn::Path)
+                .unwrap_or(columns.len());
+            columns.insert(at, column);
+        }
+    }
+    columns
+}
+
+pub fn cmd_list(backend: &dyn Backend, opts: &ListOptions, config: &Config) -> Result<()> {
+    let grouped = opts.global || opts.repo.is_some();
+    let roots = if grouped {
+        known_repos(backend)?
+    } else {
+        vec![repo_root(backend)?]
+    };
+    let cache = cache::load();
+    let mut listings = Vec::new();
+    for root in roots {
+        let listing = Listing::load(backend, root, opts, config, &cache)?;
+        if opts.repo.as_ref().is_none_or(|repo| *repo == listing.name) {
+            listings.push(listing);
+        }
+    }
+    if let Some(repo) = &opts.repo
+        && listings.is_empty()
+    {
+        bail!("no repository named '{}' has worktrees terris knows", repo);
+    }
+
+    if opts.json {
+        let json = if grouped {
+            let groups: Vec<RepoEntry> = listings
+                .iter()
+                .map(|listing| RepoEntry {
+                    repo: &listing.name,
+                    root: &listing.root,
+                    worktrees: listing.entries(opts, &cache),
+                })
+                .collect();
+            serde_json::to_string_pretty(&groups)?
+        } else {
+            serde_json::to_string_pretty(&listings[0].entries(opts, &cache))?
+        };
+        println!("{}", json);
+        return Ok(());
+    }
+    if opts.format != ListFormat::Table {
+        let mut rows = Vec::new();
+        for listing in &listings {
+            let cells = listing.cells(backend, &cache, opts.relative.as_deref());
+            for wt in listing.shown(opts) {
+                let mut row: Vec<String> = opts
+                    .columns
+                    .iter()
+                    .map(|&col| cells.cell(col, wt))
+                    .collect();
+                if grouped {
+                    row.insert(0, listing.name.clone());
+                }
+                rows.push(row);
+            }
+        }
+        print!("{}", delimited(&opts.columns, &rows, grouped, opts.format));
+        return Ok(());
+    }
+
+    let mut lines = Vec::new();
+    for (i, listing) in listings.iter().enumerate() {
+        let cells = listing.cells(backend, &cache, opts.relative.as_deref());
+        let shown: Vec<Worktree> = listing.shown(opts).cloned().collect();
+        if grouped {
+            if i > 0 {
+                lines.push("\n".to_string());
+            }
+            lines.push(format!("{}\n", listing.summary(&shown, &cells)));
+        }
+        lines.extend(worktree_table(&shown, &opts.columns, &cells, opts.paint));
+        let hidden = listing.without_branch(opts);
+        if hidden > 0 {
+            lines.push(format!(
+                "# {} worktree(s) without a branch not shown. Use --all to display.\n",
+                hidden
+            ));
+        }
+    }
+    pager::show(&lines.concat(), opts.no_pager)
+}
+
+/// A repository in the grouped `--json` list.
+#[derive(Serialize)]
+struct RepoEntry<'a> {
+    repo: &'a str,
+    root: &'a Path,
+    worktrees: Vec<ListEntry<'a>>,
+}
+
+/// One repository's worktrees and what the list shows about them.
+struct Listing {
+    name: String,
+    root: PathBuf,
+    worktrees: Vec<Worktree>,
+    metadata: Metadata,
+    sizes: Option<HashMap<PathBuf, du::Usage>>,
+    /// Checked-out commits, when a column shows them.
+    commits: Option<HashMap<PathBuf, Commit>>,
+    /// CI status by branch, when the CI column is shown.
+    ci: Option<HashMap<String, CiStatus>>,
+    /// Open pull requests by worktree path, when the PR column is shown.
+    prs: Option<HashMap<PathBuf, PullRequest>>,
+    /// How each local branch tracks its upstream.
+    tracking: HashMap<String, Tracking>,
+    /// Whether each worktree has changes, freshly checked for `--dirty` and
+    /// `--clean`.
+    dirty: Option<HashMap<PathBuf, bool>>,
+}
+
+impl Listing {
+    fn load(
+        backend: &dyn Backend,
+        root: PathBuf,
+        opts: &ListOptions,
+        config: &Config,
+        cache: &Cache,
+    ) -> Result<Self> {
+        let mut worktrees = backend.list_worktrees(&root)?;
+        match opts.sort {
+            Some(ListSort::Name) => worktrees.sort_by_key(worktree_name),
+            Some(ListSort::Recent) => {
+                let metadata = Metadata::load()?;
+                worktrees = metadata
+                    .by_recency(&worktrees)
+                    .into_iter()
+                    .cloned()
+                    .collect();
+            }
+            Some(ListSort::Age) => {
+                let metadata = Metadata::load()?;
+                let created = |wt: &Worktree| metadata.get(&wt.path).and_then(|meta| meta.created);
+                worktrees.sort_by_key(|wt| (created(wt).is_none(), created(wt)));
+            }
+            None => {}
+        }
+        let name = worktrees
+            .iter()
+            .find(|wt| wt.main)
+            .map_or_else(|| repo_name(&root), |wt| repo_name(&wt.path));
+        let metadata = metadata::indexed(&worktrees);
+        let tracking = backend.tracking(&root).unwrap_or_else(|err| {
+            tracing::warn!("could not read upstream branches: {:#}", err);
+            HashMap::new()
+        });
+        let sizes = opts.columns.contains(&ListColumn::Size).then(|| {
+            let usages = du::measure(backend, &worktrees, cache);
+            worktrees
+                .iter()
+                .map(|wt| wt.path.clone())
+                .zip(usages)
+                .collect()
+        });
+        let commits = opts
+            .columns
+            .iter()
+            .any(|col| col.needs_commit())
+            .then(|| head_commits(backend, &worktrees));
+        let ci = if opts.columns.contains(&ListColumn::Ci) {
+            Forge::detect(backend, &root, &config.forge).map(|forge| {
+                let branches: Vec<&str> =
+                    worktrees.iter().filter_map(worktree_branch_short).collect();
+                forge::ci_statuses(forge, &root, &branches)
+            })
+        } else {
+            None
+        };
+        let prs = if opts.columns.contains(&ListColumn::Pr) {
+            Forge::detect(backend, &root, &config.forge)
+                .map(|forge| forge::pull_requests(forge, &root, &worktrees))
+        } else {
+            None
+        };
+        let filtering = opts.filters.dirty || opts.filters.clean;
+        let dirty = (filtering || opts.columns.contains(&ListColumn::Dirty)).then(|| {
+            // The column may show what the daemon cached; the filters may not.
+            let unknown: Vec<&Worktree> = worktrees
+                .iter()
+                .filter(|wt| filtering || cache.get(wt).is_none())
+                .collect();
+            check_dirty(backend, &unknown)
+        });
+        Ok(Self {
+            name,
+            root,
+            worktrees,
+            metadata,
+            sizes,
+            commits,
+            ci,
+            prs,
+            tracking,
+            dirty,
+        })
+    }
+
+    /// The worktrees listed: those matching every filter given, else all
+    /// with `--all`, else those with a branch.
+    fn shown<'a>(&'a self, opts: &ListOptions) -> impl Iterator<Item = &'a Worktree> {
+        let (all, filters) = (opts.all, opts.filters);
+        self.worktrees.iter().filter(move |wt| {
+            if filters.any() {
+                return self.matches(wt, filters);
+            }
+            all || worktree_branch_short(wt).is_some()
+        })
+    }
+
+    fn matches(&self, wt: &Worktree, filters: ListFilters) -> bool {
+        let managed = self.metadata.managed(&wt.path);
+        (!filters.gone || self.tracking(wt) == Some(Tracking::Gone))
+            && (!filters.locked || wt.locked)
+            && (!filters.prunable || wt.prunable.is_some())
+            && (!filters.detached || wt.detached)
+            && (!filters.managed || (!wt.main && managed))
+            && (!filters.unmanaged || (!wt.main && !managed))
+            && (!filters.dirty || self.is_dirty(wt) == Some(true))
+            && (!filters.clean || self.is_dirty(wt) == Some(false))
+    }
+
+    /// How many worktrees are left out for having no branch.
+    fn without_branch(&self, opts: &ListOptions) -> usize {
+        if opts.all || opts.filters.any() {
+            return 0;
+        }
+        self.worktrees
+            .iter()
+            .filter(|wt| worktree_branch_short(wt).is_none())
+            .count()
+    }
+
+    /// As checked for `--dirty` or `--clean`; `None` when it was not.
+    fn is_dirty(&self, wt: &Worktree) -> Option<bool> {
+        self.dirty.as_ref()?.get(&wt.path).copied()
+    }
+
+    fn tracking(&self, wt: &Worktree) -> Option<Tracking> {
+        self.tracking.get(worktree_branch_short(wt)?).copied()
+    }
+
+    fn entries<'a>(&'a self, opts: &ListOptions, cache: &Cache) -> Vec<ListEntry<'a>> {
+        self.shown(opts)
+            .map(|wt| ListEntry {
+                index: self.metadata.index(&wt.path),
+                worktree: match &opts.relative {
+                    Some(base) => Cow::Owned(Worktree {
+                        path: relative_path(&wt.path, base),
+                        ..wt.clone()
+                    }),
+                    None => Cow::Borrowed(wt),
+                },
+                managed: self.metadata.managed(&wt.path),
+                usage: self
+                    .sizes
+                    .as_ref()
+                    .and_then(|sizes| sizes.get(&wt.path).copied()),
+                dirty: self
+                    .is_dirty(wt)
+                    .or_else(|| cache.get(wt).map(|status| status.summary.has_uncommitted())),
+                commit: self
+                    .commits
+                    .as_ref()
+                    .and_then(|commits| commits.get(&wt.path).cloned()),
+                ci: self.ci_status(wt),
+                tracking: self.tracking(wt),
+                pull_request: self.prs.as_ref().and_then(|prs| prs.get(&wt.path).cloned()),
+            })
+            .collect()
+    }
+
+    fn cells<'a>(
+        &'a self,
+        backend: &'a dyn Backend,
+        cache: &'a Cache,
+        relative: Option<&'a Path>,
+    ) -> Cells<'a> {
+        Cells {
+            backend,
+            relative,
+            metadata: &self.metadata,
+            cache,
+            sizes: self.sizes.as_ref(),
+            commits: self.commits.as_ref(),
+            ci: self.ci.as_ref(),
+            prs: self.prs.as_ref(),
+            tracking: &self.tracking,
+            fresh: self.dirty.as_ref(),
+        }
+    }
+
+    fn ci_status(&self, wt: &Worktree) -> Option<CiStatus> {
+        let branch = worktree_branch_short(wt)?;
+        self.ci.as_ref()?.get(branch).copied()
+    }
+
+    /// The header of the repository's group: `# name (root): 3 worktree(s),
+    /// 1 dirty, 1.2G`, the size only when measured or cached for every one.
+    fn summary(&self, shown: &[Worktree], cells: &Cells) -> String {
+        let dirty = shown
+            .iter()
+            .filter(|wt| cells.dirty(wt) == Some(true))
+            .count();
+        let usage = |wt: &Worktree| match self.sizes.as_ref() {
+            Some(sizes) => sizes.get(&wt.path).map(du::Usage::total),
+            None => cells
+                .cache
+                .get(wt)
+                .and_then(|status| status.usage)
+                .map(|usage| usage.total()),
+        };
+        let mut summary = format!(
+            "# {} ({}): {} worktree(s), {} dirty",
+            self.name,
+            self.root.display(),
+            shown.len(),
+            dirty
+        );
+        if let Some(total) = shown.iter().map(usage).sum::<Option<u64>>() {
+            summary.push_str(&format!(", {}", du::format_size(total)));
+        }
+        summary
+    }
+}
+
+/// The checked-out commit of each worktree that has one, from a single
+/// backend call per worktree for its id, author, time, and summary.
+fn head_commits(backend: &dyn Backend, worktrees: &[Worktree]) -> HashMap<PathBuf, Commit> {
+    let mut commits = HashMap::new();
+    for wt in worktrees {
+        if wt.head.is_none() || wt.prunable.is_some() || !wt.path.is_dir() {
+            continue;
+        }
+        match backend.head_commit(&wt.path) {
+            Ok(Some(commit)) => {
+                commits.insert(wt.path.clone(), commit);
+            }
+            Ok(None) => {}
+            Err(err) => {
+                tracing::warn!("could not read HEAD of '{}': {:#}", wt.path.display(), err);
+            }
+        }
+    }
+    commits
+}
+
+/// Whether each worktree whose directory exists has uncommitted or
+/// untracked changes, checked all at once.
+fn check_dirty(backend: &dyn Backend, worktrees: &[&Worktree]) -> HashMap<PathBuf, bool> {
+    let present: Vec<&Path> = worktrees
+        .iter()
+        .filter(|wt| wt.prunable.is_none() && wt.path.is_dir())
+        .map(|wt| wt.path.as_path())
+        .collect();
+    let results = backend.dirty_all(&present);
+    present
+        .into_iter()
+        .zip(results)
+        .filter_map(|(path, result)| match result {
+            Ok(dirty) => Some((path.to_path_buf(), dirty)),
+            Err(err) => {
+                tracing::warn!(
+                    "could not check '{}' for changes: {:#}",
+                    path.display(),
+                    err
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// The repositories terris has seen worktrees of, plus the one containing
+/// the current directory, each once, sorted by path.
+pub fn known_repos(backend: &dyn Backend) -> Result<Vec<PathBuf>> {
+    let mut candidates: Vec<PathBuf> = Metadata::load()?
+        .worktrees
+        .into_keys()
+        .filter(|path| path.is_dir())
+        .collect();
+    if let Ok(root) = repo_root(backend) {
+        candidates.push(root);
+    }
+    let mut seen = BTreeMap::new();
+    for path in candidates {
+        let Ok(root) = backend.root(&path) else {
+            continue;
+        };
+        let Ok(common) = backend.common_dir(&root) else {
+            continue;
+        };
+        let common = common.canonicalize().unwrap_or(common);
+        seen.entry(common).or_insert(root);
+    }
+    let mut roots: Vec<PathBuf> = seen
+        .into_values()
+        .map(|root| primary_root(backend, root.clone()).unwrap_or(root))
+        .collect();
+    roots.sort();
+    Ok(roots)
+}
+
+/// What the list's cells are filled in from.
+struct Cells<'a> {
+    backend: &'a dyn Backend,
+    /// Base directory for relative paths in the PATH column.
+    relative: Option<&'a Path>,
+    metadata: &'a Metadata,
+    cache: &'a Cache,
+    sizes: Option<&'a HashMap<PathBuf, du::Usage>>,
+    commits: Option<&'a HashMap<PathBuf, Commit>>,
+    ci: Option<&'a HashMap<String, CiStatus>>,
+    prs: Option<&'a HashMap<PathBuf, PullRequest>>,
+    tracking: &'a HashMap<String, Tracking>,
+    /// Dirty state checked for `--dirty` or `--clean`, more current than
+    /// the cache.
+    fresh: Option<&'a HashMap<PathBuf, bool>>,
+}
+
+impl Cells<'_> {
+    fn cell(&self, column: ListColumn, wt: &Worktree) -> String {
+        let meta = self.metadata.get(&wt.path);
+        let commit = self.commits.and_then(|commits| commits.get(&wt.path));
+        let ago = |time: Option<i64>| {
+            time.map_or("-".to_string(), |time| {
+                format_age(unix_now().saturating_sub(time))
+            })
+        };
+        match column {
+            ListColumn::Id => self
+                .metadata
+                .index(&wt.path)
+                .map_or("-".to_string(), |i| i.to_string()),
+            ListColumn::Name => worktree_name(wt),
+            ListColumn::Branch => worktree_branch_short(wt).unwrap_or("-").to_string(),
+            ListColumn::Size => {
+                let size = self
+                    .sizes
+                    .and_then(|sizes| sizes.get(&wt.path).copied())
+                    .unwrap_or_default();
+                du::format_size(size.total())
+            }
+            ListColumn::Path => match self.relative {
+                Some(base) => relative_path(&wt.path, base).to_string_lossy().to_string(),
+                None => wt.path.to_string_lossy().to_string(),
+            },
+            ListColumn::Flags => {
+                let tracking =
+                    worktree_branch_short(wt).and_then(|branch| self.tracking.get(branch).copied());
+                worktree_flags(wt, self.cache.get(wt), tracking)
+            }
+            ListColumn::Managed if wt.main => "-".to_string(),
+            ListColumn::Managed if self.metadata.managed(&wt.path) => "yes".to_string(),
+            ListColumn::Managed => "no".to_string(),
+            ListColumn::Dirty => match self.dirty(wt) {
+                Some(true) => "yes".to_string(),
+                Some(false) => "no".to_string(),
+                None => "-".to_string(),
+            },
+            ListColumn::Age => ago(meta.and_then(|meta| meta.created)),
+            ListColumn::Used => ago(gc::idle_since(wt, meta)),
+            ListColumn::Head => wt
+                .head
+                .as_deref()
+                .map_or("-".to_string(), |head| head.chars().take(7).collect()),
+            ListColumn::Committed => ago(commit.map(|commit| commit.time)),
+            ListColumn::Subject => commit.map_or("-".to_string(), |commit| commit.summary.clone()),
+            ListColumn::Author => commit.map_or("-".to_string(), |commit| commit.author.clone()),
+            ListColumn::Ci => worktree_branch_short(wt)
+                .and_then(|branch| self.ci?.get(branch))
+                .map_or("-".to_string(), |status| status.to_string()),
+            ListColumn::Pr => self
+                .prs
+                .and_then(|prs| prs.get(&wt.path))
+                .map_or("-".to_string(), |pr| pr.to_string()),
+        }
+    }
+
+    /// As checked for `--dirty` or the dirty column, else from the daemon's
+    /// cache when it knows, else asked of the backend.
+    fn dirty(&self, wt: &Worktree) -> Option<bool> {
+        if let Some(&dirty) = self.fresh.and_then(|fresh| fresh.get(&wt.path)) {
+            return Some(dirty);
+        }
+        if let Some(status) = self.cache.get(wt) {
+            return Some(status.summary.has_uncommitted());
+        }
+        if wt.prunable.is_some() || !wt.path.is_dir() {
+            return None;
+        }
+        self.backend.is_dirty(&wt.path).ok()
+    }
+}
+
+/// The worktree list as lines, each ending in a newline.
+fn worktree_table(
+    worktrees: &[Worktree],
+    columns: &[ListColumn],
+    cells: &Cells,
+    paint: Paint,
+) -> Vec<String> {
+    let rows: Vec<Vec<String>> = worktrees
+        .iter()
+        .map(|wt| columns.iter().map(|&col| cells.cell(col, wt)).collect())
+        .collect();
+    let layout: Vec<table::Column> = columns.iter().map(|col| col.column()).collect();
+    let mut lines = table::render(&layout, &rows, table::terminal_width());
+    // Color after padding so escape codes do not count toward widths.
+    for (line, row) in lines.iter_mut().skip(1).zip(&rows) {
+        for ((cell, raw), column) in line.iter_mut().zip(row).zip(columns) {
+            *cell = match column {
+                ListColumn::Branch if raw != "-" => paint.green(cell),
+                ListColumn::Flags => paint.flags(cell),
+                ListColumn::Dirty if raw == "yes" => paint.red(cell),
+                ListColumn::Ci if raw == "passed" => paint.green(cell),
+                ListColumn::Ci if raw == "failed" => paint.red(cell),
+                ListColumn::Ci if raw == "pending" => paint.yellow(cell),
+                ListColumn::Pr if raw.ends_with("(approved)") => paint.green(cell),
+                ListColumn::Pr if raw.ends_with("(changes requested)") => paint.red(cell),
+                _ => continue,
+            };
+        }
+    }
+    lines
+        .into_iter()
+        .map(|line| format!("{}\n", line.join(" ")))
+        .collect()
+}
+
+/// `rows` as CSV or TSV, after a header of column names, with a leading
+/// `repo` column when `grouped`.
+fn delimited(
+    columns: &[ListColumn],
+    rows: &[Vec<String>],
+    grouped: bool,
+    format: ListFormat,
+) -> String {
+    let (separator, field): (&str, fn(&str) -> String) = match format {
+        ListFormat::Tsv => ("\t", tsv_field),
+        _ => (",", csv_field),
+    };
+    let names = columns
+        .iter()
+        .filter_map(|col| col.to_possible_value())
+        .map(|value| value.get_name().to_string());
+    let header: Vec<String> = grouped
+        .then(|| "repo".to_string())
+        .into_iter()
+        .chain(names)
+        .collect();
+    std::iter::once(&header)
+        .chain(rows)
+        .map(|row| {
+            let fields: Vec<String> = row.iter().map(|cell| field(cell)).collect();
+            format!("{}\n", fields.join(separator))
+        })
+        .collect()
+}
+
+/// Quote a CSV field when it holds a comma, quote, or line break (RFC 4180).
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Escape what TSV cannot quote: backslashes, tabs, and line breaks.
+fn tsv_field(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delimited_output_quotes_or_escapes_special_characters() {
+        let columns = [ListColumn::Name, ListColumn::Path];
+        let rows = vec![vec!["a,\"b\"".to_string(), "/tmp/x\ty".to_string()]];
+        assert_eq!(
+            delimited(&columns, &rows, false, ListFormat::Csv),
+            "name,path\n\"a,\"\"b\"\"\",/tmp/x\ty\n"
+        );
+        assert_eq!(
+            delimited(&columns, &rows, false, ListFormat::Tsv),
+            "name\tpath\na,\"b\"\t/tmp/x\\ty\n"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! An advisory lock on the registry, so that terris processes running at the
+//! same time (parallel agents, say) neither pick the same directory for new
+//! worktrees nor lose each other's metadata updates.
+//!
+//! The lock is `~/.terris-worktrees/.lock`, held with `flock` and released
+//! by the OS even if terris is killed. Within one thread it is reentrant: a
+//! step that takes it while a caller already holds it just shares it. Other
+//! threads, such as `jobs::map` workers, open the file themselves and so
+//! wait for it like another process would.
+
+use std::cell::RefCell;
+use std::fs::{File, TryLockError};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::error::Error;
+use crate::registry_base_dir;
+
+const POLL: Duration = Duration::from_millis(50);
+
+/// Seconds to wait for another process to let go of the lock; the
+/// `registry-wait` config key.
+static WAIT: AtomicU64 = AtomicU64::new(30);
+
+pub fn set_wait(seconds: u64) {
+    WAIT.store(seconds, Ordering::Relaxed);
+}
+
+thread_local! {
+    /// The lock file while this thread holds it, with how many guards share
+    /// it.
+    static HELD: RefCell<Option<(File, usize)>> = const { RefCell::new(None) };
+}
+
+/// Holds the registry lock until dropped, on the thread that took it.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct RegistryLock(PhantomData<*const ()>);
+
+/// Take the registry lock, waiting a while for another process, or another
+/// thread, to release it.
+pub fn registry() -> Result<RegistryLock> {
+    let shared = HELD.with_borrow_mut(|held| match held {
+        Some((_, count)) => {
+            *count += 1;
+            true
+        }
+        None => false,
+    });
+    if shared {
+        return Ok(RegistryLock(PhantomData));
+    }
+    let dir = registry_base_dir()?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("create '{}'", dir.display()))?;
+    let path = dir.join(".lock");
+    let file = File::create(&path).with_context(|| format!("open '{}'", path.display()))?;
+    let wait = WAIT.load(Ordering::Relaxed);
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) if started.elapsed() < Duration::from_secs(wait) => {
+                std::thread::sleep(POLL);
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(Error::RegistryBusy { seconds: wait }.into());
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("lock '{}'", path.display()));
+            }
+        }
+    }
+    HELD.set(Some((file, 1)));
+    Ok(RegistryLock(PhantomData))
+}
+
+impl Drop for RegistryLock {
+    fn drop(&mut self) {
+        HELD.with_borrow_mut(|held| {
+            if let Some((_, count)) = held.as_mut() {
+                *count -= 1;
+                if *count == 0 {
+                    // Closing the file releases the lock.
+                    *held = None;
+                }
+            }
+        });
+    }
+}
@@ -1,330 +1,2018 @@
+use std::borrow::Cow;
 use std::ffi::OsStr;
-use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::io::{IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
 
 use anyhow::{Context, Result, bail};
-use clap::{Parser, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+mod adopt;
+mod archive;
+mod backend;
+mod cache;
+mod cargo;
+mod carry;
+mod checkout;
+mod clean;
+mod clean_artifacts;
+mod clipboard;
+mod color;
+mod completions;
+mod config;
+mod configure;
+mod current;
+mod daemon;
+mod devcontainer;
+mod diff;
+mod direnv;
+mod du;
+mod env;
+mod ephemeral;
+mod error;
+mod find;
+mod foreach;
+mod forge;
+mod gc;
+mod graduate;
+mod grep;
+mod help;
+mod hooks;
+mod info;
+mod jobs;
+mod jump;
+mod jumper;
+mod list;
+mod lock;
+mod metadata;
+mod naming;
+mod notify;
+mod open;
+mod pager;
+mod plugin;
+mod pool;
+mod port;
+mod prompt;
+mod rebase_all;
+mod registry;
+mod relocate;
+mod repos;
+mod reset;
+mod retention;
+mod script;
+mod serve;
+mod stack;
+mod sync;
+mod table;
+mod with;
+mod workspace;
+mod worktree_config;
+
+use backend::{Backend, ChangeSummary, Commit, Tracking};
+use color::{ColorChoice, Paint};
+use config::{Collision, Config};
+use error::Error;
+use forge::{CiStatus, PullRequest};
+use jumper::Jumper;
+use list::{ListColumn, ListFilters, ListFormat, ListOptions};
+use metadata::{Metadata, WorktreeMeta};
+use table::Align;
 
 #[derive(Parser)]
-#[command(name = "terris", version, about = "Git worktree manager")]
+#[command(
+    name = "terris",
+    version,
+    about = "Git worktree manager",
+    disable_help_subcommand = true,
+    after_help = "Run `terris help` for the topics registry, naming, hooks, and targets."
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Print shell completion script (bash or zsh)
     #[arg(long, value_enum, conflicts_with_all = ["all", "rm", "branch"])]
     completions: Option<CompletionShell>,
     /// List all worktrees, including those without branches
     #[arg(long, conflicts_with_all = ["rm", "branch"])]
     all: bool,
+    #[command(flatten)]
+    create: CreateOptions,
+    /// Order of the worktree list
+    #[arg(long, value_enum, value_name = "key", conflicts_with_all = ["rm", "branch"])]
+    sort: Option<ListSort>,
+    /// Add a SIZE column with each worktree's disk usage
+    #[arg(long, conflicts_with_all = ["rm", "branch"])]
+    du: bool,
+    /// Add COMMITTED, SUBJECT, and AUTHOR columns for each worktree's HEAD
+    #[arg(long, conflicts_with_all = ["rm", "branch"])]
+    last_commit: bool,
+    #[command(flatten)]
+    filters: ListFilters,
+    /// Show the list the way a view in [list.views] describes
+    #[arg(long, value_name = "name", conflicts_with_all = ["rm", "branch"])]
+    view: Option<String>,
+    /// Add a PR column with each branch's open pull or merge request
+    #[arg(long, conflicts_with_all = ["rm", "branch"])]
+    prs: bool,
+    /// Columns of the worktree list, in order, e.g. name,branch,age,dirty,path
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "columns",
+        conflicts_with_all = ["rm", "branch"]
+    )]
+    columns: Option<Vec<ListColumn>>,
+    /// Print the worktree list as a table, CSV, or TSV
+    #[arg(
+        long,
+        value_enum,
+        value_name = "format",
+        default_value_t,
+        conflicts_with_all = ["rm", "branch", "json"]
+    )]
+    output: ListFormat,
+    /// List the worktrees of every repository terris knows, grouped by repository
+    #[arg(short, long, conflicts_with_all = ["rm", "branch"])]
+    global: bool,
+    /// List only the worktrees of the repository with this directory name
+    #[arg(long, value_name = "name", conflicts_with_all = ["rm", "branch"])]
+    repo: Option<String>,
+    /// Show worktree paths relative to this directory [default: the current
+    /// one]
+    #[arg(
+        long,
+        value_name = "base",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with_all = ["rm", "branch"]
+    )]
+    relative: Option<Option<PathBuf>>,
     /// Remove a worktree by branch name
     #[arg(long = "rm", value_name = "branch", conflicts_with_all = ["branch"])]
     rm: Option<String>,
     /// Branch name to open (create if missing)
     #[arg(value_name = "branch", conflicts_with_all = ["all", "rm"])]
     branch: Option<String>,
+    /// Print what would be done without changing anything
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Log each git command, its working directory, and duration to stderr
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Emit JSON output, including errors on stderr (also TERRIS_OUTPUT=json)
+    #[arg(long, global = true)]
+    json: bool,
+    /// When to color output: auto colors on a terminal unless NO_COLOR is set
+    #[arg(long, global = true, value_enum, value_name = "when", default_value_t)]
+    color: ColorChoice,
+    /// Print long listings directly instead of through $PAGER or less
+    #[arg(long, global = true)]
+    no_pager: bool,
+    /// Run at most this many git (or other) subprocesses at once when working
+    /// on every worktree [default: the number of CPUs]
+    #[arg(short, long, global = true, value_name = "n")]
+    jobs: Option<NonZeroUsize>,
+    /// Register created worktrees with a directory jumper and drop removed
+    /// ones (overrides the `hook` config key)
+    #[arg(long, global = true, value_enum, value_name = "jumper")]
+    hook: Option<Jumper>,
+}
+
+/// Flags for creating a worktree, shared by `terris <branch>` and `terris create`.
+#[derive(Args)]
+struct CreateOptions {
+    /// What to do when the branch already has a worktree
+    #[arg(long, value_enum, value_name = "strategy", requires = "branch")]
+    on_collision: Option<Collision>,
+    /// Create the worktree at <registry>/<repo>/<branch>, without a random suffix
+    #[arg(long, requires = "branch")]
+    stable: bool,
+    /// Say what the new worktree is for; hooks see it as TERRIS_DESCRIPTION
+    #[arg(long, value_name = "text", requires = "branch")]
+    description: Option<String>,
+}
+
+impl CreateOptions {
+    /// Let the flags override the config file.
+    fn apply(&self, config: &mut Config) {
+        if let Some(collision) = self.on_collision {
+            config.on_collision = collision;
+        }
+        config.stable |= self.stable;
+        if let Some(description) = &self.description {
+            config.description = Some(description.clone());
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create (or reuse) the worktree for a branch and print its path
+    Create(CreateArgs),
+    /// Remove worktrees by branch name
+    Delete(DeleteArgs),
+    /// Remove worktrees whose branch is merged or whose upstream is gone
+    Clean(clean::CleanArgs),
+    /// Pack a worktree's files into a timestamped .tar.zst
+    Archive(archive::ArchiveArgs),
+    /// Print the path of a worktree
+    Path(PathArgs),
+    /// Print the primary worktree's path, from any worktree
+    Root(RootArgs),
+    /// Show everything known about one worktree
+    Info(info::InfoArgs),
+    /// Find the worktrees whose unmerged commits have a matching message
+    Find(find::FindArgs),
+    /// Search the files of every worktree, with ripgrep or git grep
+    Grep(grep::GrepArgs),
+    /// Print the worktree containing the current directory
+    Current(current::CurrentArgs),
+    /// Print a short segment like `repo:branch↑2✗` for shell prompts
+    Prompt(prompt::PromptArgs),
+    /// List worktrees from most to least recently used
+    Recent,
+    /// Print the most frecent worktree, in any repository, matching a query
+    Jump(jump::JumpArgs),
+    /// Remove ephemeral worktrees whose shell is gone
+    Gc(gc::GcArgs),
+    /// Run a command in a throwaway worktree checked out at a ref
+    With(with::WithArgs),
+    /// Run a command in every worktree at once, output prefixed by worktree
+    Foreach(foreach::ForeachArgs),
+    /// Create, list, delete, and run in one branch's worktrees across the
+    /// repositories of a workspace
+    Ws(workspace::WsArgs),
+    /// Lease and return pre-created worktrees
+    Pool(pool::PoolArgs),
+    /// Discard all changes in a worktree, optionally moving it to another ref
+    Reset(reset::ResetArgs),
+    /// Switch an existing worktree to another branch
+    Checkout(checkout::CheckoutArgs),
+    /// Let terris manage worktrees created with plain git
+    Adopt(adopt::AdoptArgs),
+    /// Stop managing worktrees, leaving them and their files as they are
+    Disown(adopt::DisownArgs),
+    /// Print every known repository, worktree, and its metadata as JSON
+    Export,
+    /// Recreate and record the worktrees from `terris export`
+    Import(registry::ImportArgs),
+    /// Reconnect worktrees and terris's records after the repository moved
+    Relocate(relocate::RelocateArgs),
+    /// List the repositories terris has recorded worktrees of
+    Repos(repos::ReposArgs),
+    /// Install shell completions
+    Completions(completions::CompletionsArgs),
+    /// Read and change terris's config file
+    Config(configure::ConfigArgs),
+    /// Show a command's help, or a page on a topic such as naming or hooks
+    Help(help::HelpArgs),
+    /// Print the man page, or write all of them into a directory
+    #[command(hide = true)]
+    Man(help::ManArgs),
+    /// Read or set a git config value for one worktree only
+    ConfigWorktree(worktree_config::ConfigWorktreeArgs),
+    /// Show the differences between two worktrees, uncommitted changes included
+    Diff(diff::DiffArgs),
+    /// Land a worktree's branch on its base, then remove the worktree and branch
+    Graduate(graduate::GraduateArgs),
+    /// Rebase every clean worktree's branch onto the base branch
+    RebaseAll(rebase_all::RebaseAllArgs),
+    /// Fetch once, then fast-forward every clean worktree behind its upstream
+    Sync,
+    /// Show stacked worktrees as a tree, and restack them
+    Stack(stack::StackArgs),
+    /// Show each worktree's disk usage, split into files and ignored artifacts
+    Du,
+    /// Delete ignored build output (target/, node_modules/, ...) in worktrees
+    CleanArtifacts(clean_artifacts::CleanArtifactsArgs),
+    /// Print a port reserved for a worktree (and service), allocating it once
+    Port(port::PortArgs),
+    /// Serve worktree operations to agents and editors
+    Serve(serve::ServeArgs),
+    /// Prune, collect, and cache worktree status in the background
+    Daemon(daemon::DaemonArgs),
+}
+
+#[derive(Args)]
+struct CreateArgs {
+    /// Branch name to open
+    #[arg(value_name = "branch")]
+    branch: String,
+    #[command(flatten)]
+    options: CreateOptions,
+    /// Open a shell in a new worktree and remove it, with its branch if
+    /// terris created it, when the shell exits
+    #[arg(long)]
+    ephemeral: bool,
+    /// Move the current worktree's uncommitted changes into the new worktree
+    #[arg(long, conflicts_with = "ephemeral")]
+    carry: bool,
+    /// With --carry, also move untracked files
+    #[arg(short = 'u', long, requires = "carry")]
+    include_untracked: bool,
+    /// Start the branch where a stash was made and apply (then drop) the stash
+    #[arg(
+        long,
+        value_name = "stash",
+        num_args = 0..=1,
+        default_missing_value = "stash@{0}",
+        conflicts_with_all = ["ephemeral", "carry"]
+    )]
+    from_stash: Option<String>,
+    /// Apply a patch file (`-` for stdin) in the worktree after checkout
+    #[arg(
+        long,
+        value_name = "patch",
+        conflicts_with_all = ["ephemeral", "carry", "from_stash"]
+    )]
+    apply: Option<PathBuf>,
+    /// Stack the branch on another worktree's branch, starting it there
+    #[arg(
+        long,
+        value_name = "parent",
+        conflicts_with_all = ["ephemeral", "carry", "from_stash", "apply"]
+    )]
+    on: Option<String>,
+    /// Link the repository's .devcontainer into the new worktree; `=up` also
+    /// starts the container
+    #[arg(
+        long,
+        value_enum,
+        value_name = "mode",
+        num_args = 0..=1,
+        default_missing_value = "link"
+    )]
+    devcontainer: Option<devcontainer::Devcontainer>,
+    /// After printing the path, open the worktree in an editor, tmux, or a
+    /// shell [default: the `open` config key, else editor]
+    #[arg(
+        long,
+        value_enum,
+        value_name = "action",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with = "ephemeral"
+    )]
+    open: Option<Option<open::Open>>,
+    /// Also put the path on the clipboard
+    #[arg(long, conflicts_with = "ephemeral")]
+    copy: bool,
+    /// Do not open the worktree, even when the `open` config key says to
+    #[arg(long, conflicts_with_all = ["open", "ephemeral"])]
+    no_open: bool,
+}
+
+#[derive(Args)]
+struct PathArgs {
+    /// Branch name, or `main` (primary worktree), `.` (current), `-` (previous)
+    #[arg(value_name = "target")]
+    target: String,
+    /// Also put the path on the clipboard
+    #[arg(long)]
+    copy: bool,
+    /// Print the path relative to this directory [default: the current one]
+    #[arg(long, value_name = "base", num_args = 0..=1, require_equals = true)]
+    relative: Option<Option<PathBuf>>,
+}
+
+#[derive(Args)]
+struct RootArgs {
+    /// Print the git directory shared by every worktree instead
+    #[arg(long)]
+    git_dir: bool,
 }
 
-#[derive(Clone, Copy, Debug, ValueEnum)]
+#[derive(Args)]
+struct DeleteArgs {
+    /// Branch names whose worktrees should be removed
+    #[arg(
+        value_name = "branch",
+        required_unless_present_any = ["interactive", "branch_glob", "path_prefix", "older_than"]
+    )]
+    branches: Vec<String>,
+    /// Pick the worktrees to remove from a checklist
+    #[arg(short, long, conflicts_with = "branches")]
+    interactive: bool,
+    /// Remove worktrees whose branch matches a glob, e.g. 'hotfix/*'
+    #[arg(long = "branch", value_name = "glob", conflicts_with = "branches")]
+    branch_glob: Option<glob::Pattern>,
+    /// Remove worktrees located under this directory
+    #[arg(long, value_name = "dir", conflicts_with = "branches")]
+    path_prefix: Option<PathBuf>,
+    /// Remove worktrees whose last commit is older than this, e.g. 30d, 2w, 12h
+    #[arg(long, value_name = "age", conflicts_with = "branches")]
+    older_than: Option<retention::Age>,
+    /// Discard uncommitted changes without asking
+    #[arg(short, long)]
+    force: bool,
+    /// Pack each worktree into a timestamped .tar.zst in this directory
+    /// (default: current directory) before removing it
+    #[arg(long, value_name = "dir", num_args = 0..=1, default_missing_value = ".")]
+    archive: Option<PathBuf>,
+}
+
+/// How worktrees are removed by `delete` and `--rm`.
+#[derive(Default)]
+struct RemoveOptions<'a> {
+    /// Skip the confirmation for worktrees with uncommitted changes.
+    force: bool,
+    dry_run: bool,
+    /// Directory to archive each worktree into before removing it.
+    archive: Option<&'a Path>,
+    /// Directory jumper to drop removed worktrees from.
+    hook: Option<Jumper>,
+    /// Let targets match worktree names fuzzily.
+    fuzzy: bool,
+    /// Hooks run in each worktree before it is removed.
+    pre_delete: &'a [String],
+}
+
+impl DeleteArgs {
+    fn remove_options<'a>(&'a self, config: &'a Config, dry_run: bool) -> RemoveOptions<'a> {
+        RemoveOptions {
+            force: self.force,
+            dry_run,
+            archive: self.archive.as_deref(),
+            hook: config.hook,
+            fuzzy: config.fuzzy,
+            pre_delete: &config.hooks.pre_delete,
+        }
+    }
+
+    fn has_filters(&self) -> bool {
+        self.branch_glob.is_some() || self.path_prefix.is_some() || self.older_than.is_some()
+    }
+
+    /// Whether `wt` passes every filter given on the command line.
+    fn matches(&self, backend: &dyn Backend, wt: &Worktree, now: i64) -> bool {
+        if let Some(pattern) = &self.branch_glob
+            && !worktree_branch_short(wt).is_some_and(|b| pattern.matches(b))
+        {
+            return false;
+        }
+        if let Some(prefix) = &self.path_prefix {
+            let prefix = std::path::absolute(prefix).unwrap_or_else(|_| prefix.clone());
+            if !wt.path.starts_with(prefix) {
+                return false;
+            }
+        }
+        if let Some(max_age) = self.older_than {
+            match backend.last_commit_time(&wt.path) {
+                Ok(Some(time)) if now.saturating_sub(time) > max_age.0 => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum ListSort {
+    /// Alphabetically by name
+    Name,
+    /// Most recently used first
+    Recent,
+    /// Oldest first, by when terris created them
+    Age,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
 enum CompletionShell {
     Bash,
     Zsh,
     Fish,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize)]
 struct Worktree {
     path: PathBuf,
     head: Option<String>,
     branch: Option<String>,
     detached: bool,
     locked: bool,
+    /// Reason given to `git worktree lock --reason`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lock_reason: Option<String>,
     prunable: Option<String>,
+    /// Whether this is the repository's main worktree.
+    main: bool,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
+    if clipboard::hold() {
+        return ExitCode::SUCCESS;
+    }
+    if let Some(result) = plugin::dispatch() {
+        return report(result, error::json_requested(false));
+    }
     let cli = Cli::parse();
+    if cli.verbose {
+        init_logging();
+    }
+    let json = error::json_requested(cli.json);
+    report(run(cli, json), json)
+}
+
+fn report(result: Result<()>, json: bool) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            if json {
+                eprintln!("{}", error::to_json(&err));
+            } else {
+                eprintln!("Error: {err:?}");
+            }
+            ExitCode::from(error::exit_code(&err))
+        }
+    }
+}
+
+fn run(cli: Cli, json: bool) -> Result<()> {
     if let Some(shell) = cli.completions {
         print_completions(shell);
         return Ok(());
     }
+    if let Some(Command::Jump(args)) = &cli.command {
+        // Works from anywhere, including outside a repository.
+        return jump::cmd_jump(args);
+    }
+    if let Some(Command::Prompt(args)) = &cli.command {
+        // Runs on every prompt, so skip the config and backend detection.
+        return prompt::cmd_prompt(args);
+    }
+    if let Some(Command::Config(args)) = &cli.command {
+        // Works even when the config file does not parse, to fix it.
+        return configure::cmd_config(args, cli.dry_run);
+    }
+    if let Some(Command::Completions(args)) = &cli.command {
+        return completions::cmd_completions(args, cli.dry_run);
+    }
+    if let Some(Command::Help(args)) = &cli.command {
+        return help::cmd_help(args, cli.no_pager);
+    }
+    if let Some(Command::Man(args)) = &cli.command {
+        return help::cmd_man(args, cli.dry_run);
+    }
+    if !cli.dry_run {
+        completions::verify();
+    }
+    let mut config = Config::load()?;
+    backend::git::set_program(config.git.as_deref());
+    if let Some(retries) = config.lock_retries {
+        backend::git::set_lock_retries(retries);
+    }
+    if let Some(wait) = config.registry_wait {
+        lock::set_wait(wait);
+    }
+    jobs::set_limit(cli.jobs.or(config.jobs));
+    backend::git::set_timeout(
+        config
+            .git_timeout
+            .map(|secs| std::time::Duration::from_secs(secs.get())),
+    );
+    if cli.hook.is_some() {
+        config.hook = cli.hook;
+    }
+    cli.create.apply(&mut config);
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let backend = backend::detect(&cwd, cli.dry_run);
+    if let Some(command) = cli.command {
+        return match command {
+            Command::Create(args) => {
+                args.options.apply(&mut config);
+                if let Some(mode) = args.devcontainer {
+                    config.devcontainer = mode;
+                }
+                if args.ephemeral {
+                    return ephemeral::cmd_create_ephemeral(
+                        backend.as_ref(),
+                        &args.branch,
+                        &config,
+                        cli.dry_run,
+                    );
+                }
+                let path = if let Some(stash) = &args.from_stash {
+                    carry::cmd_create_from_stash(
+                        backend.as_ref(),
+                        &args.branch,
+                        stash,
+                        &config,
+                        cli.dry_run,
+                    )
+                } else if let Some(parent) = &args.on {
+                    stack::cmd_create_on(
+                        backend.as_ref(),
+                        &args.branch,
+                        parent,
+                        &config,
+                        cli.dry_run,
+                    )
+                } else if args.carry {
+                    carry::cmd_create_carrying(
+                        backend.as_ref(),
+                        &args.branch,
+                        args.include_untracked,
+                        &config,
+                        cli.dry_run,
+                    )
+                } else {
+                    let patch = args.apply.as_deref().map(read_patch).transpose()?;
+                    cmd_ensure_branch(
+                        backend.as_ref(),
+                        &args.branch,
+                        patch.as_deref(),
+                        &config,
+                        cli.dry_run,
+                    )
+                }?;
+                if args.copy {
+                    clipboard::copy(&path, cli.dry_run);
+                }
+                if let Some(action) = open::choose(args.open, args.no_open, config.open) {
+                    open::launch(backend.as_ref(), &path, action, cli.dry_run);
+                }
+                Ok(())
+            }
+            Command::Delete(args) => cmd_delete(backend.as_ref(), &args, &config, cli.dry_run),
+            Command::Clean(args) => clean::cmd_clean(backend.as_ref(), &args, &config, cli.dry_run),
+            Command::Archive(args) => {
+                archive::cmd_archive(backend.as_ref(), &args, &config, cli.dry_run)
+            }
+            Command::Path(args) => cmd_path(backend.as_ref(), &args, &config, cli.dry_run),
+            Command::Root(args) => cmd_root(backend.as_ref(), &args),
+            Command::Info(args) => info::cmd_info(backend.as_ref(), &args, &config, json),
+            Command::Find(args) => find::cmd_find(backend.as_ref(), &args, json),
+            Command::Grep(args) => grep::cmd_grep(backend.as_ref(), &args, json),
+            Command::Current(args) => current::cmd_current(backend.as_ref(), &args, json),
+            Command::Recent => cmd_recent(backend.as_ref(), json),
+            Command::Jump(_)
+            | Command::Prompt(_)
+            | Command::Config(_)
+            | Command::Completions(_)
+            | Command::Help(_)
+            | Command::Man(_) => {
+                unreachable!("handled before backend detection")
+            }
+            Command::Gc(args) => gc::cmd_gc(backend.as_ref(), &args, &config, cli.dry_run),
+            Command::With(args) => with::cmd_with(backend.as_ref(), &args, &config, cli.dry_run),
+            Command::Foreach(args) => foreach::cmd_foreach(
+                backend.as_ref(),
+                &args,
+                &config,
+                Paint::new(cli.color),
+                cli.dry_run,
+            ),
+            Command::Ws(args) => workspace::cmd_ws(
+                backend.as_ref(),
+                &args,
+                &config,
+                Paint::new(cli.color),
+                json,
+                cli.dry_run,
+            ),
+            Command::Pool(args) => pool::cmd_pool(backend.as_ref(), &args, &config, cli.dry_run),
+            Command::Diff(args) => diff::cmd_diff(backend.as_ref(), &args, &config),
+            Command::CleanArtifacts(args) => {
+                clean_artifacts::cmd_clean_artifacts(backend.as_ref(), &args, &config, cli.dry_run)
+            }
+            Command::Port(args) => port::cmd_port(backend.as_ref(), &args, &config, cli.dry_run),
+            Command::Serve(args) => serve::cmd_serve(backend.as_ref(), &args, &config, cli.dry_run),
+            Command::Daemon(args) => {
+                daemon::cmd_daemon(backend.as_ref(), &args, &config, cli.dry_run)
+            }
+            Command::Du => du::cmd_du(backend.as_ref(), json),
+            Command::Stack(args) => stack::cmd_stack(backend.as_ref(), &args, cli.dry_run),
+            Command::Sync => sync::cmd_sync(backend.as_ref(), cli.dry_run),
+            Command::RebaseAll(args) => {
+                rebase_all::cmd_rebase_all(backend.as_ref(), &args, cli.dry_run)
+            }
+            Command::Graduate(args) => {
+                graduate::cmd_graduate(backend.as_ref(), &args, &config, cli.dry_run)
+            }
+            Command::Checkout(args) => {
+                checkout::cmd_checkout(backend.as_ref(), &args, &config, cli.dry_run)
+            }
+            Command::Reset(args) => reset::cmd_reset(backend.as_ref(), &args, &config, cli.dry_run),
+            Command::Adopt(args) => {
+                adopt::cmd_adopt(backend.as_ref(), &args, &config, json, cli.dry_run)
+            }
+            Command::Disown(args) => {
+                adopt::cmd_disown(backend.as_ref(), &args, &config, cli.dry_run)
+            }
+            Command::Export => registry::cmd_export(backend.as_ref()),
+            Command::Import(args) => registry::cmd_import(&args, cli.dry_run),
+            Command::Relocate(args) => relocate::cmd_relocate(&args, cli.dry_run),
+            Command::Repos(args) => repos::cmd_repos(backend.as_ref(), &args, json, cli.dry_run),
+            Command::ConfigWorktree(args) => {
+                worktree_config::cmd_config_worktree(backend.as_ref(), &args, &config, cli.dry_run)
+            }
+        };
+    }
     if let Some(branch) = cli.rm {
-        return cmd_delete_branch(&branch);
+        let opts = RemoveOptions {
+            dry_run: cli.dry_run,
+            hook: config.hook,
+            fuzzy: config.fuzzy,
+            pre_delete: &config.hooks.pre_delete,
+            ..RemoveOptions::default()
+        };
+        return cmd_delete_branch(backend.as_ref(), &branch, &opts);
     }
     if let Some(branch) = cli.branch {
-        return cmd_ensure_branch(&branch);
+        return cmd_ensure_branch(backend.as_ref(), &branch, None, &config, cli.dry_run)
+            .map(|_| ());
+    }
+    // Flags given alongside a view take precedence over it.
+    let view = match &cli.view {
+        Some(name) => Some(config.list.view(name)?),
+        None => None,
+    };
+    let mut filters = cli.filters;
+    let mut requested = cli.columns;
+    if let Some(view) = view {
+        filters.extend(&view.filter);
+        requested = requested.or_else(|| view.columns.clone());
     }
-    cmd_list(cli.all)
+    let opts = ListOptions {
+        all: cli.all || view.is_some_and(|view| view.all),
+        sort: cli.sort.or(view.and_then(|view| view.sort)),
+        filters,
+        columns: list::columns(requested, &config.list, cli.du, cli.last_commit, cli.prs),
+        format: cli.output,
+        global: cli.global,
+        repo: cli.repo,
+        relative: relative_base(cli.relative),
+        json,
+        paint: Paint::new(cli.color),
+        no_pager: cli.no_pager,
+    };
+    list::cmd_list(backend.as_ref(), &opts, &config)
+}
+
+fn init_logging() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .without_time()
+        .init();
 }
 
 fn print_completions(shell: CompletionShell) {
+    println!("{}", completion_script(shell));
+}
+
+/// The completion script for `shell`.
+fn completion_script(shell: CompletionShell) -> &'static str {
     match shell {
         CompletionShell::Bash => {
-            println!(
-                r#"_terris_branches() {{
+            r#"_terris_branches() {
   git for-each-ref --format='%(refname:short)' refs/heads 2>/dev/null
-}}
+}
 
-_terris_complete() {{
+_terris_complete() {
   local cur prev
-  cur="${{COMP_WORDS[COMP_CWORD]}}"
-  prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+  cur="${COMP_WORDS[COMP_CWORD]}"
+  prev="${COMP_WORDS[COMP_CWORD-1]}"
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "delete" ]]; then
+    if [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--interactive --branch --path-prefix --older-than --force --archive" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "clean" ]]; then
+    if [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--into --delete-branch" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "archive" ]]; then
+    if [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--output" -- "$cur"))
+    elif [[ "$prev" == "-o" || "$prev" == "--output" ]]; then
+      COMPREPLY=($(compgen -d -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "create" && "$prev" != "--on-collision" ]]; then
+    if [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--ephemeral --carry --include-untracked --from-stash --apply --on --devcontainer --open --no-open --copy --description --on-collision --stable" -- "$cur"))
+    elif [[ "$prev" == "--apply" ]]; then
+      COMPREPLY=($(compgen -f -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "reset" ]]; then
+    if [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--to --force --hooks" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "prompt" ]]; then
+    COMPREPLY=($(compgen -W "--starship --starship-config" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "root" ]]; then
+    COMPREPLY=($(compgen -W "--git-dir" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "current" ]]; then
+    COMPREPLY=($(compgen -W "--name --branch --path" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "daemon" ]]; then
+    COMPREPLY=($(compgen -W "--interval --once" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "serve" ]]; then
+    COMPREPLY=($(compgen -W "--mcp --http" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "clean-artifacts" ]]; then
+    COMPREPLY=($(compgen -W "--filter" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "find" ]]; then
+    if [[ "$prev" == "--base" ]]; then
+      COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "--message --ignore-case --base" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "grep" ]]; then
+    COMPREPLY=($(compgen -W "--ignore-case --fixed-strings --filter" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "foreach" ]]; then
+    if [[ "$prev" == "--output" ]]; then
+      COMPREPLY=($(compgen -W "stream buffer" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "--filter --output --fail-fast" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "stack" ]]; then
+    COMPREPLY=($(compgen -W "--restack" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "rebase-all" ]]; then
+    if [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--onto --filter" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "graduate" ]]; then
+    if [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--into --rebase --merge --squash --push" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "diff" ]]; then
+    COMPREPLY=($(compgen -W "main . - $(_terris_branches)" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "adopt" ]]; then
+    if [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--all" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W ". $(_terris_branches)" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "disown" ]]; then
+    COMPREPLY=($(compgen -W ". $(_terris_branches)" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "gc" ]]; then
+    COMPREPLY=($(compgen -W "--remove-orphans" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "relocate" ]]; then
+    COMPREPLY=($(compgen -d -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -eq 2 && "${COMP_WORDS[1]}" == "repos" ]]; then
+    COMPREPLY=($(compgen -W "forget" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -eq 2 && "${COMP_WORDS[1]}" == "import" ]]; then
+    COMPREPLY=($(compgen -f -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "completions" ]]; then
+    if [[ $COMP_CWORD -eq 2 ]]; then
+      COMPREPLY=($(compgen -W "install" -- "$cur"))
+    elif [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--rc --yes" -- "$cur"))
+    else
+      COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "config" ]]; then
+    if [[ $COMP_CWORD -eq 2 ]]; then
+      COMPREPLY=($(compgen -W "get set unset edit path" -- "$cur"))
+    elif [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--repo" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -eq 2 && "${COMP_WORDS[1]}" == "help" ]]; then
+    COMPREPLY=($(compgen -W "registry naming hooks targets create delete clean archive path root info find grep current prompt recent jump gc with foreach ws pool reset checkout adopt disown export import relocate repos completions config config-worktree diff graduate rebase-all sync stack du clean-artifacts port serve daemon" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "config-worktree" ]]; then
+    if [[ "$cur" == -* ]]; then
+      COMPREPLY=($(compgen -W "--unset" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 2 ]]; then
+      COMPREPLY=($(compgen -W "main . - $(_terris_branches)" -- "$cur"))
+    fi
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "checkout" ]]; then
+    COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -eq 2 && "${COMP_WORDS[1]}" == "pool" ]]; then
+    COMPREPLY=($(compgen -W "acquire release" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -eq 2 && "${COMP_WORDS[1]}" == "ws" ]]; then
+    COMPREPLY=($(compgen -W "create list delete foreach --workspace" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -gt 1 && "${COMP_WORDS[1]}" == "path" && "$cur" == -* ]]; then
+    COMPREPLY=($(compgen -W "--copy --relative" -- "$cur"))
+    return 0
+  fi
+
+  if [[ $COMP_CWORD -eq 2 && ( "${COMP_WORDS[1]}" == "path" || "${COMP_WORDS[1]}" == "port" || "${COMP_WORDS[1]}" == "info" ) ]]; then
+    COMPREPLY=($(compgen -W "main . - $(_terris_branches)" -- "$cur"))
+    return 0
+  fi
 
   if [[ "$cur" == -* ]]; then
-    COMPREPLY=($(compgen -W "--all --rm" -- "$cur"))
+    COMPREPLY=($(compgen -W "--all --global --repo --relative --sort --du --gone --locked --prunable --detached --managed --unmanaged --dirty --clean --view --last-commit --prs --columns --output --rm --on-collision --stable --description --dry-run --verbose --json --color --no-pager --jobs --hook" -- "$cur"))
     return 0
   fi
 
-  if [[ $COMP_CWORD -eq 1 || "$prev" == "--rm" ]]; then
+  if [[ $COMP_CWORD -eq 1 ]]; then
+    COMPREPLY=($(compgen -W "create delete clean archive path root info find grep current prompt recent jump gc with foreach ws pool reset checkout adopt disown export import relocate repos completions config help config-worktree diff graduate rebase-all sync stack du clean-artifacts port serve daemon $(_terris_branches)" -- "$cur"))
+    return 0
+  fi
+
+  if [[ "$prev" == "--hook" ]]; then
+    COMPREPLY=($(compgen -W "zoxide autojump" -- "$cur"))
+    return 0
+  fi
+
+  if [[ "$prev" == "--color" ]]; then
+    COMPREPLY=($(compgen -W "auto always never" -- "$cur"))
+    return 0
+  fi
+
+  if [[ "$prev" == "--on-collision" ]]; then
+    COMPREPLY=($(compgen -W "error reuse suffix" -- "$cur"))
+    return 0
+  fi
+
+  if [[ "$prev" == "--columns" ]]; then
+    COMPREPLY=($(compgen -W "id name branch size path flags managed dirty age used head committed subject author ci pr" -- "$cur"))
+    return 0
+  fi
+
+  if [[ "$prev" == "--output" ]]; then
+    COMPREPLY=($(compgen -W "table csv tsv" -- "$cur"))
+    return 0
+  fi
+
+  if [[ "$prev" == "--sort" ]]; then
+    COMPREPLY=($(compgen -W "name recent age" -- "$cur"))
+    return 0
+  fi
+
+  if [[ "$prev" == "--rm" ]]; then
     COMPREPLY=($(compgen -W "$(_terris_branches)" -- "$cur"))
     return 0
   fi
 
   COMPREPLY=()
-}}
+}
 
 complete -F _terris_complete terris
 "#
-            );
         }
         CompletionShell::Zsh => {
-            println!(
-                r#"#compdef terris
+            r#"#compdef terris
 
-_terris_branches() {{
+_terris_branches() {
   git for-each-ref --format='%(refname:short)' refs/heads 2>/dev/null
-}}
+}
 
-_arguments -s \
-  '--all[List all worktrees, including those without branches]' \
-  '--rm[Remove a worktree by branch name]:branch:->branches' \
-  '1:branch:->branches' \
-  '*: :->args'
+if (( CURRENT > 2 )) && [[ $words[2] == delete ]]; then
+  _arguments -s \
+    '(-i --interactive)'{-i,--interactive}'[Pick the worktrees to remove from a checklist]' \
+    '--branch[Remove worktrees whose branch matches a glob]:glob:' \
+    '--path-prefix[Remove worktrees located under this directory]:dir:_files -/' \
+    '--older-than[Remove worktrees whose last commit is older than this]:age:' \
+    '(-f --force)'{-f,--force}'[Discard uncommitted changes without asking]' \
+    '--archive=-[Pack each worktree into a .tar.zst before removing it]:dir:_files -/' \
+    '*:branch:->branches'
+elif (( CURRENT > 2 )) && [[ $words[2] == create ]]; then
+  _arguments -s \
+    '--ephemeral[Open a shell in a new worktree and remove it when the shell exits]' \
+    '--carry[Move uncommitted changes into the new worktree]' \
+    '(-u --include-untracked)'{-u,--include-untracked}'[With --carry, also move untracked files]' \
+    '--from-stash=-[Start the branch where a stash was made and apply it]:stash:' \
+    '--apply[Apply a patch file (- for stdin) after checkout]:patch:_files' \
+    '--on[Stack the branch on a parent worktree]:parent:->branches' \
+    '--devcontainer=-[Link .devcontainer into the worktree]:mode:(off link up)' \
+    '(--no-open)--open=-[Open the worktree after creating it]:action:(editor tmux shell)' \
+    '(--open)--no-open[Do not open the worktree]' \
+    '--copy[Also put the path on the clipboard]' \
+    '--description[Say what the worktree is for]:text:' \
+    '--on-collision[What to do when the branch already has a worktree]:strategy:(error reuse suffix)' \
+    '--stable[Create the worktree at <registry>/<repo>/<branch>]' \
+    '1:branch:->branches'
+elif (( CURRENT > 2 )) && [[ $words[2] == archive ]]; then
+  _arguments -s \
+    '(-o --output)'{-o,--output}'[Directory to write the archive to]:dir:_files -/' \
+    '1:branch:->branches'
+elif (( CURRENT > 2 )) && [[ $words[2] == reset ]]; then
+  _arguments -s \
+    '--to[Move the worktree and its branch to this ref]:ref:->branches' \
+    '(-f --force)'{-f,--force}'[Discard uncommitted changes without asking]' \
+    '--hooks[Run the post-create hooks again afterwards]' \
+    '1:worktree:->branches'
+elif (( CURRENT > 2 )) && [[ $words[2] == adopt ]]; then
+  _arguments -s \
+    '--all[Adopt every linked worktree terris does not manage yet]' \
+    '*:worktree:->branches'
+elif (( CURRENT > 2 )) && [[ $words[2] == disown ]]; then
+  _arguments -s \
+    '*:worktree:->branches'
+elif (( CURRENT > 2 )) && [[ $words[2] == gc ]]; then
+  _arguments -s \
+    '--remove-orphans[Delete orphaned directories in the registry without asking]'
+elif (( CURRENT > 2 )) && [[ $words[2] == relocate ]]; then
+  _arguments -s \
+    '1:old:_files -/' \
+    '2:new:_files -/'
+elif (( CURRENT == 3 )) && [[ $words[2] == repos ]]; then
+  _values 'repos command' forget
+elif (( CURRENT > 2 )) && [[ $words[2] == import ]]; then
+  _arguments -s \
+    '1:file:_files'
+elif (( CURRENT == 3 )) && [[ $words[2] == completions ]]; then
+  _values 'completions command' install
+elif (( CURRENT > 3 )) && [[ $words[2] == completions ]]; then
+  _arguments -s \
+    '--rc[Load them from the rc file instead]' \
+    '(-y --yes)'{-y,--yes}'[Change the rc file without asking]' \
+    '1:shell:(bash zsh fish)'
+elif (( CURRENT == 3 )) && [[ $words[2] == config ]]; then
+  _values 'config command' get set unset edit path
+elif (( CURRENT > 3 )) && [[ $words[2] == config ]]; then
+  _arguments -s \
+    '--repo[Use the key under \[repos.<name>\]]:name:' \
+    '1:key:' \
+    '2:value:'
+elif (( CURRENT == 3 )) && [[ $words[2] == help ]]; then
+  _values 'topic or command' registry naming hooks targets create delete clean archive path root info find grep current prompt recent jump gc with foreach ws pool reset checkout adopt disown export import relocate repos completions config config-worktree diff graduate rebase-all sync stack du clean-artifacts port serve daemon
+elif (( CURRENT > 2 )) && [[ $words[2] == config-worktree ]]; then
+  _arguments -s \
+    '--unset[Remove the key from the worktree config]' \
+    '1:worktree:->branches' \
+    '2:key:' \
+    '3:value:'
+elif (( CURRENT == 3 || CURRENT == 4 )) && [[ $words[2] == diff ]]; then
+  _values 'worktree' main . - $(_terris_branches)
+elif (( CURRENT > 2 )) && [[ $words[2] == prompt ]]; then
+  _arguments -s \
+    '(--starship-config)--starship[Print for a starship custom module]' \
+    '(--starship)--starship-config[Print the starship.toml module]'
+elif (( CURRENT > 2 )) && [[ $words[2] == find ]]; then
+  _arguments -s \
+    '--message[Regular expression to match commit messages against]:regex:' \
+    '(-i --ignore-case)'{-i,--ignore-case}'[Match regardless of case]' \
+    '--base[Leave out commits already on this branch]:branch:->branches'
+elif (( CURRENT > 2 )) && [[ $words[2] == grep ]]; then
+  _arguments -s \
+    '(-i --ignore-case)'{-i,--ignore-case}'[Match regardless of case]' \
+    '(-F --fixed-strings)'{-F,--fixed-strings}'[Take the pattern as a literal string]' \
+    '--filter[Only search branches matching a glob]:glob:' \
+    '1:pattern:'
+elif (( CURRENT > 2 )) && [[ $words[2] == foreach ]]; then
+  _arguments -s \
+    '--filter[Only run in branches matching a glob]:glob:' \
+    '--output[Interleave lines or keep each worktree together]:mode:(stream buffer)' \
+    '--fail-fast[Stop the other commands when one fails]' \
+    '*::command:_normal'
+elif (( CURRENT > 2 )) && [[ $words[2] == root ]]; then
+  _arguments -s \
+    '--git-dir[Print the shared git directory instead]'
+elif (( CURRENT > 2 )) && [[ $words[2] == current ]]; then
+  _arguments -s \
+    '(--branch --path)--name[Print only the worktree name]' \
+    '(--name --path)--branch[Print only the branch]' \
+    '(--name --branch)--path[Print only the worktree path]'
+elif (( CURRENT > 2 )) && [[ $words[2] == daemon ]]; then
+  _arguments -s \
+    '--interval[Seconds between rounds]:seconds:' \
+    '--once[Run one round and exit]'
+elif (( CURRENT > 2 )) && [[ $words[2] == serve ]]; then
+  _arguments -s \
+    '(--http)--mcp[Serve MCP tools on stdin and stdout]' \
+    '(--mcp)--http[Serve a JSON API over HTTP]:address:'
+elif (( CURRENT > 2 )) && [[ $words[2] == clean-artifacts ]]; then
+  _arguments -s \
+    '--filter[Only clean branches matching a glob]:glob:'
+elif (( CURRENT > 2 )) && [[ $words[2] == stack ]]; then
+  _arguments -s \
+    '--restack[Rebase every child whose parent has moved]'
+elif (( CURRENT > 2 )) && [[ $words[2] == rebase-all ]]; then
+  _arguments -s \
+    '--onto[Branch to rebase onto]:branch:->branches' \
+    '--filter[Only rebase branches matching a glob]:glob:'
+elif (( CURRENT > 2 )) && [[ $words[2] == graduate ]]; then
+  _arguments -s \
+    '--into[Branch to land on]:branch:->branches' \
+    '(--merge --squash)--rebase[Rebase onto the base, then fast-forward it]' \
+    '(--rebase --squash)--merge[Create a merge commit on the base]' \
+    '(--rebase --merge)--squash[Add the changes to the base as one commit]' \
+    '--push[Push the base branch afterwards]' \
+    '1:worktree:->branches'
+elif (( CURRENT > 2 )) && [[ $words[2] == checkout ]]; then
+  _values 'branch' $(_terris_branches)
+elif (( CURRENT == 3 )) && [[ $words[2] == pool ]]; then
+  _values 'pool command' acquire release
+elif (( CURRENT == 3 )) && [[ $words[2] == ws ]]; then
+  _values 'workspace command' create list delete foreach
+elif (( CURRENT > 2 )) && [[ $words[2] == path ]]; then
+  _arguments -s \
+    '--copy[Also put the path on the clipboard]' \
+    '--relative=-[Print the path relative to a directory]:base:_files -/' \
+    '1:worktree:->branches'
+elif (( CURRENT == 3 )) && [[ $words[2] == (port|info) ]]; then
+  _values 'worktree' main . - $(_terris_branches)
+elif (( CURRENT > 2 )) && [[ $words[2] == clean ]]; then
+  _arguments -s \
+    '--into[Base branch to check merges against]:branch:->branches' \
+    '--delete-branch[Also delete the branch of each removed worktree]'
+else
+  _arguments -s \
+    '--all[List all worktrees, including those without branches]' \
+    '--sort[Order of the worktree list]:key:(name recent age)' \
+    '--du[Add a SIZE column with disk usage]' \
+    '(-g --global)'{-g,--global}'[List the worktrees of every repository terris knows]' \
+    '--repo[List the worktrees of one repository]:name:' \
+    '--relative=-[Show paths relative to a directory]:base:_files -/' \
+    '--output[Print the worktree list as a table, CSV, or TSV]:format:(table csv tsv)' \
+    '--last-commit[Add the age, subject, and author of each HEAD commit]' \
+    '--gone[List only worktrees whose upstream was deleted]' \
+    '--locked[List only locked worktrees]' \
+    '--prunable[List only worktrees whose directory is missing]' \
+    '--detached[List only worktrees without a branch checked out]' \
+    '(--unmanaged)--managed[List only linked worktrees terris created or adopted]' \
+    '(--managed)--unmanaged[List only linked worktrees terris did not create or adopt]' \
+    '(--clean)--dirty[List only worktrees with uncommitted or untracked changes]' \
+    '(--dirty)--clean[List only worktrees without uncommitted or untracked changes]' \
+    '--view[Show the list the way a configured view describes]:view:' \
+    '--prs[Add the open pull or merge request of each branch]' \
+    '--columns[Columns of the worktree list]:columns:_sequence compadd - id name branch size path flags managed dirty age used head committed subject author ci pr' \
+    '--on-collision[What to do when the branch already has a worktree]:strategy:(error reuse suffix)' \
+    '--stable[Create the worktree at <registry>/<repo>/<branch>]' \
+    '--description[Say what the worktree is for]:text:' \
+    '--rm[Remove a worktree by branch name]:branch:->branches' \
+    '--dry-run[Print what would be done without changing anything]' \
+    '(-v --verbose)'{-v,--verbose}'[Log each git command]' \
+    '--json[Emit JSON output]' \
+    '--color[When to color output]:when:(auto always never)' \
+    '--no-pager[Print long listings without a pager]' \
+    '(-j --jobs)'{-j,--jobs}'[Most subprocesses to run at once]:n:' \
+    '--hook[Keep a directory jumper in sync with worktrees]:jumper:(zoxide autojump)' \
+    '1:branch:->first' \
+    '*: :->args'
+fi
 
 case $state in
+  first)
+    _values 'branch or command' create delete clean archive path root info find grep current prompt recent jump gc with foreach ws pool reset checkout adopt disown export import relocate repos completions config help config-worktree diff graduate rebase-all sync stack du clean-artifacts port serve daemon $(_terris_branches)
+    ;;
   branches)
     _values 'branches' $(_terris_branches)
     ;;
 esac
 "#
-            );
         }
         CompletionShell::Fish => {
-            println!(
-                r#"function __terris_branches
+            r#"function __terris_branches
   command git for-each-ref --format='%(refname:short)' refs/heads 2>/dev/null
 end
 
 complete -c terris -l all -d 'List all worktrees, including those without branches'
+complete -c terris -l on-collision -x -a 'error reuse suffix' -d 'What to do when the branch already has a worktree'
+complete -c terris -l stable -d 'Create the worktree at <registry>/<repo>/<branch>'
+complete -c terris -l description -x -d 'Say what the worktree is for'
+complete -c terris -l sort -x -a 'name recent age' -d 'Order of the worktree list'
+complete -c terris -l du -d 'Add a SIZE column with disk usage'
+complete -c terris -s g -l global -d 'List the worktrees of every repository terris knows'
+complete -c terris -l repo -x -d 'List the worktrees of one repository'
+complete -c terris -l relative -d 'Show paths relative to the current directory'
+complete -c terris -l output -x -a 'table csv tsv' -d 'Print the worktree list as a table, CSV, or TSV'
+complete -c terris -l last-commit -d 'Add the age, subject, and author of each HEAD commit'
+complete -c terris -l gone -d 'List only worktrees whose upstream was deleted'
+complete -c terris -l locked -d 'List only locked worktrees'
+complete -c terris -l prunable -d 'List only worktrees whose directory is missing'
+complete -c terris -l detached -d 'List only worktrees without a branch checked out'
+complete -c terris -l managed -d 'List only linked worktrees terris created or adopted'
+complete -c terris -l unmanaged -d 'List only linked worktrees terris did not create or adopt'
+complete -c terris -l dirty -d 'List only worktrees with uncommitted or untracked changes'
+complete -c terris -l clean -d 'List only worktrees without uncommitted or untracked changes'
+complete -c terris -l view -x -d 'Show the list the way a configured view describes'
+complete -c terris -l prs -d 'Add the open pull or merge request of each branch'
+complete -c terris -l columns -x -a 'id name branch size path flags managed dirty age used head committed subject author ci pr' -d 'Columns of the worktree list'
 complete -c terris -l rm -d 'Remove a worktree by branch name' -a "(__terris_branches)"
-complete -c terris -f -a "(__terris_branches)"
+complete -c terris -l dry-run -d 'Print what would be done without changing anything'
+complete -c terris -s v -l verbose -d 'Log each git command'
+complete -c terris -l json -d 'Emit JSON output'
+complete -c terris -l color -x -a 'auto always never' -d 'When to color output'
+complete -c terris -l no-pager -d 'Print long listings without a pager'
+complete -c terris -s j -l jobs -x -d 'Most subprocesses to run at once'
+complete -c terris -l hook -x -a 'zoxide autojump' -d 'Keep a directory jumper in sync with worktrees'
+complete -c terris -n '__fish_use_subcommand' -f -a "(__terris_branches)"
+complete -c terris -n '__fish_use_subcommand' -f -a create -d 'Create (or reuse) the worktree for a branch'
+complete -c terris -n '__fish_use_subcommand' -f -a delete -d 'Remove worktrees by branch name'
+complete -c terris -n '__fish_use_subcommand' -f -a clean -d 'Remove worktrees whose branch is merged or whose upstream is gone'
+complete -c terris -n '__fish_use_subcommand' -f -a archive -d "Pack a worktree's files into a timestamped .tar.zst"
+complete -c terris -n '__fish_use_subcommand' -f -a path -d 'Print the path of a worktree'
+complete -c terris -n '__fish_use_subcommand' -f -a root -d "Print the primary worktree's path"
+complete -c terris -n '__fish_seen_subcommand_from root' -l git-dir -d 'Print the shared git directory instead'
+complete -c terris -n '__fish_use_subcommand' -f -a info -d 'Show everything known about one worktree'
+complete -c terris -n '__fish_use_subcommand' -f -a find -d 'Find the worktrees whose unmerged commits have a matching message'
+complete -c terris -n '__fish_seen_subcommand_from find' -l message -x -d 'Regular expression to match commit messages against'
+complete -c terris -n '__fish_seen_subcommand_from find' -s i -l ignore-case -d 'Match regardless of case'
+complete -c terris -n '__fish_seen_subcommand_from find' -l base -x -a "(__terris_branches)" -d 'Leave out commits already on this branch'
+complete -c terris -n '__fish_use_subcommand' -f -a grep -d 'Search the files of every worktree'
+complete -c terris -n '__fish_seen_subcommand_from grep' -s i -l ignore-case -d 'Match regardless of case'
+complete -c terris -n '__fish_seen_subcommand_from grep' -s F -l fixed-strings -d 'Take the pattern as a literal string'
+complete -c terris -n '__fish_seen_subcommand_from grep' -l filter -x -d 'Only search branches matching a glob'
+complete -c terris -n '__fish_use_subcommand' -f -a current -d 'Print the worktree containing the current directory'
+complete -c terris -n '__fish_seen_subcommand_from current' -l name -d 'Print only the worktree name'
+complete -c terris -n '__fish_seen_subcommand_from current' -l branch -d 'Print only the branch'
+complete -c terris -n '__fish_seen_subcommand_from current' -l path -d 'Print only the worktree path'
+complete -c terris -n '__fish_use_subcommand' -f -a prompt -d 'Print a short worktree segment for shell prompts'
+complete -c terris -n '__fish_seen_subcommand_from prompt' -l starship -d 'Print for a starship custom module'
+complete -c terris -n '__fish_seen_subcommand_from prompt' -l starship-config -d 'Print the starship.toml module'
+complete -c terris -n '__fish_use_subcommand' -f -a recent -d 'List worktrees from most to least recently used'
+complete -c terris -n '__fish_use_subcommand' -f -a jump -d 'Print the most frecent worktree matching a query'
+complete -c terris -n '__fish_use_subcommand' -f -a gc -d 'Remove ephemeral worktrees whose shell is gone'
+complete -c terris -n '__fish_seen_subcommand_from gc' -l remove-orphans -d 'Delete orphaned directories in the registry without asking'
+complete -c terris -n '__fish_use_subcommand' -f -a with -d 'Run a command in a throwaway worktree checked out at a ref'
+complete -c terris -n '__fish_use_subcommand' -f -a foreach -d 'Run a command in every worktree at once'
+complete -c terris -n '__fish_seen_subcommand_from foreach' -l filter -x -d 'Only run in branches matching a glob'
+complete -c terris -n '__fish_seen_subcommand_from foreach' -l output -x -a 'stream buffer' -d 'Interleave lines or keep each worktree together'
+complete -c terris -n '__fish_seen_subcommand_from foreach' -l fail-fast -d 'Stop the other commands when one fails'
+complete -c terris -n '__fish_use_subcommand' -f -a ws -d 'Work on one branch across the repositories of a workspace'
+complete -c terris -n '__fish_seen_subcommand_from ws' -f -a 'create list delete foreach'
+complete -c terris -n '__fish_seen_subcommand_from ws' -s w -l workspace -x -d 'Workspace from [workspaces.<name>]'
+complete -c terris -n '__fish_use_subcommand' -f -a pool -d 'Lease and return pre-created worktrees'
+complete -c terris -n '__fish_seen_subcommand_from pool' -f -a 'acquire release'
+complete -c terris -n '__fish_use_subcommand' -f -a reset -d 'Discard all changes in a worktree'
+complete -c terris -n '__fish_seen_subcommand_from reset' -f -a "(__terris_branches)"
+complete -c terris -n '__fish_seen_subcommand_from reset' -l to -x -a "(__terris_branches)" -d 'Move the worktree and its branch to this ref'
+complete -c terris -n '__fish_seen_subcommand_from reset' -s f -l force -d 'Discard uncommitted changes without asking'
+complete -c terris -n '__fish_seen_subcommand_from reset' -l hooks -d 'Run the post-create hooks again afterwards'
+complete -c terris -n '__fish_use_subcommand' -f -a diff -d 'Show the differences between two worktrees'
+complete -c terris -n '__fish_seen_subcommand_from diff' -f -a "main . - (__terris_branches)"
+complete -c terris -n '__fish_use_subcommand' -f -a rebase-all -d "Rebase every clean worktree's branch onto the base"
+complete -c terris -n '__fish_seen_subcommand_from rebase-all' -l onto -x -a "(__terris_branches)" -d 'Branch to rebase onto'
+complete -c terris -n '__fish_seen_subcommand_from rebase-all' -l filter -x -d 'Only rebase branches matching a glob'
+complete -c terris -n '__fish_use_subcommand' -f -a clean-artifacts -d 'Delete ignored build output in worktrees'
+complete -c terris -n '__fish_seen_subcommand_from clean-artifacts' -l filter -x -d 'Only clean branches matching a glob'
+complete -c terris -n '__fish_use_subcommand' -f -a port -d 'Print a port reserved for a worktree'
+complete -c terris -n '__fish_use_subcommand' -f -a serve -d 'Serve worktree operations to agents and editors'
+complete -c terris -n '__fish_seen_subcommand_from serve' -l mcp -d 'Serve MCP tools on stdin and stdout'
+complete -c terris -n '__fish_seen_subcommand_from serve' -l http -x -d 'Serve a JSON API over HTTP on this address'
+complete -c terris -n '__fish_use_subcommand' -f -a daemon -d 'Prune, collect, and cache worktree status in the background'
+complete -c terris -n '__fish_seen_subcommand_from daemon' -l interval -x -d 'Seconds between rounds'
+complete -c terris -n '__fish_seen_subcommand_from daemon' -l once -d 'Run one round and exit'
+complete -c terris -n '__fish_use_subcommand' -f -a du -d 'Show disk usage per worktree'
+complete -c terris -n '__fish_use_subcommand' -f -a stack -d 'Show stacked worktrees and restack them'
+complete -c terris -n '__fish_seen_subcommand_from stack' -l restack -d 'Rebase every child whose parent has moved'
+complete -c terris -n '__fish_use_subcommand' -f -a sync -d 'Fetch once and fast-forward every clean worktree'
+complete -c terris -n '__fish_use_subcommand' -f -a graduate -d "Land a worktree's branch, then remove it"
+complete -c terris -n '__fish_seen_subcommand_from graduate' -f -a "(__terris_branches)"
+complete -c terris -n '__fish_seen_subcommand_from graduate' -l into -x -a "(__terris_branches)" -d 'Branch to land on'
+complete -c terris -n '__fish_seen_subcommand_from graduate' -l rebase -d 'Rebase onto the base, then fast-forward it'
+complete -c terris -n '__fish_seen_subcommand_from graduate' -l merge -d 'Create a merge commit on the base'
+complete -c terris -n '__fish_seen_subcommand_from graduate' -l squash -d 'Add the changes to the base as one commit'
+complete -c terris -n '__fish_seen_subcommand_from graduate' -l push -d 'Push the base branch afterwards'
+complete -c terris -n '__fish_use_subcommand' -f -a checkout -d 'Switch an existing worktree to another branch'
+complete -c terris -n '__fish_seen_subcommand_from checkout' -f -a "(__terris_branches)"
+complete -c terris -n '__fish_use_subcommand' -f -a adopt -d 'Let terris manage worktrees created with plain git'
+complete -c terris -n '__fish_seen_subcommand_from adopt' -f -a ". (__terris_branches)"
+complete -c terris -n '__fish_seen_subcommand_from adopt' -l all -d 'Adopt every worktree terris does not manage yet'
+complete -c terris -n '__fish_use_subcommand' -f -a disown -d 'Stop managing worktrees, leaving them as they are'
+complete -c terris -n '__fish_seen_subcommand_from disown' -f -a ". (__terris_branches)"
+complete -c terris -n '__fish_use_subcommand' -f -a export -d 'Print known repositories and worktrees as JSON'
+complete -c terris -n '__fish_use_subcommand' -f -a import -d 'Recreate worktrees from terris export'
+complete -c terris -n '__fish_seen_subcommand_from import' -F
+complete -c terris -n '__fish_use_subcommand' -f -a relocate -d 'Reconnect worktrees after the repository moved'
+complete -c terris -n '__fish_seen_subcommand_from relocate' -x -a "(__fish_complete_directories)"
+complete -c terris -n '__fish_use_subcommand' -f -a repos -d 'List the repositories terris has recorded worktrees of'
+complete -c terris -n '__fish_seen_subcommand_from repos' -f -a forget
+complete -c terris -n '__fish_use_subcommand' -f -a completions -d 'Install shell completions'
+complete -c terris -n '__fish_seen_subcommand_from completions' -f -a 'install bash zsh fish'
+complete -c terris -n '__fish_seen_subcommand_from completions' -l rc -d 'Load them from the rc file instead'
+complete -c terris -n '__fish_seen_subcommand_from completions' -s y -l yes -d 'Change the rc file without asking'
+complete -c terris -n '__fish_use_subcommand' -f -a config -d "Read and change terris's config file"
+complete -c terris -n '__fish_seen_subcommand_from config' -f -a 'get set unset edit path'
+complete -c terris -n '__fish_seen_subcommand_from config' -l repo -x -d 'Use the key under [repos.<name>]'
+complete -c terris -n '__fish_use_subcommand' -f -a help -d 'Show help on a command or topic'
+complete -c terris -n '__fish_seen_subcommand_from help' -f -a 'registry naming hooks targets'
+complete -c terris -n '__fish_use_subcommand' -f -a config-worktree -d 'Read or set git config for one worktree only'
+complete -c terris -n '__fish_seen_subcommand_from config-worktree' -f -a "main . - (__terris_branches)"
+complete -c terris -n '__fish_seen_subcommand_from config-worktree' -l unset -d "Remove the key from the worktree's config"
+complete -c terris -n '__fish_seen_subcommand_from jump' -f
+complete -c terris -n '__fish_seen_subcommand_from create' -f -a "(__terris_branches)"
+complete -c terris -n '__fish_seen_subcommand_from create' -l ephemeral -d 'Open a shell in a new worktree and remove it when the shell exits'
+complete -c terris -n '__fish_seen_subcommand_from create' -l carry -d 'Move uncommitted changes into the new worktree'
+complete -c terris -n '__fish_seen_subcommand_from create' -s u -l include-untracked -d 'With --carry, also move untracked files'
+complete -c terris -n '__fish_seen_subcommand_from create' -l from-stash -d 'Start the branch where a stash was made and apply it'
+complete -c terris -n '__fish_seen_subcommand_from create' -l apply -r -F -d 'Apply a patch file (- for stdin) after checkout'
+complete -c terris -n '__fish_seen_subcommand_from create' -l devcontainer -f -a 'off link up' -d 'Link .devcontainer into the worktree'
+complete -c terris -n '__fish_seen_subcommand_from create' -l open -f -a 'editor tmux shell' -d 'Open the worktree after creating it'
+complete -c terris -n '__fish_seen_subcommand_from create' -l no-open -d 'Do not open the worktree'
+complete -c terris -n '__fish_seen_subcommand_from create path' -l copy -d 'Also put the path on the clipboard'
+complete -c terris -n '__fish_seen_subcommand_from create' -l on -x -a "(__terris_branches)" -d 'Stack the branch on a parent worktree'
+complete -c terris -n '__fish_seen_subcommand_from clean' -l into -r -a "(__terris_branches)" -d 'Base branch to check merges against'
+complete -c terris -n '__fish_seen_subcommand_from clean' -l delete-branch -d 'Also delete the branch of each removed worktree'
+complete -c terris -n '__fish_seen_subcommand_from delete' -s i -l interactive -d 'Pick the worktrees to remove from a checklist'
+complete -c terris -n '__fish_seen_subcommand_from delete' -l branch -r -d 'Remove worktrees whose branch matches a glob'
+complete -c terris -n '__fish_seen_subcommand_from delete' -l path-prefix -r -a "(__fish_complete_directories)" -d 'Remove worktrees located under this directory'
+complete -c terris -n '__fish_seen_subcommand_from delete' -l older-than -r -d 'Remove worktrees whose last commit is older than this'
+complete -c terris -n '__fish_seen_subcommand_from delete' -s f -l force -d 'Discard uncommitted changes without asking'
+complete -c terris -n '__fish_seen_subcommand_from delete' -l archive -a "(__fish_complete_directories)" -d 'Pack each worktree into a .tar.zst before removing it'
+complete -c terris -n '__fish_seen_subcommand_from delete' -f -a "(__terris_branches)"
+complete -c terris -n '__fish_seen_subcommand_from archive' -s o -l output -r -a "(__fish_complete_directories)" -d 'Directory to write the archive to'
+complete -c terris -n '__fish_seen_subcommand_from archive' -f -a "(__terris_branches)"
+complete -c terris -n '__fish_seen_subcommand_from path port info' -f -a "main . - (__terris_branches)"
 "#
-            );
         }
     }
 }
 
-fn cmd_list(show_all: bool) -> Result<()> {
-    let root = git_root()?;
-    let worktrees = list_worktrees(&root)?;
-    if show_all {
-        print_worktrees(&worktrees);
-        return Ok(());
+/// A worktree with its list index, for `--json`.
+#[derive(Serialize)]
+struct ListEntry<'a> {
+    index: Option<u32>,
+    /// Owned when its path is shown relative to `--relative`.
+    #[serde(flatten)]
+    worktree: Cow<'a, Worktree>,
+    /// Whether terris created or adopted it.
+    managed: bool,
+    #[serde(flatten)]
+    usage: Option<du::Usage>,
+    /// Whether it has uncommitted changes, when checked for `--dirty` or
+    /// `--clean` or when the daemon's cache knows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dirty: Option<bool>,
+    /// The checked-out commit, with `--last-commit`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit: Option<Commit>,
+    /// The branch's latest CI status, when the CI column is shown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ci: Option<CiStatus>,
+    /// How the branch tracks its upstream, when the repository has remotes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tracking: Option<Tracking>,
+    /// The branch's open pull request, when the PR column is shown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pull_request: Option<PullRequest>,
+}
+
+/// Open the worktree for `branch`, creating it if needed, and apply `patch`
+/// in it when given. A patch only goes into a new worktree: with one, an
+/// existing worktree for the branch is an error rather than reused.
+fn cmd_ensure_branch(
+    backend: &dyn Backend,
+    branch: &str,
+    patch: Option<&[u8]>,
+    config: &Config,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let on_collision = match config.on_collision {
+        Collision::Reuse if patch.is_some() => Collision::Error,
+        on_collision => on_collision,
+    };
+    let path = ensure_worktree(backend, branch, config, on_collision, dry_run)?;
+    if let Some(patch) = patch {
+        backend.apply_patch(&path, patch).with_context(|| {
+            format!(
+                "apply the patch in '{}'; the new worktree was left there to fix it by hand or remove",
+                path.display()
+            )
+        })?;
+    }
+    if !dry_run {
+        metadata::record_use(&path);
+        print_path(&path);
     }
+    Ok(path)
+}
 
-    let (with_branch, without_branch): (Vec<Worktree>, Vec<Worktree>) = worktrees
-        .into_iter()
-        .partition(|wt| worktree_branch_short(wt).is_some());
-    print_worktrees(&with_branch);
-    if !without_branch.is_empty() {
-        println!(
-            "# {} worktree(s) without a branch not shown. Use --all to display.",
-            without_branch.len()
-        );
+/// Read a patch from `source`, or from stdin when it is `-`.
+fn read_patch(source: &Path) -> Result<Vec<u8>> {
+    if source == Path::new("-") {
+        let mut patch = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut patch)
+            .context("read patch from stdin")?;
+        return Ok(patch);
     }
-    Ok(())
+    std::fs::read(source).with_context(|| format!("read patch '{}'", source.display()))
 }
 
-fn cmd_ensure_branch(branch: &str) -> Result<()> {
-    let root = git_root()?;
-    let worktrees = list_worktrees(&root)?;
-    if let Some(wt) = find_worktree_by_branch(branch, &worktrees)? {
-        println!("{}", wt.path.display());
-        return Ok(());
+/// Return the path of the worktree for `branch`, creating it if needed.
+/// When the branch already has a worktree, `on_collision` (usually
+/// `config.on_collision`) decides between returning it, failing, or
+/// branching off under a suffixed name.
+///
+/// With `dry_run`, the steps are printed instead of performed.
+fn ensure_worktree(
+    backend: &dyn Backend,
+    branch: &str,
+    config: &Config,
+    on_collision: Collision,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let mut branch = branch.to_string();
+    let mut start = None;
+    if let Some(wt) = find_worktree_by_branch(&branch, &worktrees)? {
+        match on_collision {
+            Collision::Reuse => return Ok(wt.path.clone()),
+            Collision::Error => {
+                return Err(Error::WorktreeExists {
+                    branch,
+                    path: wt.path.clone(),
+                }
+                .into());
+            }
+            Collision::Suffix => {
+                let free = free_branch_name(backend, &root, &branch, &worktrees)?;
+                start = Some(std::mem::replace(&mut branch, free));
+            }
+        }
+    }
+    match add_new_worktree(backend, &root, &branch, start.as_deref(), config, dry_run) {
+        // git saw the branch in a worktree the list does not show it for,
+        // such as one in the middle of a rebase.
+        Err(err) if on_collision == Collision::Reuse => match err.downcast_ref::<Error>() {
+            Some(Error::WorktreeExists { path, .. }) => {
+                eprintln!("'{}' is already checked out in {}", branch, path.display());
+                Ok(path.clone())
+            }
+            _ => Err(err),
+        },
+        created => created,
     }
+}
 
-    let repo_name = root
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("repo")
-        .to_string();
-    let target_path = default_worktree_path(&repo_name, branch)?;
-    if let Some(parent) = target_path.parent() {
-        std::fs::create_dir_all(parent)
-            .with_context(|| format!("create worktree base directory '{}'", parent.display()))?;
+/// When `err` says the branch is checked out in another worktree, offer to
+/// use that worktree instead, returning its path if the user agrees.
+fn offer_existing_worktree(err: anyhow::Error) -> Result<PathBuf> {
+    let Some(Error::WorktreeExists { branch, path }) = err.downcast_ref::<Error>() else {
+        return Err(err);
+    };
+    if !std::io::stdin().is_terminal() {
+        return Err(err);
     }
+    let accepted = dialoguer::Confirm::new()
+        .with_prompt(format!(
+            "'{}' is checked out in {}. Go there instead?",
+            branch,
+            path.display()
+        ))
+        .default(true)
+        .interact()
+        .context("confirm switching worktrees")?;
+    if accepted { Ok(path.clone()) } else { Err(err) }
+}
 
-    let branch_exists = git_branch_exists(&root, branch)?;
-    if !branch_exists {
-        bail!("branch '{}' does not exist", branch);
+/// Create a worktree for `branch` at the configured location, first creating
+/// the branch from `start` when one is given.
+fn add_new_worktree(
+    backend: &dyn Backend,
+    root: &Path,
+    branch: &str,
+    start: Option<&str>,
+    config: &Config,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let seed = WorktreeMeta::default();
+    add_new_worktree_with(backend, root, branch, start, seed, config, dry_run)
+}
+
+/// [`add_new_worktree`], recording `seed` as the worktree's metadata before
+/// the post-create steps run, so hooks already see it.
+fn add_new_worktree_with(
+    backend: &dyn Backend,
+    root: &Path,
+    branch: &str,
+    start: Option<&str>,
+    seed: WorktreeMeta,
+    config: &Config,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let root = root.to_path_buf();
+    let template = worktree_template(config, &root, branch, start)?;
+    let template = template.as_str();
+    // Until the worktree is recorded, so a concurrent terris neither picks
+    // the same free path nor loses this worktree's metadata.
+    let registry_lock = if dry_run {
+        None
+    } else {
+        Some(lock::registry()?)
+    };
+    let mut target_path = default_worktree_path(config, template, &root, branch)?;
+    if naming::is_deterministic(template) && target_path.exists() {
+        target_path = match config.on_collision {
+            Collision::Suffix => free_path(&target_path),
+            _ => bail!(
+                "'{}' already exists; remove it or pass `--on-collision suffix`",
+                target_path.display()
+            ),
+        };
+    }
+    if let Some(parent) = target_path.parent() {
+        if dry_run {
+            if !parent.exists() {
+                println!("Would create directory {}", parent.display());
+            }
+        } else {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("create worktree base directory '{}'", parent.display())
+            })?;
+        }
     }
 
-    let mut args: Vec<String> = vec!["worktree".into(), "add".into(), "--quiet".into()];
-    args.push(target_path.to_string_lossy().to_string());
-    args.push(branch.to_string());
+    if let Some(start) = start {
+        backend
+            .create_branch(&root, branch, start)
+            .with_context(|| format!("create branch '{}' from '{}'", branch, start))?;
+        if !dry_run {
+            eprintln!("Created branch {} from {}", branch, start);
+        }
+    } else if !backend.branch_exists(&root, branch)? {
+        return Err(Error::BranchNotFound(branch.to_string()).into());
+    }
 
-    run_git_silence_stdout(&args, &root)
+    backend
+        .add_worktree(&root, &target_path, branch)
         .with_context(|| format!("create worktree '{}'", branch))?;
-    println!("{}", target_path.display());
+    // Keep worktrees placed inside the repository out of `git status`.
+    if let Ok(inside) = target_path.strip_prefix(&root)
+        && let Some(top) = inside.components().next()
+    {
+        let pattern = format!("/{}/", top.as_os_str().to_string_lossy());
+        backend.exclude_locally(&root, &pattern)?;
+    }
+    if !dry_run {
+        let meta = WorktreeMeta {
+            base: start.map(str::to_string),
+            description: config.description.clone(),
+            created: Some(unix_now()),
+            repo: Some(root.to_path_buf()),
+            ..seed
+        };
+        if let Err(err) = metadata::update(&target_path, |recorded| *recorded = meta) {
+            tracing::warn!("could not record worktree metadata: {:#}", err);
+        }
+    }
+    drop(registry_lock);
+    if let Err(err) = worktree_config::propagate(backend, &root, &target_path, config, dry_run) {
+        eprintln!("Warning: could not propagate git config: {:#}", err);
+    }
+    if let Err(err) = worktree_config::apply(backend, &root, &target_path, branch, config, dry_run)
+    {
+        eprintln!("Warning: could not apply worktree config: {:#}", err);
+    }
+    if config.shared_cargo_target {
+        cargo::share_target(backend, &root, &target_path, dry_run)?;
+    }
+    let env = env::WorktreeEnv::new(backend, &root, &target_path, Some(branch))?;
+    if config.direnv {
+        direnv::setup(backend, &root, &env, dry_run)?;
+    }
+    devcontainer::setup(backend, &root, &target_path, config.devcontainer, dry_run)?;
+    if let Some(jumper) = config.hook
+        && !dry_run
+    {
+        jumper::register(jumper, &target_path);
+    }
+    hooks::post_create(&config.hooks, &env, dry_run);
+    Ok(target_path)
+}
+
+/// `<branch>-N` for the smallest N >= 2 that is neither a branch nor checked
+/// out in a worktree.
+fn free_branch_name(
+    backend: &dyn Backend,
+    root: &Path,
+    branch: &str,
+    worktrees: &[Worktree],
+) -> Result<String> {
+    for n in 2.. {
+        let candidate = format!("{}-{}", branch, n);
+        let in_use = worktrees
+            .iter()
+            .any(|wt| worktree_branch_short(wt) == Some(candidate.as_str()));
+        if !in_use && !backend.branch_exists(root, &candidate)? {
+            return Ok(candidate);
+        }
+    }
+    unreachable!("ran out of suffixes for '{}'", branch)
+}
+
+fn cmd_path(backend: &dyn Backend, args: &PathArgs, config: &Config, dry_run: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let wt = resolve_worktree(&args.target, &worktrees, &cwd, config.fuzzy)?;
+    if !dry_run {
+        metadata::record_use(&wt.path);
+    }
+    match relative_base(args.relative.clone()) {
+        Some(base) => print_path(&relative_path(&wt.path, &base)),
+        None => print_path(&wt.path),
+    }
+    if args.copy {
+        clipboard::copy(&wt.path, dry_run);
+    }
     Ok(())
 }
 
-fn cmd_delete_branch(branch: &str) -> Result<()> {
-    let root = git_root()?;
-    let worktrees = list_worktrees(&root)?;
-    let wt = find_worktree_by_branch(branch, &worktrees)?
-        .with_context(|| format!("no worktree matches branch '{}'", branch))?;
+/// A worktree together with when it was last used, for `recent --json`.
+#[derive(Serialize)]
+struct RecentEntry<'a> {
+    #[serde(flatten)]
+    worktree: &'a Worktree,
+    last_used: Option<i64>,
+}
 
-    let mut args: Vec<String> = vec!["worktree".into(), "remove".into()];
-    args.push(wt.path.to_string_lossy().to_string());
-    run_git_silence_stdout(&args, &root)
-        .with_context(|| format!("remove worktree '{}'", branch))?;
+fn cmd_root(backend: &dyn Backend, args: &RootArgs) -> Result<()> {
+    let root = repo_root(backend)?;
+    if args.git_dir {
+        let dir = backend.common_dir(&root)?;
+        print_path(&std::path::absolute(&dir).unwrap_or(dir));
+        return Ok(());
+    }
+    let worktrees = backend.list_worktrees(&root)?;
+    let main = worktrees
+        .iter()
+        .find(|wt| wt.main)
+        .ok_or_else(|| Error::WorktreeNotFound("main".to_string()))?;
+    print_path(&main.path);
     Ok(())
 }
 
-fn git_root() -> Result<PathBuf> {
-    let cwd = std::env::current_dir().context("read current directory")?;
-    let output = run_git(["rev-parse", "--show-toplevel"], &cwd)
-        .context("not a git repository (or any parent)")?;
-    Ok(PathBuf::from(output.trim()))
-}
-
-fn git_branch_exists(root: &Path, branch: &str) -> Result<bool> {
-    let ref_name = format!("refs/heads/{}", branch);
-    let status = Command::new("git")
-        .arg("rev-parse")
-        .arg("--verify")
-        .arg("--quiet")
-        .arg(ref_name)
-        .current_dir(root)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .context("check branch existence")?;
-    Ok(status.success())
-}
-
-fn list_worktrees(root: &Path) -> Result<Vec<Worktree>> {
-    let output = run_git(["worktree", "list", "--porcelain"], root)?;
-    Ok(parse_worktrees(&output))
-}
-
-fn parse_worktrees(output: &str) -> Vec<Worktree> {
-    let mut worktrees = Vec::new();
-    let mut current: Option<Worktree> = None;
-    for line in output.lines() {
-        if let Some(path) = line.strip_prefix("worktree ") {
-            if let Some(wt) = current.take() {
-                worktrees.push(wt);
-            }
-            current = Some(Worktree {
-                path: PathBuf::from(path.trim()),
-                ..Worktree::default()
+fn cmd_recent(backend: &dyn Backend, json: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let metadata = Metadata::load()?;
+    let sorted = metadata.by_recency(&worktrees);
+    if json {
+        let entries: Vec<RecentEntry> = sorted
+            .iter()
+            .map(|wt| RecentEntry {
+                worktree: wt,
+                last_used: metadata.last_used(&wt.path),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    let now = unix_now();
+    let mut rows = vec![["NAME".to_string(), "USED".to_string(), "PATH".to_string()]];
+    for wt in sorted {
+        let used = metadata
+            .last_used(&wt.path)
+            .map_or("never".to_string(), |t| {
+                format!("{} ago", format_age(now.saturating_sub(t)))
             });
-            continue;
-        }
-        if let Some(wt) = current.as_mut() {
-            if let Some(head) = line.strip_prefix("HEAD ") {
-                wt.head = Some(head.trim().to_string());
-            } else if let Some(branch) = line.strip_prefix("branch ") {
-                wt.branch = Some(branch.trim().to_string());
-            } else if line.trim() == "detached" {
-                wt.detached = true;
-            } else if line.trim() == "locked" {
-                wt.locked = true;
-            } else if let Some(prunable) = line.strip_prefix("prunable ") {
-                wt.prunable = Some(prunable.trim().to_string());
-            }
-        }
+        rows.push([worktree_name(wt), used, wt.path.display().to_string()]);
     }
-    if let Some(wt) = current.take() {
-        worktrees.push(wt);
+    for line in align_rows(&rows) {
+        println!("{}", line);
     }
-    worktrees
+    Ok(())
 }
 
-fn run_git<I, S>(args: I, cwd: &Path) -> Result<String>
-where
-    I: IntoIterator<Item = S>,
-    S: AsRef<OsStr>,
-{
-    let args_vec: Vec<String> = args
-        .into_iter()
-        .map(|arg| arg.as_ref().to_string_lossy().to_string())
-        .collect();
-    let output = Command::new("git")
-        .args(&args_vec)
-        .current_dir(cwd)
-        .output()
-        .with_context(|| format!("run git {}", args_vec.join(" ")))?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        bail!("{}", stderr.trim());
-    }
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
-}
-
-fn run_git_silence_stdout<I, S>(args: I, cwd: &Path) -> Result<()>
-where
-    I: IntoIterator<Item = S>,
-    S: AsRef<OsStr>,
-{
-    let args_vec: Vec<String> = args
-        .into_iter()
-        .map(|arg| arg.as_ref().to_string_lossy().to_string())
-        .collect();
-    let output = Command::new("git")
-        .args(&args_vec)
-        .current_dir(cwd)
-        .stdout(Stdio::null())
-        .output()
-        .with_context(|| format!("run git {}", args_vec.join(" ")))?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        bail!("{}", stderr.trim());
+fn cmd_delete_branch(backend: &dyn Backend, branch: &str, opts: &RemoveOptions) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let Some(wt) = resolve_worktree_to_change(branch, &worktrees, &cwd, opts.fuzzy, "delete")?
+    else {
+        return Ok(());
+    };
+
+    remove_checked(backend, &root, wt, opts)
+        .with_context(|| format!("remove worktree '{}'", branch))?;
+    Ok(())
+}
+
+/// Remove `wt`, first summarizing any unsaved work in it and asking before
+/// discarding uncommitted changes. Discarded changes are saved to a backup
+/// ref first. Returns whether the worktree was removed.
+fn remove_checked(
+    backend: &dyn Backend,
+    root: &Path,
+    wt: &Worktree,
+    opts: &RemoveOptions,
+) -> Result<bool> {
+    let summary = backend.change_summary(&wt.path)?;
+    let name = worktree_name(wt);
+    let dirty = summary.has_uncommitted();
+    if dirty && !confirm_discard(wt, &summary, "Delete", opts.force, opts.dry_run)? {
+        return Ok(false);
+    }
+    if !opts.pre_delete.is_empty() {
+        let env = env::WorktreeEnv::new(backend, root, &wt.path, worktree_branch_short(wt))?;
+        hooks::pre_delete(opts.pre_delete, &env, opts.force, opts.dry_run)
+            .with_context(|| format!("pre-delete hook for '{}'", name))?;
+    }
+    archive_before_removal(backend, wt, opts)?;
+    if dirty && let Some(backup) = backend.backup_changes(&wt.path, &name)? {
+        eprintln!("Saved uncommitted changes to {}", backup);
+        eprintln!("  Recover with: git cherry-pick --no-commit {}", backup);
+    }
+    backend.remove_worktree(root, &wt.path, dirty)?;
+    if !opts.dry_run {
+        forget_worktree(&wt.path, opts.hook);
+    }
+    Ok(true)
+}
+
+/// Show the unsaved work in `wt` and ask whether to `action` it anyway.
+/// `force` and `dry_run` skip the question; without a terminal to ask on,
+/// the worktree counts as dirty.
+fn confirm_discard(
+    wt: &Worktree,
+    summary: &ChangeSummary,
+    action: &str,
+    force: bool,
+    dry_run: bool,
+) -> Result<bool> {
+    let name = worktree_name(wt);
+    eprintln!("Worktree '{}' has unsaved work:", name);
+    for line in summary.lines() {
+        eprintln!("  {}", line);
+    }
+    if force || dry_run {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::DirtyWorktree(wt.path.clone()).into());
+    }
+    let confirmed = dialoguer::Confirm::new()
+        .with_prompt(format!("{} '{}' anyway?", action, name))
+        .default(false)
+        .interact()
+        .with_context(|| format!("confirm {}", action.to_lowercase()))?;
+    if !confirmed {
+        eprintln!("Kept {}", name);
+    }
+    Ok(confirmed)
+}
+
+/// Drop a removed worktree from terris's metadata and the directory jumper.
+fn forget_worktree(path: &Path, hook: Option<Jumper>) {
+    metadata::forget(path);
+    if let Some(jumper) = hook {
+        jumper::unregister(jumper, path);
+    }
+}
+
+fn archive_before_removal(
+    backend: &dyn Backend,
+    wt: &Worktree,
+    opts: &RemoveOptions,
+) -> Result<()> {
+    if let Some(dir) = opts.archive {
+        let file = archive::archive_worktree(backend, wt, dir, opts.dry_run)?;
+        if !opts.dry_run {
+            eprintln!("Archived {} to {}", worktree_name(wt), file.display());
+        }
     }
     Ok(())
 }
 
-fn print_worktrees(worktrees: &[Worktree]) {
-    let mut rows: Vec<(String, String, String, String)> = Vec::new();
-    for wt in worktrees {
-        let name = worktree_name(wt);
-        let branch = worktree_branch_short(wt).unwrap_or("-").to_string();
-        let flags = worktree_flags(wt);
-        let path = wt.path.to_string_lossy().to_string();
-        rows.push((name, branch, path, flags));
+fn cmd_delete(
+    backend: &dyn Backend,
+    args: &DeleteArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let opts = args.remove_options(config, dry_run);
+    if let [branch] = args.branches.as_slice() {
+        return cmd_delete_branch(backend, branch, &opts);
     }
 
-    let name_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(4).max(4);
-    let branch_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(6).max(6);
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let targets: Vec<&Worktree> = if args.branches.is_empty() {
+        let now = unix_now();
+        worktrees
+            .iter()
+            .filter(|wt| !wt.main && args.matches(backend, wt, now))
+            .collect()
+    } else {
+        let cwd = std::env::current_dir().context("read current directory")?;
+        args.branches
+            .iter()
+            .filter_map(|target| {
+                resolve_worktree_to_change(target, &worktrees, &cwd, opts.fuzzy, "delete")
+                    .transpose()
+            })
+            .collect::<Result<_>>()?
+    };
+    if targets.is_empty() {
+        println!("No worktrees to delete.");
+        return Ok(());
+    }
+    if args.interactive {
+        return delete_interactive(backend, &root, &targets, &opts);
+    }
+    if args.has_filters() && dry_run {
+        println!("Would delete {} worktree(s):", targets.len());
+    }
+    remove_worktrees(backend, &root, &targets, &opts)
+}
 
-    println!(
-        "{:name_width$} {:branch_width$} PATH FLAGS",
-        "NAME",
-        "BRANCH",
-        name_width = name_width,
-        branch_width = branch_width
-    );
-    for (name, branch, path, flags) in rows {
-        println!(
-            "{:name_width$} {:branch_width$} {} {}",
-            name,
-            branch,
-            path,
-            flags,
-            name_width = name_width,
-            branch_width = branch_width
+fn delete_interactive(
+    backend: &dyn Backend,
+    root: &Path,
+    candidates: &[&Worktree],
+    opts: &RemoveOptions,
+) -> Result<()> {
+    let now = unix_now();
+    let mut rows = Vec::new();
+    for wt in candidates {
+        let age = backend
+            .last_commit_time(&wt.path)
+            .ok()
+            .flatten()
+            .map(|t| format_age(now.saturating_sub(t)))
+            .unwrap_or_else(|| "-".to_string());
+        let dirty = if backend.is_dirty(&wt.path).unwrap_or(false) {
+            "dirty"
+        } else {
+            "clean"
+        };
+        rows.push([
+            worktree_name(wt),
+            worktree_branch_short(wt).unwrap_or("-").to_string(),
+            age,
+            dirty.to_string(),
+        ]);
+    }
+    let items = align_rows(&rows);
+
+    let selected = dialoguer::MultiSelect::new()
+        .with_prompt("Select worktrees to delete (space to toggle, enter to accept)")
+        .items(&items)
+        .interact()
+        .context("interactive selection requires a terminal")?;
+    if selected.is_empty() {
+        println!("Nothing selected.");
+        return Ok(());
+    }
+
+    eprintln!("About to delete:");
+    for &i in &selected {
+        eprintln!("  {}", items[i]);
+    }
+    let confirmed = dialoguer::Confirm::new()
+        .with_prompt(format!("Delete {} worktree(s)?", selected.len()))
+        .default(false)
+        .interact()
+        .context("interactive selection requires a terminal")?;
+    if !confirmed {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let targets: Vec<&Worktree> = selected.iter().map(|&i| candidates[i]).collect();
+    remove_worktrees(backend, root, &targets, opts)
+}
+
+/// Remove each worktree in turn, reporting failures without stopping early.
+fn remove_worktrees(
+    backend: &dyn Backend,
+    root: &Path,
+    targets: &[&Worktree],
+    opts: &RemoveOptions,
+) -> Result<()> {
+    let mut failed = 0;
+    for wt in targets {
+        let name = worktree_name(wt);
+        match remove_checked(backend, root, wt, opts) {
+            Ok(false) => {}
+            Ok(true) if opts.dry_run => {}
+            Ok(true) => eprintln!("Deleted {}", name),
+            Err(err) => {
+                eprintln!("Error: remove worktree '{}': {:#}", name, err);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!(
+            "failed to delete {} of {} worktree(s)",
+            failed,
+            targets.len()
         );
     }
+    Ok(())
+}
+
+/// The root of the primary worktree of the repository containing the
+/// current directory, also when that is inside a linked worktree, so the
+/// repository's name, registry layout, and per-repository state are the
+/// same from every worktree.
+fn repo_root(backend: &dyn Backend) -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let root = backend.root(&cwd)?;
+    primary_root(backend, root)
+}
+
+/// The primary worktree of the repository `root` is a worktree of, or
+/// `root` itself if the primary one is gone (or is a bare repository).
+fn primary_root(backend: &dyn Backend, root: PathBuf) -> Result<PathBuf> {
+    let worktrees = backend.list_worktrees(&root)?;
+    Ok(worktrees
+        .into_iter()
+        .find(|wt| wt.main && (wt.path.join(".git").exists() || wt.path.join(".jj").exists()))
+        .map_or(root, |wt| wt.path))
 }
 
 fn worktree_name(wt: &Worktree) -> String {
@@ -333,9 +2021,7 @@ fn worktree_name(wt: &Worktree) -> String {
     }
     wt.path
         .file_name()
-        .and_then(OsStr::to_str)
-        .unwrap_or("-")
-        .to_string()
+        .map_or("-".to_string(), |name| name.to_string_lossy().into_owned())
 }
 
 fn worktree_branch_short(wt: &Worktree) -> Option<&str> {
@@ -344,7 +2030,53 @@ fn worktree_branch_short(wt: &Worktree) -> Option<&str> {
         .map(|b| b.strip_prefix("refs/heads/").unwrap_or(b))
 }
 
-fn worktree_flags(wt: &Worktree) -> String {
+/// Print `path` on its own line exactly as the filesystem has it, so
+/// `cd "$(terris ...)"` works even when it is not valid UTF-8.
+fn print_path(path: &Path) {
+    let mut out = std::io::stdout().lock();
+    let _ = out.write_all(path.as_os_str().as_bytes());
+    let _ = out.write_all(b"\n");
+}
+
+/// The directory a `--relative[=<base>]` flag makes paths relative to.
+fn relative_base(flag: Option<Option<PathBuf>>) -> Option<PathBuf> {
+    flag.map(|base| base.unwrap_or_else(|| PathBuf::from(".")))
+}
+
+/// `path` relative to `base`, with `..` to climb out of `base` where
+/// needed. Both are resolved first, symlinks included, so a `base` given
+/// as `.` or through a symlink still shares the worktree's prefix.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let resolve = |p: &Path| {
+        p.canonicalize()
+            .or_else(|_| std::path::absolute(p))
+            .unwrap_or_else(|_| p.to_path_buf())
+    };
+    let (path, base) = (resolve(path), resolve(base));
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative: PathBuf = base
+        .components()
+        .skip(common)
+        .map(|_| Component::ParentDir)
+        .collect();
+    relative.extend(path.components().skip(common));
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+/// `wt`'s flags, with `dirty` when `status` (from the daemon's cache) says
+/// so.
+fn worktree_flags(
+    wt: &Worktree,
+    status: Option<&cache::Status>,
+    tracking: Option<Tracking>,
+) -> String {
     let mut flags = Vec::new();
     if wt.detached {
         flags.push("detached");
@@ -352,41 +2084,281 @@ fn worktree_flags(wt: &Worktree) -> String {
     if wt.locked {
         flags.push("locked");
     }
-    if wt.prunable.is_some() {
-        flags.push("prunable");
+    if wt.prunable.is_some() {
+        flags.push("prunable");
+    }
+    if status.is_some_and(|status| status.summary.has_uncommitted()) {
+        flags.push("dirty");
+    }
+    match tracking {
+        Some(Tracking::Gone) => flags.push("gone"),
+        Some(Tracking::Untracked) => flags.push("no-upstream"),
+        _ => {}
+    }
+    if flags.is_empty() {
+        "-".to_string()
+    } else {
+        flags.join(",")
+    }
+}
+
+fn find_worktree_by_branch<'a>(
+    branch: &str,
+    worktrees: &'a [Worktree],
+) -> Result<Option<&'a Worktree>> {
+    let matches: Vec<&Worktree> = worktrees
+        .iter()
+        .filter(|w| worktree_branch_short(w) == Some(branch))
+        .collect();
+    if matches.is_empty() {
+        return Ok(None);
+    }
+    if matches.len() > 1 {
+        let names: Vec<String> = matches
+            .iter()
+            .map(|w| w.path.display().to_string())
+            .collect();
+        return Err(Error::AmbiguousTarget {
+            target: branch.to_string(),
+            candidates: names,
+        }
+        .into());
+    }
+    Ok(Some(matches[0]))
+}
+
+/// Find the worktree a command-line target refers to.
+///
+/// Besides branch names this understands a few keywords: `main` is the
+/// primary worktree, `.` is the worktree containing `cwd`, and `-` is the
+/// previously visited worktree. Otherwise the first of these that matches
+/// wins: the branch name, the directory name, a number from the ID column of
+/// the worktree list, a unique prefix of the branch or of one of its
+/// `/`-separated parts, and (with `fuzzy`) the target's characters appearing
+/// in order in the branch name. Several matches at one level are an error.
+fn resolve_worktree<'a>(
+    target: &str,
+    worktrees: &'a [Worktree],
+    cwd: &Path,
+    fuzzy: bool,
+) -> Result<&'a Worktree> {
+    if let Some(wt) = exact_worktree(target, worktrees, cwd)? {
+        return Ok(wt);
+    }
+    loose_worktree(target, worktrees, fuzzy)?
+        .ok_or_else(|| Error::WorktreeNotFound(target.to_string()).into())
+}
+
+/// [`resolve_worktree`] for commands that discard or rewrite what is in the
+/// worktree. A target found only by prefix or fuzzily is confirmed first,
+/// naming the worktree it found, and is an error without a terminal to ask
+/// on. `action` completes the question, e.g. "delete". `None` when the
+/// user declines.
+fn resolve_worktree_to_change<'a>(
+    target: &str,
+    worktrees: &'a [Worktree],
+    cwd: &Path,
+    fuzzy: bool,
+    action: &str,
+) -> Result<Option<&'a Worktree>> {
+    if let Some(wt) = exact_worktree(target, worktrees, cwd)? {
+        return Ok(Some(wt));
+    }
+    let wt = loose_worktree(target, worktrees, fuzzy)?
+        .ok_or_else(|| Error::WorktreeNotFound(target.to_string()))?;
+    let name = worktree_name(wt);
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::InexactTarget {
+            target: target.to_string(),
+            matched: name,
+        }
+        .into());
+    }
+    let confirmed = dialoguer::Confirm::new()
+        .with_prompt(format!(
+            "'{}' matches {} ({}); {} {}?",
+            target,
+            name,
+            wt.path.display(),
+            action,
+            name
+        ))
+        .default(false)
+        .interact()
+        .with_context(|| format!("confirm {}", action))?;
+    if !confirmed {
+        eprintln!("Kept {}", name);
+        return Ok(None);
+    }
+    Ok(Some(wt))
+}
+
+/// The worktree `target` names outright: a keyword, its branch, its
+/// directory name, or its ID.
+fn exact_worktree<'a>(
+    target: &str,
+    worktrees: &'a [Worktree],
+    cwd: &Path,
+) -> Result<Option<&'a Worktree>> {
+    match target {
+        "main" => worktrees
+            .iter()
+            .find(|wt| wt.main)
+            .map(Some)
+            .ok_or_else(|| Error::WorktreeNotFound(target.to_string()).into()),
+        "." => current_worktree(worktrees, cwd)
+            .map(Some)
+            .ok_or_else(|| Error::WorktreeNotFound(target.to_string()).into()),
+        "-" => previous_worktree(worktrees, cwd, &Metadata::load()?)
+            .map(Some)
+            .context("no previously visited worktree"),
+        branch => {
+            if let Some(wt) = find_worktree_by_branch(branch, worktrees)? {
+                return Ok(Some(wt));
+            }
+            let by_dir = |wt: &&Worktree| wt.path.file_name() == Some(OsStr::new(branch));
+            if let Some(wt) = unique_match(branch, worktrees, by_dir)? {
+                return Ok(Some(wt));
+            }
+            let Ok(index) = branch.parse::<u32>() else {
+                return Ok(None);
+            };
+            let metadata = metadata::indexed(worktrees);
+            Ok(worktrees
+                .iter()
+                .find(|wt| metadata.index(&wt.path) == Some(index)))
+        }
+    }
+}
+
+/// The worktree whose branch `target` is a unique prefix of, or with
+/// `fuzzy`, a unique subsequence of.
+fn loose_worktree<'a>(
+    target: &str,
+    worktrees: &'a [Worktree],
+    fuzzy: bool,
+) -> Result<Option<&'a Worktree>> {
+    let by_prefix = |wt: &&Worktree| {
+        worktree_branch_short(wt).is_some_and(|b| b.split('/').any(|part| part.starts_with(target)))
+    };
+    if let Some(wt) = unique_match(target, worktrees, by_prefix)? {
+        return Ok(Some(wt));
     }
-    if flags.is_empty() {
-        "-".to_string()
-    } else {
-        flags.join(",")
+    let by_fuzzy =
+        |wt: &&Worktree| worktree_branch_short(wt).is_some_and(|b| is_subsequence(target, b));
+    if fuzzy {
+        return unique_match(target, worktrees, by_fuzzy);
     }
+    Ok(None)
 }
 
-fn find_worktree_by_branch<'a>(
-    branch: &str,
+/// The only worktree satisfying `pred`, `None` if there is none, or an
+/// ambiguity error naming every candidate.
+fn unique_match<'a>(
+    target: &str,
     worktrees: &'a [Worktree],
+    pred: impl Fn(&&Worktree) -> bool,
 ) -> Result<Option<&'a Worktree>> {
-    let matches: Vec<&Worktree> = worktrees
-        .iter()
-        .filter(|w| worktree_branch_short(w) == Some(branch))
-        .collect();
-    if matches.is_empty() {
-        return Ok(None);
+    let matches: Vec<&Worktree> = worktrees.iter().filter(pred).collect();
+    match matches.as_slice() {
+        [] => Ok(None),
+        [wt] => Ok(Some(wt)),
+        _ => Err(Error::AmbiguousTarget {
+            target: target.to_string(),
+            candidates: matches.iter().map(|wt| worktree_name(wt)).collect(),
+        }
+        .into()),
     }
-    if matches.len() > 1 {
-        let names: Vec<String> = matches
-            .iter()
-            .map(|w| w.path.display().to_string())
-            .collect();
-        bail!("branch '{}' is ambiguous: {}", branch, names.join(", "));
+}
+
+/// Whether the characters of `needle` appear in order in `haystack`,
+/// ignoring ASCII case.
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle
+        .chars()
+        .all(|n| haystack.by_ref().any(|h| h.eq_ignore_ascii_case(&n)))
+}
+
+/// The most recently used worktree other than the one containing `cwd`.
+fn previous_worktree<'a>(
+    worktrees: &'a [Worktree],
+    cwd: &Path,
+    metadata: &Metadata,
+) -> Option<&'a Worktree> {
+    let current = current_worktree(worktrees, cwd).map(|wt| &wt.path);
+    metadata
+        .by_recency(worktrees)
+        .into_iter()
+        .filter(|wt| metadata.last_used(&wt.path).is_some())
+        .find(|wt| Some(&wt.path) != current)
+}
+
+/// The worktree containing `cwd`, preferring the innermost one when a
+/// worktree is nested inside another.
+fn current_worktree<'a>(worktrees: &'a [Worktree], cwd: &Path) -> Option<&'a Worktree> {
+    worktrees
+        .iter()
+        .filter(|wt| cwd.starts_with(&wt.path))
+        .max_by_key(|wt| wt.path.components().count())
+}
+
+/// The path template for a new worktree: from the naming script if there is
+/// one, otherwise from the config file.
+fn worktree_template(
+    config: &Config,
+    root: &Path,
+    branch: &str,
+    start: Option<&str>,
+) -> Result<String> {
+    let repo = repo_name(root);
+    match &config.naming_script {
+        Some(file) if !config.stable => script::Script::load(file)?.template(serde_json::json!({
+            "branch": branch,
+            "repo": repo,
+            "repo_root": root,
+            "start": start,
+        })),
+        _ => Ok(config.path_template(&repo).to_string()),
     }
-    Ok(Some(matches[0]))
 }
 
-fn default_worktree_path(repo_name: &str, branch: &str) -> Result<PathBuf> {
-    let suffix = random_suffix(8);
-    let base = registry_base_dir()?;
-    Ok(base.join(repo_name).join(format!("{}-{}", branch, suffix)))
+/// Where a new worktree for `branch` goes, following `template`.
+fn default_worktree_path(
+    config: &Config,
+    template: &str,
+    root: &Path,
+    branch: &str,
+) -> Result<PathBuf> {
+    let base = config.base_dir(&repo_name(root))?;
+    let vars = naming::Vars {
+        base: &base,
+        repo_root: root,
+        branch,
+        now: unix_now(),
+    };
+    naming::render(template, &vars)
+}
+
+/// The repository's directory name, used for registry paths and
+/// per-repository config.
+fn repo_name(root: &Path) -> String {
+    root.file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("repo")
+        .to_string()
+}
+
+/// `<path>-N` for the smallest N >= 2 that does not exist yet.
+fn free_path(path: &Path) -> PathBuf {
+    (2..)
+        .map(|n| {
+            let mut candidate = path.as_os_str().to_owned();
+            candidate.push(format!("-{}", n));
+            PathBuf::from(candidate)
+        })
+        .find(|candidate| !candidate.exists())
+        .expect("some suffix is free")
 }
 
 fn registry_base_dir() -> Result<PathBuf> {
@@ -394,6 +2366,79 @@ fn registry_base_dir() -> Result<PathBuf> {
     Ok(PathBuf::from(home).join(".terris-worktrees"))
 }
 
+/// Pad each column of `rows` to a common width and join columns with spaces.
+fn align_rows<const N: usize>(rows: &[[String; N]]) -> Vec<String> {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(table::width(cell));
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| table::pad(cell, width, Align::Left))
+                .collect();
+            cells.join(" ").trim_end().to_string()
+        })
+        .collect()
+}
+
+/// FNV-1a: a hash that, unlike std's, is the same in every build.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Format unix seconds as a sortable UTC timestamp, e.g. `20260131-174502`.
+fn format_timestamp(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    // Civil-from-days conversion (proleptic Gregorian calendar).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Compact human-readable age, e.g. `45m`, `3h`, `12d`, `8w`.
+fn format_age(secs: i64) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+    const WEEK: i64 = 7 * DAY;
+    match secs {
+        s if s < HOUR => format!("{}m", s / MINUTE),
+        s if s < DAY => format!("{}h", s / HOUR),
+        s if s < 8 * WEEK => format!("{}d", s / DAY),
+        s => format!("{}w", s / WEEK),
+    }
+}
+
 fn random_suffix(len: usize) -> String {
     let mut rng = rand::rng();
     let mut out = String::with_capacity(len);
@@ -407,19 +2452,29 @@ fn random_suffix(len: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
+    use std::sync::{Mutex, MutexGuard};
+
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
 
     struct EnvGuard {
         key: &'static str,
         prior: Option<std::ffi::OsString>,
+        _lock: MutexGuard<'static, ()>,
     }
 
     impl EnvGuard {
         fn set(key: &'static str, value: &Path) -> Self {
+            let lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
             let prior = std::env::var_os(key);
             unsafe {
                 std::env::set_var(key, value);
             }
-            Self { key, prior }
+            Self {
+                key,
+                prior,
+                _lock: lock,
+            }
         }
     }
 
@@ -436,6 +2491,233 @@ mod tests {
         }
     }
 
+    /// In-memory backend so command logic can be tested without git.
+    #[derive(Default)]
+    struct FakeBackend {
+        worktrees: RefCell<Vec<Worktree>>,
+        branches: RefCell<Vec<String>>,
+        removed: RefCell<Vec<PathBuf>>,
+    }
+
+    impl Backend for FakeBackend {
+        fn root(&self, _cwd: &Path) -> Result<PathBuf> {
+            Ok(PathBuf::from("/repo"))
+        }
+
+        fn list_worktrees(&self, _root: &Path) -> Result<Vec<Worktree>> {
+            Ok(self.worktrees.borrow().clone())
+        }
+
+        fn branch_exists(&self, _root: &Path, branch: &str) -> Result<bool> {
+            Ok(self.branches.borrow().iter().any(|b| b == branch))
+        }
+
+        fn add_worktree(&self, _root: &Path, path: &Path, branch: &str) -> Result<()> {
+            self.worktrees.borrow_mut().push(wt(
+                &path.to_string_lossy(),
+                Some(&format!("refs/heads/{branch}")),
+            ));
+            Ok(())
+        }
+
+        fn add_detached(&self, _root: &Path, path: &Path, _rev: &str) -> Result<()> {
+            self.worktrees
+                .borrow_mut()
+                .push(wt(&path.to_string_lossy(), None));
+            Ok(())
+        }
+
+        fn remove_worktree(&self, _root: &Path, path: &Path, _force: bool) -> Result<()> {
+            self.removed.borrow_mut().push(path.to_path_buf());
+            Ok(())
+        }
+
+        fn is_dirty(&self, _path: &Path) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn dirty_all(&self, paths: &[&Path]) -> Vec<Result<bool>> {
+            paths.iter().map(|_| Ok(false)).collect()
+        }
+
+        fn change_summary(&self, _path: &Path) -> Result<ChangeSummary> {
+            Ok(ChangeSummary::default())
+        }
+
+        fn list_files(&self, _path: &Path) -> Result<Vec<PathBuf>> {
+            Ok(Vec::new())
+        }
+
+        fn backup_changes(&self, _path: &Path, _name: &str) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn last_commit_time(&self, _path: &Path) -> Result<Option<i64>> {
+            Ok(None)
+        }
+
+        fn head_commit(&self, _path: &Path) -> Result<Option<backend::Commit>> {
+            Ok(None)
+        }
+
+        fn search_commits(
+            &self,
+            _root: &Path,
+            _branch: &str,
+            _base: &str,
+            _pattern: &str,
+            _ignore_case: bool,
+        ) -> Result<Vec<backend::Commit>> {
+            Ok(Vec::new())
+        }
+
+        fn is_merged(&self, _root: &Path, _branch: &str, _base: &str) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn unique_commits(&self, _root: &Path, _branch: &str) -> Result<usize> {
+            Ok(0)
+        }
+
+        fn upstream_gone(&self, _root: &Path, _branch: &str) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn tracking(&self, _root: &Path) -> Result<std::collections::HashMap<String, Tracking>> {
+            Ok(std::collections::HashMap::new())
+        }
+
+        fn delete_branch(&self, _root: &Path, _branch: &str, _force: bool) -> Result<()> {
+            Ok(())
+        }
+
+        fn create_branch(&self, _root: &Path, branch: &str, _start: &str) -> Result<()> {
+            self.branches.borrow_mut().push(branch.to_string());
+            Ok(())
+        }
+
+        fn exclude_locally(&self, _root: &Path, _pattern: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn enable_worktree_config(&self, _root: &Path) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn worktree_config(&self, _path: &Path, _key: &str) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn set_worktree_config(
+            &self,
+            _path: &Path,
+            _key: &str,
+            _value: Option<&str>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        fn add_worktree_config(&self, _path: &Path, _key: &str, _value: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn own_config(&self, _root: &Path) -> Result<Vec<(String, String)>> {
+            Ok(Vec::new())
+        }
+
+        fn repair_worktrees(&self, _root: &Path, _moved: &[PathBuf]) -> Result<()> {
+            Ok(())
+        }
+
+        fn reset_to(&self, _path: &Path, _rev: Option<&str>) -> Result<()> {
+            Ok(())
+        }
+
+        fn stash_push(&self, _path: &Path, _untracked: bool) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn stash_apply(&self, _path: &Path, _stash: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn stash_drop(&self, _root: &Path, _stash: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn apply_patch(&self, _path: &Path, _patch: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn diff_worktrees(&self, _from: &Path, _to: &Path, _paths: &[String]) -> Result<()> {
+            Ok(())
+        }
+
+        fn integrate(
+            &self,
+            _source: &Path,
+            _branch: &str,
+            _base: &str,
+            _base_worktree: Option<&Path>,
+            _how: backend::Integration,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        fn rebase(&self, _path: &Path, _onto: &str, _upstream: Option<&str>) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn fetch(&self, _root: &Path) -> Result<()> {
+            Ok(())
+        }
+
+        fn upstream_divergence(
+            &self,
+            _root: &Path,
+            _branch: &str,
+        ) -> Result<Option<backend::Divergence>> {
+            Ok(None)
+        }
+
+        fn fast_forward(&self, _path: &Path) -> Result<()> {
+            Ok(())
+        }
+
+        fn prune(&self, _root: &Path) -> Result<()> {
+            Ok(())
+        }
+
+        fn upstream(&self, _root: &Path, _branch: &str) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn remote_url(&self, _root: &Path, _remote: &str) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn push_branch(&self, _root: &Path, _branch: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn common_dir(&self, root: &Path) -> Result<PathBuf> {
+            Ok(root.join(".git"))
+        }
+
+        fn resolve_rev(&self, _root: &Path, rev: &str) -> Result<String> {
+            Ok(rev.to_string())
+        }
+
+        fn switch_branch(&self, path: &Path, branch: &str) -> Result<()> {
+            for wt in self.worktrees.borrow_mut().iter_mut() {
+                if wt.path == path {
+                    wt.branch = Some(format!("refs/heads/{branch}"));
+                }
+            }
+            Ok(())
+        }
+    }
+
     fn wt(path: &str, branch: Option<&str>) -> Worktree {
         Worktree {
             path: PathBuf::from(path),
@@ -444,36 +2726,6 @@ mod tests {
         }
     }
 
-    #[test]
-    fn parse_worktrees_parses_porcelain() {
-        let input = "\
-worktree /repo
-HEAD 111111
-branch refs/heads/main
-
-worktree /repo/feature
-HEAD 222222
-detached
-locked
-prunable stale
-";
-        let worktrees = parse_worktrees(input);
-        assert_eq!(worktrees.len(), 2);
-        assert_eq!(worktrees[0].path, PathBuf::from("/repo"));
-        assert_eq!(worktrees[0].head.as_deref(), Some("111111"));
-        assert_eq!(worktrees[0].branch.as_deref(), Some("refs/heads/main"));
-        assert!(!worktrees[0].detached);
-        assert!(!worktrees[0].locked);
-        assert!(worktrees[0].prunable.is_none());
-
-        assert_eq!(worktrees[1].path, PathBuf::from("/repo/feature"));
-        assert_eq!(worktrees[1].head.as_deref(), Some("222222"));
-        assert!(worktrees[1].branch.is_none());
-        assert!(worktrees[1].detached);
-        assert!(worktrees[1].locked);
-        assert_eq!(worktrees[1].prunable.as_deref(), Some("stale"));
-    }
-
     #[test]
     fn worktree_display_helpers() {
         let mut wt = Worktree {
@@ -486,14 +2738,17 @@ prunable stale
         };
         assert_eq!(worktree_branch_short(&wt), Some("feature"));
         assert_eq!(worktree_name(&wt), "feature");
-        assert_eq!(worktree_flags(&wt), "detached,locked,prunable");
+        assert_eq!(
+            worktree_flags(&wt, None, Some(Tracking::Gone)),
+            "detached,locked,prunable,gone"
+        );
 
         wt.branch = None;
         assert_eq!(worktree_name(&wt), "feature");
         wt.detached = false;
         wt.locked = false;
         wt.prunable = None;
-        assert_eq!(worktree_flags(&wt), "-");
+        assert_eq!(worktree_flags(&wt, None, Some(Tracking::Tracked)), "-");
     }
 
     #[test]
@@ -510,22 +2765,172 @@ prunable stale
         assert!(missing.is_none());
     }
 
+    #[test]
+    fn resolve_worktree_understands_keywords() {
+        let mut primary = wt("/repo", Some("refs/heads/trunk"));
+        primary.main = true;
+        let worktrees = vec![
+            primary,
+            wt("/repo/.worktrees/feature", Some("refs/heads/feature")),
+        ];
+
+        let cwd = Path::new("/repo/.worktrees/feature/src");
+        let main = resolve_worktree("main", &worktrees, cwd, false).unwrap();
+        assert_eq!(main.path, PathBuf::from("/repo"));
+        let here = resolve_worktree(".", &worktrees, cwd, false).unwrap();
+        assert_eq!(here.path, PathBuf::from("/repo/.worktrees/feature"));
+        let here = resolve_worktree(".", &worktrees, Path::new("/repo/src"), false).unwrap();
+        assert_eq!(here.path, PathBuf::from("/repo"));
+        let by_branch = resolve_worktree("feature", &worktrees, cwd, false).unwrap();
+        assert_eq!(by_branch.path, PathBuf::from("/repo/.worktrees/feature"));
+
+        assert!(resolve_worktree(".", &worktrees, Path::new("/elsewhere"), false).is_err());
+        assert!(resolve_worktree("missing", &worktrees, cwd, false).is_err());
+    }
+
+    #[test]
+    fn resolve_worktree_matches_prefixes_and_fuzzy() {
+        let worktrees = vec![
+            wt(
+                "/wt/auth-refactor-x",
+                Some("refs/heads/feature/auth-refactor"),
+            ),
+            wt("/wt/authz-y", Some("refs/heads/authz")),
+            wt("/wt/login-z", Some("refs/heads/fix/login-page")),
+        ];
+        let cwd = Path::new("/");
+        let resolve = |target, fuzzy| resolve_worktree(target, &worktrees, cwd, fuzzy);
+
+        assert_eq!(
+            resolve("login", false).unwrap().path,
+            PathBuf::from("/wt/login-z")
+        );
+        assert_eq!(
+            resolve("authz-y", false).unwrap().path,
+            PathBuf::from("/wt/authz-y")
+        );
+        let err = resolve("auth", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'auth' is ambiguous: feature/auth-refactor, authz"
+        );
+
+        assert!(resolve("lgnpg", false).is_err());
+        assert_eq!(
+            resolve("lgnpg", true).unwrap().path,
+            PathBuf::from("/wt/login-z")
+        );
+        assert!(resolve("uh", true).is_err());
+
+        // Only the branch and directory name count as exact.
+        let exact = |target| {
+            exact_worktree(target, &worktrees, cwd)
+                .unwrap()
+                .map(|wt| wt.path.clone())
+        };
+        assert_eq!(exact("fix/login-page"), Some(PathBuf::from("/wt/login-z")));
+        assert_eq!(exact("login-z"), Some(PathBuf::from("/wt/login-z")));
+        assert_eq!(exact("login"), None);
+        assert_eq!(exact("lgnpg"), None);
+    }
+
+    #[test]
+    fn assign_indices_is_stable_and_fills_gaps() {
+        let mut worktrees = vec![
+            wt("/repo", Some("refs/heads/main")),
+            wt("/repo/a", Some("refs/heads/a")),
+            wt("/repo/b", Some("refs/heads/b")),
+        ];
+        let mut metadata = Metadata::default();
+        assert!(metadata.assign_indices(&worktrees));
+        assert!(!metadata.assign_indices(&worktrees));
+        assert_eq!(metadata.index(Path::new("/repo/a")), Some(2));
+
+        metadata.worktrees.remove(Path::new("/repo/a"));
+        worktrees.remove(1);
+        worktrees.insert(0, wt("/repo/c", Some("refs/heads/c")));
+        assert!(metadata.assign_indices(&worktrees));
+        assert_eq!(metadata.index(Path::new("/repo")), Some(1));
+        assert_eq!(metadata.index(Path::new("/repo/b")), Some(3));
+        assert_eq!(metadata.index(Path::new("/repo/c")), Some(2));
+    }
+
+    #[test]
+    fn previous_worktree_skips_current_and_unused() {
+        let mut primary = wt("/repo", Some("refs/heads/main"));
+        primary.main = true;
+        let worktrees = vec![
+            primary,
+            wt("/repo/.worktrees/a", Some("refs/heads/a")),
+            wt("/repo/.worktrees/b", Some("refs/heads/b")),
+        ];
+        let mut metadata = Metadata::default();
+        for (path, time) in [("/repo/.worktrees/a", 200), ("/repo", 100)] {
+            metadata.worktrees.insert(
+                PathBuf::from(path),
+                metadata::WorktreeMeta {
+                    last_used: Some(time),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let from_a = previous_worktree(&worktrees, Path::new("/repo/.worktrees/a"), &metadata);
+        assert_eq!(from_a.unwrap().path, PathBuf::from("/repo"));
+        let from_b = previous_worktree(&worktrees, Path::new("/repo/.worktrees/b"), &metadata);
+        assert_eq!(from_b.unwrap().path, PathBuf::from("/repo/.worktrees/a"));
+
+        let names: Vec<String> = metadata
+            .by_recency(&worktrees)
+            .into_iter()
+            .map(worktree_name)
+            .collect();
+        assert_eq!(names, ["a", "main", "b"]);
+        assert!(previous_worktree(&worktrees, Path::new("/x"), &Metadata::default()).is_none());
+    }
+
     #[test]
     fn default_worktree_path_uses_home_registry_and_suffix() {
         let temp_home = std::env::temp_dir().join("terris-tests-home");
         let _ = std::fs::create_dir_all(&temp_home);
         let _guard = EnvGuard::set("HOME", &temp_home);
 
-        let path = default_worktree_path("repo", "branch").unwrap();
+        let mut config = Config::default();
+        let repo = Path::new("/src/repo");
+        let stable =
+            default_worktree_path(&config, naming::STABLE_TEMPLATE, repo, "feature/x").unwrap();
+        assert_eq!(stable, temp_home.join(".terris-worktrees/repo/feature/x"));
+
+        let path =
+            default_worktree_path(&config, naming::DEFAULT_TEMPLATE, repo, "branch").unwrap();
         let base = temp_home.join(".terris-worktrees").join("repo");
         assert!(path.starts_with(&base));
 
+        config.repos.entry("repo".into()).or_default().base_dir = Some("/fast/work".into());
+        let moved =
+            default_worktree_path(&config, naming::STABLE_TEMPLATE, repo, "feature/x").unwrap();
+        assert_eq!(moved, PathBuf::from("/fast/work/repo/feature/x"));
+
         let file_name = path.file_name().and_then(OsStr::to_str).unwrap();
         let suffix = file_name.strip_prefix("branch-").unwrap();
         assert_eq!(suffix.len(), 8);
         assert!(suffix.chars().all(|c| c.is_ascii_lowercase()));
     }
 
+    #[test]
+    fn relative_path_climbs_out_of_the_base() {
+        let rel = |path, base| relative_path(Path::new(path), Path::new(base));
+        assert_eq!(
+            rel("/nowhere/wt/a", "/nowhere/repo/src"),
+            Path::new("../../wt/a")
+        );
+        assert_eq!(
+            rel("/nowhere/repo/.worktrees/x", "/nowhere/repo"),
+            Path::new(".worktrees/x")
+        );
+        assert_eq!(rel("/nowhere/repo", "/nowhere/repo"), Path::new("."));
+    }
+
     #[test]
     fn find_worktree_by_branch_matches() {
         let worktrees = vec![
@@ -536,4 +2941,161 @@ prunable stale
         let by_branch = find_worktree_by_branch("main", &worktrees).unwrap();
         assert_eq!(by_branch.unwrap().path, PathBuf::from("/repo/alpha"));
     }
+
+    #[test]
+    fn ensure_worktree_reuses_or_creates() {
+        let temp_home = std::env::temp_dir().join("terris-tests-home");
+        let _ = std::fs::create_dir_all(&temp_home);
+        let _guard = EnvGuard::set("HOME", &temp_home);
+
+        let backend = FakeBackend {
+            worktrees: RefCell::new(vec![wt("/repo", Some("refs/heads/main"))]),
+            branches: RefCell::new(vec!["main".into(), "feature".into()]),
+            ..FakeBackend::default()
+        };
+
+        let existing = ensure_worktree(
+            &backend,
+            "main",
+            &Config::default(),
+            Collision::Reuse,
+            false,
+        )
+        .unwrap();
+        assert_eq!(existing, PathBuf::from("/repo"));
+
+        let created = ensure_worktree(
+            &backend,
+            "feature",
+            &Config::default(),
+            Collision::Reuse,
+            false,
+        )
+        .unwrap();
+        assert!(created.starts_with(temp_home.join(".terris-worktrees").join("repo")));
+        assert_eq!(
+            ensure_worktree(
+                &backend,
+                "feature",
+                &Config::default(),
+                Collision::Reuse,
+                false
+            )
+            .unwrap(),
+            created
+        );
+
+        let err = ensure_worktree(
+            &backend,
+            "missing",
+            &Config::default(),
+            Collision::Reuse,
+            false,
+        )
+        .unwrap_err();
+        assert!(format!("{err}").contains("does not exist"));
+    }
+
+    #[test]
+    fn ensure_worktree_applies_collision_strategy() {
+        let temp_home = std::env::temp_dir().join("terris-tests-home");
+        let _ = std::fs::create_dir_all(&temp_home);
+        let _guard = EnvGuard::set("HOME", &temp_home);
+
+        let backend = FakeBackend {
+            worktrees: RefCell::new(vec![
+                wt("/repo", Some("refs/heads/main")),
+                wt("/wt/feature", Some("refs/heads/feature")),
+            ]),
+            branches: RefCell::new(vec!["main".into(), "feature".into(), "feature-2".into()]),
+            ..FakeBackend::default()
+        };
+        let config = Config::default();
+
+        let err =
+            ensure_worktree(&backend, "feature", &config, Collision::Error, false).unwrap_err();
+        assert_eq!(error::exit_code(&err), 9);
+
+        let created =
+            ensure_worktree(&backend, "feature", &config, Collision::Suffix, false).unwrap();
+        assert_ne!(created, PathBuf::from("/wt/feature"));
+        assert!(backend.branches.borrow().contains(&"feature-3".to_string()));
+        let worktrees = backend.worktrees.borrow();
+        let last = worktrees.last().unwrap();
+        assert_eq!(worktree_branch_short(last), Some("feature-3"));
+    }
+
+    #[test]
+    fn delete_branch_removes_matching_worktree() {
+        let temp_home = std::env::temp_dir().join("terris-tests-home");
+        let _ = std::fs::create_dir_all(&temp_home);
+        let _guard = EnvGuard::set("HOME", &temp_home);
+
+        let backend = FakeBackend {
+            worktrees: RefCell::new(vec![wt("/wt/feature", Some("refs/heads/feature"))]),
+            ..FakeBackend::default()
+        };
+        cmd_delete_branch(&backend, "feature", &RemoveOptions::default()).unwrap();
+        assert_eq!(
+            *backend.removed.borrow(),
+            vec![PathBuf::from("/wt/feature")]
+        );
+        assert!(cmd_delete_branch(&backend, "missing", &RemoveOptions::default()).is_err());
+    }
+
+    #[test]
+    fn format_age_picks_unit() {
+        assert_eq!(format_age(59), "0m");
+        assert_eq!(format_age(3 * 3600 + 5), "3h");
+        assert_eq!(format_age(12 * 86400), "12d");
+        assert_eq!(format_age(70 * 86400), "10w");
+    }
+
+    #[test]
+    fn align_rows_pads_columns() {
+        let rows = [
+            ["a".to_string(), "main".to_string()],
+            ["long".to_string(), "x".to_string()],
+        ];
+        assert_eq!(align_rows(&rows), vec!["a    main", "long x"]);
+    }
+
+    #[test]
+    fn delete_filters_select_matching_worktrees() {
+        let temp_home = std::env::temp_dir().join("terris-tests-home");
+        let _ = std::fs::create_dir_all(&temp_home);
+        let _guard = EnvGuard::set("HOME", &temp_home);
+
+        let cli = Cli::try_parse_from([
+            "terris",
+            "delete",
+            "--branch",
+            "hotfix/*",
+            "--path-prefix",
+            "/wt",
+        ])
+        .unwrap();
+        let Some(Command::Delete(args)) = cli.command else {
+            panic!("expected delete command");
+        };
+        let backend = FakeBackend {
+            worktrees: RefCell::new(vec![
+                wt("/repo", Some("refs/heads/main")),
+                wt("/wt/a", Some("refs/heads/hotfix/a")),
+                wt("/elsewhere/b", Some("refs/heads/hotfix/b")),
+                wt("/wt/c", Some("refs/heads/feature/c")),
+            ]),
+            ..FakeBackend::default()
+        };
+        backend.worktrees.borrow_mut()[0].main = true;
+        cmd_delete(&backend, &args, &Config::default(), false).unwrap();
+        assert_eq!(*backend.removed.borrow(), vec![PathBuf::from("/wt/a")]);
+    }
+
+    #[test]
+    fn format_timestamp_is_utc_civil_time() {
+        assert_eq!(format_timestamp(0), "19700101-000000");
+        assert_eq!(format_timestamp(1_769_881_502), "20260131-174502");
+        assert_eq!(format_timestamp(951_782_400), "20000229-000000");
+    }
 }
@@ -0,0 +1,256 @@
+//! Per-worktree bookkeeping that git does not track, such as when a worktree
+//! was last used. Stored as JSON next to the worktrees terris creates.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::forge::PullRequestLookup;
+use crate::lock;
+use crate::{Worktree, registry_base_dir, unix_now};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Keyed by worktree path.
+    #[serde(default)]
+    pub worktrees: BTreeMap<PathBuf, WorktreeMeta>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WorktreeMeta {
+    /// Unix time the worktree was last opened with `terris <branch>` or
+    /// `terris path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<i64>,
+    /// Unix time terris created the worktree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<i64>,
+    /// Primary worktree of the repository, recorded when terris creates or
+    /// adopts the worktree, so `terris repos` knows where it belongs even
+    /// once its directory is gone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<PathBuf>,
+    /// How many times the worktree has been opened, for frecency ranking.
+    #[serde(default)]
+    pub uses: u32,
+    /// Number shown in the list's ID column; stable until the worktree is
+    /// removed, and unique within its repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    /// Created by `create --ephemeral`: removed when its shell exits or by
+    /// `terris gc`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ephemeral: bool,
+    /// terris created the branch along with the worktree, so it may delete
+    /// it again.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub owns_branch: bool,
+    /// Process currently using the worktree; `gc` leaves it alone while
+    /// this is alive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Set for worktrees managed by `terris pool`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool: Option<PoolState>,
+    /// Branch this worktree's branch is stacked on (`create --on`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// The parent's commit the branch was last based on, so a restack
+    /// moves only the branch's own commits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stacked_on: Option<String>,
+    /// What the branch was started from, when terris created it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    /// What the worktree is for, from `--description`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// First port of the block `terris port` reserved for the worktree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Named services given ports after `port`, in allocation order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<String>,
+    /// The branch's open pull or merge request, as `--prs` or `info` last
+    /// looked it up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<PullRequestLookup>,
+    /// Handed back with `terris disown`: terris neither manages nor collects
+    /// it until it is adopted again.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disowned: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PoolState {
+    /// Clean and waiting for `pool acquire`.
+    Free,
+    /// Handed out; returns to the pool on `pool release`.
+    Leased,
+}
+
+impl WorktreeMeta {
+    /// Whether terris created or adopted the worktree.
+    pub fn managed(&self) -> bool {
+        self.created.is_some()
+    }
+
+    /// zoxide-style frecency: use count weighted by how recently it was used.
+    pub fn frecency(&self, now: i64) -> f64 {
+        let Some(last_used) = self.last_used else {
+            return 0.0;
+        };
+        let age = now.saturating_sub(last_used);
+        let weight = match age {
+            a if a < 3600 => 4.0,
+            a if a < 86400 => 2.0,
+            a if a < 7 * 86400 => 0.5,
+            _ => 0.25,
+        };
+        f64::from(self.uses.max(1)) * weight
+    }
+}
+
+impl Metadata {
+    fn file() -> Result<PathBuf> {
+        Ok(registry_base_dir()?.join("metadata.json"))
+    }
+
+    /// Read the store, treating a missing file as empty.
+    pub fn load() -> Result<Self> {
+        let file = Self::file()?;
+        let data = match std::fs::read_to_string(&file) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("read '{}'", file.display()));
+            }
+        };
+        serde_json::from_str(&data).with_context(|| format!("parse '{}'", file.display()))
+    }
+
+    /// Write the store atomically so concurrent readers never see a partial file.
+    pub fn save(&self) -> Result<()> {
+        let file = Self::file()?;
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("create '{}'", parent.display()))?;
+        }
+        let tmp = file.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("write '{}'", tmp.display()))?;
+        std::fs::rename(&tmp, &file).with_context(|| format!("write '{}'", file.display()))
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&WorktreeMeta> {
+        self.worktrees.get(path)
+    }
+
+    pub fn last_used(&self, path: &Path) -> Option<i64> {
+        self.worktrees.get(path).and_then(|meta| meta.last_used)
+    }
+
+    pub fn index(&self, path: &Path) -> Option<u32> {
+        self.worktrees.get(path).and_then(|meta| meta.index)
+    }
+
+    /// Whether terris created or adopted the worktree at `path`.
+    pub fn managed(&self, path: &Path) -> bool {
+        self.worktrees.get(path).is_some_and(WorktreeMeta::managed)
+    }
+
+    /// Give each of `worktrees` (all from one repository) an index, keeping
+    /// recorded ones and handing out the smallest free numbers, starting at
+    /// 1, to the rest. Returns whether anything changed.
+    pub fn assign_indices(&mut self, worktrees: &[Worktree]) -> bool {
+        let mut taken = std::collections::BTreeSet::new();
+        let mut missing = Vec::new();
+        for wt in worktrees {
+            match self.index(&wt.path) {
+                Some(index) if taken.insert(index) => {}
+                _ => missing.push(&wt.path),
+            }
+        }
+        let mut next = 1;
+        for path in &missing {
+            while taken.contains(&next) {
+                next += 1;
+            }
+            taken.insert(next);
+            self.worktrees.entry(path.to_path_buf()).or_default().index = Some(next);
+        }
+        !missing.is_empty()
+    }
+
+    /// `worktrees` ordered from most to least recently used; never-used
+    /// worktrees keep their relative order at the end.
+    pub fn by_recency<'a>(&self, worktrees: &'a [Worktree]) -> Vec<&'a Worktree> {
+        let mut sorted: Vec<&Worktree> = worktrees.iter().collect();
+        sorted.sort_by_key(|wt| std::cmp::Reverse(self.last_used(&wt.path)));
+        sorted
+    }
+}
+
+/// Load the store with every one of `worktrees` indexed, saving any newly
+/// assigned indices. A store that cannot be read or written is logged and
+/// treated as empty, so listing never fails because of it, but it is never
+/// written back: what it holds is not lost to a passing read error.
+pub fn indexed(worktrees: &[Worktree]) -> Metadata {
+    let mut metadata = Metadata::load().unwrap_or_else(|err| {
+        tracing::warn!("could not read worktree metadata: {:#}", err);
+        Metadata::default()
+    });
+    if !metadata.assign_indices(worktrees) {
+        return metadata;
+    }
+    // Assign again on what is stored now that no one else can change it.
+    // A store that cannot be read now is left alone rather than replaced.
+    let result = lock::registry().and_then(|_lock| {
+        let mut stored = Metadata::load()?;
+        stored.assign_indices(worktrees);
+        stored.save()?;
+        metadata = stored;
+        Ok(())
+    });
+    if let Err(err) = result {
+        tracing::warn!("could not save worktree indices: {:#}", err);
+    }
+    metadata
+}
+
+/// Change what is recorded for the worktree at `path` and save the store.
+pub fn update(path: &Path, change: impl FnOnce(&mut WorktreeMeta)) -> Result<()> {
+    let _lock = lock::registry()?;
+    let mut metadata = Metadata::load()?;
+    change(metadata.worktrees.entry(path.to_path_buf()).or_default());
+    metadata.save()
+}
+
+/// Note that the worktree at `path` was just used. Failures are logged and
+/// otherwise ignored: bookkeeping must never break the command itself.
+pub fn record_use(path: &Path) {
+    let result = update(path, |meta| {
+        meta.last_used = Some(unix_now());
+        meta.uses = meta.uses.saturating_add(1);
+    });
+    if let Err(err) = result {
+        tracing::warn!("could not record worktree use: {:#}", err);
+    }
+}
+
+/// Drop everything recorded about a removed worktree.
+pub fn forget(path: &Path) {
+    let result = lock::registry().and_then(|_lock| {
+        let mut metadata = Metadata::load()?;
+        if metadata.worktrees.remove(path).is_none() {
+            return Ok(());
+        }
+        metadata.save()
+    });
+    if let Err(err) = result {
+        tracing::warn!("could not update worktree metadata: {:#}", err);
+    }
+}
@@ -0,0 +1,141 @@
+//! Worktree path templates.
+//!
+//! A template is a path with `{placeholder}`s, e.g. `{base}/{repo}/{branch}`:
+//!
+//! - `{base}`: the registry directory, `~/.terris-worktrees`, unless the
+//!   repository has a `base-dir`
+//! - `{repo}`: the repository's directory name
+//! - `{repo_parent}`: the directory containing the repository
+//! - `{branch}`: the branch name; `/` in it makes nested directories
+//! - `{branch_slug}`: the branch name with `/` replaced by `-`
+//! - `{date}`: today's date (UTC) as `YYYY-MM-DD`
+//! - `{suffix}`: eight random lowercase letters
+//!
+//! A leading `~/` is the home directory, and a relative result is taken
+//! relative to the repository root.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+/// Where worktrees go unless configured otherwise.
+pub const DEFAULT_TEMPLATE: &str = "{base}/{repo}/{branch}-{suffix}";
+/// The template used by `--stable`.
+pub const STABLE_TEMPLATE: &str = "{base}/{repo}/{branch}";
+/// The template for the `in-repo` location.
+pub const IN_REPO_TEMPLATE: &str = ".worktrees/{branch}";
+/// The template for the `sibling` location.
+pub const SIBLING_TEMPLATE: &str = "{repo_parent}/{repo}-{branch_slug}";
+
+/// Values substituted into a template.
+pub struct Vars<'a> {
+    pub base: &'a Path,
+    pub repo_root: &'a Path,
+    pub branch: &'a str,
+    /// Unix seconds, for `{date}`.
+    pub now: i64,
+}
+
+/// Expand `template` into a worktree path.
+pub fn render(template: &str, vars: &Vars) -> Result<PathBuf> {
+    let repo = vars
+        .repo_root
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("repo");
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .with_context(|| format!("unclosed '{{' in path template '{}'", template))?;
+        let name = &rest[open + 1..open + close];
+        let value = match name {
+            "base" => vars.base.to_string_lossy().into_owned(),
+            "repo" => repo.to_string(),
+            "repo_parent" => vars
+                .repo_root
+                .parent()
+                .unwrap_or(Path::new("/"))
+                .to_string_lossy()
+                .into_owned(),
+            "branch" => vars.branch.to_string(),
+            "branch_slug" => vars.branch.replace('/', "-"),
+            "date" => {
+                let stamp = crate::format_timestamp(vars.now);
+                format!("{}-{}-{}", &stamp[..4], &stamp[4..6], &stamp[6..8])
+            }
+            "suffix" => crate::random_suffix(8),
+            _ => bail!(
+                "unknown placeholder '{{{}}}' in path template '{}'",
+                name,
+                template
+            ),
+        };
+        out.push_str(&value);
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+
+    let path = match out.strip_prefix("~/") {
+        Some(under_home) => {
+            let home = std::env::var_os("HOME").context("HOME is not set")?;
+            PathBuf::from(home).join(under_home)
+        }
+        None => PathBuf::from(out),
+    };
+    Ok(vars.repo_root.join(path))
+}
+
+/// Whether rendering `template` twice gives the same path on the same day,
+/// so an existing directory there is a collision rather than bad luck.
+pub fn is_deterministic(template: &str) -> bool {
+    !template.contains("{suffix}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars<'a>(branch: &'a str) -> Vars<'a> {
+        Vars {
+            base: Path::new("/home/me/.terris-worktrees"),
+            repo_root: Path::new("/src/api"),
+            branch,
+            now: 1_769_881_502,
+        }
+    }
+
+    #[test]
+    fn render_substitutes_placeholders() {
+        let render = |template| render(template, &vars("feature/login")).unwrap();
+        assert_eq!(
+            render(STABLE_TEMPLATE),
+            PathBuf::from("/home/me/.terris-worktrees/api/feature/login")
+        );
+        assert_eq!(
+            render(SIBLING_TEMPLATE),
+            PathBuf::from("/src/api-feature-login")
+        );
+        assert_eq!(
+            render("{base}/{repo}/{date}-{branch_slug}"),
+            PathBuf::from("/home/me/.terris-worktrees/api/2026-01-31-feature-login")
+        );
+        assert_eq!(
+            render(".worktrees/{branch}"),
+            PathBuf::from("/src/api/.worktrees/feature/login")
+        );
+        let random = render(DEFAULT_TEMPLATE);
+        let name = random.file_name().unwrap().to_str().unwrap();
+        assert_eq!(name.len(), "login-".len() + 8);
+    }
+
+    #[test]
+    fn render_rejects_bad_templates() {
+        assert!(render("{base}/{nope}", &vars("x")).is_err());
+        assert!(render("{base}/{repo", &vars("x")).is_err());
+        assert!(is_deterministic(STABLE_TEMPLATE));
+        assert!(!is_deterministic(DEFAULT_TEMPLATE));
+    }
+}
@@ -0,0 +1,159 @@
+//! Desktop notifications from `terris gc` and `terris daemon` about what
+//! they could not fix alone: stale worktree entries, worktrees still past
+//! their retention limit, and uncommitted changes left alone for weeks.
+//! Off unless `[notify] enabled = true`.
+
+use std::path::Path;
+
+use anyhow::Result;
+use notify_rust::Notification;
+use serde::Deserialize;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::gc;
+use crate::metadata::Metadata;
+use crate::retention::Age;
+use crate::{Worktree, repo_name, unix_now, worktree_name};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NotifyConfig {
+    pub enabled: bool,
+    /// Uncommitted changes in a worktree unused for this long are reported.
+    pub stale_after: Age,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            stale_after: Age(14 * 86_400),
+        }
+    }
+}
+
+/// Worktrees that need a person, by name.
+#[derive(Debug, Default, PartialEq)]
+pub struct Attention {
+    /// Administrative entries whose directory is gone.
+    pub prunable: Vec<String>,
+    /// Past their retention limit, typically because they hold unsaved work.
+    pub expired: Vec<String>,
+    /// Holding uncommitted changes, with how long they have been unused.
+    pub stale: Vec<(String, Age)>,
+}
+
+/// What in the repository at `root` needs attention, after gc has run.
+pub fn survey(
+    backend: &dyn Backend,
+    root: &Path,
+    worktrees: &[Worktree],
+    config: &Config,
+) -> Result<Attention> {
+    let metadata = Metadata::load()?;
+    let retention = config.retention(&repo_name(root));
+    let mut attention = Attention::default();
+    // Unused long enough to count as stale, if they hold changes.
+    let mut idle = Vec::new();
+    for wt in worktrees.iter().filter(|wt| !wt.main) {
+        let name = worktree_name(wt);
+        if wt.prunable.is_some() {
+            attention.prunable.push(name);
+            continue;
+        }
+        let meta = metadata.get(&wt.path);
+        if gc::over_limit(retention, wt, meta)?.is_some() {
+            attention.expired.push(name);
+            continue;
+        }
+        if let Some(since) = gc::idle_since(wt, meta) {
+            let unused = Age(unix_now() - since);
+            if unused > config.notify.stale_after {
+                idle.push((wt.path.as_path(), name, unused));
+            }
+        }
+    }
+    let paths: Vec<&Path> = idle.iter().map(|(path, _, _)| *path).collect();
+    for ((_, name, unused), dirty) in idle.into_iter().zip(backend.dirty_all(&paths)) {
+        if dirty? {
+            attention.stale.push((name, unused));
+        }
+    }
+    Ok(attention)
+}
+
+impl Attention {
+    pub fn is_empty(&self) -> bool {
+        self.prunable.is_empty() && self.expired.is_empty() && self.stale.is_empty()
+    }
+
+    /// One line per kind of problem.
+    pub fn body(&self) -> String {
+        let mut lines = Vec::new();
+        if !self.prunable.is_empty() {
+            lines.push(format!("Missing directories: {}", self.prunable.join(", ")));
+        }
+        if !self.expired.is_empty() {
+            lines.push(format!(
+                "Past their retention limit: {}",
+                self.expired.join(", ")
+            ));
+        }
+        if !self.stale.is_empty() {
+            let stale: Vec<String> = self
+                .stale
+                .iter()
+                .map(|(name, unused)| format!("{} ({})", name, unused))
+                .collect();
+            lines.push(format!("Uncommitted changes: {}", stale.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Show `attention` as a desktop notification for the repository at `root`.
+/// Failing to reach the desktop only warns.
+pub fn send(root: &Path, attention: &Attention, dry_run: bool) {
+    let count = attention.prunable.len() + attention.expired.len() + attention.stale.len();
+    let summary = format!(
+        "terris: {} worktree(s) in {} need attention",
+        count,
+        repo_name(root)
+    );
+    let body = attention.body();
+    if dry_run {
+        println!("Would notify: {}\n{}", summary, body);
+        return;
+    }
+    let shown = Notification::new()
+        .appname("terris")
+        .summary(&summary)
+        .body(&body)
+        .show();
+    if let Err(err) = shown {
+        tracing::warn!("could not send desktop notification: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_has_a_line_per_kind_of_problem() {
+        let attention = Attention {
+            prunable: vec!["gone".into()],
+            expired: Vec::new(),
+            stale: vec![
+                ("feature".into(), Age(21 * 86_400)),
+                ("fix".into(), Age(3_600)),
+            ],
+        };
+        assert_eq!(
+            attention.body(),
+            "Missing directories: gone\nUncommitted changes: feature (21d), fix (1h)"
+        );
+        assert!(Attention::default().is_empty());
+    }
+}
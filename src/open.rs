@@ -0,0 +1,134 @@
+//! `terris create --open`: start working in a new worktree as soon as its
+//! path is printed, in an editor, a tmux window, or a shell.
+
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::backend::Backend;
+use crate::configure;
+use crate::env::WorktreeEnv;
+use crate::{repo_root, worktree_branch_short};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Open {
+    /// Open the worktree in $VISUAL or $EDITOR
+    Editor,
+    /// Open a tmux window in the worktree, or a session outside tmux
+    Tmux,
+    /// Start $SHELL in the worktree; exiting it returns to where terris ran
+    Shell,
+}
+
+/// What to open after creating a worktree: `--open`, with `default` (the
+/// `open` config key) when no action is named, or else `default` alone,
+/// which is skipped without a terminal so scripts never end up in an
+/// editor or shell.
+pub fn choose(flag: Option<Option<Open>>, no_open: bool, default: Option<Open>) -> Option<Open> {
+    match flag {
+        _ if no_open => None,
+        Some(action) => Some(action.or(default).unwrap_or(Open::Editor)),
+        None => default.filter(|_| std::io::stdin().is_terminal()),
+    }
+}
+
+/// Launch `action` in the new worktree at `path`, warning instead of
+/// failing: the worktree exists and its path is printed either way.
+pub fn launch(backend: &dyn Backend, path: &Path, action: Open, dry_run: bool) {
+    if dry_run {
+        println!("Would open {} in {}", name(action), path.display());
+        return;
+    }
+    if let Err(err) = run(backend, path, action) {
+        eprintln!("Warning: could not open the worktree: {:#}", err);
+    }
+}
+
+fn name(action: Open) -> &'static str {
+    match action {
+        Open::Editor => "the editor",
+        Open::Tmux => "tmux",
+        Open::Shell => "a shell",
+    }
+}
+
+fn run(backend: &dyn Backend, path: &Path, action: Open) -> Result<()> {
+    let mut command = match action {
+        Open::Editor => {
+            let editor = configure::editor();
+            let mut command = Command::new("sh");
+            command
+                .arg("-c")
+                .arg(format!("{} \"$1\"", editor))
+                .arg("sh")
+                .arg(path);
+            command
+        }
+        Open::Tmux => {
+            let window = tmux_name(path);
+            let mut command = Command::new("tmux");
+            if std::env::var_os("TMUX").is_some() {
+                command.args(["new-window", "-n", &window]);
+            } else {
+                // Attach to the session instead when it is already there.
+                command.args(["new-session", "-A", "-s", &window]);
+            }
+            command.arg("-c").arg(path);
+            command
+        }
+        Open::Shell => {
+            let shell = std::env::var_os("SHELL").unwrap_or_else(|| "/bin/sh".into());
+            eprintln!("Entering {}; exit the shell to return.", path.display());
+            Command::new(shell)
+        }
+    };
+    command.current_dir(path);
+    let root = repo_root(backend)?;
+    let branch = backend
+        .list_worktrees(&root)?
+        .into_iter()
+        .find(|wt| wt.path == path)
+        .and_then(|wt| worktree_branch_short(&wt).map(str::to_string));
+    WorktreeEnv::new(backend, &root, path, branch.as_deref())?.apply(&mut command);
+    let status = command
+        .status()
+        .with_context(|| format!("start {}", name(action)))?;
+    // A shell's exit status is whatever its last command left.
+    if !status.success() && action != Open::Shell {
+        bail!("{} exited with {}", name(action), status);
+    }
+    Ok(())
+}
+
+/// The worktree's directory name, which tmux accepts as a window or session
+/// name once its `.` and `:` are replaced.
+fn tmux_name(path: &Path) -> String {
+    path.file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+        .replace(['.', ':'], "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_wins_over_the_config_default() {
+        assert_eq!(
+            choose(Some(Some(Open::Tmux)), false, Some(Open::Shell)),
+            Some(Open::Tmux)
+        );
+        assert_eq!(
+            choose(Some(None), false, Some(Open::Shell)),
+            Some(Open::Shell)
+        );
+        assert_eq!(choose(Some(None), false, None), Some(Open::Editor));
+        assert_eq!(choose(None, true, Some(Open::Shell)), None);
+        assert_eq!(tmux_name(Path::new("/w/repo/v1.2-abc")), "v1-2-abc");
+    }
+}
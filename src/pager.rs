@@ -0,0 +1,61 @@
+//! Paging for output taller than the terminal, the way git does it: through
+//! `$TERRIS_PAGER`, `$PAGER`, or `less`, with `LESS=FRX` unless `LESS` is
+//! already set. Output to anything but a terminal is never paged.
+
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+
+/// Print `text`, through the pager when it would not fit on the screen.
+pub fn show(text: &str, no_pager: bool) -> Result<()> {
+    let pager = (!no_pager && std::io::stdout().is_terminal())
+        .then(pager_command)
+        .flatten();
+    let Some(pager) = pager else {
+        print!("{}", text);
+        return Ok(());
+    };
+    // Leave a row for the shell prompt that follows.
+    let fits = console::Term::stdout()
+        .size_checked()
+        .is_none_or(|(rows, _)| text.lines().count() < usize::from(rows));
+    if fits {
+        print!("{}", text);
+        return Ok(());
+    }
+
+    let mut command = Command::new("sh");
+    command.args(["-c", &pager]).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            tracing::warn!("could not start pager `{}`: {}", pager, err);
+            print!("{}", text);
+            return Ok(());
+        }
+    };
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Quitting the pager early closes the pipe; that is not an error.
+    if let Err(err) = stdin.write_all(text.as_bytes())
+        && err.kind() != std::io::ErrorKind::BrokenPipe
+    {
+        return Err(err.into());
+    }
+    drop(stdin);
+    child.wait()?;
+    Ok(())
+}
+
+/// The pager to use, or `None` when it is set to nothing or to `cat`.
+fn pager_command() -> Option<String> {
+    let pager = ["TERRIS_PAGER", "PAGER"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok())
+        .unwrap_or_else(|| "less".to_string());
+    let pager = pager.trim();
+    (!pager.is_empty() && pager != "cat").then(|| pager.to_string())
+}
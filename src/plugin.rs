@@ -0,0 +1,112 @@
+//! External subcommands: like git and cargo, `terris foo args...` runs a
+//! `terris-foo` executable from PATH when `foo` is not a built-in command,
+//! so teams can ship their own workflows as separate programs.
+//!
+//! The plugin gets the remaining arguments unchanged, `TERRIS` pointing at
+//! this executable, and the `TERRIS_*` variables from [`WorktreeEnv`] when
+//! run inside a worktree.
+
+use std::ffi::OsString;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use clap::CommandFactory;
+
+use crate::backend::{self, Backend};
+use crate::env::WorktreeEnv;
+use crate::error::Error;
+use crate::{Cli, current_worktree, primary_root, worktree_branch_short};
+
+/// Run the plugin named by the first argument, if there is one. `None`
+/// leaves the arguments to the built-in parser, which also covers
+/// `terris <branch>`: an existing branch wins over a plugin of the same name.
+pub fn dispatch() -> Option<Result<()>> {
+    let mut args = std::env::args_os().skip(1);
+    let name = args.next()?.into_string().ok()?;
+    if name.starts_with('-') || is_builtin(&name) {
+        return None;
+    }
+    let program = find(&name)?;
+    let cwd = std::env::current_dir().ok()?;
+    let backend = backend::detect(&cwd, false);
+    let root = backend
+        .root(&cwd)
+        .and_then(|root| primary_root(backend.as_ref(), root))
+        .ok();
+    if let Some(root) = &root
+        && backend.branch_exists(root, &name).unwrap_or(false)
+    {
+        return None;
+    }
+    let args: Vec<OsString> = args.collect();
+    Some(run(
+        backend.as_ref(),
+        root.as_deref(),
+        &cwd,
+        &program,
+        &args,
+    ))
+}
+
+fn is_builtin(name: &str) -> bool {
+    Cli::command().find_subcommand(name).is_some()
+}
+
+/// The first executable `terris-<name>` on PATH.
+fn find(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(format!("terris-{}", name)))
+        .find(|candidate| {
+            candidate
+                .metadata()
+                .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        })
+}
+
+fn run(
+    backend: &dyn Backend,
+    root: Option<&Path>,
+    cwd: &Path,
+    program: &Path,
+    args: &[OsString],
+) -> Result<()> {
+    let mut child = Command::new(program);
+    child.args(args);
+    if let Ok(exe) = std::env::current_exe() {
+        child.env("TERRIS", exe);
+    }
+    if let Some(root) = root {
+        match describe(backend, root, cwd) {
+            Ok(Some(env)) => env.apply(&mut child),
+            Ok(None) => {}
+            Err(err) => tracing::warn!("could not describe worktree: {:#}", err),
+        }
+    }
+    let command = program
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let status = child
+        .status()
+        .with_context(|| format!("run `{}`", program.display()))?;
+    if !status.success() {
+        let code = status.code().and_then(|code| u8::try_from(code).ok());
+        return Err(Error::CommandFailed {
+            command,
+            code: code.unwrap_or(1),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// The context for the worktree containing `cwd`.
+fn describe(backend: &dyn Backend, root: &Path, cwd: &Path) -> Result<Option<WorktreeEnv>> {
+    let worktrees = backend.list_worktrees(root)?;
+    let Some(wt) = current_worktree(&worktrees, cwd) else {
+        return Ok(None);
+    };
+    WorktreeEnv::new(backend, root, &wt.path, worktree_branch_short(wt)).map(Some)
+}
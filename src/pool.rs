@@ -0,0 +1,150 @@
+//! `terris pool`: pre-created detached worktrees that are leased and
+//! returned, so CI jobs and agents get an isolated checkout without paying
+//! for a full checkout each time.
+//!
+//! Pool worktrees live at `<base>/<repo>/pool-<id>`, `<base>` being the
+//! registry unless the repository has a `base-dir`; whether each one is
+//! free or leased is kept in the metadata store.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Subcommand};
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::error::Error;
+use crate::lock;
+use crate::metadata::{self, Metadata, PoolState};
+use crate::{Worktree, print_path, repo_name, repo_root};
+
+#[derive(Args)]
+pub struct PoolArgs {
+    #[command(subcommand)]
+    command: PoolCommand,
+}
+
+#[derive(Subcommand)]
+enum PoolCommand {
+    /// Lease a clean worktree at a commit and print its path
+    Acquire {
+        /// Commit, tag, or branch to check out [default: the primary
+        /// worktree's HEAD]
+        #[arg(long, value_name = "ref")]
+        base: Option<String>,
+    },
+    /// Reset a leased worktree and return it to the pool
+    Release {
+        /// Pool ID (`3` or `pool-3`) or path printed by `acquire`
+        #[arg(value_name = "id")]
+        id: String,
+    },
+}
+
+pub fn cmd_pool(
+    backend: &dyn Backend,
+    args: &PoolArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    match &args.command {
+        PoolCommand::Acquire { base } => acquire(backend, base.as_deref(), config, dry_run),
+        PoolCommand::Release { id } => release(backend, id, dry_run),
+    }
+}
+
+fn acquire(
+    backend: &dyn Backend,
+    base: Option<&str>,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    // Held until the lease is recorded, so no two processes lease one worktree.
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(lock::registry()?)
+    };
+    let metadata = Metadata::load()?;
+    let base = match base {
+        Some(base) => base.to_string(),
+        // A bare `HEAD` would mean the pool worktree's own HEAD.
+        None => worktrees
+            .iter()
+            .find(|wt| wt.main)
+            .and_then(|wt| wt.head.clone())
+            .context("the primary worktree has no commit to start from")?,
+    };
+
+    let free = pool_worktrees(&worktrees, &metadata)
+        .find(|(_, state)| *state == PoolState::Free)
+        .map(|(wt, _)| wt.path.clone());
+    let path = match free {
+        Some(path) => {
+            backend.reset_to(&path, Some(&base))?;
+            path
+        }
+        None => {
+            let path = free_slot(config, &root)?;
+            if !dry_run && let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("create directory '{}'", parent.display()))?;
+            }
+            backend.add_detached(&root, &path, &base)?;
+            path
+        }
+    };
+    if !dry_run {
+        metadata::update(&path, |meta| meta.pool = Some(PoolState::Leased))?;
+    }
+    print_path(&path);
+    Ok(())
+}
+
+fn release(backend: &dyn Backend, id: &str, dry_run: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let metadata = Metadata::load()?;
+    let slot = format!("pool-{}", id);
+    let (wt, state) = pool_worktrees(&worktrees, &metadata)
+        .find(|(wt, _)| {
+            wt.path == Path::new(id)
+                || wt
+                    .path
+                    .file_name()
+                    .is_some_and(|name| name == id || name == slot.as_str())
+        })
+        .ok_or_else(|| Error::WorktreeNotFound(id.to_string()))?;
+    if state != PoolState::Leased {
+        bail!("pool worktree '{}' is not leased", id);
+    }
+
+    backend.reset_to(&wt.path, None)?;
+    if !dry_run {
+        metadata::update(&wt.path, |meta| meta.pool = Some(PoolState::Free))?;
+        eprintln!("Returned {} to the pool", wt.path.display());
+    }
+    Ok(())
+}
+
+fn pool_worktrees<'a>(
+    worktrees: &'a [Worktree],
+    metadata: &'a Metadata,
+) -> impl Iterator<Item = (&'a Worktree, PoolState)> {
+    worktrees
+        .iter()
+        .filter(|wt| !wt.main)
+        .filter_map(|wt| Some((wt, metadata.get(&wt.path)?.pool?)))
+}
+
+/// `<base>/<repo>/pool-N` for the smallest N not taken yet.
+fn free_slot(config: &Config, root: &Path) -> Result<PathBuf> {
+    let repo = repo_name(root);
+    let dir = config.base_dir(&repo)?.join(repo);
+    Ok((1..)
+        .map(|n| dir.join(format!("pool-{}", n)))
+        .find(|path| !path.exists())
+        .expect("unbounded range"))
+}
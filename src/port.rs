@@ -0,0 +1,150 @@
+//! `terris port`: give each worktree its own block of ports so dev servers
+//! in different worktrees stop fighting over the same one.
+//!
+//! A worktree's block is [`BLOCK`] ports starting at a base picked from a
+//! hash of its path, so it usually comes out the same when recreated, and
+//! moved along when another worktree holds it or something is listening.
+//! The base serves the default service; named services get the following
+//! ports in the order they are first asked for.
+
+use std::net::TcpListener;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::lock;
+use crate::metadata::{Metadata, WorktreeMeta};
+use crate::{fnv1a, repo_root, resolve_worktree};
+
+/// Ports per worktree: the base and up to `BLOCK - 1` named services.
+const BLOCK: u16 = 10;
+const FIRST: u16 = 20000;
+const SLOTS: u16 = 1000;
+
+#[derive(Args)]
+pub struct PortArgs {
+    /// Worktree to allocate for
+    #[arg(value_name = "target")]
+    target: String,
+    /// Service within the worktree, e.g. `api`; omit for the base port
+    #[arg(value_name = "service")]
+    service: Option<String>,
+}
+
+pub fn cmd_port(
+    backend: &dyn Backend,
+    args: &PortArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let wt = resolve_worktree(&args.target, &worktrees, &cwd, config.fuzzy)?;
+
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(lock::registry()?)
+    };
+    let mut metadata = Metadata::load()?;
+    let held = metadata
+        .get(&wt.path)
+        .map(|meta| (meta.port, meta.services.len()));
+    let base = match held.and_then(|(port, _)| port) {
+        Some(base) => base,
+        None => {
+            let base = free_base(&metadata, &wt.path)?;
+            metadata.worktrees.entry(wt.path.clone()).or_default().port = Some(base);
+            base
+        }
+    };
+    let meta = metadata.worktrees.entry(wt.path.clone()).or_default();
+    let port = match &args.service {
+        None => base,
+        Some(service) => service_port(meta, base, service)?,
+    };
+    let reserved = held != Some((meta.port, meta.services.len()));
+    if dry_run && reserved {
+        println!("Would reserve port {} for {}", port, wt.path.display());
+        return Ok(());
+    }
+    if reserved {
+        metadata.save()?;
+    }
+    println!("{}", port);
+    Ok(())
+}
+
+/// The port for `service`, reserving the next one in the block if it has
+/// none yet.
+fn service_port(meta: &mut WorktreeMeta, base: u16, service: &str) -> Result<u16> {
+    let index = match meta.services.iter().position(|s| s == service) {
+        Some(index) => index,
+        None => {
+            if meta.services.len() + 1 >= usize::from(BLOCK) {
+                bail!(
+                    "a worktree has room for {} named services; this one has them all",
+                    BLOCK - 1
+                );
+            }
+            meta.services.push(service.to_string());
+            meta.services.len() - 1
+        }
+    };
+    Ok(base + 1 + index as u16)
+}
+
+/// First base, probing from the one `path` hashes to, that no other
+/// worktree holds and nothing is listening on.
+fn free_base(metadata: &Metadata, path: &Path) -> Result<u16> {
+    let start = (fnv1a(path.to_string_lossy().as_bytes()) % u64::from(SLOTS)) as u16;
+    let taken: Vec<u16> = metadata
+        .worktrees
+        .values()
+        .filter_map(|meta| meta.port)
+        .collect();
+    for step in 0..SLOTS {
+        let base = FIRST + (start + step) % SLOTS * BLOCK;
+        if !taken.contains(&base) && (base..base + BLOCK).all(is_free) {
+            return Ok(base);
+        }
+    }
+    bail!(
+        "no free block of {} ports between {} and {}",
+        BLOCK,
+        FIRST,
+        FIRST + SLOTS * BLOCK - 1
+    )
+}
+
+fn is_free(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// `TERRIS_PORT` for the base and `TERRIS_PORT_<SERVICE>` for each named
+/// service, once `terris port` has allocated them.
+pub fn env_vars(meta: &WorktreeMeta) -> Vec<(String, String)> {
+    let Some(base) = meta.port else {
+        return Vec::new();
+    };
+    let mut vars = vec![("TERRIS_PORT".to_string(), base.to_string())];
+    for (index, service) in meta.services.iter().enumerate() {
+        let name: String = service
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let port = base + 1 + index as u16;
+        vars.push((format!("TERRIS_PORT_{}", name), port.to_string()));
+    }
+    vars
+}
@@ -0,0 +1,209 @@
+//! `terris prompt`: a short segment such as `repo:branch↑2✗` for shell
+//! prompts. It reads `.git` directly instead of running git, and takes the
+//! working tree's state from the status [`cache`](crate::cache), computing
+//! and caching it briefly only when no current entry exists.
+//!
+//! `--starship` prints the same segment for starship's custom modules, and
+//! nothing in the primary worktree, where starship's own git modules already
+//! say everything; `--starship-config` prints the module to paste.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::backend::{self, ChangeSummary};
+use crate::cache::{self, Status};
+use crate::{Worktree, unix_now};
+
+/// How long a status `terris prompt` computed itself is reused.
+const TTL: i64 = 5;
+
+/// A starship custom module showing the segment. The conditional group
+/// hides the module, spacing included, whenever terris prints nothing.
+const STARSHIP_MODULE: &str = r#"[custom.terris]
+description = "The terris worktree containing the current directory"
+command = "terris prompt --starship"
+when = true
+require_repo = true
+format = "([$symbol$output]($style) )"
+symbol = "🌳 "
+style = "bold green"
+"#;
+
+#[derive(Args)]
+pub struct PromptArgs {
+    /// Print for a starship custom module: nothing in the primary worktree
+    #[arg(long)]
+    starship: bool,
+    /// Print the starship.toml module that runs `terris prompt --starship`
+    #[arg(long, conflicts_with = "starship")]
+    starship_config: bool,
+}
+
+pub fn cmd_prompt(args: &PromptArgs) -> Result<()> {
+    if args.starship_config {
+        print!("{}", STARSHIP_MODULE);
+        return Ok(());
+    }
+    let cwd = std::env::current_dir().context("read current directory")?;
+    // Outside a repository the segment is empty, not an error.
+    let Some(location) = Location::find(&cwd) else {
+        return Ok(());
+    };
+    if args.starship && location.is_primary() {
+        return Ok(());
+    }
+    let Some(head) = location.head() else {
+        return Ok(());
+    };
+    let summary = status(&location.worktree, head.id.clone());
+    println!(
+        "{}",
+        segment(&location.repo_name(), &head, summary.as_ref())
+    );
+    Ok(())
+}
+
+/// The worktree containing the current directory and its git directories.
+struct Location {
+    worktree: PathBuf,
+    /// The worktree's own git directory, holding `HEAD`.
+    git_dir: PathBuf,
+    /// The directory shared by all worktrees, holding refs.
+    common_dir: PathBuf,
+}
+
+struct Head {
+    /// Checked-out branch, `None` when detached.
+    branch: Option<String>,
+    /// Commit id, `None` on a branch without commits.
+    id: Option<String>,
+}
+
+impl Location {
+    fn find(cwd: &Path) -> Option<Self> {
+        let dir = cwd.ancestors().find(|dir| dir.join(".git").exists())?;
+        let dot_git = dir.join(".git");
+        if dot_git.is_dir() {
+            return Some(Self {
+                worktree: dir.to_path_buf(),
+                git_dir: dot_git.clone(),
+                common_dir: dot_git,
+            });
+        }
+        // A linked worktree's `.git` file points at its git directory, which
+        // names the shared one in `commondir`.
+        let pointer = std::fs::read_to_string(&dot_git).ok()?;
+        let git_dir = dir.join(pointer.strip_prefix("gitdir:")?.trim());
+        let common_dir = match std::fs::read_to_string(git_dir.join("commondir")) {
+            Ok(common) => git_dir.join(common.trim()),
+            Err(_) => git_dir.clone(),
+        };
+        Some(Self {
+            worktree: dir.to_path_buf(),
+            git_dir,
+            common_dir: std::fs::canonicalize(&common_dir).unwrap_or(common_dir),
+        })
+    }
+
+    /// Whether this is the repository's primary worktree, whose git
+    /// directory is the shared one.
+    fn is_primary(&self) -> bool {
+        self.git_dir == self.common_dir
+    }
+
+    /// The repository's name: the directory holding `.git`, or the bare
+    /// repository's directory without `.git`.
+    fn repo_name(&self) -> String {
+        let dir = if self.common_dir.ends_with(".git") {
+            self.common_dir.parent().unwrap_or(&self.common_dir)
+        } else {
+            &self.common_dir
+        };
+        let name = dir.file_name().map_or_else(
+            || "repo".to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        name.strip_suffix(".git")
+            .map_or(name.clone(), str::to_string)
+    }
+
+    fn head(&self) -> Option<Head> {
+        let head = std::fs::read_to_string(self.git_dir.join("HEAD")).ok()?;
+        let head = head.trim();
+        Some(match head.strip_prefix("ref: ") {
+            Some(reference) => Head {
+                branch: Some(
+                    reference
+                        .strip_prefix("refs/heads/")
+                        .unwrap_or(reference)
+                        .to_string(),
+                ),
+                id: self.resolve(reference),
+            },
+            None => Head {
+                branch: None,
+                id: Some(head.to_string()),
+            },
+        })
+    }
+
+    /// The commit `reference` points at, loose or packed.
+    fn resolve(&self, reference: &str) -> Option<String> {
+        if let Ok(id) = std::fs::read_to_string(self.common_dir.join(reference)) {
+            return Some(id.trim().to_string());
+        }
+        let packed = std::fs::read_to_string(self.common_dir.join("packed-refs")).ok()?;
+        packed.lines().find_map(|line| {
+            let (id, name) = line.split_once(' ')?;
+            (name == reference).then(|| id.to_string())
+        })
+    }
+}
+
+/// The worktree's unsaved work: cached if current, else computed and cached
+/// for [`TTL`] seconds. `None` when git cannot tell.
+fn status(path: &Path, head: Option<String>) -> Option<ChangeSummary> {
+    let wt = Worktree {
+        path: path.to_path_buf(),
+        head,
+        ..Worktree::default()
+    };
+    let mut cache = cache::load();
+    if let Some(status) = cache.get(&wt) {
+        return Some(status.summary);
+    }
+    let summary = backend::detect(path, false).change_summary(path).ok()?;
+    let status = Status {
+        expires: unix_now() + TTL,
+        head: wt.head,
+        summary,
+        usage: None,
+    };
+    cache.worktrees.insert(wt.path, status);
+    if let Err(err) = cache.save() {
+        tracing::warn!("could not write status cache: {:#}", err);
+    }
+    Some(summary)
+}
+
+/// `repo:branch`, or `repo:@<short id>` when detached, then `↑N` for
+/// unpushed commits and `✗` for uncommitted changes.
+fn segment(repo: &str, head: &Head, summary: Option<&ChangeSummary>) -> String {
+    let name = match (&head.branch, &head.id) {
+        (Some(branch), _) => branch.clone(),
+        (None, Some(id)) => format!("@{}", id.get(..7).unwrap_or(id)),
+        (None, None) => "@".to_string(),
+    };
+    let mut segment = format!("{}:{}", repo, name);
+    if let Some(summary) = summary {
+        if summary.unpushed > 0 {
+            segment.push_str(&format!("↑{}", summary.unpushed));
+        }
+        if summary.has_uncommitted() {
+            segment.push('✗');
+        }
+    }
+    segment
+}
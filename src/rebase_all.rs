@@ -0,0 +1,94 @@
+//! `terris rebase-all`: rebase every worktree's branch onto the base, each
+//! in its own worktree so a conflict stays where it happened.
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::{repo_root, worktree_branch_short, worktree_name};
+
+#[derive(Args)]
+pub struct RebaseAllArgs {
+    /// Branch to rebase onto [default: the primary worktree's branch]
+    #[arg(long, value_name = "branch")]
+    onto: Option<String>,
+    /// Only rebase worktrees whose branch matches a glob, e.g. 'feature/*'
+    #[arg(long, value_name = "glob")]
+    filter: Option<glob::Pattern>,
+}
+
+pub fn cmd_rebase_all(backend: &dyn Backend, args: &RebaseAllArgs, dry_run: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let onto = match &args.onto {
+        Some(onto) => onto.clone(),
+        None => worktrees
+            .iter()
+            .find(|wt| wt.main)
+            .and_then(worktree_branch_short)
+            .context("the primary worktree has no branch; pass `--onto <branch>`")?
+            .to_string(),
+    };
+
+    let (mut rebased, mut conflicted, mut failed) = (0, 0, 0);
+    for wt in worktrees.iter().filter(|wt| !wt.main) {
+        let Some(branch) = worktree_branch_short(wt) else {
+            continue;
+        };
+        if branch == onto || args.filter.as_ref().is_some_and(|p| !p.matches(branch)) {
+            continue;
+        }
+        let name = worktree_name(wt);
+        if wt.locked {
+            println!("Skipped {}: locked", name);
+            continue;
+        }
+        match backend.is_dirty(&wt.path) {
+            Ok(false) => {}
+            Ok(true) => {
+                println!("Skipped {}: uncommitted changes", name);
+                continue;
+            }
+            Err(err) => {
+                println!("Skipped {}: could not read status ({:#})", name, err);
+                continue;
+            }
+        }
+        // Already contains the base: nothing to do.
+        if backend.is_merged(&root, &onto, branch)? {
+            println!("Up to date {}", name);
+            continue;
+        }
+        match backend.rebase(&wt.path, &onto, None) {
+            Ok(true) => {
+                rebased += 1;
+                if !dry_run {
+                    println!("Rebased {} onto {}", name, onto);
+                }
+            }
+            Ok(false) => {
+                conflicted += 1;
+                println!(
+                    "Conflict in {}: resolve in {}, then `git rebase --continue` (or `--abort`)",
+                    name,
+                    wt.path.display()
+                );
+            }
+            Err(err) => {
+                eprintln!("Error: rebase '{}': {:#}", name, err);
+                failed += 1;
+            }
+        }
+    }
+    if rebased + conflicted + failed == 0 {
+        println!("Nothing to rebase.");
+    }
+    if conflicted + failed > 0 {
+        bail!(
+            "{} worktree(s) conflicted and {} failed",
+            conflicted,
+            failed
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,201 @@
+//! `terris export` and `terris import`: every repository terris knows, its
+//! worktrees, and what terris recorded about them, as one JSON document, to
+//! move to another machine or to restore after the registry directory was
+//! wiped.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{self, Backend};
+use crate::list::known_repos;
+use crate::lock;
+use crate::metadata::{Metadata, WorktreeMeta};
+use crate::worktree_branch_short;
+
+/// Bumped when an older terris could no longer read what is exported.
+const VERSION: u32 = 1;
+
+#[derive(Args)]
+pub struct ImportArgs {
+    /// File written by `terris export`, or `-` for stdin
+    #[arg(value_name = "file")]
+    file: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Registry {
+    version: u32,
+    repos: Vec<Repo>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Repo {
+    /// The primary worktree.
+    root: PathBuf,
+    /// Where `origin` points, to clone from when the repository is missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+    worktrees: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: PathBuf,
+    /// Short branch name; `None` when detached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    head: Option<String>,
+    #[serde(default)]
+    meta: WorktreeMeta,
+}
+
+pub fn cmd_export(backend: &dyn Backend) -> Result<()> {
+    let metadata = Metadata::load()?;
+    let mut repos = Vec::new();
+    for root in known_repos(backend)? {
+        let backend = backend::detect(&root, false);
+        let worktrees = match backend.list_worktrees(&root) {
+            Ok(worktrees) => worktrees,
+            Err(err) => {
+                eprintln!("Skipped {}: {:#}", root.display(), err);
+                continue;
+            }
+        };
+        let origin = backend.remote_url(&root, "origin").unwrap_or_default();
+        let worktrees = worktrees
+            .iter()
+            .filter(|wt| !wt.main && wt.prunable.is_none())
+            .map(|wt| Entry {
+                path: wt.path.clone(),
+                branch: worktree_branch_short(wt).map(str::to_string),
+                head: wt.head.clone(),
+                meta: metadata.get(&wt.path).cloned().unwrap_or_default(),
+            })
+            .collect();
+        repos.push(Repo {
+            root,
+            origin,
+            worktrees,
+        });
+    }
+    let registry = Registry {
+        version: VERSION,
+        repos,
+    };
+    println!("{}", serde_json::to_string_pretty(&registry)?);
+    Ok(())
+}
+
+pub fn cmd_import(args: &ImportArgs, dry_run: bool) -> Result<()> {
+    let data = if args.file == Path::new("-") {
+        let mut data = String::new();
+        std::io::stdin()
+            .read_to_string(&mut data)
+            .context("read registry from stdin")?;
+        data
+    } else {
+        std::fs::read_to_string(&args.file)
+            .with_context(|| format!("read '{}'", args.file.display()))?
+    };
+    let registry: Registry =
+        serde_json::from_str(&data).with_context(|| format!("parse '{}'", args.file.display()))?;
+    if registry.version > VERSION {
+        bail!(
+            "'{}' was written by a newer terris (format {}, this one reads up to {})",
+            args.file.display(),
+            registry.version,
+            VERSION
+        );
+    }
+
+    let _lock = lock::registry()?;
+    let mut metadata = Metadata::load()?;
+    let mut created = 0;
+    let mut recorded = 0;
+    let mut skipped = 0;
+    for repo in registry.repos {
+        if !repo.root.is_dir() {
+            match &repo.origin {
+                Some(origin) => eprintln!(
+                    "Skipped {}: not found; clone {} there and import again",
+                    repo.root.display(),
+                    origin
+                ),
+                None => eprintln!("Skipped {}: not found", repo.root.display()),
+            }
+            skipped += repo.worktrees.len();
+            continue;
+        }
+        let backend = backend::detect(&repo.root, dry_run);
+        let worktrees = backend.list_worktrees(&repo.root)?;
+        for entry in repo.worktrees {
+            let exists = worktrees.iter().any(|wt| wt.path == entry.path);
+            if !exists {
+                if let Err(err) = restore(backend.as_ref(), &repo.root, &entry, dry_run) {
+                    eprintln!("Skipped {}: {:#}", entry.path.display(), err);
+                    skipped += 1;
+                    continue;
+                }
+                created += 1;
+            }
+            if dry_run {
+                println!("Would record metadata for {}", entry.path.display());
+            } else {
+                metadata.worktrees.insert(entry.path, entry.meta);
+            }
+            recorded += 1;
+        }
+    }
+    if !dry_run {
+        metadata.save()?;
+        eprintln!(
+            "Imported {} worktree(s), {} of them created again; {} skipped",
+            recorded, created, skipped
+        );
+    }
+    if skipped > 0 {
+        bail!("{} worktree(s) could not be imported", skipped);
+    }
+    Ok(())
+}
+
+/// Create the worktree `entry` describes, on its branch (from `origin` when
+/// only the remote has it) or else detached at its commit.
+fn restore(backend: &dyn Backend, root: &Path, entry: &Entry, dry_run: bool) -> Result<()> {
+    if entry.path.exists() {
+        bail!("something else is already there");
+    }
+    if let Some(parent) = entry.path.parent() {
+        if dry_run {
+            if !parent.exists() {
+                println!("Would create directory {}", parent.display());
+            }
+        } else {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("create '{}'", parent.display()))?;
+        }
+    }
+    match (&entry.branch, &entry.head) {
+        (Some(branch), _) => {
+            if !backend.branch_exists(root, branch)? {
+                let remote = format!("origin/{}", branch);
+                backend.resolve_rev(root, &remote).with_context(|| {
+                    format!("branch '{}' is gone, locally and on origin", branch)
+                })?;
+                backend.create_branch(root, branch, &remote)?;
+            }
+            backend.add_worktree(root, &entry.path, branch)?;
+        }
+        (None, Some(head)) => backend.add_detached(root, &entry.path, head)?,
+        (None, None) => bail!("neither a branch nor a commit was exported"),
+    }
+    if !dry_run {
+        eprintln!("Created {}", entry.path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+//! `terris relocate <old> <new>`: after the repository was moved or renamed,
+//! point its linked worktrees at it again and carry over what terris
+//! recorded under the old paths.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+
+use crate::backend;
+use crate::cache::Cache;
+use crate::gc;
+use crate::lock;
+use crate::metadata::Metadata;
+
+#[derive(Args)]
+pub struct RelocateArgs {
+    /// Where the repository was
+    #[arg(value_name = "old")]
+    old: PathBuf,
+    /// Where the repository is now
+    #[arg(value_name = "new")]
+    new: PathBuf,
+}
+
+pub fn cmd_relocate(args: &RelocateArgs, dry_run: bool) -> Result<()> {
+    let old = std::path::absolute(&args.old)
+        .with_context(|| format!("resolve '{}'", args.old.display()))?;
+    let new = args
+        .new
+        .canonicalize()
+        .with_context(|| format!("find '{}'", args.new.display()))?;
+    if old.exists() {
+        bail!(
+            "'{}' still exists; move the repository to '{}' first",
+            old.display(),
+            new.display()
+        );
+    }
+    let backend = backend::detect(&new, dry_run);
+    let root = backend.root(&new)?;
+
+    // Worktrees inside the repository moved along with it; git still has
+    // them at their old paths.
+    let worktrees = backend.list_worktrees(&root)?;
+    let moved: Vec<PathBuf> = worktrees
+        .iter()
+        .filter(|wt| !wt.main)
+        .filter_map(|wt| moved_to(&wt.path, &old, &new))
+        .filter(|path| path.is_dir())
+        .collect();
+    let broken = worktrees
+        .iter()
+        .filter(|wt| !wt.main)
+        .map(|wt| moved_to(&wt.path, &old, &new).unwrap_or_else(|| wt.path.clone()))
+        .filter(|path| path.is_dir() && !gc::registered(path))
+        .count();
+    backend
+        .repair_worktrees(&root, &moved)
+        .context("repair the links between the repository and its worktrees")?;
+
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(lock::registry()?)
+    };
+    let mut metadata = Metadata::load()?;
+    if dry_run {
+        for path in metadata.worktrees.keys() {
+            if let Some(to) = moved_to(path, &old, &new) {
+                println!(
+                    "Would move the records of {} to {}",
+                    path.display(),
+                    to.display()
+                );
+            }
+        }
+        return Ok(());
+    }
+    let records = rekey(&mut metadata.worktrees, &old, &new);
+    let mut repos = 0;
+    for meta in metadata.worktrees.values_mut() {
+        if let Some(repo) = meta
+            .repo
+            .as_deref()
+            .and_then(|repo| moved_to(repo, &old, &new))
+        {
+            meta.repo = Some(repo);
+            repos += 1;
+        }
+    }
+    // Worktrees kept outside the repository keep their keys, but still name
+    // it as their `repo`.
+    if records + repos > 0 {
+        metadata.save()?;
+    }
+    let mut cache = Cache::load()?;
+    let cached = rekey(&mut cache.worktrees, &old, &new)
+        + rekey(&mut cache.notified, &old, &new)
+        + rekey(&mut cache.ci, &old, &new);
+    if cached > 0 {
+        cache.save()?;
+    }
+    eprintln!(
+        "Relocated {} to {}: repaired {} worktree link(s), moved the records of {}",
+        old.display(),
+        new.display(),
+        broken,
+        records
+    );
+    Ok(())
+}
+
+/// Where `path` is now, if it was inside `old`.
+fn moved_to(path: &Path, old: &Path, new: &Path) -> Option<PathBuf> {
+    path.strip_prefix(old).ok().map(|rest| new.join(rest))
+}
+
+/// Move the entries of `map` under `old` to the same place under `new`,
+/// returning how many moved.
+fn rekey<V>(map: &mut BTreeMap<PathBuf, V>, old: &Path, new: &Path) -> usize {
+    let keys: Vec<PathBuf> = map
+        .keys()
+        .filter(|path| path.starts_with(old))
+        .cloned()
+        .collect();
+    for key in &keys {
+        if let (Some(value), Some(to)) = (map.remove(key), moved_to(key, old, new)) {
+            map.insert(to, value);
+        }
+    }
+    keys.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_paths_inside_the_old_location_move() {
+        let mut map = BTreeMap::from([
+            (PathBuf::from("/src/app"), 1),
+            (PathBuf::from("/src/app/.worktrees/fix"), 2),
+            (PathBuf::from("/src/app-feature"), 3),
+        ]);
+        assert_eq!(
+            rekey(&mut map, Path::new("/src/app"), Path::new("/code/app")),
+            2
+        );
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            [
+                (PathBuf::from("/code/app"), 1),
+                (PathBuf::from("/code/app/.worktrees/fix"), 2),
+                (PathBuf::from("/src/app-feature"), 3),
+            ]
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! `terris repos`: the repositories terris has recorded worktrees of, with
+//! how many worktrees each has, the disk they use, and when one was last
+//! used. `terris repos forget <name>` drops the records of one that is gone.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use clap::{Args, Subcommand};
+use serde::Serialize;
+
+use crate::backend::Backend;
+use crate::cache::{self, Cache};
+use crate::du;
+use crate::lock;
+use crate::metadata::Metadata;
+use crate::{align_rows, format_age, primary_root, registry_base_dir, repo_name, unix_now};
+
+#[derive(Args)]
+pub struct ReposArgs {
+    #[command(subcommand)]
+    command: Option<ReposCommand>,
+}
+
+#[derive(Subcommand)]
+enum ReposCommand {
+    /// Drop everything recorded about a repository that no longer exists
+    Forget {
+        /// Repository name, as `terris repos` shows it, or its path
+        #[arg(value_name = "name")]
+        name: String,
+    },
+}
+
+/// A repository and what is recorded about its worktrees.
+#[derive(Serialize)]
+struct Repo {
+    name: String,
+    /// Its primary worktree, unless only the registry's layout tells which
+    /// repository the records belong to.
+    root: Option<PathBuf>,
+    /// The repository is gone; only terris's records of it are left.
+    missing: bool,
+    /// Linked worktrees, as git lists them, or as recorded when missing.
+    worktrees: usize,
+    /// Bytes in the linked worktrees, unless the repository is missing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    /// Unix time a worktree was last opened with terris, or else created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_used: Option<i64>,
+    #[serde(skip)]
+    records: Vec<PathBuf>,
+}
+
+pub fn cmd_repos(backend: &dyn Backend, args: &ReposArgs, json: bool, dry_run: bool) -> Result<()> {
+    match &args.command {
+        Some(ReposCommand::Forget { name }) => forget(backend, name, dry_run),
+        None => list(backend, json),
+    }
+}
+
+fn list(backend: &dyn Backend, json: bool) -> Result<()> {
+    let metadata = Metadata::load()?;
+    let mut repos = recorded(backend, &metadata)?;
+    let cache = cache::load();
+    for repo in repos.iter_mut().filter(|repo| !repo.missing) {
+        let Some(root) = &repo.root else {
+            continue;
+        };
+        let linked: Vec<_> = backend
+            .list_worktrees(root)?
+            .into_iter()
+            .filter(|wt| !wt.main)
+            .collect();
+        repo.worktrees = linked.len();
+        let usage = du::measure(backend, &linked, &cache);
+        repo.size = Some(usage.iter().map(du::Usage::total).sum());
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&repos)?);
+        return Ok(());
+    }
+    if repos.is_empty() {
+        println!("No repositories recorded yet.");
+        return Ok(());
+    }
+    let mut rows = vec![[
+        "NAME".to_string(),
+        "WORKTREES".to_string(),
+        "SIZE".to_string(),
+        "USED".to_string(),
+        "PATH".to_string(),
+    ]];
+    let now = unix_now();
+    for repo in &repos {
+        let path = repo
+            .root
+            .as_ref()
+            .map_or("-".to_string(), |root| root.display().to_string());
+        rows.push([
+            repo.name.clone(),
+            repo.worktrees.to_string(),
+            repo.size.map_or("-".to_string(), du::format_size),
+            repo.last_used
+                .map_or("-".to_string(), |time| format_age(now.saturating_sub(time))),
+            if repo.missing {
+                format!("{} (missing)", path)
+            } else {
+                path
+            },
+        ]);
+    }
+    for line in align_rows(&rows) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn forget(backend: &dyn Backend, name: &str, dry_run: bool) -> Result<()> {
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(lock::registry()?)
+    };
+    let mut metadata = Metadata::load()?;
+    let repos = recorded(backend, &metadata)?;
+    let matching: Vec<&Repo> = repos
+        .iter()
+        .filter(|repo| repo.name == name || repo.root.as_deref() == Some(Path::new(name)))
+        .collect();
+    let repo = match matching.as_slice() {
+        [] => bail!("no recorded repository is named '{}'", name),
+        [repo] => *repo,
+        several => {
+            let roots: Vec<String> = several
+                .iter()
+                .filter_map(|repo| repo.root.as_ref())
+                .map(|root| root.display().to_string())
+                .collect();
+            bail!(
+                "several recorded repositories are named '{}'; give the path of the one to forget: {}",
+                name,
+                roots.join(", ")
+            );
+        }
+    };
+    if !repo.missing {
+        bail!(
+            "'{}' still exists; only repositories that are gone can be forgotten",
+            repo.root.as_deref().unwrap_or(Path::new(name)).display()
+        );
+    }
+    if dry_run {
+        println!(
+            "Would forget {} and its {} worktree record(s)",
+            repo.name,
+            repo.records.len()
+        );
+        return Ok(());
+    }
+
+    for path in &repo.records {
+        metadata.worktrees.remove(path);
+    }
+    metadata.save()?;
+    let mut cache = Cache::load()?;
+    for path in &repo.records {
+        cache.worktrees.remove(path);
+    }
+    if let Some(root) = &repo.root {
+        cache.notified.remove(root);
+        cache.ci.remove(root);
+    }
+    cache.save()?;
+    eprintln!(
+        "Forgot {} and its {} worktree record(s)",
+        repo.name,
+        repo.records.len()
+    );
+    Ok(())
+}
+
+/// Every repository with a worktree in `metadata`, sorted by name. A record
+/// belongs to the repository it says it was created in, or else the one its
+/// directory is a worktree of, or else the one it is inside of, or else the
+/// one named by its place in the registry. Records none of these tell about
+/// are left out.
+fn recorded(backend: &dyn Backend, metadata: &Metadata) -> Result<Vec<Repo>> {
+    let base = registry_base_dir()?;
+    let mut by_root: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    let mut unknown = Vec::new();
+    for (path, meta) in &metadata.worktrees {
+        match meta.repo.clone().or_else(|| repository_of(backend, path)) {
+            Some(root) => by_root.entry(root).or_default().push(path.clone()),
+            None => unknown.push(path),
+        }
+    }
+    let mut by_name: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for path in unknown {
+        // Such as the primary worktree of a repository that is gone.
+        let inside = by_root.iter_mut().find(|(root, _)| path.starts_with(root));
+        if let Some((_, records)) = inside {
+            records.push(path.clone());
+        } else if let Some(name) = registry_name(&base, path) {
+            by_name.entry(name).or_default().push(path.clone());
+        }
+    }
+
+    let mut repos: Vec<Repo> = by_root
+        .into_iter()
+        .map(|(root, records)| Repo {
+            name: repo_name(&root),
+            missing: !root.is_dir(),
+            root: Some(root),
+            worktrees: 0,
+            size: None,
+            last_used: None,
+            records,
+        })
+        .collect();
+    for (name, records) in by_name {
+        let mut same_name = repos.iter_mut().filter(|repo| repo.name == name);
+        if let (Some(repo), None) = (same_name.next(), same_name.next()) {
+            repo.records.extend(records);
+            continue;
+        }
+        repos.push(Repo {
+            name,
+            root: None,
+            missing: true,
+            worktrees: 0,
+            size: None,
+            last_used: None,
+            records,
+        });
+    }
+    for repo in &mut repos {
+        let root = repo.root.as_deref();
+        repo.worktrees = repo
+            .records
+            .iter()
+            .filter(|path| Some(path.as_path()) != root)
+            .count();
+        repo.last_used = repo
+            .records
+            .iter()
+            .filter_map(|path| metadata.get(path))
+            .filter_map(|meta| meta.last_used.or(meta.created))
+            .max();
+    }
+    repos.sort_by(|a, b| (&a.name, &a.root).cmp(&(&b.name, &b.root)));
+    Ok(repos)
+}
+
+/// The primary worktree of the repository `path` is a worktree of, if it
+/// still is one.
+fn repository_of(backend: &dyn Backend, path: &Path) -> Option<PathBuf> {
+    if !path.is_dir() {
+        return None;
+    }
+    let root = backend.root(path).ok()?;
+    primary_root(backend, root).ok()
+}
+
+/// The repository a path in the registry was made for, from its first
+/// component under `base`, as every `path-template` starting with
+/// `{base}/{repo}` lays them out.
+fn registry_name(base: &Path, path: &Path) -> Option<String> {
+    let name = path.strip_prefix(base).ok()?.components().next()?;
+    Some(name.as_os_str().to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_paths_are_named_after_their_repository() {
+        let base = Path::new("/home/me/.terris-worktrees");
+        assert_eq!(
+            registry_name(base, &base.join("api/feature/login")),
+            Some("api".to_string())
+        );
+        assert_eq!(registry_name(base, Path::new("/src/api-feature")), None);
+    }
+}
@@ -0,0 +1,73 @@
+//! `terris reset`: recycle a worktree for new work without removing and
+//! recreating it.
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::env::WorktreeEnv;
+use crate::{
+    confirm_discard, hooks, repo_root, resolve_worktree_to_change, worktree_branch_short,
+    worktree_name,
+};
+
+#[derive(Args)]
+pub struct ResetArgs {
+    /// Worktree to reset
+    #[arg(value_name = "target")]
+    target: String,
+    /// Move the worktree (and its branch) to this commit, tag, or branch
+    #[arg(long, value_name = "ref")]
+    to: Option<String>,
+    /// Discard uncommitted changes without asking
+    #[arg(short, long)]
+    force: bool,
+    /// Run the post-create hooks again afterwards
+    #[arg(long)]
+    hooks: bool,
+}
+
+pub fn cmd_reset(
+    backend: &dyn Backend,
+    args: &ResetArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let Some(wt) =
+        resolve_worktree_to_change(&args.target, &worktrees, &cwd, config.fuzzy, "reset")?
+    else {
+        return Ok(());
+    };
+    let name = worktree_name(wt);
+
+    let summary = backend.change_summary(&wt.path)?;
+    // Moving the branch elsewhere also drops its unpushed commits.
+    let unpushed_lost = args.to.is_some() && summary.unpushed > 0;
+    if summary.has_uncommitted() || unpushed_lost {
+        if !confirm_discard(wt, &summary, "Reset", args.force, dry_run)? {
+            return Ok(());
+        }
+        if summary.has_uncommitted()
+            && let Some(backup) = backend.backup_changes(&wt.path, &name)?
+        {
+            eprintln!("Saved uncommitted changes to {}", backup);
+            eprintln!("  Recover with: git cherry-pick --no-commit {}", backup);
+        }
+    }
+
+    backend
+        .reset_to(&wt.path, args.to.as_deref())
+        .with_context(|| format!("reset worktree '{}'", name))?;
+    if args.hooks {
+        let env = WorktreeEnv::new(backend, &root, &wt.path, worktree_branch_short(wt))?;
+        hooks::post_create(&config.hooks, &env, dry_run);
+    }
+    if !dry_run {
+        eprintln!("Reset {}", name);
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Age limits enforced by `terris gc` (and so by `terris daemon`), set under
+//! `[retention]` or per repository under `[repos.<name>.retention]`:
+//!
+//! ```toml
+//! [retention]
+//! ephemeral = "2d"
+//! default = "60d"
+//!
+//! [retention.branches]
+//! "review/*" = "14d"
+//! ```
+//!
+//! Only worktrees terris created or adopted are limited. A worktree's age
+//! counts from when it was last opened with terris, or else from when it was
+//! created.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Retention {
+    /// Limit for ephemeral worktrees, applied once the process that created
+    /// them has exited.
+    pub ephemeral: Option<Age>,
+    /// Limits for branches matching a glob; the longest matching pattern
+    /// wins.
+    pub branches: BTreeMap<String, Age>,
+    /// Limit for every other worktree.
+    pub default: Option<Age>,
+}
+
+/// The rule that applies to a worktree.
+#[derive(Debug, PartialEq)]
+pub struct Rule<'a> {
+    /// `ephemeral`, `default`, or the branch pattern, for reports.
+    pub name: &'a str,
+    pub limit: Age,
+}
+
+impl Retention {
+    pub fn is_empty(&self) -> bool {
+        self.ephemeral.is_none() && self.branches.is_empty() && self.default.is_none()
+    }
+
+    /// The rule for a worktree on `branch`, if any limits it.
+    pub fn rule(&self, branch: Option<&str>, ephemeral: bool) -> Result<Option<Rule<'_>>> {
+        if ephemeral {
+            return Ok(self.ephemeral.map(|limit| Rule {
+                name: "ephemeral",
+                limit,
+            }));
+        }
+        let mut matched: Option<(&String, &Age)> = None;
+        if let Some(branch) = branch {
+            for (pattern, limit) in &self.branches {
+                let glob = glob::Pattern::new(pattern)
+                    .with_context(|| format!("invalid retention pattern '{}'", pattern))?;
+                if glob.matches(branch)
+                    && matched.is_none_or(|(best, _)| pattern.len() > best.len())
+                {
+                    matched = Some((pattern, limit));
+                }
+            }
+        }
+        Ok(match matched {
+            Some((pattern, limit)) => Some(Rule {
+                name: pattern,
+                limit: *limit,
+            }),
+            None => self.default.map(|limit| Rule {
+                name: "default",
+                limit,
+            }),
+        })
+    }
+}
+
+/// A duration in seconds, written like `90m`, `36h`, `14d`, or `2w`; a bare
+/// number is days. `--older-than` reads the same.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Age(pub i64);
+
+impl FromStr for Age {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let digits = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(digits);
+        let unit: i64 = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3_600,
+            "" | "d" => 86_400,
+            "w" => 7 * 86_400,
+            _ => bail!(
+                "invalid age '{}'; expected a number of s, m, h, d, or w, e.g. 14d",
+                text
+            ),
+        };
+        number
+            .parse::<i64>()
+            .ok()
+            .and_then(|number| number.checked_mul(unit))
+            .map(Age)
+            .with_context(|| format!("invalid age '{}'", text))
+    }
+}
+
+impl TryFrom<String> for Age {
+    type Error = anyhow::Error;
+
+    fn try_from(text: String) -> Result<Self> {
+        text.parse()
+    }
+}
+
+impl fmt::Display for Age {
+    /// In the largest unit that keeps it whole-ish: `20d`, `5h`, `12m`, `30s`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            secs if secs >= 86_400 => write!(f, "{}d", secs / 86_400),
+            secs if secs >= 3_600 => write!(f, "{}h", secs / 3_600),
+            secs if secs >= 60 => write!(f, "{}m", secs / 60),
+            secs => write!(f, "{}s", secs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_prefers_ephemeral_then_longest_pattern_then_default() {
+        let retention: Retention = toml::from_str(
+            r#"
+ephemeral = "2d"
+default = "60d"
+[branches]
+"review/*" = "14d"
+"review/big-*" = "30d"
+"#,
+        )
+        .unwrap();
+        let rule = |branch, ephemeral| retention.rule(branch, ephemeral).unwrap().unwrap();
+        assert_eq!(rule(Some("review/x"), true).name, "ephemeral");
+        assert_eq!(rule(Some("review/x"), false).limit, Age(14 * 86_400));
+        assert_eq!(rule(Some("review/big-one"), false).name, "review/big-*");
+        assert_eq!(rule(Some("feature"), false).name, "default");
+        assert_eq!(rule(None, false).name, "default");
+        assert!(
+            Retention::default()
+                .rule(Some("x"), false)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn ages_parse_and_display() {
+        assert_eq!(Age::try_from("36h".to_string()).unwrap(), Age(36 * 3_600));
+        assert_eq!(Age::try_from("2w".to_string()).unwrap().to_string(), "14d");
+        assert_eq!("7".parse::<Age>().unwrap(), Age(7 * 86_400));
+        assert_eq!("45s".parse::<Age>().unwrap().to_string(), "45s");
+        assert!("3y".parse::<Age>().is_err());
+        assert!("d".parse::<Age>().is_err());
+        assert!("9223372036854775807w".parse::<Age>().is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Rhai scripts for rules that are too involved for the config file: hooks
+//! given as a `.rhai` file, the `[gc] policy` that picks worktrees for
+//! `terris gc` to remove, and the `naming-script` that picks each new
+//! worktree's path template.
+//!
+//! A script sees its input as one constant (`ctx` for hooks, `wt` for the gc
+//! policy, `create` for naming) and its last expression is the result. `throw`
+//! fails it. `print` writes to stderr, so `terris <branch>` still prints only
+//! the path.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, anyhow};
+use rhai::{AST, Dynamic, Engine, EvalAltResult, Scope};
+use serde::Serialize;
+
+use crate::env::WorktreeEnv;
+
+/// Stops a script that loops forever.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    path: PathBuf,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::with_engine(engine(), path)
+    }
+
+    fn with_engine(engine: Engine, path: &Path) -> Result<Self> {
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|err| anyhow!("{}", err))
+            .with_context(|| format!("load script '{}'", path.display()))?;
+        Ok(Self {
+            engine,
+            ast,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Run the script with `value` bound to the constant `name`.
+    fn eval<T: Clone + 'static>(&self, name: &str, value: impl Serialize) -> Result<T> {
+        let value = rhai::serde::to_dynamic(value).map_err(|err| anyhow!("{}", err))?;
+        let mut scope = Scope::new();
+        scope.push_constant(name, value);
+        self.engine
+            .eval_ast_with_scope::<T>(&mut scope, &self.ast)
+            .map_err(|err| anyhow!("{}", err))
+            .with_context(|| format!("script '{}'", self.path.display()))
+    }
+
+    /// Ask a gc policy whether to remove the worktree described by `wt`.
+    pub fn collects(&self, wt: impl Serialize) -> Result<bool> {
+        self.eval("wt", wt)
+    }
+
+    /// Ask a naming script for the path template of a new worktree.
+    pub fn template(&self, create: impl Serialize) -> Result<String> {
+        self.eval("create", create)
+    }
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| eprintln!("{}", text));
+    engine
+}
+
+/// Whether a hook entry names a script rather than a shell command.
+pub fn is_script(command: &str) -> bool {
+    command.ends_with(".rhai") && !command.contains(char::is_whitespace)
+}
+
+/// Run the hook script at `file`, relative to the worktree. Besides `ctx`,
+/// it gets `sh(command)`, which runs a shell command in the worktree and
+/// returns its exit code.
+pub fn run_hook(file: &str, hook: &str, env: &WorktreeEnv) -> Result<()> {
+    let mut engine = engine();
+    let vars = env.vars();
+    let dir = env.path.clone();
+    engine.register_fn(
+        "sh",
+        move |command: &str| -> Result<i64, Box<EvalAltResult>> {
+            let status = Command::new("sh")
+                .args(["-c", command])
+                .current_dir(&dir)
+                .envs(vars.iter().cloned())
+                .stdout(Stdio::from(std::io::stderr()))
+                .status()
+                .map_err(|err| format!("could not run `{}`: {}", command, err))?;
+            Ok(status.code().unwrap_or(-1).into())
+        },
+    );
+    let script = Script::with_engine(engine, &resolve(Path::new(file), &env.path))?;
+    script.eval::<Dynamic>("ctx", env.to_json(hook)).map(drop)
+}
+
+/// `file` with a leading `~/` expanded and, if relative, taken relative to
+/// `dir`.
+pub fn resolve(file: &Path, dir: &Path) -> PathBuf {
+    match (file.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(under_home), Some(home)) => PathBuf::from(home).join(under_home),
+        _ => dir.join(file),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(source: &str) -> (tempfile::TempDir, Script) {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("policy.rhai");
+        std::fs::write(&path, source).unwrap();
+        let script = Script::load(&path).unwrap();
+        (dir, script)
+    }
+
+    #[test]
+    fn policy_sees_worktree_fields() {
+        let (_dir, policy) = script(r#"wt.merged && wt.idle_days > 14 && wt.branch != "keep""#);
+        let wt = |merged, idle_days, branch| serde_json::json!({ "merged": merged, "idle_days": idle_days, "branch": branch });
+        assert!(policy.collects(wt(true, 20, "old")).unwrap());
+        assert!(!policy.collects(wt(false, 20, "old")).unwrap());
+        assert!(!policy.collects(wt(true, 3, "old")).unwrap());
+        assert!(!policy.collects(wt(true, 20, "keep")).unwrap());
+    }
+
+    #[test]
+    fn errors_name_the_script() {
+        let (_dir, policy) = script(r#"throw "no""#);
+        let err = policy.collects(serde_json::json!({})).unwrap_err();
+        assert!(format!("{:#}", err).contains("policy.rhai"), "{:#}", err);
+        let (_dir, naming) = script("42");
+        assert!(naming.template(serde_json::json!({})).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+//! `terris serve`: worktree operations for programs rather than people, so
+//! coding agents and editors can work in isolated worktrees without parsing
+//! the CLI's text output.
+//!
+//! Every operation takes and returns JSON; the protocol modules only decide
+//! how requests arrive.
+
+mod http;
+mod mcp;
+
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::backend::Backend;
+use crate::cache;
+use crate::config::Config;
+use crate::env::WorktreeEnv;
+use crate::error::Error;
+use crate::metadata::{self, Metadata, WorktreeMeta};
+use crate::{
+    ListEntry, RemoveOptions, Worktree, add_new_worktree_with, ephemeral, exact_worktree,
+    find_worktree_by_branch, loose_worktree, remove_checked, repo_root, resolve_worktree,
+    worktree_branch_short, worktree_name,
+};
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct ServeArgs {
+    /// Serve Model Context Protocol tools on stdin and stdout
+    #[arg(long)]
+    mcp: bool,
+    /// Serve a JSON API over HTTP on a loopback address, e.g. 127.0.0.1:7878
+    #[arg(long, value_name = "addr")]
+    http: Option<SocketAddr>,
+}
+
+pub fn cmd_serve(
+    backend: &dyn Backend,
+    args: &ServeArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    // Dry runs print to stdout, which belongs to the protocol.
+    if dry_run {
+        bail!("`serve` does not support --dry-run");
+    }
+    let api = Api {
+        backend,
+        root: repo_root(backend)?,
+        config,
+    };
+    match args.http {
+        Some(addr) => http::serve(&api, addr),
+        None => mcp::serve(&api),
+    }
+}
+
+/// One operation, as advertised to clients.
+pub struct Operation {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// JSON Schema of the parameters.
+    pub params: Value,
+}
+
+pub fn operations() -> Vec<Operation> {
+    let target = json!({
+        "type": "string",
+        "description": "Branch, directory name, or list ID of the worktree",
+    });
+    vec![
+        Operation {
+            name: "list",
+            description: "List the repository's worktrees",
+            params: json!({ "type": "object", "properties": {} }),
+        },
+        Operation {
+            name: "create",
+            description: "Create a worktree for a branch and return its path. \
+                The branch is created from `base` (default: the primary worktree's HEAD) \
+                if it does not exist. Ephemeral worktrees, the default, are removed with \
+                their branch by `delete` or, once this server exits, by `terris gc`.",
+            params: json!({
+                "type": "object",
+                "properties": {
+                    "branch": { "type": "string" },
+                    "base": { "type": "string", "description": "Commit, tag, or branch to start a new branch from" },
+                    "ephemeral": { "type": "boolean", "default": true },
+                },
+                "required": ["branch"],
+            }),
+        },
+        Operation {
+            name: "exec",
+            description: "Run a shell command in a worktree and return its exit code and output",
+            params: json!({
+                "type": "object",
+                "properties": { "worktree": target, "command": { "type": "string" } },
+                "required": ["worktree", "command"],
+            }),
+        },
+        Operation {
+            name: "status",
+            description: "Report a worktree's branch, HEAD, and unsaved work",
+            params: json!({
+                "type": "object",
+                "properties": { "worktree": target },
+                "required": ["worktree"],
+            }),
+        },
+        Operation {
+            name: "delete",
+            description: "Remove a worktree. Uncommitted changes are refused unless `force` is \
+                set, in which case they are saved to a backup ref first.",
+            params: json!({
+                "type": "object",
+                "properties": { "worktree": target, "force": { "type": "boolean", "default": false } },
+                "required": ["worktree"],
+            }),
+        },
+    ]
+}
+
+#[derive(Deserialize)]
+struct CreateParams {
+    branch: String,
+    base: Option<String>,
+    #[serde(default = "default_true")]
+    ephemeral: bool,
+}
+
+#[derive(Deserialize)]
+struct ExecParams {
+    worktree: String,
+    command: String,
+}
+
+#[derive(Deserialize)]
+struct TargetParams {
+    worktree: String,
+    #[serde(default)]
+    force: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub struct Api<'a> {
+    backend: &'a dyn Backend,
+    root: PathBuf,
+    config: &'a Config,
+}
+
+impl Api<'_> {
+    /// Run the operation `name` with `params`.
+    pub fn call(&self, name: &str, params: Value) -> Result<Value> {
+        match name {
+            "list" => self.list(),
+            "create" => self.create(serde_json::from_value(params)?),
+            "exec" => self.exec(serde_json::from_value(params)?),
+            "status" => self.status(serde_json::from_value(params)?),
+            "delete" => self.delete(serde_json::from_value(params)?),
+            _ => bail!("unknown operation '{}'", name),
+        }
+    }
+
+    fn list(&self) -> Result<Value> {
+        let worktrees = self.backend.list_worktrees(&self.root)?;
+        let metadata = metadata::indexed(&worktrees);
+        let cache = cache::load();
+        let entries: Vec<ListEntry> = worktrees
+            .iter()
+            .map(|wt| ListEntry {
+                index: metadata.index(&wt.path),
+                worktree: Cow::Borrowed(wt),
+                managed: metadata.managed(&wt.path),
+                usage: None,
+                dirty: cache.get(wt).map(|status| status.summary.has_uncommitted()),
+                commit: None,
+                ci: None,
+                tracking: None,
+                pull_request: None,
+            })
+            .collect();
+        Ok(serde_json::to_value(entries)?)
+    }
+
+    fn create(&self, params: CreateParams) -> Result<Value> {
+        let worktrees = self.backend.list_worktrees(&self.root)?;
+        if let Some(wt) = find_worktree_by_branch(&params.branch, &worktrees)? {
+            return Err(Error::WorktreeExists {
+                branch: params.branch,
+                path: wt.path.clone(),
+            }
+            .into());
+        }
+        let owns_branch = !self.backend.branch_exists(&self.root, &params.branch)?;
+        let start = match (owns_branch, params.base) {
+            (true, Some(base)) => Some(base),
+            (true, None) => Some(
+                worktrees
+                    .iter()
+                    .find(|wt| wt.main)
+                    .and_then(|wt| wt.head.clone())
+                    .context("the primary worktree has no commit to start the branch from")?,
+            ),
+            (false, Some(_)) => bail!(
+                "branch '{}' already exists; `base` only applies to new branches",
+                params.branch
+            ),
+            (false, None) => None,
+        };
+        // The server's pid keeps `terris gc` away while it may still be used.
+        let seed = WorktreeMeta {
+            ephemeral: params.ephemeral,
+            owns_branch: params.ephemeral && owns_branch,
+            pid: params.ephemeral.then(std::process::id),
+            ..WorktreeMeta::default()
+        };
+        let path = add_new_worktree_with(
+            self.backend,
+            &self.root,
+            &params.branch,
+            start.as_deref(),
+            seed,
+            self.config,
+            false,
+        )?;
+        Ok(json!({ "path": path, "branch": params.branch, "ephemeral": params.ephemeral }))
+    }
+
+    fn exec(&self, params: ExecParams) -> Result<Value> {
+        let worktrees = self.backend.list_worktrees(&self.root)?;
+        let wt = self.resolve(&params.worktree, &worktrees)?;
+        let mut sh = Command::new("sh");
+        sh.args(["-c", &params.command]).current_dir(&wt.path);
+        WorktreeEnv::new(
+            self.backend,
+            &self.root,
+            &wt.path,
+            worktree_branch_short(wt),
+        )?
+        .apply(&mut sh);
+        let output = sh
+            .output()
+            .with_context(|| format!("run `{}`", params.command))?;
+        Ok(json!({
+            "exit_code": output.status.code(),
+            "stdout": String::from_utf8_lossy(&output.stdout),
+            "stderr": String::from_utf8_lossy(&output.stderr),
+        }))
+    }
+
+    fn status(&self, params: TargetParams) -> Result<Value> {
+        let worktrees = self.backend.list_worktrees(&self.root)?;
+        let wt = self.resolve(&params.worktree, &worktrees)?;
+        let summary = self.backend.change_summary(&wt.path)?;
+        Ok(json!({
+            "path": wt.path,
+            "branch": worktree_branch_short(wt),
+            "head": wt.head,
+            "clean": !summary.has_uncommitted(),
+            "modified": summary.modified,
+            "untracked": summary.untracked,
+            "unpushed": summary.unpushed,
+        }))
+    }
+
+    fn delete(&self, params: TargetParams) -> Result<Value> {
+        let worktrees = self.backend.list_worktrees(&self.root)?;
+        let wt = self.resolve_exactly(&params.worktree, &worktrees)?;
+        if wt.main {
+            bail!("cannot delete the primary worktree");
+        }
+        let opts = RemoveOptions {
+            force: params.force,
+            hook: self.config.hook,
+            pre_delete: &self.config.hooks.pre_delete,
+            ..RemoveOptions::default()
+        };
+        let meta = Metadata::load()?.get(&wt.path).cloned();
+        let removed = match meta {
+            Some(meta) if meta.ephemeral => {
+                ephemeral::retire(self.backend, &self.root, wt, &meta, &opts)?;
+                true
+            }
+            _ => remove_checked(self.backend, &self.root, wt, &opts)?,
+        };
+        Ok(json!({ "path": wt.path, "removed": removed }))
+    }
+
+    fn resolve<'w>(&self, target: &str, worktrees: &'w [Worktree]) -> Result<&'w Worktree> {
+        resolve_worktree(target, worktrees, Path::new(&self.root), self.config.fuzzy)
+    }
+
+    /// [`Self::resolve`] for operations that remove work: there is no one to
+    /// confirm a prefix or fuzzy match with, so only exact targets count.
+    fn resolve_exactly<'w>(&self, target: &str, worktrees: &'w [Worktree]) -> Result<&'w Worktree> {
+        if let Some(wt) = exact_worktree(target, worktrees, Path::new(&self.root))? {
+            return Ok(wt);
+        }
+        Err(
+            match loose_worktree(target, worktrees, self.config.fuzzy)? {
+                Some(wt) => Error::InexactTarget {
+                    target: target.to_string(),
+                    matched: worktree_name(wt),
+                },
+                None => Error::WorktreeNotFound(target.to_string()),
+            }
+            .into(),
+        )
+    }
+}
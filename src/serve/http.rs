@@ -0,0 +1,347 @@
+//! A small JSON API over HTTP/1.1, one request per connection, handled in
+//! order:
+//!
+//! - `GET /worktrees`: `list`
+//! - `POST /worktrees`: `create`, with the parameters as the body
+//! - `GET /worktrees/<worktree>/status`: `status`
+//! - `POST /worktrees/<worktree>/exec`: `exec`, with `{"command": ...}`
+//! - `DELETE /worktrees/<worktree>[?force=true]`: `delete`
+//!
+//! Errors have the same JSON shape as the CLI's `--json` errors.
+//!
+//! `exec` runs arbitrary commands, so every request needs the server's token
+//! as `Authorization: Bearer <token>`: `TERRIS_SERVE_TOKEN`, or one made up
+//! at startup and printed after the address. Requests from browsers, which
+//! carry an `Origin` header or a name other than a loopback address in
+//! `Host`, are refused, and a POST body must be sent as `application/json`
+//! so no HTML form can make one.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use rand::Rng;
+use serde_json::{Value, json};
+
+use super::Api;
+use crate::error::{self, Error};
+
+/// Largest request body accepted.
+const MAX_BODY: usize = 1 << 20;
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// The token clients must send, instead of a random one.
+const TOKEN_VAR: &str = "TERRIS_SERVE_TOKEN";
+
+pub fn serve(api: &Api, addr: SocketAddr) -> Result<()> {
+    // `exec` runs arbitrary commands; keep other machines out altogether.
+    if !addr.ip().is_loopback() {
+        bail!(
+            "refusing to serve on {}; bind to a loopback address such as 127.0.0.1",
+            addr
+        );
+    }
+    let token = std::env::var(TOKEN_VAR)
+        .ok()
+        .filter(|token| !token.is_empty());
+    let listener = TcpListener::bind(addr).with_context(|| format!("listen on {}", addr))?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let token = new_token();
+            eprintln!("Token: {}", token);
+            token
+        }
+    };
+    for stream in listener.incoming() {
+        let handled = stream
+            .context("accept connection")
+            .and_then(|stream| handle(api, &token, stream));
+        if let Err(err) = handled {
+            tracing::warn!("{:#}", err);
+        }
+    }
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    host: Option<String>,
+    origin: Option<String>,
+    authorization: Option<String>,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+fn new_token() -> String {
+    let bytes: [u8; 16] = rand::rng().random();
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn handle(api: &Api, token: &str, mut stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let (status, body) = match read_request(&mut BufReader::new(&stream)) {
+        Ok(request) => match check(&request, token) {
+            Ok(()) => respond(api, &request),
+            Err(refused) => refused,
+        },
+        Err(err) => (400, error_body("bad_request", &format!("{:#}", err))),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        bail!("malformed request line");
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        host: None,
+        origin: None,
+        authorization: None,
+        content_type: None,
+        body: Vec::new(),
+    };
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => length = value.parse().context("invalid Content-Length")?,
+            "host" => request.host = Some(value),
+            "origin" => request.origin = Some(value),
+            "authorization" => request.authorization = Some(value),
+            "content-type" => request.content_type = Some(value),
+            _ => {}
+        }
+    }
+    if length > MAX_BODY {
+        bail!("request body larger than {} bytes", MAX_BODY);
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Request { body, ..request })
+}
+
+/// Refuse requests from browsers, without the token, or whose body is not
+/// declared as JSON, with the status code and JSON body to send instead.
+fn check(request: &Request, token: &str) -> Result<(), (u16, String)> {
+    if request.origin.is_some() {
+        let message = "requests with an Origin are refused";
+        return Err((403, error_body("forbidden", message)));
+    }
+    if !request.host.as_deref().is_some_and(is_loopback_host) {
+        let message = "the Host header must name a loopback address";
+        return Err((403, error_body("forbidden", message)));
+    }
+    let sent = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    if !sent.is_some_and(|sent| same_token(sent, token)) {
+        let message = "send the server's token as `Authorization: Bearer <token>`";
+        return Err((401, error_body("unauthorized", message)));
+    }
+    let json = request.content_type.as_deref().is_some_and(|value| {
+        let media = value.split(';').next().unwrap_or_default().trim();
+        media.eq_ignore_ascii_case("application/json")
+    });
+    if request.method == "POST" && !json {
+        let message = "POST bodies must be sent as `Content-Type: application/json`";
+        return Err((415, error_body("unsupported_media_type", message)));
+    }
+    Ok(())
+}
+
+/// Whether a `Host` header, with or without a port, names this machine.
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((name, _)) => name,
+            None => return false,
+        },
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Compare tokens in time independent of where they first differ.
+fn same_token(sent: &str, token: &str) -> bool {
+    sent.len() == token.len()
+        && sent
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Run the operation `request` asks for; the status code and JSON body.
+fn respond(api: &Api, request: &Request) -> (u16, String) {
+    let mut params = if request.body.is_empty() {
+        json!({})
+    } else {
+        match serde_json::from_slice::<Value>(&request.body) {
+            Ok(params @ Value::Object(_)) => params,
+            Ok(_) => return (400, error_body("bad_request", "the body must be an object")),
+            Err(err) => return (400, error_body("bad_request", &err.to_string())),
+        }
+    };
+    let segments: Vec<String> = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .map(percent_decode)
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let operation = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["worktrees"]) => "list",
+        ("POST", ["worktrees"]) => "create",
+        ("GET", ["worktrees", target, "status"]) => {
+            params["worktree"] = json!(target);
+            "status"
+        }
+        ("POST", ["worktrees", target, "exec"]) => {
+            params["worktree"] = json!(target);
+            "exec"
+        }
+        ("DELETE", ["worktrees", target]) => {
+            params["worktree"] = json!(target);
+            if request
+                .query
+                .split('&')
+                .any(|q| q == "force=true" || q == "force=1")
+            {
+                params["force"] = json!(true);
+            }
+            "delete"
+        }
+        _ => {
+            let message = format!("no route for {} {}", request.method, request.path);
+            return (404, error_body("not_found", &message));
+        }
+    };
+    match api.call(operation, params) {
+        Ok(value) => {
+            let status = if operation == "create" { 201 } else { 200 };
+            (status, value.to_string())
+        }
+        Err(err) => (status_for(&err), error::to_json(&err)),
+    }
+}
+
+fn status_for(err: &anyhow::Error) -> u16 {
+    if err.downcast_ref::<serde_json::Error>().is_some() {
+        return 400;
+    }
+    match err.downcast_ref::<Error>() {
+        Some(Error::WorktreeNotFound(_) | Error::BranchNotFound(_)) => 404,
+        Some(
+            Error::AmbiguousTarget { .. } | Error::DirtyWorktree(_) | Error::WorktreeExists { .. },
+        ) => 409,
+        _ => 500,
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        415 => "Unsupported Media Type",
+        _ => "Internal Server Error",
+    }
+}
+
+fn error_body(code: &str, message: &str) -> String {
+    json!({ "code": code, "message": message }).to_string()
+}
+
+/// Decode `%XX` escapes, so branch names with `/` fit in one path segment.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| segment.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_decode_handles_escapes_and_stray_percents() {
+        assert_eq!(percent_decode("feature%2Flogin"), "feature/login");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("a%zzb"), "a%zzb");
+    }
+
+    #[test]
+    fn read_request_splits_query_and_reads_body() {
+        let raw = "POST /worktrees/x/exec?y=1 HTTP/1.1\r\nHost: a\r\ncontent-length: 2\r\n\r\n{}";
+        let request = read_request(&mut raw.as_bytes()).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/worktrees/x/exec");
+        assert_eq!(request.query, "y=1");
+        assert_eq!(request.host.as_deref(), Some("a"));
+        assert_eq!(request.body, b"{}");
+    }
+
+    #[test]
+    fn only_loopback_hosts_are_accepted() {
+        assert!(is_loopback_host("127.0.0.1:7878"));
+        assert!(is_loopback_host("localhost"));
+        assert!(is_loopback_host("[::1]:7878"));
+        assert!(!is_loopback_host("evil.example:7878"));
+        assert!(!is_loopback_host("[::1"));
+    }
+}
@@ -0,0 +1,96 @@
+//! The Model Context Protocol over stdio: one JSON-RPC 2.0 message per line
+//! on stdin, each answer as one line on stdout. Every operation is offered
+//! as a tool; anything else terris prints goes to stderr.
+
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+use serde_json::{Value, json};
+
+use super::{Api, operations};
+
+/// Used when the client does not say which version it speaks.
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+pub fn serve(api: &Api) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => handle(api, &message),
+            Err(err) => Some(error(Value::Null, PARSE_ERROR, err.to_string())),
+        };
+        if let Some(reply) = reply {
+            writeln!(stdout, "{}", reply)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// The reply to `message`; notifications get none.
+fn handle(api: &Api, message: &Value) -> Option<Value> {
+    let id = message.get("id")?.clone();
+    let params = &message["params"];
+    let result = match message["method"].as_str().unwrap_or_default() {
+        "initialize" => json!({
+            "protocolVersion": params["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION),
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "terris", "version": env!("CARGO_PKG_VERSION") },
+        }),
+        "ping" => json!({}),
+        "tools/list" => {
+            let tools: Vec<Value> = operations()
+                .into_iter()
+                .map(|op| {
+                    json!({
+                        "name": op.name,
+                        "description": op.description,
+                        "inputSchema": op.params,
+                    })
+                })
+                .collect();
+            json!({ "tools": tools })
+        }
+        "tools/call" => call_tool(api, params),
+        method => {
+            return Some(error(
+                id,
+                METHOD_NOT_FOUND,
+                format!("unknown method '{}'", method),
+            ));
+        }
+    };
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
+
+/// Failed operations are tool results with `isError`, so the model sees why.
+fn call_tool(api: &Api, params: &Value) -> Value {
+    let name = params["name"].as_str().unwrap_or_default();
+    let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+    let (text, is_error) = match api.call(name, arguments) {
+        Ok(value) => (
+            serde_json::to_string_pretty(&value).unwrap_or_default(),
+            false,
+        ),
+        Err(err) => (format!("{:#}", err), true),
+    };
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": is_error,
+    })
+}
+
+fn error(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
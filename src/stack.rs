@@ -0,0 +1,216 @@
+//! Stacked worktrees: `terris create <branch> --on <parent>` starts a branch
+//! on another worktree's branch, and `terris stack` shows the stacks and
+//! rebases children after their parent moves.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::error::Error;
+use crate::metadata::{self, Metadata};
+use crate::{
+    Worktree, add_new_worktree, find_worktree_by_branch, print_path, repo_root, resolve_worktree,
+    worktree_branch_short,
+};
+
+#[derive(Args)]
+pub struct StackArgs {
+    /// Rebase every child whose parent has moved, parents first
+    #[arg(long)]
+    restack: bool,
+}
+
+pub fn cmd_create_on(
+    backend: &dyn Backend,
+    branch: &str,
+    parent: &str,
+    config: &Config,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let parent_wt = resolve_worktree(parent, &worktrees, &cwd, config.fuzzy)?;
+    let parent_branch =
+        worktree_branch_short(parent_wt).context("the parent worktree has no branch")?;
+    if parent_branch == branch {
+        bail!("'{}' cannot be stacked on itself", branch);
+    }
+    if let Some(wt) = find_worktree_by_branch(branch, &worktrees)? {
+        return Err(Error::WorktreeExists {
+            branch: branch.to_string(),
+            path: wt.path.clone(),
+        }
+        .into());
+    }
+    let start = if backend.branch_exists(&root, branch)? {
+        None
+    } else {
+        Some(parent_branch)
+    };
+    let path = add_new_worktree(backend, &root, branch, start, config, dry_run)?;
+    if !dry_run {
+        let stacked_on = backend.resolve_rev(&root, parent_branch).ok();
+        let result = metadata::update(&path, |meta| {
+            meta.parent = Some(parent_branch.to_string());
+            meta.stacked_on = stacked_on;
+        });
+        if let Err(err) = result {
+            eprintln!("Warning: could not record the parent branch: {:#}", err);
+        }
+        metadata::record_use(&path);
+        print_path(&path);
+    }
+    Ok(path)
+}
+
+/// A stacked worktree and where it sits.
+struct Child<'a> {
+    wt: &'a Worktree,
+    branch: &'a str,
+    stacked_on: Option<String>,
+}
+
+pub fn cmd_stack(backend: &dyn Backend, args: &StackArgs, dry_run: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let metadata = Metadata::load()?;
+
+    let mut children: BTreeMap<&str, Vec<Child>> = BTreeMap::new();
+    for wt in &worktrees {
+        let (Some(branch), Some(meta)) = (worktree_branch_short(wt), metadata.get(&wt.path)) else {
+            continue;
+        };
+        if let Some(parent) = &meta.parent {
+            children.entry(parent.as_str()).or_default().push(Child {
+                wt,
+                branch,
+                stacked_on: meta.stacked_on.clone(),
+            });
+        }
+    }
+    if children.is_empty() {
+        println!("No stacked worktrees; create one with `terris create <branch> --on <parent>`.");
+        return Ok(());
+    }
+    // A stack starts at a parent that is not itself stacked.
+    let stacked: Vec<&str> = children.values().flatten().map(|c| c.branch).collect();
+    let bottoms: Vec<&str> = children
+        .keys()
+        .copied()
+        .filter(|parent| !stacked.contains(parent))
+        .collect();
+
+    let mut walk = Walk {
+        backend,
+        root: &root,
+        children: &children,
+        restack: args.restack,
+        dry_run,
+        failed: 0,
+    };
+    for bottom in bottoms {
+        println!("{}", bottom);
+        walk.visit(bottom, 1, true)?;
+    }
+    if walk.failed > 0 {
+        bail!("failed to restack {} worktree(s)", walk.failed);
+    }
+    Ok(())
+}
+
+struct Walk<'a> {
+    backend: &'a dyn Backend,
+    root: &'a Path,
+    children: &'a BTreeMap<&'a str, Vec<Child<'a>>>,
+    restack: bool,
+    dry_run: bool,
+    failed: usize,
+}
+
+impl Walk<'_> {
+    /// Print (and with `--restack`, rebase) the children of `parent`. When
+    /// `parent_ok` is false the parent was not restacked, so its children
+    /// are left alone too.
+    fn visit(&mut self, parent: &str, depth: usize, parent_ok: bool) -> Result<()> {
+        let Some(children) = self.children.get(parent) else {
+            return Ok(());
+        };
+        for child in children {
+            let indent = "  ".repeat(depth);
+            let mut ok = parent_ok;
+            let note = if !self.backend.branch_exists(self.root, parent)? {
+                ok = false;
+                "  (parent branch is gone)".to_string()
+            } else if self.backend.is_merged(self.root, parent, child.branch)? {
+                // Up to date, possibly after a rebase finished by hand.
+                let tip = self.backend.resolve_rev(self.root, parent)?;
+                if child.stacked_on.as_deref() != Some(tip.as_str()) && !self.dry_run {
+                    record_stacked_on(&child.wt.path, tip);
+                }
+                String::new()
+            } else if !self.restack {
+                "  (needs restack)".to_string()
+            } else if !parent_ok {
+                ok = false;
+                "  (skipped: parent not restacked)".to_string()
+            } else {
+                let (note, restacked) = self.restack(parent, child)?;
+                ok = restacked;
+                note
+            };
+            println!(
+                "{}{}  {}{}",
+                indent,
+                child.branch,
+                child.wt.path.display(),
+                note
+            );
+            self.visit(child.branch, depth + 1, ok)?;
+        }
+        Ok(())
+    }
+
+    /// Rebase `child` onto the current tip of `parent`.
+    fn restack(&mut self, parent: &str, child: &Child) -> Result<(String, bool)> {
+        if child.wt.locked {
+            return Ok(("  (skipped: locked)".to_string(), false));
+        }
+        if self.backend.is_dirty(&child.wt.path)? {
+            return Ok(("  (skipped: uncommitted changes)".to_string(), false));
+        }
+        let tip = self.backend.resolve_rev(self.root, parent)?;
+        match self
+            .backend
+            .rebase(&child.wt.path, parent, child.stacked_on.as_deref())
+        {
+            Ok(true) => {
+                if !self.dry_run {
+                    record_stacked_on(&child.wt.path, tip);
+                }
+                Ok(("  (restacked)".to_string(), true))
+            }
+            Ok(false) => {
+                self.failed += 1;
+                Ok((
+                    "  (conflict: resolve it there and run `git rebase --continue`)".to_string(),
+                    false,
+                ))
+            }
+            Err(err) => {
+                self.failed += 1;
+                Ok((format!("  (failed: {:#})", err), false))
+            }
+        }
+    }
+}
+
+fn record_stacked_on(path: &Path, tip: String) {
+    if let Err(err) = metadata::update(path, |meta| meta.stacked_on = Some(tip)) {
+        tracing::warn!("could not update worktree metadata: {:#}", err);
+    }
+}
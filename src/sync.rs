@@ -0,0 +1,89 @@
+//! `terris sync`: fetch once, then fast-forward every worktree whose branch
+//! is behind its upstream. The worktrees' status is read in parallel.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use crate::backend::Backend;
+use crate::{repo_root, worktree_branch_short, worktree_name};
+
+pub fn cmd_sync(backend: &dyn Backend, dry_run: bool) -> Result<()> {
+    let root = repo_root(backend)?;
+    // Worktrees share one object store and one set of remote refs, so a
+    // single fetch covers all of them.
+    backend.fetch(&root).context("fetch")?;
+    let worktrees = backend.list_worktrees(&root)?;
+
+    let (mut moved, mut failed) = (0, 0);
+    let mut behind = Vec::new();
+    for wt in &worktrees {
+        let Some(branch) = worktree_branch_short(wt) else {
+            continue;
+        };
+        let name = worktree_name(wt);
+        match backend.upstream_divergence(&root, branch) {
+            Ok(Some(divergence)) if divergence.behind > 0 => behind.push((wt, name, divergence)),
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("Error: compare '{}' with its upstream: {:#}", name, err);
+                failed += 1;
+            }
+        }
+    }
+    // Only the worktrees that could move need their status read.
+    let paths: Vec<&Path> = behind
+        .iter()
+        .filter(|(_, _, divergence)| divergence.ahead == 0)
+        .map(|(wt, _, _)| wt.path.as_path())
+        .collect();
+    let dirty: HashMap<&Path, Result<bool>> = paths
+        .iter()
+        .copied()
+        .zip(backend.dirty_all(&paths))
+        .collect();
+
+    for (wt, name, divergence) in &behind {
+        if divergence.ahead > 0 {
+            println!(
+                "Skipped {}: diverged ({} ahead, {} behind)",
+                name, divergence.ahead, divergence.behind
+            );
+            continue;
+        }
+        match &dirty[wt.path.as_path()] {
+            Ok(false) => {}
+            Ok(true) => {
+                println!(
+                    "Skipped {}: uncommitted changes ({} behind)",
+                    name, divergence.behind
+                );
+                continue;
+            }
+            Err(err) => {
+                println!("Skipped {}: could not read status ({:#})", name, err);
+                continue;
+            }
+        }
+        match backend.fast_forward(&wt.path) {
+            Ok(()) => {
+                moved += 1;
+                if !dry_run {
+                    println!("Updated {}: {} new commit(s)", name, divergence.behind);
+                }
+            }
+            Err(err) => {
+                eprintln!("Error: fast-forward '{}': {:#}", name, err);
+                failed += 1;
+            }
+        }
+    }
+    if moved + failed == 0 && !dry_run {
+        println!("Everything is up to date.");
+    }
+    if failed > 0 {
+        bail!("failed to sync {} worktree(s)", failed);
+    }
+    Ok(())
+}
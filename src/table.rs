@@ -0,0 +1,190 @@
+//! Column layout for tables printed to a terminal. Widths are measured in
+//! terminal cells rather than bytes, so CJK text and emoji line up, and
+//! columns that may overflow are cut with an ellipsis to fit the screen.
+
+use std::io::IsTerminal;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Columns are never cut narrower than this.
+const MIN_WIDTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// What to cut when a column does not fit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    /// Never cut.
+    Keep,
+    /// Cut the end, keeping the start.
+    End,
+    /// Cut the start, keeping the end; for paths.
+    Start,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Column {
+    pub header: &'static str,
+    pub align: Align,
+    pub overflow: Overflow,
+}
+
+/// How many cells `text` takes up in a terminal.
+pub fn width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// Pad `text` with spaces to `width` cells.
+pub fn pad(text: &str, width: usize, align: Align) -> String {
+    let fill = " ".repeat(width.saturating_sub(self::width(text)));
+    match align {
+        Align::Left => format!("{}{}", text, fill),
+        Align::Right => format!("{}{}", fill, text),
+    }
+}
+
+/// Cut `text` to at most `max` cells, marking the cut with `…`.
+pub fn truncate(text: &str, max: usize, overflow: Overflow) -> String {
+    if overflow == Overflow::Keep || width(text) <= max {
+        return text.to_string();
+    }
+    let mut kept = Vec::new();
+    let mut used = 1;
+    let chars: Box<dyn Iterator<Item = char>> = match overflow {
+        Overflow::Start => Box::new(text.chars().rev()),
+        _ => Box::new(text.chars()),
+    };
+    for c in chars {
+        let w = c.width().unwrap_or(0);
+        if used + w > max {
+            break;
+        }
+        used += w;
+        kept.push(c);
+    }
+    match overflow {
+        Overflow::Start => std::iter::once('…').chain(kept.into_iter().rev()).collect(),
+        _ => kept.into_iter().chain(std::iter::once('…')).collect(),
+    }
+}
+
+/// The width to fit tables to: the terminal's, when stdout is one.
+pub fn terminal_width() -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    console::Term::stdout()
+        .size_checked()
+        .map(|(_, columns)| usize::from(columns))
+}
+
+/// Lay out `rows` under `columns`: the header line first, then one line per
+/// row, with cells padded but the last column left unpadded. With
+/// `max_width`, the widest columns that may overflow are cut until a line
+/// fits in it.
+pub fn render(
+    columns: &[Column],
+    rows: &[Vec<String>],
+    max_width: Option<usize>,
+) -> Vec<Vec<String>> {
+    let mut widths: Vec<usize> = columns.iter().map(|col| width(col.header)).collect();
+    for row in rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(width(cell));
+        }
+    }
+    if let Some(max_width) = max_width {
+        shrink(columns, &mut widths, max_width);
+    }
+
+    let headers: Vec<String> = columns.iter().map(|col| col.header.to_string()).collect();
+    std::iter::once(&headers)
+        .chain(rows)
+        .map(|row| {
+            let last = row.len().saturating_sub(1);
+            row.iter()
+                .zip(columns.iter().zip(&widths))
+                .enumerate()
+                .map(|(i, (cell, (col, &w)))| {
+                    let cell = truncate(cell, w, col.overflow);
+                    if i == last && col.align == Align::Left {
+                        cell
+                    } else {
+                        pad(&cell, w, col.align)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Narrow the widest cuttable column, one cell at a time, until the columns
+/// and the spaces between them fit in `max_width` or nothing is left to cut.
+fn shrink(columns: &[Column], widths: &mut [usize], max_width: usize) {
+    let mut total = widths.iter().sum::<usize>() + widths.len().saturating_sub(1);
+    while total > max_width {
+        let widest = columns
+            .iter()
+            .zip(widths.iter())
+            .enumerate()
+            .filter(|(_, (col, w))| col.overflow != Overflow::Keep && **w > MIN_WIDTH)
+            .max_by_key(|(_, (_, w))| **w)
+            .map(|(i, _)| i);
+        let Some(i) = widest else {
+            break;
+        };
+        widths[i] -= 1;
+        total -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_characters_count_twice() {
+        assert_eq!(width("功能"), 4);
+        assert_eq!(pad("功能", 6, Align::Left), "功能  ");
+        assert_eq!(truncate("功能分支名", 7, Overflow::End), "功能分…");
+        assert_eq!(
+            truncate("/home/me/worktrees/repo", 10, Overflow::Start),
+            "…rees/repo"
+        );
+        assert_eq!(truncate("short", 10, Overflow::End), "short");
+    }
+
+    #[test]
+    fn render_cuts_the_widest_column_to_fit() {
+        let columns = [
+            Column {
+                header: "NAME",
+                align: Align::Left,
+                overflow: Overflow::End,
+            },
+            Column {
+                header: "PATH",
+                align: Align::Left,
+                overflow: Overflow::Start,
+            },
+            Column {
+                header: "FLAGS",
+                align: Align::Left,
+                overflow: Overflow::Keep,
+            },
+        ];
+        let rows = vec![vec![
+            "feature".to_string(),
+            "/a/very/long/path/to/a/worktree".to_string(),
+            "dirty".to_string(),
+        ]];
+        let lines = render(&columns, &rows, Some(30));
+        assert_eq!(lines[0].join(" "), "NAME    PATH             FLAGS");
+        assert_eq!(lines[1].join(" "), "feature …h/to/a/worktree dirty");
+        assert!(width(&lines[1].join(" ")) <= 30);
+    }
+}
@@ -0,0 +1,123 @@
+//! `terris with <ref> -- <cmd...>`: run one command in a throwaway detached
+//! worktree, e.g. the test suite against an old release.
+
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::env::WorktreeEnv;
+use crate::error::Error;
+use crate::{default_worktree_path, metadata, naming, repo_root};
+
+#[derive(Args)]
+pub struct WithArgs {
+    /// Commit, tag, or branch to check out
+    #[arg(value_name = "ref")]
+    reference: String,
+    /// Command to run in the worktree
+    #[arg(last = true, required = true, value_name = "cmd")]
+    command: Vec<String>,
+}
+
+pub fn cmd_with(
+    backend: &dyn Backend,
+    args: &WithArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let template = naming::DEFAULT_TEMPLATE;
+    let path = default_worktree_path(config, template, &root, &slug(&args.reference))?;
+    let command = args.command.join(" ");
+    if !dry_run && let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("create directory '{}'", parent.display()))?;
+    }
+    backend
+        .add_detached(&root, &path, &args.reference)
+        .with_context(|| format!("check out '{}'", args.reference))?;
+    if dry_run {
+        println!("Would run `{}` in {}", command, path.display());
+        backend.remove_worktree(&root, &path, true)?;
+        return Ok(());
+    }
+
+    // If terris is killed before cleaning up, `terris gc` removes the worktree.
+    let recorded = metadata::update(&path, |meta| {
+        meta.ephemeral = true;
+        meta.pid = Some(std::process::id());
+    });
+    if let Err(err) = recorded {
+        tracing::warn!("could not record worktree: {:#}", err);
+    }
+
+    let mut child = Command::new(&args.command[0]);
+    child.args(&args.command[1..]).current_dir(&path);
+    match WorktreeEnv::new(backend, &root, &path, None) {
+        Ok(env) => env.apply(&mut child),
+        Err(err) => tracing::warn!("could not describe worktree: {:#}", err),
+    }
+    let status = child
+        .spawn()
+        .and_then(|mut child| {
+            // ^C is meant for the command; terris stays to clean up after it.
+            let _ignored = IgnoreSignals::new();
+            child.wait()
+        })
+        .with_context(|| format!("run `{}`", command));
+    let removed = backend.remove_worktree(&root, &path, true);
+    metadata::forget(&path);
+    removed.with_context(|| format!("remove worktree '{}'", path.display()))?;
+
+    let status = status?;
+    if !status.success() {
+        let code = status.code().and_then(|code| u8::try_from(code).ok());
+        return Err(Error::CommandFailed {
+            command,
+            code: code.unwrap_or(1),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Ignores SIGINT and SIGTERM in terris until dropped. Set after the child
+/// is spawned, since ignored signals stay ignored across exec.
+struct IgnoreSignals([libc::sighandler_t; 2]);
+
+impl IgnoreSignals {
+    fn new() -> Self {
+        // SAFETY: SIG_IGN runs no code in the handler.
+        unsafe {
+            Self([
+                libc::signal(libc::SIGINT, libc::SIG_IGN),
+                libc::signal(libc::SIGTERM, libc::SIG_IGN),
+            ])
+        }
+    }
+}
+
+impl Drop for IgnoreSignals {
+    fn drop(&mut self) {
+        // SAFETY: restores the handlers `new` replaced.
+        unsafe {
+            libc::signal(libc::SIGINT, self.0[0]);
+            libc::signal(libc::SIGTERM, self.0[1]);
+        }
+    }
+}
+
+/// A directory-name-safe form of `reference`: `v1.2.0` stays as is,
+/// `origin/main~2` becomes `origin-main-2`.
+fn slug(reference: &str) -> String {
+    reference
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '-',
+        })
+        .collect()
+}
@@ -0,0 +1,418 @@
+//! `terris ws`: workspaces, named sets of repositories configured under
+//! `[workspaces.<name>]`, whose worktrees are created, listed, deleted, and
+//! worked in together, one same-named branch in each repository.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{self, Backend};
+use crate::color::Paint;
+use crate::config::Config;
+use crate::env::WorktreeEnv;
+use crate::foreach::{self, ForeachOutput};
+use crate::metadata;
+use crate::{
+    RemoveOptions, add_new_worktree, align_rows, find_worktree_by_branch, primary_root, print_path,
+    remove_checked, repo_name, worktree_branch_short,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct WorkspaceConfig {
+    /// The repositories in the workspace, each its primary worktree.
+    pub repos: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct WsArgs {
+    /// Workspace from [workspaces.<name>] [default: the one containing the
+    /// current repository, or the only one configured]
+    #[arg(short, long, global = true, value_name = "name")]
+    workspace: Option<String>,
+    #[command(subcommand)]
+    command: WsCommand,
+}
+
+#[derive(Subcommand)]
+enum WsCommand {
+    /// Create (or reuse) a worktree for the branch in every repository and
+    /// print their paths
+    Create {
+        #[arg(value_name = "branch")]
+        branch: String,
+        /// Where repositories without the branch start it [default: their
+        /// primary worktree's HEAD]
+        #[arg(long, value_name = "ref")]
+        base: Option<String>,
+    },
+    /// List the branches checked out in the workspace's repositories and
+    /// which repositories have a worktree for each
+    List,
+    /// Remove the branch's worktree from every repository
+    Delete {
+        #[arg(value_name = "branch")]
+        branch: String,
+        /// Discard uncommitted changes without asking
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Run a command in the branch's worktree of every repository at once
+    Foreach {
+        #[arg(value_name = "branch")]
+        branch: String,
+        /// Interleave lines as they come, or keep each repository's together
+        #[arg(long, value_enum, value_name = "mode")]
+        output: Option<ForeachOutput>,
+        /// Stop the other commands as soon as one fails
+        #[arg(long)]
+        fail_fast: bool,
+        /// Command to run in each worktree
+        #[arg(last = true, required = true, value_name = "cmd")]
+        command: Vec<String>,
+    },
+}
+
+/// One repository of the workspace.
+struct Member {
+    name: String,
+    root: PathBuf,
+    backend: Box<dyn Backend>,
+}
+
+/// A branch of the workspace and its worktree in each repository.
+#[derive(Serialize)]
+struct Group {
+    branch: String,
+    /// Worktree paths by repository name.
+    worktrees: BTreeMap<String, PathBuf>,
+    /// Repositories without a worktree for the branch.
+    missing: Vec<String>,
+}
+
+pub fn cmd_ws(
+    backend: &dyn Backend,
+    args: &WsArgs,
+    config: &Config,
+    paint: Paint,
+    json: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let members = members(backend, args.workspace.as_deref(), config, dry_run)?;
+    match &args.command {
+        WsCommand::Create { branch, base } => {
+            create(&members, branch, base.as_deref(), config, dry_run)
+        }
+        WsCommand::List => list(&members, json),
+        WsCommand::Delete { branch, force } => delete(&members, branch, *force, config, dry_run),
+        WsCommand::Foreach {
+            branch,
+            output,
+            fail_fast,
+            command,
+        } => {
+            let mode = output.unwrap_or(config.foreach.output);
+            run(&members, branch, command, mode, *fail_fast, paint, dry_run)
+        }
+    }
+}
+
+/// The repositories of the workspace named `name`, or else of the one the
+/// current repository belongs to, or else of the only one configured.
+fn members(
+    backend: &dyn Backend,
+    name: Option<&str>,
+    config: &Config,
+    dry_run: bool,
+) -> Result<Vec<Member>> {
+    let names: Vec<&str> = config.workspaces.keys().map(String::as_str).collect();
+    if names.is_empty() {
+        bail!("no workspaces are configured; add a [workspaces.<name>] with its repos");
+    }
+    let (name, workspace) = match name {
+        Some(name) => match config.workspaces.get_key_value(name) {
+            Some(found) => found,
+            None => bail!(
+                "no workspace is named '{}'; configured: {}",
+                name,
+                names.join(", ")
+            ),
+        },
+        None => {
+            let current = crate::repo_root(backend).ok();
+            let containing: Vec<_> = config
+                .workspaces
+                .iter()
+                .filter(|(_, ws)| {
+                    current.as_ref().is_some_and(|current| {
+                        ws.repos.iter().any(|repo| canonical(repo) == *current)
+                    })
+                })
+                .collect();
+            match (containing.as_slice(), names.as_slice()) {
+                ([found], _) => *found,
+                ([], [_]) => config.workspaces.iter().next().expect("one workspace"),
+                _ => bail!("pick a workspace with --workspace: {}", names.join(", ")),
+            }
+        }
+    };
+    if workspace.repos.is_empty() {
+        bail!("workspace '{}' has no repos", name);
+    }
+
+    let mut members = Vec::new();
+    for repo in &workspace.repos {
+        if !repo.is_dir() {
+            bail!(
+                "'{}' of workspace '{}' is not a directory",
+                repo.display(),
+                name
+            );
+        }
+        let backend = backend::detect(repo, dry_run);
+        let root = backend
+            .root(repo)
+            .and_then(|root| primary_root(backend.as_ref(), root))
+            .with_context(|| format!("workspace '{}'", name))?;
+        members.push(Member {
+            name: repo_name(&root),
+            root,
+            backend,
+        });
+    }
+    Ok(members)
+}
+
+fn canonical(path: &std::path::Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn create(
+    members: &[Member],
+    branch: &str,
+    base: Option<&str>,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let mut failed = Vec::new();
+    for member in members {
+        match create_in(member, branch, base, config, dry_run) {
+            Ok(path) if !dry_run => print_path(&path),
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("{}: {:#}", member.name, err);
+                failed.push(member.name.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        bail!(
+            "could not create '{}' in {} of {} repositories: {}",
+            branch,
+            failed.len(),
+            members.len(),
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// The worktree for `branch` in `member`, created, with the branch if it is
+/// missing, unless one is already there.
+fn create_in(
+    member: &Member,
+    branch: &str,
+    base: Option<&str>,
+    config: &Config,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let backend = member.backend.as_ref();
+    let worktrees = backend.list_worktrees(&member.root)?;
+    if let Some(wt) = find_worktree_by_branch(branch, &worktrees)? {
+        return Ok(wt.path.clone());
+    }
+    let start = if backend.branch_exists(&member.root, branch)? {
+        None
+    } else {
+        // A bare `HEAD` would mean the new worktree's own HEAD.
+        let head = worktrees
+            .iter()
+            .find(|wt| wt.main)
+            .and_then(|wt| wt.head.clone());
+        Some(
+            base.map(str::to_string)
+                .or(head)
+                .context("the primary worktree has no commit to start from")?,
+        )
+    };
+    let path = add_new_worktree(
+        backend,
+        &member.root,
+        branch,
+        start.as_deref(),
+        config,
+        dry_run,
+    )?;
+    if !dry_run {
+        metadata::record_use(&path);
+    }
+    Ok(path)
+}
+
+fn list(members: &[Member], json: bool) -> Result<()> {
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+    for member in members {
+        let worktrees = member.backend.list_worktrees(&member.root)?;
+        for wt in worktrees.iter().filter(|wt| !wt.main) {
+            let Some(branch) = worktree_branch_short(wt) else {
+                continue;
+            };
+            groups
+                .entry(branch.to_string())
+                .or_insert_with(|| Group {
+                    branch: branch.to_string(),
+                    worktrees: BTreeMap::new(),
+                    missing: Vec::new(),
+                })
+                .worktrees
+                .insert(member.name.clone(), wt.path.clone());
+        }
+    }
+    let mut groups: Vec<Group> = groups.into_values().collect();
+    for group in &mut groups {
+        group.missing = members
+            .iter()
+            .filter(|member| !group.worktrees.contains_key(&member.name))
+            .map(|member| member.name.clone())
+            .collect();
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&groups)?);
+        return Ok(());
+    }
+    if groups.is_empty() {
+        println!("No worktrees in the workspace.");
+        return Ok(());
+    }
+    let mut rows = vec![[
+        "BRANCH".to_string(),
+        "REPOS".to_string(),
+        "MISSING".to_string(),
+    ]];
+    for group in &groups {
+        rows.push([
+            group.branch.clone(),
+            format!("{}/{}", group.worktrees.len(), members.len()),
+            if group.missing.is_empty() {
+                "-".to_string()
+            } else {
+                group.missing.join(",")
+            },
+        ]);
+    }
+    for line in align_rows(&rows) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn delete(
+    members: &[Member],
+    branch: &str,
+    force: bool,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let opts = RemoveOptions {
+        force,
+        dry_run,
+        hook: config.hook,
+        pre_delete: &config.hooks.pre_delete,
+        ..RemoveOptions::default()
+    };
+    let mut deleted = 0;
+    let mut failed = Vec::new();
+    for member in members {
+        let backend = member.backend.as_ref();
+        let removed = backend.list_worktrees(&member.root).and_then(|worktrees| {
+            let Some(wt) = find_worktree_by_branch(branch, &worktrees)?.filter(|wt| !wt.main)
+            else {
+                return Ok(false);
+            };
+            remove_checked(backend, &member.root, wt, &opts)
+        });
+        match removed {
+            Ok(false) => {}
+            Ok(true) => {
+                deleted += 1;
+                if !dry_run {
+                    eprintln!("Deleted {} in {}", branch, member.name);
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: {:#}", member.name, err);
+                failed.push(member.name.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        bail!(
+            "could not delete '{}' in {} of {} repositories: {}",
+            branch,
+            failed.len(),
+            members.len(),
+            failed.join(", ")
+        );
+    }
+    if deleted == 0 && !dry_run {
+        println!("No worktrees for '{}' in the workspace.", branch);
+    }
+    Ok(())
+}
+
+fn run(
+    members: &[Member],
+    branch: &str,
+    args: &[String],
+    mode: ForeachOutput,
+    fail_fast: bool,
+    paint: Paint,
+    dry_run: bool,
+) -> Result<()> {
+    let command = args.join(" ");
+    let mut commands = Vec::new();
+    for member in members {
+        let backend = member.backend.as_ref();
+        let worktrees = backend.list_worktrees(&member.root)?;
+        let Some(wt) = find_worktree_by_branch(branch, &worktrees)? else {
+            eprintln!("Skipped {}: no worktree for '{}'", member.name, branch);
+            continue;
+        };
+        if dry_run {
+            println!("Would run `{}` in {}", command, wt.path.display());
+            continue;
+        }
+        let mut child = Command::new(&args[0]);
+        child.args(&args[1..]).current_dir(&wt.path);
+        match WorktreeEnv::new(backend, &member.root, &wt.path, Some(branch)) {
+            Ok(env) => env.apply(&mut child),
+            Err(err) => tracing::warn!("could not describe worktree: {:#}", err),
+        }
+        commands.push((member.name.clone(), child));
+    }
+    if dry_run {
+        return Ok(());
+    }
+    if commands.is_empty() {
+        bail!(
+            "no repository of the workspace has a worktree for '{}'",
+            branch
+        );
+    }
+    foreach::run_all(commands, &command, mode, fail_fast, paint)
+}
@@ -0,0 +1,159 @@
+//! Git config that belongs to one worktree only, such as a different
+//! `user.email` for work and open-source worktrees. Git keeps it in
+//! `config.worktree` once `extensions.worktreeConfig` is on, which terris
+//! turns on the first time it is needed.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use clap::Args;
+
+use crate::backend::Backend;
+use crate::config::Config;
+use crate::{repo_name, repo_root, resolve_worktree};
+
+#[derive(Args)]
+pub struct ConfigWorktreeArgs {
+    /// Worktree whose config to read or change
+    #[arg(value_name = "target")]
+    target: String,
+    /// Config key, e.g. `user.email`
+    #[arg(value_name = "key")]
+    key: String,
+    /// Value to set; omit to print the current one
+    #[arg(value_name = "value", conflicts_with = "unset")]
+    value: Option<String>,
+    /// Remove the key from the worktree's config
+    #[arg(long)]
+    unset: bool,
+}
+
+pub fn cmd_config_worktree(
+    backend: &dyn Backend,
+    args: &ConfigWorktreeArgs,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let root = repo_root(backend)?;
+    let worktrees = backend.list_worktrees(&root)?;
+    let cwd = std::env::current_dir().context("read current directory")?;
+    let wt = resolve_worktree(&args.target, &worktrees, &cwd, config.fuzzy)?;
+
+    if args.value.is_none() && !args.unset {
+        return match backend.worktree_config(&wt.path, &args.key)? {
+            Some(value) => {
+                println!("{}", value);
+                Ok(())
+            }
+            None => bail!("'{}' is not set in {}", args.key, wt.path.display()),
+        };
+    }
+    enable(backend, &root, dry_run)?;
+    backend
+        .set_worktree_config(&wt.path, &args.key, args.value.as_deref())
+        .with_context(|| format!("set '{}' in {}", args.key, wt.path.display()))
+}
+
+/// Set the `[worktree-config]` entries whose glob matches `branch` in the new
+/// worktree at `path`, the repository's own entries last.
+pub fn apply(
+    backend: &dyn Backend,
+    root: &Path,
+    path: &Path,
+    branch: &str,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    let repo = config.repos.get(&repo_name(root));
+    let mut values = BTreeMap::new();
+    let tables =
+        std::iter::once(&config.worktree_config).chain(repo.map(|repo| &repo.worktree_config));
+    for table in tables {
+        for (pattern, entries) in table {
+            let glob = glob::Pattern::new(pattern)
+                .with_context(|| format!("parse worktree-config glob '{}'", pattern))?;
+            if glob.matches(branch) {
+                values.extend(entries);
+            }
+        }
+    }
+    if values.is_empty() {
+        return Ok(());
+    }
+    enable(backend, root, dry_run)?;
+    for (key, value) in values {
+        backend
+            .set_worktree_config(path, key, Some(value))
+            .with_context(|| format!("set '{}' in {}", key, path.display()))?;
+    }
+    Ok(())
+}
+
+/// Copy the entries of the repository's own config whose key matches one of
+/// the `propagate-config` globs into the new worktree at `path`. Relative
+/// paths are made absolute, so they keep naming what they named from the
+/// primary worktree at `root`: hooks there, included files next to the
+/// shared config.
+pub fn propagate(
+    backend: &dyn Backend,
+    root: &Path,
+    path: &Path,
+    config: &Config,
+    dry_run: bool,
+) -> Result<()> {
+    if config.propagate_config.is_empty() {
+        return Ok(());
+    }
+    let globs = config
+        .propagate_config
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("parse propagate-config glob '{}'", pattern))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Git lowercases section and key names, so match regardless of case.
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..glob::MatchOptions::new()
+    };
+    let entries: Vec<(String, String)> = backend
+        .own_config(root)?
+        .into_iter()
+        .filter(|(key, _)| globs.iter().any(|glob| glob.matches_with(key, options)))
+        .collect();
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let common_dir = backend.common_dir(root)?;
+    enable(backend, root, dry_run)?;
+    for (key, value) in entries {
+        let base = if key == "core.hookspath" {
+            Some(root)
+        } else if key == "include.path" || (key.starts_with("includeif.") && key.ends_with(".path"))
+        {
+            Some(common_dir.as_path())
+        } else {
+            None
+        };
+        let value = match base {
+            Some(base) if !value.starts_with('~') && Path::new(&value).is_relative() => {
+                base.join(&value).to_string_lossy().into_owned()
+            }
+            _ => value,
+        };
+        backend
+            .add_worktree_config(path, &key, &value)
+            .with_context(|| format!("copy '{}' into {}", key, path.display()))?;
+    }
+    Ok(())
+}
+
+/// Turn on `extensions.worktreeConfig`, saying so the first time.
+fn enable(backend: &dyn Backend, root: &Path, dry_run: bool) -> Result<()> {
+    if backend.enable_worktree_config(root)? && !dry_run {
+        eprintln!("Enabled extensions.worktreeConfig in {}", root.display());
+    }
+    Ok(())
+}
@@ -20,9 +20,8 @@ fn run_git_with_env(args: &[&str], cwd: &std::path::Path, envs: &[(&str, &str)])
     assert!(status.success(), "git {:?} failed", args);
 }
 
-#[test]
-fn worktree_create_stdout_is_single_line() {
-    let temp_dir = TempDir::new().expect("create temp dir");
+/// Create a repository with one commit and a `feature` branch.
+fn init_repo(temp_dir: &TempDir) -> std::path::PathBuf {
     let repo_dir = temp_dir.path().join("repo");
     std::fs::create_dir_all(&repo_dir).expect("create repo dir");
 
@@ -56,15 +55,28 @@ fn worktree_create_stdout_is_single_line() {
     );
 
     run_git(&["branch", "feature"], &repo_dir);
+    repo_dir
+}
 
+fn terris(repo_dir: &std::path::Path, home_dir: &std::path::Path) -> Command {
+    std::fs::create_dir_all(home_dir).expect("create home dir");
+    let bin = assert_cmd::cargo::cargo_bin!("terris");
+    let mut cmd = Command::new(bin);
+    cmd.current_dir(repo_dir)
+        .env("HOME", home_dir)
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("TERRIS_CONFIG");
+    cmd
+}
+
+#[test]
+fn worktree_create_stdout_is_single_line() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
     let home_dir = temp_dir.path().join("home");
-    std::fs::create_dir_all(&home_dir).expect("create home dir");
 
-    let bin = assert_cmd::cargo::cargo_bin!("terris");
-    let output = Command::new(bin)
+    let output = terris(&repo_dir, &home_dir)
         .arg("feature")
-        .current_dir(&repo_dir)
-        .env("HOME", &home_dir)
         .output()
         .expect("run terris");
 
@@ -73,3 +85,4282 @@ fn worktree_create_stdout_is_single_line() {
     let lines: Vec<&str> = stdout.lines().collect();
     assert_eq!(lines.len(), 1, "stdout should be a single line: {stdout:?}");
 }
+
+#[test]
+fn dry_run_create_changes_nothing() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--dry-run", "feature"])
+        .output()
+        .expect("run terris");
+
+    assert!(output.status.success(), "terris failed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Would create directory"), "{stdout}");
+    assert!(stdout.contains("Would run: git worktree add"), "{stdout}");
+    assert!(!home_dir.join(".terris-worktrees").exists());
+}
+
+#[test]
+fn clean_removes_merged_worktrees() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let worktree = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["clean", "--delete-branch"])
+        .output()
+        .expect("run terris clean");
+    assert!(output.status.success(), "terris clean failed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Deleted feature"), "{stdout}");
+    assert!(!std::path::Path::new(&worktree).exists());
+
+    let branches = Command::new("git")
+        .args(["branch", "--list", "feature"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("list branches");
+    assert!(String::from_utf8_lossy(&branches.stdout).trim().is_empty());
+}
+
+#[test]
+fn unpushed_commits_without_remotes_count_against_the_primary_branch() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let unpushed = || {
+        let output = terris(&repo_dir, &home_dir)
+            .args(["--json", "info", "feature"])
+            .output()
+            .expect("run terris info");
+        assert!(output.status.success(), "info failed: {:?}", output);
+        let info: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+        info["changes"]["unpushed"].clone()
+    };
+    assert_eq!(unpushed(), 0);
+    run_git(
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "--allow-empty",
+            "-m",
+            "work",
+        ],
+        &worktree,
+    );
+    assert_eq!(unpushed(), 1);
+
+    // A branch without commits yet has nothing to lose, and can go.
+    run_git(&["checkout", "--orphan", "fresh"], &worktree);
+    run_git(&["rm", "-rq", "--cached", "."], &worktree);
+    std::fs::remove_file(worktree.join("README.md")).expect("remove file");
+    let output = terris(&repo_dir, &home_dir)
+        .args(["delete", "fresh"])
+        .output()
+        .expect("run terris delete");
+    assert!(output.status.success(), "delete failed: {:?}", output);
+    assert!(!worktree.exists());
+}
+
+#[test]
+fn delete_refuses_a_prefix_match_without_a_terminal() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+    for args in [
+        &["delete", "feat"][..],
+        &["--rm", "feat"],
+        &["reset", "feat"],
+    ] {
+        let output = terris(&repo_dir, &home_dir)
+            .args(args)
+            .output()
+            .expect("run terris");
+        assert_eq!(output.status.code(), Some(14), "{:?}: {:?}", args, output);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("'feat' only partly matches feature"),
+            "{}",
+            stderr
+        );
+        assert!(worktree.exists());
+    }
+
+    // Navigation still takes the prefix.
+    let output = terris(&repo_dir, &home_dir)
+        .args(["path", "feat"])
+        .output()
+        .expect("run terris path");
+    assert!(output.status.success(), "path failed: {:?}", output);
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["delete", "feature"])
+        .output()
+        .expect("run terris delete");
+    assert!(output.status.success(), "delete failed: {:?}", output);
+    assert!(!worktree.exists());
+}
+
+#[test]
+fn delete_dirty_worktree_requires_force() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    let worktree = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    std::fs::write(std::path::Path::new(&worktree).join("scratch.txt"), "wip\n")
+        .expect("write untracked file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["delete", "feature"])
+        .output()
+        .expect("run terris delete");
+    assert_eq!(output.status.code(), Some(8));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 untracked file(s)"), "{stderr}");
+    assert!(std::path::Path::new(&worktree).exists());
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["delete", "--force", "feature"])
+        .output()
+        .expect("run terris delete --force");
+    assert!(output.status.success(), "terris delete --force failed");
+    assert!(!std::path::Path::new(&worktree).exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("refs/terris/backup/feature"), "{stderr}");
+
+    let files = Command::new("git")
+        .args([
+            "show",
+            "--name-only",
+            "--format=",
+            "refs/terris/backup/feature",
+        ])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("inspect backup ref");
+    assert_eq!(String::from_utf8_lossy(&files.stdout).trim(), "scratch.txt");
+}
+
+#[test]
+fn delete_archive_writes_tarball_before_removing() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let archive_dir = temp_dir.path().join("archives");
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    let worktree = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["delete", "--archive"])
+        .arg(&archive_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris delete --archive");
+    assert!(output.status.success(), "terris delete --archive failed");
+    assert!(!std::path::Path::new(&worktree).exists());
+
+    let archives: Vec<_> = std::fs::read_dir(&archive_dir)
+        .expect("read archive dir")
+        .map(|entry| entry.expect("dir entry").file_name())
+        .collect();
+    assert_eq!(archives.len(), 1);
+    let name = archives[0].to_string_lossy();
+    assert!(
+        name.starts_with("feature-") && name.ends_with(".tar.zst"),
+        "{name}"
+    );
+}
+
+#[test]
+fn delete_archive_keeps_files_with_non_utf8_names() {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let archive_dir = temp_dir.path().join("archives");
+    run_git(&["branch", "arc"], &repo_dir);
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("arc")
+        .output()
+        .expect("run terris");
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let name = std::ffi::OsStr::from_bytes(b"caf\xe9.txt");
+    std::fs::write(worktree.join(name), "latin-1\n").expect("write file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["delete", "--force", "--archive"])
+        .arg(&archive_dir)
+        .arg("arc")
+        .output()
+        .expect("run terris delete --archive");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!worktree.exists());
+
+    let archive = std::fs::read_dir(&archive_dir)
+        .expect("read archive dir")
+        .next()
+        .expect("an archive")
+        .expect("dir entry")
+        .path();
+    let decoder =
+        zstd::Decoder::new(std::fs::File::open(&archive).expect("open archive")).expect("zstd");
+    let mut tar = tar::Archive::new(decoder);
+    let names: Vec<Vec<u8>> = tar
+        .entries()
+        .expect("read archive")
+        .map(|entry| {
+            let entry = entry.expect("archive entry");
+            entry
+                .path()
+                .expect("entry path")
+                .into_owned()
+                .into_os_string()
+                .into_vec()
+        })
+        .collect();
+    assert!(
+        names.iter().any(|name| name.ends_with(b"/caf\xe9.txt")),
+        "{names:?}"
+    );
+}
+
+#[test]
+fn recent_lists_opened_worktrees_first() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    let worktree = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("recent")
+        .output()
+        .expect("run terris recent");
+    assert!(output.status.success(), "terris recent failed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let first = stdout.lines().nth(1).expect("first row");
+    assert!(first.starts_with("feature"), "{stdout}");
+    assert!(first.contains("ago"), "{stdout}");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["path", "-"])
+        .output()
+        .expect("run terris path -");
+    assert!(output.status.success(), "terris path - failed");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), worktree);
+}
+
+#[test]
+fn copy_without_a_clipboard_still_prints_the_path() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let run = |args: &[&str]| {
+        terris(&repo_dir, &home_dir)
+            .env_remove("DISPLAY")
+            .env_remove("WAYLAND_DISPLAY")
+            .args(args)
+            .output()
+            .expect("run terris")
+    };
+
+    let output = run(&["create", "feature", "--copy"]);
+    assert!(output.status.success(), "create failed: {:?}", output);
+    let worktree = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("could not copy the path"));
+
+    let output = run(&["path", "feature", "--copy", "--dry-run"]);
+    assert!(output.status.success(), "path failed: {:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}\nWould copy {} to the clipboard\n", worktree, worktree)
+    );
+}
+
+#[test]
+fn path_dry_run_records_nothing() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--dry-run", "path", "main"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "path failed: {:?}", output);
+    assert!(!home_dir.join(".terris-worktrees/metadata.json").exists());
+}
+
+#[test]
+fn stable_paths_have_no_random_suffix() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let expected = home_dir.join(".terris-worktrees/repo/feature");
+
+    // A leftover directory at the stable path is a collision.
+    std::fs::create_dir_all(&expected).expect("create leftover dir");
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--stable", "feature"])
+        .output()
+        .expect("run terris --stable");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("already exists"), "{stderr}");
+
+    std::fs::remove_dir(&expected).expect("remove leftover dir");
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--stable", "feature"])
+        .output()
+        .expect("run terris --stable");
+    assert!(output.status.success(), "terris --stable failed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim(), expected.to_string_lossy());
+}
+
+#[test]
+fn relative_prints_paths_from_cwd_or_a_base() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let run = |args: &[&str]| {
+        let output = terris(&repo_dir, &home_dir)
+            .args(args)
+            .output()
+            .expect("run terris");
+        assert!(output.status.success(), "terris failed: {:?}", output);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    run(&["--stable", "feature"]);
+    assert_eq!(
+        run(&["path", "feature", "--relative"]),
+        "../home/.terris-worktrees/repo/feature\n"
+    );
+    let base = format!("--relative={}", home_dir.display());
+    assert_eq!(run(&["path", "main", &base]), "../repo\n");
+    let list = run(&[&base, "--columns", "branch,path", "--output", "tsv"]);
+    assert!(
+        list.contains("feature\t.terris-worktrees/repo/feature\n"),
+        "{list}"
+    );
+    let entries: serde_json::Value =
+        serde_json::from_str(&run(&["--json", &base])).expect("parse list json");
+    let feature = entries
+        .as_array()
+        .and_then(|entries| {
+            entries
+                .iter()
+                .find(|entry| entry["branch"] == "refs/heads/feature")
+        })
+        .expect("feature entry");
+    assert_eq!(
+        feature["path"], ".terris-worktrees/repo/feature",
+        "{entries}"
+    );
+}
+
+#[test]
+fn repo_base_dir_moves_its_worktrees_out_of_the_registry() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "[repos.repo]\nbase-dir = \"fast\"\n").expect("write config");
+    let run = |args: &[&str]| {
+        terris(&repo_dir, &home_dir)
+            .env("TERRIS_CONFIG", &config)
+            .args(args)
+            .output()
+            .expect("run terris")
+    };
+
+    let output = run(&["--stable", "feature"]);
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    let expected = temp_dir.path().join("fast/repo/feature");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        expected.to_string_lossy()
+    );
+    let output = run(&["--output", "tsv"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&*expected.to_string_lossy()));
+
+    let output = run(&["delete", "feature"]);
+    assert!(output.status.success(), "delete failed: {:?}", output);
+    assert!(!expected.exists());
+}
+
+#[test]
+fn config_sets_gets_and_unsets_keys_in_place() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("terris/config.toml");
+    let run = |args: &[&str]| {
+        terris(temp_dir.path(), &home_dir)
+            .env("TERRIS_CONFIG", &config)
+            .arg("config")
+            .args(args)
+            .output()
+            .expect("run terris config")
+    };
+
+    let output = run(&["set", "path-template", "{base}/{repo}/{branch}"]);
+    assert!(output.status.success(), "set failed: {:?}", output);
+    let output = run(&["set", "--repo", "api", "location", "sibling"]);
+    assert!(output.status.success(), "set failed: {:?}", output);
+    let output = run(&["get", "--repo", "api", "location"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "sibling\n");
+
+    let output = run(&["set", "location", "nowhere"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("refusing to set location"));
+
+    let output = run(&["unset", "path-template"]);
+    assert!(output.status.success(), "unset failed: {:?}", output);
+    assert!(!run(&["get", "path-template"]).status.success());
+    assert_eq!(
+        std::fs::read_to_string(&config).expect("read config"),
+        "\n[repos.api]\nlocation = \"sibling\"\n"
+    );
+}
+
+#[test]
+fn completions_install_writes_the_script_and_keeps_it_current() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let run = |args: &[&str]| {
+        terris(&repo_dir, &home_dir)
+            .env_remove("XDG_DATA_HOME")
+            .args(args)
+            .output()
+            .expect("run terris")
+    };
+
+    let output = run(&["completions", "install", "fish"]);
+    assert!(output.status.success(), "install failed: {:?}", output);
+    let script = home_dir.join(".config/fish/completions/terris.fish");
+    let expected = std::fs::read_to_string(&script).expect("read script");
+    assert!(expected.contains("complete -c terris"));
+
+    // What an older terris wrote and recorded.
+    let record = home_dir.join(".terris-worktrees/completions.json");
+    let mut installed: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&record).expect("read record"))
+            .expect("parse record");
+    let fnv = "# old\n"
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    installed["hash"] = format!("{:016x}", fnv).into();
+    std::fs::write(&record, installed.to_string()).expect("write record");
+    std::fs::write(&script, "# old\n").expect("write stale script");
+    let output = run(&["--dry-run", "--all"]);
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    assert_eq!(
+        std::fs::read_to_string(&script).expect("read script"),
+        "# old\n"
+    );
+    let output = run(&["--all"]);
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Updated the fish completions"));
+    assert_eq!(
+        std::fs::read_to_string(&script).expect("read script"),
+        expected
+    );
+
+    // Edited by hand: left alone, and no longer checked.
+    std::fs::write(&script, "# mine\n").expect("edit script");
+    let output = run(&["--all"]);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("were changed since terris wrote them"),
+        "{:?}",
+        output
+    );
+    assert_eq!(
+        std::fs::read_to_string(&script).expect("read script"),
+        "# mine\n"
+    );
+    let output = run(&["--all"]);
+    assert!(output.stderr.is_empty(), "{:?}", output);
+
+    let output = run(&["completions", "install", "fish"]);
+    assert!(output.status.success(), "install failed: {:?}", output);
+
+    std::fs::remove_file(&script).expect("remove script");
+    let output = run(&["--all"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("are gone"));
+    let output = run(&["--all"]);
+    assert!(output.stderr.is_empty(), "{:?}", output);
+
+    for _ in 0..2 {
+        let output = run(&["completions", "install", "bash", "--rc", "--yes"]);
+        assert!(output.status.success(), "install failed: {:?}", output);
+    }
+    let bashrc = std::fs::read_to_string(home_dir.join(".bashrc")).expect("read .bashrc");
+    assert_eq!(
+        bashrc,
+        "# terris completions\neval \"$(terris --completions bash)\"\n"
+    );
+}
+
+#[test]
+fn help_topics_and_man_pages() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let home_dir = temp_dir.path().join("home");
+    let run = |args: &[&str]| {
+        terris(temp_dir.path(), &home_dir)
+            .args(args)
+            .output()
+            .expect("run terris")
+    };
+
+    let output = run(&["help", "naming"]);
+    assert!(output.status.success(), "help failed: {:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("{branch_slug}"));
+    let output = run(&["help", "pool", "acquire"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Usage: terris pool acquire"));
+    let output = run(&["help", "nonsense"]);
+    assert!(!output.status.success());
+
+    let man_dir = temp_dir.path().join("man");
+    let output = run(&["man", "--out-dir", man_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "man failed: {:?}", output);
+    for page in [
+        "terris.1",
+        "terris-create.1",
+        "terris-pool-acquire.1",
+        "terris-hooks.7",
+    ] {
+        assert!(man_dir.join(page).is_file(), "no {}", page);
+    }
+    assert!(!man_dir.join("terris-man.1").exists());
+}
+
+#[test]
+fn in_repo_location_is_excluded_from_status() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "[repos.repo]\nlocation = \"in-repo\"\n").expect("write config");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout.trim(),
+        repo_dir.join(".worktrees/feature").to_string_lossy()
+    );
+
+    let status = Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git status");
+    assert!(String::from_utf8_lossy(&status.stdout).trim().is_empty());
+}
+
+#[test]
+fn ephemeral_worktree_is_removed_when_shell_exits() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let marker = temp_dir.path().join("shell-ran-in");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("SHELL", "/bin/sh")
+        .args(["create", "--ephemeral", "spike"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            let script = format!("pwd > '{}'\n", marker.display());
+            child.stdin.take().unwrap().write_all(script.as_bytes())?;
+            child.wait_with_output()
+        })
+        .expect("run terris create --ephemeral");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let shell_dir = std::fs::read_to_string(&marker).expect("shell ran");
+    assert!(shell_dir.contains("spike-"), "{shell_dir}");
+    assert!(!std::path::Path::new(shell_dir.trim()).exists());
+    let branches = Command::new("git")
+        .args(["branch", "--list", "spike"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git branch");
+    assert!(String::from_utf8_lossy(&branches.stdout).trim().is_empty());
+}
+
+#[test]
+fn ephemeral_branch_with_commits_is_kept() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("SHELL", "/bin/sh")
+        .args(["create", "--ephemeral", "spike"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            let script = "echo idea > idea.txt && git add idea.txt && \
+                git -c user.name=Test -c user.email=test@example.com commit -qm idea\n";
+            child.stdin.take().unwrap().write_all(script.as_bytes())?;
+            child.wait_with_output()
+        })
+        .expect("run terris create --ephemeral");
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Kept branch spike: it has 1 commit(s) no other branch has"),
+        "{stderr}"
+    );
+    let log = Command::new("git")
+        .args(["log", "-1", "--format=%s", "spike"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git log");
+    assert_eq!(String::from_utf8_lossy(&log.stdout).trim(), "idea");
+}
+
+#[test]
+fn with_runs_command_in_throwaway_worktree_and_keeps_exit_code() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["tag", "v1"], &repo_dir);
+
+    let output = terris(&repo_dir, &home_dir)
+        .args([
+            "with",
+            "v1",
+            "--",
+            "sh",
+            "-c",
+            "test -f README.md && exit 3",
+        ])
+        .output()
+        .expect("run terris with");
+    assert_eq!(output.status.code(), Some(3));
+
+    let list = Command::new("git")
+        .args(["worktree", "list", "--porcelain"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git worktree list");
+    let list = String::from_utf8_lossy(&list.stdout);
+    assert_eq!(list.matches("worktree ").count(), 1, "{list}");
+}
+
+#[test]
+fn with_cleans_up_when_interrupted() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    // What ^C and a polite kill would do to terris while the command runs.
+    let output = terris(&repo_dir, &home_dir)
+        .args([
+            "with",
+            "HEAD",
+            "--",
+            "sh",
+            "-c",
+            "sleep 0.5; kill -INT $PPID; kill -TERM $PPID; sleep 0.2",
+        ])
+        .output()
+        .expect("run terris with");
+    assert!(output.status.success(), "{:?}", output);
+
+    let list = Command::new("git")
+        .args(["worktree", "list", "--porcelain"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git worktree list");
+    let list = String::from_utf8_lossy(&list.stdout);
+    assert_eq!(list.matches("worktree ").count(), 1, "{list}");
+}
+
+#[test]
+fn pool_release_resets_and_reuses_worktrees() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let acquire = || {
+        let output = terris(&repo_dir, &home_dir)
+            .args(["pool", "acquire"])
+            .output()
+            .expect("run terris pool acquire");
+        assert!(output.status.success(), "pool acquire failed");
+        std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim())
+    };
+
+    let first = acquire();
+    assert!(first.ends_with("repo/pool-1"), "{}", first.display());
+    let second = acquire();
+    assert!(second.ends_with("repo/pool-2"), "{}", second.display());
+
+    std::fs::write(first.join("README.md"), "changed\n").expect("modify file");
+    std::fs::write(first.join("scratch.txt"), "x\n").expect("write file");
+    let output = terris(&repo_dir, &home_dir)
+        .args(["pool", "release", "1"])
+        .output()
+        .expect("run terris pool release");
+    assert!(output.status.success(), "pool release failed");
+    let output = terris(&repo_dir, &home_dir)
+        .args(["pool", "release", "1"])
+        .output()
+        .expect("run terris pool release");
+    assert!(!output.status.success(), "released twice");
+
+    assert_eq!(acquire(), first);
+    let readme = std::fs::read_to_string(first.join("README.md")).expect("read file");
+    assert_eq!(readme, "test\n");
+    assert!(!first.join("scratch.txt").exists());
+}
+
+#[test]
+fn reset_discards_changes_and_reruns_hooks() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        "[hooks]\npost-create = [\"echo hook >> hook.log\"]\n",
+    )
+    .expect("write config");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let worktree = std::path::PathBuf::from(stdout.trim());
+    assert_eq!(stdout.lines().count(), 1, "hook output leaked: {stdout}");
+    assert!(worktree.join("hook.log").exists());
+
+    std::fs::write(worktree.join("README.md"), "changed\n").expect("modify file");
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["reset", "feature"])
+        .output()
+        .expect("run terris reset");
+    assert_eq!(output.status.code(), Some(8), "reset without a terminal");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["reset", "feature", "--force", "--hooks"])
+        .output()
+        .expect("run terris reset --force");
+    assert!(output.status.success(), "reset --force failed");
+    let readme = std::fs::read_to_string(worktree.join("README.md")).expect("read file");
+    assert_eq!(readme, "test\n");
+    let log = std::fs::read_to_string(worktree.join("hook.log")).expect("read hook log");
+    assert_eq!(log, "hook\n");
+}
+
+#[test]
+fn checkout_switches_branch_in_place() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let worktree = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["checkout", "feature", "topic"])
+        .output()
+        .expect("run terris checkout");
+    assert!(output.status.success(), "terris checkout failed");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), worktree);
+    let head = Command::new("git")
+        .args(["branch", "--show-current"])
+        .current_dir(&worktree)
+        .output()
+        .expect("run git branch");
+    assert_eq!(String::from_utf8_lossy(&head.stdout).trim(), "topic");
+
+    let main = Command::new("git")
+        .args(["branch", "--show-current"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git branch");
+    let main = String::from_utf8_lossy(&main.stdout).trim().to_string();
+    let output = terris(&repo_dir, &home_dir)
+        .args(["checkout", "topic", &main])
+        .output()
+        .expect("run terris checkout");
+    assert_eq!(output.status.code(), Some(9));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&repo_dir.to_string_lossy().to_string()),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn branch_held_by_rebasing_worktree_is_reused() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+    // Stop an interactive rebase on the branch so its worktree lists as detached.
+    std::fs::write(worktree.join("a.txt"), "a\n").expect("write file");
+    run_git(&["add", "a.txt"], &worktree);
+    run_git(
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-qm",
+            "a",
+        ],
+        &worktree,
+    );
+    run_git_with_env(
+        &["rebase", "-q", "-i", "HEAD~1"],
+        &worktree,
+        &[("GIT_SEQUENCE_EDITOR", "sed -i s/^pick/edit/")],
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        worktree.to_string_lossy()
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("already checked out"), "{stderr}");
+}
+
+#[test]
+fn create_carry_moves_changes_to_new_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::write(repo_dir.join("README.md"), "changed\n").expect("modify file");
+    run_git(&["add", "README.md"], &repo_dir);
+    std::fs::write(repo_dir.join("notes.txt"), "new\n").expect("write file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["create", "wip", "--carry", "-u"])
+        .output()
+        .expect("run terris create --carry");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+    let status = |dir: &std::path::Path| {
+        let output = Command::new("git")
+            .args(["status", "--porcelain"])
+            .current_dir(dir)
+            .output()
+            .expect("run git status");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    assert_eq!(status(&repo_dir), "");
+    assert_eq!(status(&worktree), "M  README.md\n?? notes.txt\n");
+    let stashes = Command::new("git")
+        .args(["stash", "list"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git stash list");
+    assert!(stashes.stdout.is_empty());
+}
+
+#[test]
+fn create_from_stash_applies_it_on_its_base() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::write(repo_dir.join("README.md"), "parked\n").expect("modify file");
+    run_git(&["stash", "push", "--quiet"], &repo_dir);
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["create", "revived", "--from-stash"])
+        .output()
+        .expect("run terris create --from-stash");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let readme = std::fs::read_to_string(worktree.join("README.md")).expect("read file");
+    assert_eq!(readme, "parked\n");
+    let stashes = Command::new("git")
+        .args(["stash", "list"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git stash list");
+    assert!(stashes.stdout.is_empty());
+}
+
+#[test]
+fn create_apply_reads_patch_from_stdin() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::write(repo_dir.join("README.md"), "patched\n").expect("modify file");
+    let diff = Command::new("git")
+        .args(["diff"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git diff");
+    run_git(&["checkout", "--", "README.md"], &repo_dir);
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["create", "feature", "--apply", "-"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            child.stdin.take().unwrap().write_all(&diff.stdout)?;
+            child.wait_with_output()
+        })
+        .expect("run terris create --apply");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let readme = std::fs::read_to_string(worktree.join("README.md")).expect("read file");
+    assert_eq!(readme, "patched\n");
+
+    // A patch never lands on top of an existing worktree's work.
+    let patch = temp_dir.path().join("readme.patch");
+    std::fs::write(&patch, &diff.stdout).expect("write patch");
+    let output = terris(&repo_dir, &home_dir)
+        .args(["create", "feature", "--apply"])
+        .arg(&patch)
+        .output()
+        .expect("run terris create --apply");
+    assert_eq!(output.status.code(), Some(9), "{:?}", output);
+}
+
+#[test]
+fn diff_compares_working_trees() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    std::fs::write(worktree.join("README.md"), "edited\n").expect("modify file");
+    std::fs::write(worktree.join("new.txt"), "new\n").expect("write file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["diff", "main", "feature"])
+        .env("GIT_PAGER", "cat")
+        .output()
+        .expect("run terris diff");
+    assert!(output.status.success(), "terris diff failed");
+    let diff = String::from_utf8_lossy(&output.stdout);
+    assert!(diff.contains("+edited"), "{diff}");
+    assert!(diff.contains("b/new.txt"), "{diff}");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["diff", "main", "feature", "--", "new.txt"])
+        .env("GIT_PAGER", "cat")
+        .output()
+        .expect("run terris diff");
+    let diff = String::from_utf8_lossy(&output.stdout);
+    assert!(!diff.contains("README.md"), "{diff}");
+
+    let status = Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(&worktree)
+        .output()
+        .expect("run git status");
+    assert_eq!(
+        String::from_utf8_lossy(&status.stdout),
+        " M README.md\n?? new.txt\n"
+    );
+}
+
+#[test]
+fn graduate_squashes_into_base_and_removes_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    std::fs::write(worktree.join("feature.txt"), "done\n").expect("write file");
+    run_git(&["add", "feature.txt"], &worktree);
+    run_git(
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-qm",
+            "feature",
+        ],
+        &worktree,
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["graduate", "feature", "--squash"])
+        .env("GIT_AUTHOR_NAME", "Test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .output()
+        .expect("run terris graduate");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(repo_dir.join("feature.txt").exists());
+    assert!(!worktree.exists());
+    let branches = Command::new("git")
+        .args(["branch", "--list", "feature"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git branch");
+    assert!(branches.stdout.is_empty());
+}
+
+#[test]
+fn rebase_all_rebases_clean_worktrees_onto_base() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "dirty"], &repo_dir);
+    let mut paths = Vec::new();
+    for branch in ["feature", "dirty"] {
+        let output = terris(&repo_dir, &home_dir)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(output.status.success(), "terris failed");
+        paths.push(std::path::PathBuf::from(
+            String::from_utf8_lossy(&output.stdout).trim(),
+        ));
+    }
+    std::fs::write(paths[1].join("wip.txt"), "wip\n").expect("write file");
+    std::fs::write(repo_dir.join("base.txt"), "base\n").expect("write file");
+    run_git(&["add", "base.txt"], &repo_dir);
+    run_git(
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-qm",
+            "base",
+        ],
+        &repo_dir,
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("rebase-all")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .output()
+        .expect("run terris rebase-all");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Rebased feature onto"), "{}", stdout);
+    assert!(
+        stdout.contains("Skipped dirty: uncommitted changes"),
+        "{}",
+        stdout
+    );
+    assert!(paths[0].join("base.txt").exists());
+    assert!(!paths[1].join("base.txt").exists());
+}
+
+#[test]
+fn sync_fast_forwards_clean_worktrees_after_one_fetch() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let origin = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let clone = temp_dir.path().join("clone");
+    let status = Command::new("git")
+        .args(["clone", "-q"])
+        .arg(&origin)
+        .arg(&clone)
+        .status()
+        .expect("run git clone");
+    assert!(status.success());
+    run_git(&["branch", "-q", "feature", "origin/feature"], &clone);
+    let output = terris(&clone, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    std::fs::write(worktree.join("wip.txt"), "wip\n").expect("write file");
+
+    std::fs::write(origin.join("new.txt"), "new\n").expect("write file");
+    run_git(&["add", "new.txt"], &origin);
+    run_git(
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-qm",
+            "new",
+        ],
+        &origin,
+    );
+    run_git(&["branch", "-f", "feature", "HEAD"], &origin);
+
+    let output = terris(&clone, &home_dir)
+        .arg("sync")
+        .output()
+        .expect("run terris sync");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(": 1 new commit(s)"), "{}", stdout);
+    assert!(
+        stdout.contains("Skipped feature: uncommitted changes (1 behind)"),
+        "{}",
+        stdout
+    );
+    assert!(clone.join("new.txt").exists());
+    assert!(!worktree.join("new.txt").exists());
+}
+
+#[test]
+fn stack_restacks_child_after_parent_is_amended() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let commit = |dir: &std::path::Path, file: &str, args: &[&str]| {
+        std::fs::write(dir.join(file), format!("{}\n", file)).expect("write file");
+        run_git(&["add", file], dir);
+        let mut full = vec![
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-q",
+        ];
+        full.extend_from_slice(args);
+        run_git(&full, dir);
+    };
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let parent = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    commit(&parent, "part1.txt", &["-m", "part1"]);
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["create", "part2", "--on", "feature"])
+        .output()
+        .expect("run terris create --on");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let child = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    assert!(child.join("part1.txt").exists());
+    commit(&child, "part2.txt", &["-m", "part2"]);
+
+    // Rewrite the parent's commit; the child still sits on the old one.
+    commit(&parent, "fixup.txt", &["--amend", "-m", "part1"]);
+    let output = terris(&repo_dir, &home_dir)
+        .arg("stack")
+        .output()
+        .expect("run terris stack");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("feature\n  part2  "), "{}", stdout);
+    assert!(stdout.contains("(needs restack)"), "{}", stdout);
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["stack", "--restack"])
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .output()
+        .expect("run terris stack --restack");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(child.join("fixup.txt").exists());
+    let log = Command::new("git")
+        .args(["log", "--format=%s", "feature..part2"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git log");
+    assert_eq!(String::from_utf8_lossy(&log.stdout), "part2\n");
+}
+
+#[test]
+fn du_separates_ignored_artifacts() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::write(repo_dir.join(".gitignore"), "target/\n").expect("write file");
+    std::fs::create_dir(repo_dir.join("target")).expect("create dir");
+    std::fs::write(repo_dir.join("target/out.bin"), vec![0u8; 4096]).expect("write file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["du", "--json"])
+        .output()
+        .expect("run terris du");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let entries: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+    let main = &entries[0];
+    assert_eq!(main["main"], true);
+    assert_eq!(main["ignored"], 4096);
+    // README.md and .gitignore.
+    assert_eq!(main["files"], 5 + 8);
+}
+
+#[test]
+fn clean_artifacts_removes_ignored_build_output_only() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::write(repo_dir.join(".gitignore"), "target/\nnode_modules/\n").expect("write file");
+    std::fs::create_dir_all(repo_dir.join("target/debug")).expect("create dir");
+    std::fs::write(repo_dir.join("target/debug/app"), vec![0u8; 2048]).expect("write file");
+    std::fs::create_dir_all(repo_dir.join("web/node_modules/left-pad")).expect("create dir");
+    std::fs::write(repo_dir.join("web/node_modules/left-pad/index.js"), "x\n").expect("write file");
+    std::fs::create_dir(repo_dir.join("dist")).expect("create dir");
+    std::fs::write(repo_dir.join("dist/keep.txt"), "keep\n").expect("write file");
+    run_git(&["add", "dist/keep.txt"], &repo_dir);
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("clean-artifacts")
+        .output()
+        .expect("run terris clean-artifacts");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Kept dist in"), "{}", stdout);
+    assert!(stdout.contains("Reclaimed 2.0K"), "{}", stdout);
+    assert!(!repo_dir.join("target").exists());
+    assert!(!repo_dir.join("web/node_modules").exists());
+    assert!(repo_dir.join("dist/keep.txt").exists());
+}
+
+#[test]
+fn shared_cargo_target_points_new_worktrees_at_primary_target() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::write(
+        repo_dir.join("Cargo.toml"),
+        "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n",
+    )
+    .expect("write file");
+    run_git(&["add", "Cargo.toml"], &repo_dir);
+    run_git(
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-qm",
+            "cargo",
+        ],
+        &repo_dir,
+    );
+    run_git(&["branch", "rusty"], &repo_dir);
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "shared-cargo-target = true\n").expect("write config");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("rusty")
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let cargo_config =
+        std::fs::read_to_string(worktree.join(".cargo/config.toml")).expect("read cargo config");
+    let target = repo_dir.join("target");
+    assert!(
+        cargo_config.contains(&format!("target-dir = \"{}\"", target.display())),
+        "{}",
+        cargo_config
+    );
+    let status = Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(&worktree)
+        .output()
+        .expect("run git status");
+    assert!(status.stdout.is_empty());
+}
+
+#[test]
+fn direnv_writes_and_allows_envrc() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::write(repo_dir.join(".envrc"), "export FOO=1\n").expect("write file");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "direnv = true\n").expect("write config");
+    // A stand-in direnv that records what it was asked to allow.
+    let bin = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin).expect("create dir");
+    let allowed = temp_dir.path().join("allowed");
+    let script = bin.join("direnv");
+    std::fs::write(
+        &script,
+        format!("#!/bin/sh\necho \"$@\" >> '{}'\n", allowed.display()),
+    )
+    .expect("write script");
+    std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+        .expect("chmod script");
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .env("PATH", path)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let envrc = std::fs::read_to_string(worktree.join(".envrc")).expect("read .envrc");
+    assert!(
+        envrc.contains("export TERRIS_BRANCH='feature'"),
+        "{}",
+        envrc
+    );
+    assert!(
+        envrc.contains(&format!(
+            "source_env '{}'",
+            repo_dir.join(".envrc").display()
+        )),
+        "{}",
+        envrc
+    );
+    let allowed = std::fs::read_to_string(&allowed).expect("read allowed");
+    assert_eq!(allowed.trim(), format!("allow {}", worktree.display()));
+}
+
+#[test]
+fn create_devcontainer_links_config_and_starts_container() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::create_dir(repo_dir.join(".devcontainer")).expect("create dir");
+    std::fs::write(repo_dir.join(".devcontainer/devcontainer.json"), "{}\n").expect("write file");
+    let bin = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin).expect("create dir");
+    let calls = temp_dir.path().join("calls");
+    let script = bin.join("devcontainer");
+    std::fs::write(
+        &script,
+        format!("#!/bin/sh\necho \"$@\" >> '{}'\n", calls.display()),
+    )
+    .expect("write script");
+    std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+        .expect("chmod script");
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("PATH", path)
+        .args(["create", "feature", "--devcontainer=up"])
+        .output()
+        .expect("run terris create");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    assert!(worktree.join(".devcontainer/devcontainer.json").exists());
+    let calls = std::fs::read_to_string(&calls).expect("read calls");
+    assert!(
+        calls.starts_with(&format!("up --workspace-folder {}", worktree.display())),
+        "{}",
+        calls
+    );
+    let status = Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(&worktree)
+        .output()
+        .expect("run git status");
+    assert!(status.stdout.is_empty());
+}
+
+#[test]
+fn create_open_starts_a_shell_in_the_new_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let opened = temp_dir.path().join("opened");
+    let shell = temp_dir.path().join("shell");
+    std::fs::write(
+        &shell,
+        format!(
+            "#!/bin/sh\necho \"$PWD $TERRIS_BRANCH\" >> '{}'\n",
+            opened.display()
+        ),
+    )
+    .expect("write script");
+    std::fs::set_permissions(&shell, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+        .expect("chmod script");
+    run_git(&["branch", "other"], &repo_dir);
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "open = \"shell\"\n").expect("write config");
+    let create = |args: &[&str]| {
+        terris(&repo_dir, &home_dir)
+            .env("SHELL", &shell)
+            .env("TERRIS_CONFIG", &config)
+            .arg("create")
+            .args(args)
+            .output()
+            .expect("run terris create")
+    };
+
+    // Without a terminal, the config default alone opens nothing.
+    let output = create(&["feature"]);
+    assert!(output.status.success(), "create failed: {:?}", output);
+    assert!(!opened.exists());
+
+    let output = create(&["other", "--open"]);
+    assert!(output.status.success(), "create failed: {:?}", output);
+    let worktree = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let opened = std::fs::read_to_string(&opened).expect("read opened");
+    assert_eq!(opened.trim(), format!("{} other", worktree));
+
+    let output = create(&["--open", "third"]);
+    assert!(!output.status.success(), "--open takes its action after =");
+}
+
+#[test]
+fn commands_in_worktrees_see_terris_env() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let out = temp_dir.path().join("env.txt");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[hooks]\npost-create = [\"echo $TERRIS_BRANCH $TERRIS_REPO > '{}'\"]\n",
+            out.display()
+        ),
+    )
+    .expect("write config");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let seen = std::fs::read_to_string(&out).expect("read hook output");
+    assert_eq!(seen.trim(), format!("feature {}", repo_dir.display()));
+
+    let script = format!(
+        "echo ${{TERRIS_BRANCH:-none}} $TERRIS_PATH $PWD > '{}'",
+        out.display()
+    );
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_BRANCH", "stale")
+        .args(["with", "HEAD", "--", "sh", "-c", &script])
+        .output()
+        .expect("run terris with");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let seen = std::fs::read_to_string(&out).expect("read command output");
+    let fields: Vec<&str> = seen.split_whitespace().collect();
+    assert_eq!(fields[0], "none");
+    assert_eq!(fields[1], fields[2]);
+}
+
+#[test]
+fn port_allocates_stable_ports_and_exports_them_to_hooks() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let out = temp_dir.path().join("port.txt");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[hooks]\npost-create = [\"echo $TERRIS_PORT_WEB > '{}'\"]\n",
+            out.display()
+        ),
+    )
+    .expect("write config");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+
+    let port = |args: &[&str]| -> u16 {
+        let output = terris(&repo_dir, &home_dir)
+            .arg("port")
+            .args(args)
+            .output()
+            .expect("run terris port");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .expect("port number")
+    };
+    let base = port(&["feature"]);
+    assert!((20000..30000).contains(&base));
+    assert_eq!(port(&["feature"]), base);
+    assert_eq!(port(&["feature", "web"]), base + 1);
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--dry-run", "port", "feature", "api"])
+        .output()
+        .expect("run terris port");
+    assert!(output.status.success(), "dry run failed: {:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with(&format!("Would reserve port {} for ", base + 2)),
+        "{}",
+        stdout
+    );
+    assert_eq!(port(&["feature", "db"]), base + 2);
+    assert_eq!(port(&["feature", "web"]), base + 1);
+    assert_ne!(port(&["main"]), base);
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["reset", "feature", "--hooks"])
+        .output()
+        .expect("run terris reset");
+    assert!(output.status.success(), "terris reset failed");
+    let seen = std::fs::read_to_string(&out).expect("read hook output");
+    assert_eq!(seen.trim(), (base + 1).to_string());
+}
+
+#[test]
+fn hooks_get_context_and_pre_delete_can_keep_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let context = temp_dir.path().join("context.json");
+    let deleted = temp_dir.path().join("deleted.txt");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[hooks]\npost-create = [\"cat > '{}'\"]\npre-delete = [\"echo $TERRIS_HOOK $TERRIS_DESCRIPTION > '{}'; exit 1\"]\n",
+            context.display(),
+            deleted.display()
+        ),
+    )
+    .expect("write config");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["create", "feature", "--description", "Fix login"])
+        .output()
+        .expect("run terris create");
+    assert!(output.status.success(), "terris create failed");
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let context: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&context).expect("read context"))
+            .expect("parse context");
+    assert_eq!(context["hook"], "post-create");
+    assert_eq!(context["branch"], "feature");
+    assert_eq!(context["description"], "Fix login");
+    assert_eq!(context["ephemeral"], false);
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["delete", "feature"])
+        .output()
+        .expect("run terris delete");
+    assert!(!output.status.success());
+    assert!(worktree.exists());
+    let seen = std::fs::read_to_string(&deleted).expect("read hook output");
+    assert_eq!(seen.trim(), "pre-delete Fix login");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["delete", "--force", "feature"])
+        .output()
+        .expect("run terris delete --force");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!worktree.exists());
+}
+
+#[test]
+fn unknown_subcommands_run_plugins_from_path() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let bin = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin).expect("create dir");
+    let plugins = [
+        (
+            "terris-hello",
+            "#!/bin/sh\necho \"$TERRIS_BRANCH $*\"\n[ \"$1\" = fail ] && exit 3\nexit 0\n",
+        ),
+        // Shadowed by the branch of the same name.
+        ("terris-feature", "#!/bin/sh\necho plugin\n"),
+    ];
+    for (name, body) in plugins {
+        let script = bin.join(name);
+        std::fs::write(&script, body).expect("write script");
+        std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .expect("chmod script");
+    }
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("PATH", &path)
+        .args(["hello", "a", "--b"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "master a --b\n");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("PATH", &path)
+        .args(["hello", "fail"])
+        .output()
+        .expect("run terris");
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("PATH", &path)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    assert!(worktree.is_dir(), "{}", worktree.display());
+}
+
+#[test]
+fn rhai_scripts_name_worktrees_run_hooks_and_pick_gc_victims() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "unmerged"], &repo_dir);
+    let hook = temp_dir.path().join("hook.rhai");
+    std::fs::write(
+        &hook,
+        "if ctx.hook != \"post-create\" { throw \"wrong hook\" }\nsh(\"touch from-hook\");\n",
+    )
+    .expect("write hook");
+    std::fs::write(
+        temp_dir.path().join("naming.rhai"),
+        "\"{repo_parent}/wt-\" + create.branch\n",
+    )
+    .expect("write naming script");
+    std::fs::write(temp_dir.path().join("gc.rhai"), "wt.merged && !wt.dirty\n")
+        .expect("write policy");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "naming-script = \"naming.rhai\"\n[hooks]\npost-create = ['{}']\n[gc]\npolicy = \"gc.rhai\"\n",
+            hook.display()
+        ),
+    )
+    .expect("write config");
+
+    for branch in ["feature", "unmerged"] {
+        let output = terris(&repo_dir, &home_dir)
+            .env("TERRIS_CONFIG", &config)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let worktree = temp_dir.path().join(format!("wt-{}", branch));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            worktree.display().to_string()
+        );
+        assert!(worktree.join("from-hook").exists());
+    }
+    let unmerged = temp_dir.path().join("wt-unmerged");
+    std::fs::write(unmerged.join("from-hook"), "x\n").expect("write file");
+    run_git(&["add", "from-hook"], &unmerged);
+    run_git_with_env(
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-q",
+            "-m",
+            "work",
+        ],
+        &unmerged,
+        &[],
+    );
+    std::fs::remove_file(temp_dir.path().join("wt-feature/from-hook")).expect("remove file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("gc")
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!temp_dir.path().join("wt-feature").exists());
+    assert!(unmerged.exists());
+}
+
+#[test]
+fn retention_limits_remove_old_worktrees_by_branch_pattern() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "review/fix"], &repo_dir);
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        "stable = true\n[retention]\nephemeral = \"1d\"\ndefault = \"60d\"\n[retention.branches]\n\"review/*\" = \"1d\"\n",
+    )
+    .expect("write config");
+    for branch in ["feature", "review/fix"] {
+        let output = terris(&repo_dir, &home_dir)
+            .env("TERRIS_CONFIG", &config)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    // Both were last used two days ago; feature is ephemeral, and the
+    // process using it (this test) is still running.
+    let store = home_dir.join(".terris-worktrees/metadata.json");
+    let mut metadata: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&store).expect("read metadata"))
+            .expect("parse metadata");
+    let two_days_ago = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("clock")
+        .as_secs()
+        - 2 * 86_400;
+    for (path, meta) in metadata["worktrees"]
+        .as_object_mut()
+        .expect("worktrees")
+        .iter_mut()
+    {
+        meta["last_used"] = two_days_ago.into();
+        if path.ends_with("/feature") {
+            meta["ephemeral"] = true.into();
+            meta["pid"] = std::process::id().into();
+        }
+    }
+    std::fs::write(&store, metadata.to_string()).expect("write metadata");
+    let review = home_dir.join(".terris-worktrees/repo/review/fix");
+    let feature = home_dir.join(".terris-worktrees/repo/feature");
+    // Worktrees terris did not create are never collected, however old.
+    let plain = temp_dir.path().join("plain");
+    run_git(
+        &[
+            "worktree",
+            "add",
+            "-b",
+            "review/plain",
+            plain.to_str().unwrap(),
+        ],
+        &repo_dir,
+    );
+    let touched = Command::new("touch")
+        .args(["-d", "3 days ago"])
+        .arg(&plain)
+        .status()
+        .expect("run touch");
+    assert!(touched.success());
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["--dry-run", "gc"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Would remove review/fix (unused for 2d, over the 1d limit for review/*)"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("Would remove feature"), "{}", stdout);
+    assert!(!stdout.contains("review/plain"), "{}", stdout);
+    assert!(review.exists());
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("gc")
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!review.exists());
+    assert!(feature.exists());
+    assert!(plain.exists());
+}
+
+#[test]
+fn gc_notifies_about_long_unused_uncommitted_changes() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "stable = true\n[notify]\nenabled = true\n").expect("write config");
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let worktree = home_dir.join(".terris-worktrees/repo/feature");
+    std::fs::write(worktree.join("README.md"), "changed\n").expect("write file");
+    let store = home_dir.join(".terris-worktrees/metadata.json");
+    let mut metadata: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&store).expect("read metadata"))
+            .expect("parse metadata");
+    let month_ago = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("clock")
+        .as_secs()
+        - 30 * 86_400;
+    metadata["worktrees"][worktree.display().to_string()]["last_used"] = month_ago.into();
+    std::fs::write(&store, metadata.to_string()).expect("write metadata");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["--dry-run", "gc"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Would notify: terris: 1 worktree(s) in repo need attention\nUncommitted changes: feature (30d)"),
+        "{}",
+        stdout
+    );
+
+    // Without a desktop to notify, gc still succeeds.
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .env_remove("DBUS_SESSION_BUS_ADDRESS")
+        .arg("gc")
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn info_shows_git_state_and_metadata_for_one_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--description", "try the new parser", "feature"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let worktree = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    run_git(
+        &["worktree", "lock", "--reason", "on a USB drive", &worktree],
+        &repo_dir,
+    );
+    std::fs::write(std::path::Path::new(&worktree).join("notes.txt"), "x\n").expect("write file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--json", "info", "feature"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse info");
+    assert_eq!(info["path"], worktree.as_str());
+    assert_eq!(info["lock_reason"], "on a USB drive");
+    assert_eq!(info["changes"]["untracked"], 1);
+    assert_eq!(info["commit"]["summary"], "init");
+    assert_eq!(info["metadata"]["description"], "try the new parser");
+    assert!(info["metadata"]["created"].is_i64());
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["info", "feature"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("locked      on a USB drive"), "{}", stdout);
+    assert!(
+        stdout.contains("unsaved     1 untracked file(s)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("description try the new parser"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn current_names_the_worktree_containing_the_directory() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let nested = worktree.join("src");
+    std::fs::create_dir(&nested).expect("create dir");
+
+    let output = terris(&nested, &home_dir)
+        .args(["current", "--branch"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "feature\n");
+
+    let output = terris(&nested, &home_dir)
+        .args(["--json", "current"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let current: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+    assert_eq!(current["path"], worktree.display().to_string());
+    assert_eq!(current["main"], false);
+
+    let outside = temp_dir.path().join("elsewhere");
+    std::fs::create_dir(&outside).expect("create dir");
+    let output = terris(&outside, &home_dir)
+        .args(["current", "--path"])
+        .output()
+        .expect("run terris");
+    assert_eq!(output.status.code(), Some(10));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn root_prints_the_primary_worktree_and_git_dir_from_a_linked_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+    for (args, expected) in [
+        (&["root"][..], repo_dir.clone()),
+        (&["root", "--git-dir"][..], repo_dir.join(".git")),
+    ] {
+        for dir in [&repo_dir, &worktree] {
+            let output = terris(dir, &home_dir)
+                .args(args)
+                .output()
+                .expect("run terris");
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout).trim(),
+                expected.display().to_string()
+            );
+        }
+    }
+}
+
+#[test]
+fn prompt_prints_repo_branch_and_state_segment() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    // Branches resolve through packed-refs too.
+    run_git(&["pack-refs", "--all"], &repo_dir);
+    std::fs::write(worktree.join("scratch.txt"), "x\n").expect("write file");
+
+    let output = terris(&worktree, &home_dir)
+        .arg("prompt")
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "repo:feature✗\n");
+    let cache =
+        std::fs::read_to_string(home_dir.join(".terris-worktrees/cache.json")).expect("read cache");
+    assert!(cache.contains("\"untracked\": 1"), "{}", cache);
+
+    let output = terris(temp_dir.path(), &home_dir)
+        .arg("prompt")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn prompt_starship_stays_silent_in_the_primary_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+    let starship = |dir: &std::path::Path| {
+        let output = terris(dir, &home_dir)
+            .args(["prompt", "--starship"])
+            .output()
+            .expect("run terris");
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert_eq!(starship(&worktree), "repo:feature\n");
+    assert_eq!(starship(&repo_dir), "");
+    assert_eq!(starship(temp_dir.path()), "");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["prompt", "--starship-config"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let module = String::from_utf8_lossy(&output.stdout);
+    assert!(module.starts_with("[custom.terris]\n"), "{}", module);
+    assert!(module.contains("command = \"terris prompt --starship\""));
+}
+
+#[test]
+fn list_colors_only_when_asked_or_on_a_terminal() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+
+    // Piped output stays plain, and NO_COLOR is no reason to color either.
+    for color in ["auto", "never"] {
+        let output = terris(&repo_dir, &home_dir)
+            .args(["--color", color])
+            .output()
+            .expect("run terris");
+        assert!(output.status.success());
+        assert!(!String::from_utf8_lossy(&output.stdout).contains('\x1b'));
+    }
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--color", "always"])
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\x1b[32mfeature"), "{}", stdout);
+    assert!(
+        stdout
+            .lines()
+            .next()
+            .is_some_and(|header| !header.contains('\x1b'))
+    );
+}
+
+#[test]
+fn list_is_not_paged_when_stdout_is_not_a_terminal() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    for args in [&["--all"][..], &["--all", "--no-pager"][..]] {
+        let output = terris(&repo_dir, &home_dir)
+            .args(args)
+            .env("PAGER", "false")
+            .output()
+            .expect("run terris");
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with("ID NAME"), "{}", stdout);
+        assert!(stdout.contains(" master "), "{}", stdout);
+    }
+}
+
+#[test]
+fn list_leaves_an_unreadable_metadata_store_alone() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let store = home_dir.join(".terris-worktrees/metadata.json");
+    std::fs::create_dir_all(store.parent().unwrap()).expect("create registry");
+    std::fs::write(&store, "{ not json").expect("write metadata");
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("--all")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(" master "), "{}", stdout);
+    assert_eq!(
+        std::fs::read_to_string(&store).expect("read metadata"),
+        "{ not json"
+    );
+}
+
+#[test]
+fn list_aligns_wide_characters_by_display_width() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "功能"], &repo_dir);
+    for branch in ["feature", "功能"] {
+        let output = terris(&repo_dir, &home_dir)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(output.status.success());
+    }
+
+    let output = terris(&repo_dir, &home_dir).output().expect("run terris");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Each CJK character takes two cells, so the PATH column starts at the
+    // same cell on every line even though byte offsets differ.
+    let path_cell = |line: &str| {
+        let start = line.find(" /").expect("path column");
+        line[..start]
+            .chars()
+            .map(|c| if c.is_ascii() { 1 } else { 2 })
+            .sum::<usize>()
+    };
+    let cells: Vec<usize> = stdout.lines().skip(1).map(path_cell).collect();
+    assert_eq!(cells.len(), 3, "{}", stdout);
+    assert!(cells.iter().all(|&cell| cell == cells[0]), "{}", stdout);
+    assert!(!stdout.contains('…'), "{}", stdout);
+}
+
+#[test]
+fn list_columns_come_from_the_flag_or_the_config() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    std::fs::write(worktree.join("scratch.txt"), "x\n").expect("write file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--columns", "name,dirty,age"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "NAME    DIRTY AGE", "{}", stdout);
+    assert!(lines.contains(&"feature yes    0m"), "{}", stdout);
+    assert!(lines.contains(&"master  no      -"), "{}", stdout);
+
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "[list]\ncolumns = [\"branch\", \"head\"]\n").expect("write config");
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("--du")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("BRANCH  HEAD    SIZE\n"), "{}", stdout);
+}
+
+#[test]
+fn list_prints_csv_and_tsv() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "odd,name"], &repo_dir);
+    let output = terris(&repo_dir, &home_dir)
+        .arg("odd,name")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+
+    let list = |format: &str| {
+        let output = terris(&repo_dir, &home_dir)
+            .args(["--output", format, "--columns", "id,branch"])
+            .output()
+            .expect("run terris");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert_eq!(list("csv"), "id,branch\n1,master\n2,\"odd,name\"\n");
+    assert_eq!(list("tsv"), "id\tbranch\n1\tmaster\n2\todd,name\n");
+}
+
+#[test]
+fn global_list_groups_worktrees_by_repository() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["clone", "-q", "repo", "other"], temp_dir.path());
+    let other_dir = temp_dir.path().join("other");
+    run_git(&["branch", "topic"], &other_dir);
+    for (dir, branch) in [(&repo_dir, "feature"), (&other_dir, "topic")] {
+        let output = terris(dir, &home_dir)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(output.status.success());
+    }
+
+    let output = terris(temp_dir.path(), &home_dir)
+        .args(["--global", "--columns", "branch,dirty"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let headers: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("# "))
+        .collect();
+    assert_eq!(headers.len(), 2, "{}", stdout);
+    assert!(headers[0].starts_with("# other ("), "{}", stdout);
+    assert!(
+        headers[0].ends_with("): 2 worktree(s), 0 dirty"),
+        "{}",
+        stdout
+    );
+    assert!(headers[1].starts_with("# repo ("), "{}", stdout);
+
+    let output = terris(temp_dir.path(), &home_dir)
+        .args(["--repo", "other", "--output", "csv", "--columns", "branch"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "repo,branch\nother,master\nother,topic\n"
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--repo", "missing"])
+        .output()
+        .expect("run terris");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn last_commit_columns_show_head_age_subject_and_author() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--last-commit", "--columns", "branch", "--output", "tsv"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "branch\tcommitted\tsubject\tauthor\nmaster\t0m\tinit\tTest\n"
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--json", "--last-commit"])
+        .output()
+        .expect("run terris");
+    let list: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+    assert_eq!(list[0]["commit"]["summary"], "init");
+    assert_eq!(list[0]["commit"]["author"], "Test");
+}
+
+#[test]
+fn ci_column_asks_the_forge_once_per_branch_and_caches() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(
+        &[
+            "remote",
+            "add",
+            "origin",
+            "https://github.com/example/repo.git",
+        ],
+        &repo_dir,
+    );
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+
+    let bin = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin).expect("create dir");
+    let log = temp_dir.path().join("gh.log");
+    let gh = bin.join("gh");
+    std::fs::write(
+        &gh,
+        format!(
+            "#!/bin/sh\necho \"$*\" >> {}\ncase \"$*\" in\n  *feature*) echo '[{{\"status\":\"completed\",\"conclusion\":\"failure\"}}]' ;;\n  *) echo '[{{\"status\":\"completed\",\"conclusion\":\"success\"}}]' ;;\nesac\n",
+            log.display()
+        ),
+    )
+    .expect("write script");
+    std::fs::set_permissions(&gh, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+        .expect("chmod script");
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    for _ in 0..2 {
+        let output = terris(&repo_dir, &home_dir)
+            .env("PATH", &path)
+            .args(["--columns", "branch,ci", "--output", "csv"])
+            .output()
+            .expect("run terris");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "branch,ci\nmaster,passed\nfeature,failed\n"
+        );
+    }
+    let calls = std::fs::read_to_string(&log).expect("read log");
+    assert_eq!(calls.lines().count(), 2, "{}", calls);
+    assert!(
+        calls.contains("run list --branch feature --limit 1"),
+        "{}",
+        calls
+    );
+}
+
+#[test]
+fn prs_column_and_info_show_the_open_pull_request() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(
+        &[
+            "remote",
+            "add",
+            "origin",
+            "https://github.com/example/repo.git",
+        ],
+        &repo_dir,
+    );
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+
+    let bin = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin).expect("create dir");
+    let log = temp_dir.path().join("gh.log");
+    let gh = bin.join("gh");
+    std::fs::write(
+        &gh,
+        format!(
+            "#!/bin/sh\necho \"$*\" >> {}\ncase \"$*\" in\n  *feature*) echo '[{{\"number\":7,\"isDraft\":false,\"reviewDecision\":\"CHANGES_REQUESTED\",\"url\":\"https://github.com/example/repo/pull/7\"}}]' ;;\n  *) echo '[]' ;;\nesac\n",
+            log.display()
+        ),
+    )
+    .expect("write script");
+    std::fs::set_permissions(&gh, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+        .expect("chmod script");
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    for _ in 0..2 {
+        let output = terris(&repo_dir, &home_dir)
+            .env("PATH", &path)
+            .args(["--columns", "branch", "--prs", "--output", "csv"])
+            .output()
+            .expect("run terris");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "branch,pr\nmaster,-\nfeature,#7 open (changes requested)\n"
+        );
+    }
+    let calls = std::fs::read_to_string(&log).expect("read log");
+    assert_eq!(calls.lines().count(), 2, "{}", calls);
+    assert!(calls.contains("pr list --head feature"), "{}", calls);
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("PATH", &path)
+        .args(["info", "feature"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("#7 open (changes requested) https://github.com/example/repo/pull/7"),
+        "{}",
+        stdout
+    );
+    let calls = std::fs::read_to_string(&log).expect("read log");
+    assert_eq!(calls.lines().count(), 2, "{}", calls);
+}
+
+#[test]
+fn gone_upstreams_are_flagged_and_filtered() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let remote_dir = temp_dir.path().join("remote.git");
+    run_git(
+        &["init", "--bare", remote_dir.to_str().unwrap()],
+        temp_dir.path(),
+    );
+    run_git(
+        &["remote", "add", "origin", remote_dir.to_str().unwrap()],
+        &repo_dir,
+    );
+    run_git(
+        &["push", "-q", "-u", "origin", "master", "feature"],
+        &repo_dir,
+    );
+    run_git(&["branch", "solo"], &repo_dir);
+    for branch in ["feature", "solo"] {
+        let output = terris(&repo_dir, &home_dir)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    run_git(&["push", "-q", "origin", "--delete", "feature"], &repo_dir);
+    run_git(&["fetch", "-q", "--prune"], &repo_dir);
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--columns", "branch,flags", "--output", "csv"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "branch,flags\nmaster,-\nfeature,gone\nsolo,no-upstream\n"
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--gone", "--columns", "branch", "--output", "csv"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "branch\nfeature\n");
+}
+
+#[test]
+fn list_filters_by_state_and_combines_filters() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let manual = temp_dir.path().join("manual");
+    let raw = temp_dir.path().join("raw");
+    run_git(
+        &[
+            "worktree",
+            "add",
+            "-q",
+            "-b",
+            "manual",
+            manual.to_str().unwrap(),
+        ],
+        &repo_dir,
+    );
+    run_git(
+        &["worktree", "add", "-q", "--detach", raw.to_str().unwrap()],
+        &repo_dir,
+    );
+    run_git(&["worktree", "lock", raw.to_str().unwrap()], &repo_dir);
+
+    let list = |filters: &[&str]| {
+        let output = terris(&repo_dir, &home_dir)
+            .args(filters)
+            .args(["--sort", "name", "--columns", "name", "--output", "csv"])
+            .output()
+            .expect("run terris");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert_eq!(list(&["--unmanaged"]), "name\nmanual\nraw\n");
+    assert_eq!(list(&["--managed"]), "name\nfeature\n");
+    assert_eq!(list(&["--locked"]), "name\nraw\n");
+    assert_eq!(list(&["--unmanaged", "--detached"]), "name\nraw\n");
+    assert_eq!(list(&["--locked", "--prunable"]), "name\n");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--all", "--sort", "name", "--columns", "name,managed"])
+        .args(["--output", "csv"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "name,managed\nfeature,yes\nmanual,no\nmaster,-\nraw,no\n"
+    );
+}
+
+#[test]
+fn dirty_filter_lists_only_worktrees_with_changes() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "other"], &repo_dir);
+    let mut paths = Vec::new();
+    for branch in ["feature", "other"] {
+        let output = terris(&repo_dir, &home_dir)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(output.status.success());
+        paths.push(std::path::PathBuf::from(
+            String::from_utf8_lossy(&output.stdout).trim(),
+        ));
+    }
+    std::fs::write(paths[0].join("notes.txt"), "draft\n").expect("write file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--dirty", "--columns", "name,dirty", "--output", "csv"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "name,dirty\nfeature,yes\n"
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--json", "--dirty"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let entries: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("parse list json");
+    assert_eq!(entries.as_array().map(Vec::len), Some(1), "{}", entries);
+    assert_eq!(entries[0]["dirty"], true, "{}", entries);
+}
+
+#[test]
+fn find_reports_the_worktree_with_a_matching_unmerged_commit() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "other"], &repo_dir);
+    for (branch, message) in [
+        ("feature", "Fix the TimeZone offset in reports"),
+        ("other", "Document the time format"),
+    ] {
+        let output = terris(&repo_dir, &home_dir)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(output.status.success());
+        let path = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        run_git_with_env(
+            &[
+                "-c",
+                "user.name=Test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-q",
+                "--allow-empty",
+                "-m",
+                message,
+            ],
+            &path,
+            &[],
+        );
+    }
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["find", "--message", "time ?zone", "-i"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 1, "{}", stdout);
+    assert!(stdout.starts_with("feature feature "), "{}", stdout);
+    assert!(
+        stdout
+            .trim_end()
+            .ends_with("Fix the TimeZone offset in reports"),
+        "{}",
+        stdout
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["find", "--message", "^init$"])
+        .output()
+        .expect("run terris");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn grep_prefixes_matches_with_the_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let feature = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    std::fs::write(feature.join("notes.txt"), "one\nretry_after = 30\n").expect("write file");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["grep", "RETRY_after", "-i"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "feature:notes.txt:2:retry_after = 30\n"
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["grep", "retry_after", "--filter", "master"])
+        .output()
+        .expect("run terris");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn list_views_come_from_the_config_and_yield_to_flags() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        "[list.views]\nstale = { sort = \"age\", filter = [\"clean\"], columns = [\"name\", \"branch\"] }\n",
+    )
+    .expect("write config");
+    run_git(&["branch", "other"], &repo_dir);
+    let mut paths = Vec::new();
+    for branch in ["feature", "other"] {
+        let output = terris(&repo_dir, &home_dir)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(output.status.success());
+        paths.push(std::path::PathBuf::from(
+            String::from_utf8_lossy(&output.stdout).trim(),
+        ));
+    }
+    std::fs::write(paths[1].join("notes.txt"), "draft\n").expect("write file");
+
+    let list = |args: &[&str]| {
+        terris(&repo_dir, &home_dir)
+            .env("TERRIS_CONFIG", &config)
+            .args(args)
+            .args(["--output", "csv"])
+            .output()
+            .expect("run terris")
+    };
+    let output = list(&["--view", "stale"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "name,branch\nfeature,feature\nmaster,master\n"
+    );
+
+    let output = list(&["--view", "stale", "--columns", "name"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "name\nfeature\nmaster\n"
+    );
+
+    let output = list(&["--view", "fresh"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("known views: stale"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn foreach_prefixes_output_and_stops_early_on_failure() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+
+    let output = terris(&repo_dir, &home_dir)
+        .args([
+            "foreach",
+            "--output",
+            "buffer",
+            "--",
+            "sh",
+            "-c",
+            "echo \"on $TERRIS_BRANCH\"; echo oops >&2",
+        ])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect();
+    lines.sort();
+    assert_eq!(lines, ["feature | on feature", "master  | on master"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .filter(|line| line.ends_with("| oops"))
+            .count(),
+        2
+    );
+
+    let started = std::time::Instant::now();
+    let output = terris(&repo_dir, &home_dir)
+        .args([
+            "--jobs",
+            "2",
+            "foreach",
+            "--fail-fast",
+            "--",
+            "sh",
+            "-c",
+            "if [ \"$TERRIS_BRANCH\" = feature ]; then exit 3; fi; sleep 30",
+        ])
+        .output()
+        .expect("run terris");
+    assert!(!output.status.success());
+    assert!(started.elapsed() < std::time::Duration::from_secs(20));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("feature: `sh -c"), "{}", stderr);
+    assert!(stderr.contains("1 canceled"), "{}", stderr);
+}
+
+#[test]
+fn jobs_limit_runs_foreach_one_worktree_at_a_time() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "jobs = 1\n").expect("write config");
+
+    // One at a time, the primary worktree runs first and fails before the
+    // other starts, so --fail-fast never runs it.
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args([
+            "foreach",
+            "--fail-fast",
+            "--",
+            "sh",
+            "-c",
+            "echo \"ran $TERRIS_BRANCH\"; exit 3",
+        ])
+        .output()
+        .expect("run terris");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim(), "master  | ran master");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 canceled"));
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["--jobs", "2", "foreach", "--", "true"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--jobs", "0", "sync"])
+        .output()
+        .expect("run terris");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn stalled_git_commands_are_killed_and_leave_no_half_made_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "slow"], &repo_dir);
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "git-timeout = 1\n").expect("write config");
+
+    // A git that does its work for `worktree add` and then hangs, like one
+    // waiting on a credential helper or a network mount.
+    let real_git = std::process::Command::new("sh")
+        .args(["-c", "command -v git"])
+        .output()
+        .expect("find git");
+    let real_git = String::from_utf8_lossy(&real_git.stdout).trim().to_string();
+    let bin = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin).expect("create dir");
+    let git = bin.join("git");
+    std::fs::write(
+        &git,
+        format!(
+            "#!/bin/sh\n{real_git} \"$@\" || exit $?\nif [ \"$1 $2\" = \"worktree add\" ]; then exec sleep 30; fi\n"
+        ),
+    )
+    .expect("write script");
+    std::fs::set_permissions(&git, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+        .expect("chmod script");
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let started = std::time::Instant::now();
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .env("PATH", &path)
+        .arg("slow")
+        .output()
+        .expect("run terris");
+    assert!(started.elapsed() < std::time::Duration::from_secs(20));
+    assert_eq!(output.status.code(), Some(11));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("`git worktree add --quiet") && stderr.contains("within 1s"),
+        "{}",
+        stderr
+    );
+
+    let list = Command::new("git")
+        .args(["worktree", "list", "--porcelain"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git");
+    let list = String::from_utf8_lossy(&list.stdout);
+    assert!(!list.contains("refs/heads/slow"), "{}", list);
+    let registry = home_dir.join(".terris-worktrees").join("repo");
+    let left: Vec<_> = std::fs::read_dir(&registry)
+        .map(|entries| entries.flatten().map(|entry| entry.file_name()).collect())
+        .unwrap_or_default();
+    assert!(left.is_empty(), "{:?}", left);
+}
+
+#[test]
+fn git_executable_comes_from_terris_git_or_the_config() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let real_git = Command::new("sh")
+        .args(["-c", "command -v git"])
+        .output()
+        .expect("find git");
+    let real_git = String::from_utf8_lossy(&real_git.stdout).trim().to_string();
+    let log = temp_dir.path().join("git.log");
+    let wrapper = temp_dir.path().join("my-git");
+    std::fs::write(
+        &wrapper,
+        format!(
+            "#!/bin/sh\necho \"$1\" >> '{}'\nexec {} \"$@\"\n",
+            log.display(),
+            real_git
+        ),
+    )
+    .expect("write script");
+    std::fs::set_permissions(
+        &wrapper,
+        std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )
+    .expect("chmod script");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, format!("git = '{}'\n", wrapper.display())).expect("write config");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let calls = std::fs::read_to_string(&log).expect("read log");
+    assert!(calls.lines().any(|line| line == "worktree"), "{}", calls);
+
+    // TERRIS_GIT wins over the config.
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .env("TERRIS_GIT", temp_dir.path().join("missing-git"))
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("missing-git"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn old_gits_get_fallbacks_or_a_clear_version_error() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let real_git = Command::new("sh")
+        .args(["-c", "command -v git"])
+        .output()
+        .expect("find git");
+    let real_git = String::from_utf8_lossy(&real_git.stdout).trim().to_string();
+    // A git claiming to be `version`, and refusing `worktree remove` as
+    // gits before 2.17 would.
+    let old_git = |version: &str| {
+        let script = temp_dir.path().join(format!("git-{}", version));
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\ncase \"$1 $2\" in\n  '--version ') echo 'git version {version}' ;;\n  'worktree remove') echo \"unknown subcommand: remove\" >&2; exit 129 ;;\n  *) exec {real_git} \"$@\" ;;\nesac\n"
+            ),
+        )
+        .expect("write script");
+        std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .expect("chmod script");
+        script
+    };
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_GIT", old_git("2.16.2"))
+        .args(["delete", "feature"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!std::path::Path::new(&path).exists());
+    let list = Command::new("git")
+        .args(["worktree", "list", "--porcelain"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git");
+    assert!(!String::from_utf8_lossy(&list.stdout).contains("feature"));
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_GIT", old_git("2.5.1"))
+        .output()
+        .expect("run terris");
+    assert_eq!(output.status.code(), Some(12));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("requires git >= 2.7.0"), "{}", stderr);
+    assert!(stderr.contains("2.5.1"), "{}", stderr);
+}
+
+#[test]
+fn git_commands_retry_while_another_process_holds_a_lock() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let lock = repo_dir.join(".git/refs/heads/held.lock");
+
+    // Nothing retried: the held lock fails the branch creation.
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "lock-retries = 0\n").expect("write config");
+    std::fs::write(&lock, "").expect("hold lock");
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["create", "held", "--on", "master"])
+        .output()
+        .expect("run terris");
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("held.lock"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // With the default retries, the other process lets go in time.
+    let release = std::thread::spawn({
+        let lock = lock.clone();
+        move || {
+            std::thread::sleep(std::time::Duration::from_millis(150));
+            std::fs::remove_file(&lock).expect("release lock");
+        }
+    });
+    let output = terris(&repo_dir, &home_dir)
+        .args(["create", "held", "--on", "master"])
+        .output()
+        .expect("run terris");
+    release.join().expect("release lock");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn worktrees_at_non_utf8_paths_are_printed_and_removed_intact() {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let mut name = b"caf".to_vec();
+    name.push(0xe9);
+    let wt_dir = temp_dir
+        .path()
+        .join(std::ffi::OsString::from_vec(name.clone()));
+    let status = Command::new("git")
+        .arg("worktree")
+        .arg("add")
+        .arg(&wt_dir)
+        .arg("feature")
+        .current_dir(&repo_dir)
+        .status()
+        .expect("run git");
+    assert!(status.success());
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["path", "feature"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let mut expected = wt_dir.as_os_str().as_bytes().to_vec();
+    expected.push(b'\n');
+    assert_eq!(output.stdout, expected);
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["delete", "feature"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!wt_dir.exists());
+}
+
+#[test]
+fn worktrees_created_from_a_linked_worktree_belong_to_the_repository() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "other"], &repo_dir);
+    run_git(&["branch", "third"], &repo_dir);
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let feature_dir = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+    let output = terris(&feature_dir, &home_dir)
+        .arg("other")
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let other_dir = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    assert_eq!(
+        other_dir.parent(),
+        Some(home_dir.join(".terris-worktrees").join("repo").as_path())
+    );
+
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "location = \"in-repo\"\n").expect("write config");
+    let output = terris(&feature_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("third")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success());
+    let third_dir = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    assert_eq!(
+        third_dir.canonicalize().expect("canonicalize"),
+        repo_dir
+            .join(".worktrees/third")
+            .canonicalize()
+            .expect("canonicalize")
+    );
+}
+
+#[test]
+fn worktree_config_applies_to_one_worktree_only() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        "[worktree-config.\"feat*\"]\n\"user.email\" = \"oss@example.org\"\n",
+    )
+    .expect("write config");
+    let git_config = |key: &str, cwd: &std::path::Path| {
+        let output = Command::new("git")
+            .args(["config", "--get", key])
+            .current_dir(cwd)
+            .output()
+            .expect("run git config");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let feature = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    assert_eq!(git_config("extensions.worktreeConfig", &repo_dir), "true");
+    assert_eq!(git_config("user.email", &feature), "oss@example.org");
+    assert_ne!(git_config("user.email", &repo_dir), "oss@example.org");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["config-worktree", "main", "core.hooksPath", "hooks-main"])
+        .output()
+        .expect("run terris config-worktree");
+    assert!(output.status.success(), "set failed: {:?}", output);
+    assert_eq!(git_config("core.hooksPath", &repo_dir), "hooks-main");
+    assert_eq!(git_config("core.hooksPath", &feature), "");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["config-worktree", "feature", "user.email"])
+        .output()
+        .expect("run terris config-worktree");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "oss@example.org"
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["config-worktree", "feature", "user.email", "--unset"])
+        .output()
+        .expect("run terris config-worktree --unset");
+    assert!(output.status.success(), "unset failed: {:?}", output);
+    let output = terris(&repo_dir, &home_dir)
+        .args(["config-worktree", "feature", "user.email"])
+        .output()
+        .expect("run terris config-worktree");
+    assert!(!output.status.success(), "an unset key should be an error");
+}
+
+#[test]
+fn propagated_config_keeps_pointing_at_the_primary_worktree() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        "propagate-config = [\"core.hooksPath\", \"merge.*.driver\"]\n",
+    )
+    .expect("write config");
+    run_git(&["config", "core.hooksPath", ".husky/_"], &repo_dir);
+    run_git(&["config", "merge.lockfile.driver", "npm i"], &repo_dir);
+    run_git(&["config", "merge.lockfile.name", "lockfile"], &repo_dir);
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    let feature = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let own = |key: &str| {
+        let output = Command::new("git")
+            .args(["config", "--worktree", "--get", key])
+            .current_dir(&feature)
+            .output()
+            .expect("run git config");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    };
+    assert_eq!(
+        own("core.hooksPath"),
+        repo_dir.join(".husky/_").to_string_lossy()
+    );
+    assert_eq!(own("merge.lockfile.driver"), "npm i");
+    assert_eq!(own("merge.lockfile.name"), "");
+}
+
+#[test]
+fn info_exclude_rules_of_the_repository_apply_in_new_worktrees() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::write(repo_dir.join(".git/info/exclude"), "notes.txt\n").expect("write exclude");
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed");
+    let feature = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    std::fs::write(feature.join("notes.txt"), "mine\n").expect("write notes");
+
+    let status = Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(&feature)
+        .output()
+        .expect("run git status");
+    assert!(String::from_utf8_lossy(&status.stdout).trim().is_empty());
+}
+
+#[test]
+fn hook_frameworks_are_installed_in_new_worktrees() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    std::fs::write(repo_dir.join(".pre-commit-config.yaml"), "repos: []\n").expect("write file");
+    run_git(&["add", "."], &repo_dir);
+    run_git_with_env(
+        &[
+            "-c",
+            "user.name=Test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "-m",
+            "pre-commit",
+        ],
+        &repo_dir,
+        &[],
+    );
+    run_git(&["branch", "-f", "feature", "HEAD"], &repo_dir);
+    let bin = temp_dir.path().join("bin");
+    std::fs::create_dir(&bin).expect("create dir");
+    let calls = temp_dir.path().join("calls");
+    for tool in ["pre-commit", "lefthook"] {
+        let script = bin.join(tool);
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho {} \"$@\" \"$PWD\" >> '{}'\n",
+                tool,
+                calls.display()
+            ),
+        )
+        .expect("write script");
+        std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .expect("chmod script");
+    }
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "[hooks]\ninstall-frameworks = true\n").expect("write config");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("PATH", path)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    let worktree = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let calls = std::fs::read_to_string(&calls).expect("read calls");
+    assert_eq!(
+        calls.trim(),
+        format!("pre-commit install {}", worktree.display())
+    );
+}
+
+#[test]
+fn adopt_all_takes_over_worktrees_made_with_git() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let by_git = temp_dir.path().join("by-git");
+    run_git(
+        &[
+            "worktree",
+            "add",
+            by_git.to_str().expect("utf-8 path"),
+            "feature",
+        ],
+        &repo_dir,
+    );
+    run_git(&["worktree", "add", "--detach", "../detached"], &repo_dir);
+    let unmanaged = |home: &std::path::Path| {
+        let output = terris(&repo_dir, home)
+            .args(["--all", "--unmanaged", "--columns", "name"])
+            .output()
+            .expect("run terris --unmanaged");
+        assert!(output.status.success(), "list failed: {:?}", output);
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .skip(1)
+            .map(|line| line.trim().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(unmanaged(&home_dir), ["feature", "detached"]);
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--json", "adopt", "--all"])
+        .output()
+        .expect("run terris adopt --all");
+    assert!(output.status.success(), "adopt failed: {:?}", output);
+    let adopted: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+    let names: Vec<&str> = adopted
+        .as_array()
+        .expect("an array")
+        .iter()
+        .map(|entry| entry["name"].as_str().expect("a name"))
+        .collect();
+    assert_eq!(names, ["feature", "detached"]);
+    assert!(unmanaged(&home_dir).is_empty());
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["adopt", "--all"])
+        .output()
+        .expect("run terris adopt --all");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already managed"));
+}
+
+#[test]
+fn disowned_worktrees_are_kept_out_of_gc_and_adopt_all() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "[retention]\ndefault = \"1d\"\n").expect("write config");
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    let feature = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["disown", "feature"])
+        .output()
+        .expect("run terris disown");
+    assert!(output.status.success(), "disown failed: {:?}", output);
+    // Even after two days unused, nothing is over its limit.
+    let store = home_dir.join(".terris-worktrees/metadata.json");
+    let mut metadata: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&store).expect("read metadata"))
+            .expect("parse metadata");
+    let meta = &mut metadata["worktrees"][feature.to_str().expect("utf-8 path")];
+    assert_eq!(meta["disowned"], true);
+    assert!(meta.get("created").is_none());
+    let two_days_ago = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("clock")
+        .as_secs()
+        - 2 * 86_400;
+    meta["last_used"] = two_days_ago.into();
+    std::fs::write(&store, metadata.to_string()).expect("write metadata");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["--dry-run", "gc"])
+        .output()
+        .expect("run terris gc");
+    assert!(output.status.success(), "gc failed: {:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "Nothing to collect."
+    );
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["adopt", "--all"])
+        .output()
+        .expect("run terris adopt --all");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(feature.exists());
+}
+
+#[test]
+fn exported_registry_is_restored_after_wiping_it() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--description", "try the parser", "feature"])
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    let feature = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+    let output = terris(&repo_dir, &home_dir)
+        .arg("export")
+        .output()
+        .expect("run terris export");
+    assert!(output.status.success(), "export failed: {:?}", output);
+    let exported = temp_dir.path().join("registry.json");
+    std::fs::write(&exported, &output.stdout).expect("write export");
+    let registry: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+    assert_eq!(registry["repos"][0]["worktrees"][0]["branch"], "feature");
+
+    run_git(
+        &["worktree", "remove", feature.to_str().expect("utf-8 path")],
+        &repo_dir,
+    );
+    std::fs::remove_dir_all(home_dir.join(".terris-worktrees")).expect("wipe registry");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["import", exported.to_str().expect("utf-8 path")])
+        .output()
+        .expect("run terris import");
+    assert!(output.status.success(), "import failed: {:?}", output);
+    assert!(feature.join("README.md").exists());
+    let output = terris(&repo_dir, &home_dir)
+        .args(["info", "feature"])
+        .output()
+        .expect("run terris info");
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("try the parser"),
+        "{:?}",
+        output
+    );
+}
+
+#[test]
+fn concurrent_creates_keep_every_worktrees_metadata() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let branches: Vec<String> = (0..6).map(|i| format!("agent-{}", i)).collect();
+    for branch in &branches {
+        run_git(&["branch", branch], &repo_dir);
+    }
+
+    let children: Vec<_> = branches
+        .iter()
+        .map(|branch| {
+            terris(&repo_dir, &home_dir)
+                .arg(branch)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn()
+                .expect("spawn terris")
+        })
+        .collect();
+    for mut child in children {
+        assert!(child.wait().expect("wait for terris").success());
+    }
+
+    let store = home_dir.join(".terris-worktrees/metadata.json");
+    let metadata: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&store).expect("read metadata"))
+            .expect("parse metadata");
+    let created = metadata["worktrees"]
+        .as_object()
+        .expect("worktrees")
+        .values()
+        .filter(|meta| meta.get("created").is_some())
+        .count();
+    assert_eq!(created, branches.len());
+}
+
+#[test]
+fn a_held_registry_lock_fails_with_registry_busy() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let registry = home_dir.join(".terris-worktrees");
+    std::fs::create_dir_all(&registry).expect("create registry");
+    let held = std::fs::File::create(registry.join(".lock")).expect("create lock file");
+    held.lock().expect("take the lock");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(&config, "registry-wait = 1\n").expect("write config");
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert_eq!(output.status.code(), Some(13), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("registry is busy"),
+        "{:?}",
+        output
+    );
+    assert!(!registry.join("repo").exists());
+}
+
+#[test]
+fn relocate_reconnects_worktrees_after_the_repository_moves() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "inside"], &repo_dir);
+    let in_repo = temp_dir.path().join("config.toml");
+    std::fs::write(&in_repo, "location = \"in-repo\"\n").expect("write config");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    let feature = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &in_repo)
+        .arg("inside")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+
+    let moved = temp_dir.path().join("moved");
+    std::fs::create_dir(&moved).expect("create dir");
+    let new_dir = moved.join("repo");
+    std::fs::rename(&repo_dir, &new_dir).expect("move repository");
+    let output = terris(temp_dir.path(), &home_dir)
+        .args(["relocate"])
+        .arg(&repo_dir)
+        .arg(&new_dir)
+        .output()
+        .expect("run terris relocate");
+    assert!(output.status.success(), "relocate failed: {:?}", output);
+
+    for worktree in [feature, new_dir.join(".worktrees/inside")] {
+        let status = Command::new("git")
+            .args(["status", "--porcelain"])
+            .current_dir(&worktree)
+            .output()
+            .expect("run git status");
+        assert!(
+            status.status.success(),
+            "{}: {:?}",
+            worktree.display(),
+            status
+        );
+    }
+    let output = terris(&new_dir, &home_dir)
+        .args(["--json", "info", "inside"])
+        .output()
+        .expect("run terris info");
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+    assert!(info["metadata"]["created"].is_number(), "{}", info);
+}
+
+#[test]
+fn relocate_updates_the_repository_of_worktrees_kept_outside_it() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+
+    let new_dir = temp_dir.path().join("moved");
+    std::fs::rename(&repo_dir, &new_dir).expect("move repository");
+    let output = terris(temp_dir.path(), &home_dir)
+        .args(["relocate"])
+        .arg(&repo_dir)
+        .arg(&new_dir)
+        .output()
+        .expect("run terris relocate");
+    assert!(output.status.success(), "relocate failed: {:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("repaired 1 worktree link(s), moved the records of 0"),
+        "{}",
+        stderr
+    );
+
+    let output = terris(temp_dir.path(), &home_dir)
+        .args(["--json", "repos"])
+        .output()
+        .expect("run terris repos");
+    assert!(output.status.success(), "repos failed: {:?}", output);
+    let listed: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+    assert_eq!(listed.as_array().map(Vec::len), Some(1), "{}", listed);
+    assert_eq!(listed[0]["missing"], false, "{}", listed);
+    let root = std::path::PathBuf::from(listed[0]["root"].as_str().expect("root"));
+    assert_eq!(
+        root.canonicalize().ok(),
+        new_dir.canonicalize().ok(),
+        "{}",
+        listed
+    );
+}
+
+#[test]
+fn gc_removes_registry_directories_git_no_longer_knows() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "gone"], &repo_dir);
+    let mut paths = Vec::new();
+    for branch in ["feature", "gone"] {
+        let output = terris(&repo_dir, &home_dir)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(output.status.success(), "terris failed: {:?}", output);
+        paths.push(std::path::PathBuf::from(
+            String::from_utf8_lossy(&output.stdout).trim(),
+        ));
+    }
+    let (live, pruned) = (&paths[0], &paths[1]);
+    // Git forgets the worktree but its directory stays behind.
+    let gitdir = std::fs::read_to_string(pruned.join(".git")).expect("read .git");
+    std::fs::remove_dir_all(gitdir.trim_end().trim_start_matches("gitdir: "))
+        .expect("remove worktree entry");
+    let leftover = home_dir.join(".terris-worktrees/repo/half-removed/target");
+    std::fs::create_dir_all(&leftover).expect("create leftover");
+    std::fs::write(leftover.join("build.log"), "").expect("write leftover");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["--dry-run", "gc"])
+        .output()
+        .expect("run terris gc");
+    assert!(output.status.success(), "gc failed: {:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&pruned.display().to_string()), "{}", stdout);
+    assert!(stdout.contains("half-removed (orphaned)"), "{}", stdout);
+    assert!(!stdout.contains(&live.display().to_string()), "{}", stdout);
+
+    // Without a terminal to ask on, they are only listed.
+    let output = terris(&repo_dir, &home_dir)
+        .arg("gc")
+        .output()
+        .expect("run terris gc");
+    assert!(output.status.success(), "gc failed: {:?}", output);
+    assert!(pruned.exists());
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["gc", "--remove-orphans"])
+        .output()
+        .expect("run terris gc");
+    assert!(output.status.success(), "gc failed: {:?}", output);
+    assert!(!pruned.exists());
+    assert!(!leftover.exists());
+    assert!(live.join(".git").is_file());
+}
+
+#[test]
+fn repos_lists_recorded_repositories_and_forgets_gone_ones() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let output = terris(&repo_dir, &home_dir)
+        .arg("feature")
+        .output()
+        .expect("run terris");
+    assert!(output.status.success(), "terris failed: {:?}", output);
+    let feature = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let repos = || {
+        let output = terris(temp_dir.path(), &home_dir)
+            .args(["--json", "repos"])
+            .output()
+            .expect("run terris repos");
+        assert!(output.status.success(), "repos failed: {:?}", output);
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).expect("parse json")
+    };
+
+    let listed = repos();
+    assert_eq!(listed.as_array().map(Vec::len), Some(1), "{}", listed);
+    assert_eq!(listed[0]["name"], "repo");
+    assert_eq!(listed[0]["worktrees"], 1);
+    assert_eq!(listed[0]["missing"], false);
+    assert!(listed[0]["size"].as_u64().is_some_and(|size| size > 0));
+    let output = terris(temp_dir.path(), &home_dir)
+        .args(["repos", "forget", "repo"])
+        .output()
+        .expect("run terris repos forget");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("still exists"));
+
+    std::fs::remove_dir_all(&feature).expect("remove worktree");
+    std::fs::remove_dir_all(&repo_dir).expect("remove repository");
+    let listed = repos();
+    assert_eq!(listed[0]["missing"], true, "{}", listed);
+    let output = terris(temp_dir.path(), &home_dir)
+        .args(["repos", "forget", "repo"])
+        .output()
+        .expect("run terris repos forget");
+    assert!(output.status.success(), "forget failed: {:?}", output);
+    assert_eq!(repos(), serde_json::json!([]));
+}
+
+#[test]
+fn ws_creates_lists_runs_in_and_deletes_a_branch_across_repositories() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let web_dir = temp_dir.path().join("web");
+    run_git(
+        &["clone", "--quiet", &repo_dir.to_string_lossy(), "web"],
+        temp_dir.path(),
+    );
+    let home_dir = temp_dir.path().join("home");
+    let config = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[workspaces.shop]\nrepos = [{:?}, \"web\"]\n",
+            repo_dir.to_string_lossy()
+        ),
+    )
+    .expect("write config");
+    let ws = |args: &[&str]| {
+        terris(&web_dir, &home_dir)
+            .env("TERRIS_CONFIG", &config)
+            .arg("ws")
+            .args(args)
+            .output()
+            .expect("run terris ws")
+    };
+
+    let output = ws(&["create", "feature-x"]);
+    assert!(output.status.success(), "ws create failed: {:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let paths: Vec<&str> = stdout.lines().collect();
+    assert_eq!(paths.len(), 2, "{}", stdout);
+    for path in &paths {
+        assert!(std::path::Path::new(path).join(".git").is_file());
+    }
+
+    let output = ws(&[
+        "--workspace",
+        "shop",
+        "foreach",
+        "feature-x",
+        "--",
+        "git",
+        "branch",
+        "--show-current",
+    ]);
+    assert!(output.status.success(), "ws foreach failed: {:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("repo | feature-x"), "{}", stdout);
+    assert!(stdout.contains("web  | feature-x"), "{}", stdout);
+
+    let output = terris(&repo_dir, &home_dir)
+        .env("TERRIS_CONFIG", &config)
+        .args(["--json", "ws", "list"])
+        .output()
+        .expect("run terris ws list");
+    assert!(output.status.success(), "ws list failed: {:?}", output);
+    let listed: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+    assert_eq!(listed[0]["branch"], "feature-x", "{}", listed);
+    assert_eq!(listed[0]["worktrees"].as_object().map(|w| w.len()), Some(2));
+    assert_eq!(listed[0]["missing"], serde_json::json!([]));
+
+    let output = ws(&["delete", "feature-x"]);
+    assert!(output.status.success(), "ws delete failed: {:?}", output);
+    for path in &paths {
+        assert!(!std::path::Path::new(path).exists());
+    }
+    let output = ws(&["list"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No worktrees"));
+}
+
+#[test]
+fn serve_mcp_creates_runs_in_and_deletes_worktrees() {
+    use std::io::Write;
+
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let requests = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"create","arguments":{"branch":"agent"}}}"#,
+        r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"exec","arguments":{"worktree":"agent","command":"echo $TERRIS_BRANCH"}}}"#,
+        r#"{"jsonrpc":"2.0","id":5,"method":"tools/call","params":{"name":"delete","arguments":{"worktree":"agent"}}}"#,
+        r#"{"jsonrpc":"2.0","id":6,"method":"tools/call","params":{"name":"status","arguments":{"worktree":"agent"}}}"#,
+    ];
+
+    let mut child = terris(&repo_dir, &home_dir)
+        .args(["serve", "--mcp"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("run terris");
+    let mut stdin = child.stdin.take().expect("open stdin");
+    for request in requests {
+        writeln!(stdin, "{}", request).expect("write request");
+    }
+    drop(stdin);
+    let output = child.wait_with_output().expect("wait for terris");
+    assert!(output.status.success());
+
+    let replies: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("parse reply"))
+        .collect();
+    // The notification gets no reply.
+    assert_eq!(replies.len(), 6, "{:?}", replies);
+    assert_eq!(replies[0]["result"]["serverInfo"]["name"], "terris");
+    let tools = replies[1]["result"]["tools"].as_array().expect("tools");
+    assert!(tools.iter().any(|tool| tool["name"] == "exec"));
+
+    let text = |reply: &serde_json::Value| -> serde_json::Value {
+        assert_eq!(reply["result"]["isError"], false, "{}", reply);
+        serde_json::from_str(reply["result"]["content"][0]["text"].as_str().unwrap()).unwrap()
+    };
+    let created = text(&replies[2]);
+    let worktree = std::path::PathBuf::from(created["path"].as_str().unwrap());
+    assert_eq!(text(&replies[3])["stdout"], "agent\n");
+    assert_eq!(text(&replies[4])["removed"], true);
+    assert!(!worktree.exists());
+    // Gone with its branch: the worktree no longer resolves.
+    assert_eq!(replies[5]["result"]["isError"], true);
+    let branches = Command::new("git")
+        .args(["branch", "--list", "agent"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git");
+    assert!(branches.stdout.is_empty());
+}
+
+#[test]
+fn serve_http_answers_json_requests() {
+    use std::io::{BufRead, Read, Write};
+
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let mut child = terris(&repo_dir, &home_dir)
+        .args(["serve", "--http", "127.0.0.1:0"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("run terris");
+    let mut stderr = std::io::BufReader::new(child.stderr.take().expect("open stderr"));
+    let mut line = String::new();
+    stderr.read_line(&mut line).expect("read address");
+    let addr = line
+        .trim()
+        .strip_prefix("Listening on http://")
+        .expect("listening line")
+        .to_string();
+    line.clear();
+    stderr.read_line(&mut line).expect("read token");
+    let token = line
+        .trim()
+        .strip_prefix("Token: ")
+        .expect("token line")
+        .to_string();
+    let request = |method: &str, path: &str, body: &str| -> String {
+        let mut stream = std::net::TcpStream::connect(&addr).expect("connect");
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            addr,
+            token,
+            body.len(),
+            body
+        )
+        .expect("send request");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("read response");
+        response
+    };
+
+    let created = request(
+        "POST",
+        "/worktrees",
+        r#"{"branch":"feature","ephemeral":false}"#,
+    );
+    let status = request("GET", "/worktrees/feature/status", "");
+    let missing = request("GET", "/worktrees/nope/status", "");
+    let deleted = request("DELETE", "/worktrees/feature", "");
+    child.kill().expect("stop server");
+    let _ = child.wait();
+
+    assert!(created.starts_with("HTTP/1.1 201 "), "{}", created);
+    assert!(status.starts_with("HTTP/1.1 200 "), "{}", status);
+    assert!(status.contains(r#""clean":true"#), "{}", status);
+    assert!(missing.starts_with("HTTP/1.1 404 "), "{}", missing);
+    assert!(
+        missing.contains(r#""code":"worktree_not_found""#),
+        "{}",
+        missing
+    );
+    assert!(deleted.contains(r#""removed":true"#), "{}", deleted);
+}
+
+#[test]
+fn serve_http_refuses_requests_without_the_token_or_json() {
+    use std::io::{BufRead, Read, Write};
+
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    let mut child = terris(&repo_dir, &home_dir)
+        .args(["serve", "--http", "127.0.0.1:0"])
+        .env("TERRIS_SERVE_TOKEN", "secret")
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("run terris");
+    let mut stderr = std::io::BufReader::new(child.stderr.take().expect("open stderr"));
+    let mut line = String::new();
+    stderr.read_line(&mut line).expect("read address");
+    let addr = line
+        .trim()
+        .strip_prefix("Listening on http://")
+        .expect("listening line")
+        .to_string();
+    let request = |headers: &str| -> String {
+        let body = r#"{"worktree":"main","command":"true"}"#;
+        let mut stream = std::net::TcpStream::connect(&addr).expect("connect");
+        write!(
+            stream,
+            "POST /worktrees/main/exec HTTP/1.1\r\nHost: {}\r\n{}Content-Length: {}\r\n\r\n{}",
+            addr,
+            headers,
+            body.len(),
+            body
+        )
+        .expect("send request");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("read response");
+        response
+    };
+
+    let tokenless = request("Content-Type: application/json\r\n");
+    let wrong = request("Authorization: Bearer guess\r\nContent-Type: application/json\r\n");
+    let text = request("Authorization: Bearer secret\r\nContent-Type: text/plain\r\n");
+    let browser = request(
+        "Authorization: Bearer secret\r\nContent-Type: application/json\r\n\
+         Origin: http://evil.example\r\n",
+    );
+    let ran = request("Authorization: Bearer secret\r\nContent-Type: application/json\r\n");
+    child.kill().expect("stop server");
+    let _ = child.wait();
+
+    assert!(tokenless.starts_with("HTTP/1.1 401 "), "{}", tokenless);
+    assert!(wrong.starts_with("HTTP/1.1 401 "), "{}", wrong);
+    assert!(text.starts_with("HTTP/1.1 415 "), "{}", text);
+    assert!(browser.starts_with("HTTP/1.1 403 "), "{}", browser);
+    assert!(ran.starts_with("HTTP/1.1 200 "), "{}", ran);
+}
+
+#[test]
+fn daemon_prunes_and_caches_status_for_the_list() {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let repo_dir = init_repo(&temp_dir);
+    let home_dir = temp_dir.path().join("home");
+    run_git(&["branch", "gone"], &repo_dir);
+    let mut paths = Vec::new();
+    for branch in ["feature", "gone"] {
+        let output = terris(&repo_dir, &home_dir)
+            .arg(branch)
+            .output()
+            .expect("run terris");
+        assert!(output.status.success());
+        paths.push(std::path::PathBuf::from(
+            String::from_utf8_lossy(&output.stdout).trim(),
+        ));
+    }
+    std::fs::write(paths[0].join("README.md"), "changed\n").expect("write file");
+    std::fs::remove_dir_all(&paths[1]).expect("remove worktree");
+
+    let output = terris(&repo_dir, &home_dir)
+        .args(["daemon", "--once"])
+        .output()
+        .expect("run terris");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let listed = Command::new("git")
+        .args(["worktree", "list"])
+        .current_dir(&repo_dir)
+        .output()
+        .expect("run git");
+    assert!(!String::from_utf8_lossy(&listed.stdout).contains("[gone]"));
+
+    let output = terris(&repo_dir, &home_dir).output().expect("run terris");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let feature = stdout
+        .lines()
+        .find(|line| line.contains("feature"))
+        .expect("feature row");
+    assert!(feature.ends_with(" dirty"), "{}", stdout);
+    let master = stdout
+        .lines()
+        .find(|line| line.contains("master"))
+        .expect("master row");
+    assert!(master.ends_with(" -"), "{}", stdout);
+}